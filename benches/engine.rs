@@ -0,0 +1,82 @@
+//! Micro-benchmarks of the move generator, making and taking back moves, the
+//! evaluation and a fixed-depth search on reference positions, to catch
+//! performance regressions numerically. Run with `cargo bench`, or e.g.
+//! `cargo bench -- eval` for a single group.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use pawndropper::eval::eval;
+use pawndropper::game::GameState;
+use pawndropper::magic::MagicBitboard;
+use pawndropper::move_bitboards::PSEUDO_LEGAL_MOVES;
+use pawndropper::search::SearchAsync;
+
+/// Opening, middlegame (with castling, en passant and pins) and endgame
+/// positions.
+const POSITIONS: [(&str, &str); 4] = [
+    ("start", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+    ("italian", "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4"),
+    ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+    ("endgame", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+];
+
+fn game(fen: &str) -> GameState {
+    GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), fen).unwrap()
+}
+
+fn legal_moves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("legal_moves");
+    for (name, fen) in POSITIONS {
+        let game = game(fen);
+        group.bench_function(name, |b| b.iter(|| black_box(&game).get_legal_moves()));
+    }
+    group.finish();
+}
+
+fn make_undo(c: &mut Criterion) {
+    let mut group = c.benchmark_group("make_undo");
+    for (name, fen) in POSITIONS {
+        let mut game = game(fen);
+        let (legal_moves, _) = game.get_legal_moves();
+
+        // Every legal move of the position, made and taken back
+        group.bench_function(name, |b| b.iter(|| {
+            for m in &legal_moves {
+                game.make_move(black_box(m));
+                game.undo_move();
+            }
+        }));
+    }
+    group.finish();
+}
+
+fn evaluation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eval");
+    for (name, fen) in POSITIONS {
+        let game = game(fen);
+        group.bench_function(name, |b| b.iter(|| eval(black_box(&game))));
+    }
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search_depth_5");
+    group.sample_size(10);
+    for (name, fen) in POSITIONS {
+        let game = game(fen);
+
+        // A fresh searcher every time, the transposition table would make
+        // repeated searches trivial
+        group.bench_function(name, |b| b.iter_batched(
+            || (SearchAsync::with_hash_size(16), game.clone()),
+            |(mut searcher, mut game)| searcher.find_best_legal_move(&mut game, 5, &[]),
+            BatchSize::LargeInput,
+        ));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, legal_moves, make_undo, evaluation, search);
+criterion_main!(benches);
@@ -0,0 +1,110 @@
+//! Deciding when the engine resigns and whether it accepts draw offers in
+//! games against the user.
+
+use crate::game::GameState;
+
+/// Keeps track of the engine's evaluations over a game. The engine resigns
+/// once it's been clearly lost for a few moves in a row, and accepts a draw
+/// when it isn't better, or when it's about even and the game is heading for
+/// a draw by repetition or the fifty move rule anyway.
+#[derive(Default)]
+pub struct Adjudicator {
+    last_eval: Option<i32>,
+    lost_moves: usize,
+}
+
+impl Adjudicator {
+    // Evaluation (in centipawns, from the engine's perspective) at or below
+    // which the engine considers itself lost, and for how many of its moves
+    // in a row before resigning
+    const RESIGN_THRESHOLD: i32 = -800;
+    const RESIGN_MOVES: usize = 3;
+
+    // Evaluation up to which a draw is accepted in drawish positions: after
+    // a repetition, or with this many half moves without a capture or pawn
+    // move
+    const DRAWISH_MARGIN: i32 = 50;
+    const DRAWISH_HALF_MOVE_CLOCK: usize = 80;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the evaluation of the engine's search for its move.
+    ///
+    /// * `eval`: Evaluation in centipawns from the engine's perspective
+    pub fn record_eval(&mut self, eval: i32) {
+        self.last_eval = Some(eval);
+        if eval <= Self::RESIGN_THRESHOLD {
+            self.lost_moves += 1;
+        } else {
+            self.lost_moves = 0;
+        }
+    }
+
+    /// Whether the engine should resign instead of making its move.
+    pub fn should_resign(&self) -> bool {
+        self.lost_moves >= Self::RESIGN_MOVES
+    }
+
+    /// Whether the engine accepts a draw offered in `game`.
+    pub fn accepts_draw(&self, game: &GameState) -> bool {
+        let Some(eval) = self.last_eval else {
+            return false;
+        };
+
+        let drawish = game.repetitions() >= 2 || game.half_move_clock() >= Self::DRAWISH_HALF_MOVE_CLOCK;
+        eval <= 0 || (drawish && eval <= Self::DRAWISH_MARGIN)
+    }
+
+    /// Forget the evaluations, for a new game or taken back moves.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_resign() {
+        let mut adjudicator = Adjudicator::new();
+        for eval in [-900, -1000, 0, -900, -900] {
+            adjudicator.record_eval(eval);
+            assert!(!adjudicator.should_resign());
+        }
+        adjudicator.record_eval(-900);
+        assert!(adjudicator.should_resign());
+
+        adjudicator.reset();
+        assert!(!adjudicator.should_resign());
+    }
+
+    #[test]
+    fn test_accepts_draw() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        let mut adjudicator = Adjudicator::new();
+        assert!(!adjudicator.accepts_draw(&game));
+
+        adjudicator.record_eval(-10);
+        assert!(adjudicator.accepts_draw(&game));
+        adjudicator.record_eval(30);
+        assert!(!adjudicator.accepts_draw(&game));
+
+        // A repetition makes a small advantage not worth playing on for
+        game.apply_moves(&["g1f3", "g8f6", "f3g1", "f6g8"]).unwrap();
+        assert_eq!(game.repetitions(), 2);
+        assert!(adjudicator.accepts_draw(&game));
+        adjudicator.record_eval(200);
+        assert!(!adjudicator.accepts_draw(&game));
+
+        // So does being close to the fifty move rule
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 85 60";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        adjudicator.record_eval(30);
+        assert!(adjudicator.accepts_draw(&game));
+    }
+}
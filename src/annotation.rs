@@ -0,0 +1,155 @@
+//! Annotating the user's moves in games against the engine with `!`, `?!`,
+//! `?` and `??`, by how much of the evaluation they lose. The evaluations
+//! before and after a move are those of the engine's searches for its moves,
+//! so annotating takes no extra search.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::board::Side;
+use crate::error::Error;
+
+/// Judgement of a move, written as its move suffix annotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Annotation {
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Annotation {
+    /// The suffix annotation, e.g. `?!`.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Annotation::Good => "!",
+            Annotation::Inaccuracy => "?!",
+            Annotation::Mistake => "?",
+            Annotation::Blunder => "??",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Annotation::Good => "good move",
+            Annotation::Inaccuracy => "inaccuracy",
+            Annotation::Mistake => "mistake",
+            Annotation::Blunder => "blunder",
+        }
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// Centipawns a move must lose to be an inaccuracy, a mistake or a blunder.
+/// A move gaining at least `inaccuracy` over the evaluation before it found
+/// something the engine didn't expect, and is good.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnnotationThresholds {
+    pub inaccuracy: i32,
+    pub mistake: i32,
+    pub blunder: i32,
+}
+
+impl Default for AnnotationThresholds {
+    fn default() -> Self {
+        Self {
+            inaccuracy: 50,
+            mistake: 100,
+            blunder: 300,
+        }
+    }
+}
+
+impl AnnotationThresholds {
+    // Evaluations are capped at this, so a won position stays won whether
+    // it's a mate or a few pieces up, and giving away a mate in a position
+    // that's still won isn't a blunder
+    const MAX_EVAL: i32 = 1000;
+
+    /// Centipawns `side` lost with its move from a position evaluated at
+    /// `eval_before` to one evaluated at `eval_after`, both from white's
+    /// perspective. Negative if the move gained.
+    pub fn loss(side: Side, eval_before: i32, eval_after: i32) -> i32 {
+        let loss = eval_before.clamp(-Self::MAX_EVAL, Self::MAX_EVAL) - eval_after.clamp(-Self::MAX_EVAL, Self::MAX_EVAL);
+        if side == Side::White { loss } else { -loss }
+    }
+
+    /// Annotation of a move that lost `loss` centipawns, see `loss`, if it
+    /// deserves one.
+    pub fn annotate(&self, loss: i32) -> Option<Annotation> {
+        if loss >= self.blunder {
+            Some(Annotation::Blunder)
+        } else if loss >= self.mistake {
+            Some(Annotation::Mistake)
+        } else if loss >= self.inaccuracy {
+            Some(Annotation::Inaccuracy)
+        } else if -loss >= self.inaccuracy {
+            Some(Annotation::Good)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for AnnotationThresholds {
+    type Err = Error;
+
+    /// Parse the thresholds of an inaccuracy, a mistake and a blunder in
+    /// centipawns, increasing, e.g. `50,100,300`.
+    fn from_str(thresholds: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidAnnotationThresholds(thresholds.to_owned());
+        let values = thresholds
+            .split(',')
+            .map(|value| value.trim().parse::<i32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match values[..] {
+            [inaccuracy, mistake, blunder] if 0 < inaccuracy && inaccuracy <= mistake && mistake <= blunder => {
+                Ok(Self { inaccuracy, mistake, blunder })
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for AnnotationThresholds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.inaccuracy, self.mistake, self.blunder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE_SCORE;
+
+    #[test]
+    fn test_annotate() {
+        let thresholds = AnnotationThresholds::default();
+        assert_eq!(thresholds.annotate(AnnotationThresholds::loss(Side::White, 30, 20)), None);
+        assert_eq!(thresholds.annotate(AnnotationThresholds::loss(Side::White, 30, -30)), Some(Annotation::Inaccuracy));
+        assert_eq!(thresholds.annotate(AnnotationThresholds::loss(Side::Black, -30, 100)), Some(Annotation::Mistake));
+        assert_eq!(thresholds.annotate(AnnotationThresholds::loss(Side::Black, 0, 350)), Some(Annotation::Blunder));
+        assert_eq!(thresholds.annotate(AnnotationThresholds::loss(Side::Black, 0, -80)), Some(Annotation::Good));
+
+        // Still winning after missing a mate
+        assert_eq!(thresholds.annotate(AnnotationThresholds::loss(Side::White, MATE_SCORE - 5, 1200)), None);
+        assert_eq!(thresholds.annotate(AnnotationThresholds::loss(Side::White, MATE_SCORE - 5, 0)), Some(Annotation::Blunder));
+    }
+
+    #[test]
+    fn test_parse_thresholds() {
+        let thresholds: AnnotationThresholds = "40, 90,200".parse().unwrap();
+        assert_eq!(thresholds, AnnotationThresholds { inaccuracy: 40, mistake: 90, blunder: 200 });
+        assert_eq!(thresholds.to_string(), "40,90,200");
+        assert!("50,100".parse::<AnnotationThresholds>().is_err());
+        assert!("100,50,300".parse::<AnnotationThresholds>().is_err());
+        assert!("0,100,300".parse::<AnnotationThresholds>().is_err());
+        assert!("a,b,c".parse::<AnnotationThresholds>().is_err());
+    }
+}
@@ -0,0 +1,64 @@
+//! Search benchmark: a fixed-depth search of a set of positions, reporting
+//! the node count and speed. The total node count is a signature of the
+//! search, it only changes when the search or evaluation changes.
+
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::search::{format_score, SearchAsync};
+
+/// Opening, middlegame and endgame positions.
+const BENCH_POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "2r3k1/pp3ppp/2n1b3/q2p4/3P4/2PB1N2/P4PPP/1R1Q2K1 b - - 0 20",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+    "6k1/5p2/6p1/8/7p/8/6PP/6K1 b - - 0 1",
+];
+
+/// Search every benchmark position to `depth` with a fresh searcher, and
+/// print the nodes and speed of each position and in total.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `depth`: Search depth
+pub fn run(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard, depth: usize) -> Result<(), Error> {
+    let mut total_nodes = 0;
+    let mut total_time = Duration::ZERO;
+
+    for fen in BENCH_POSITIONS {
+        let mut game = GameState::from_fen(pl_moves, magics, fen)?;
+        let (legal_moves, _) = game.get_legal_moves();
+        let mut searcher = SearchAsync::new();
+
+        let start = Instant::now();
+        let (eval, best_move, _) = searcher.find_best_legal_move(&mut game, depth, &[]);
+        let elapsed = start.elapsed();
+
+        println!(
+            "{}: {} {} ({} nodes in {:.2?})",
+            fen,
+            best_move.to_algebraic_with_state(&legal_moves),
+            format_score(eval),
+            searcher.nodes(),
+            elapsed
+        );
+        total_nodes += searcher.nodes();
+        total_time += elapsed;
+    }
+
+    println!(
+        "Total: {} nodes in {:.2?} ({:.0} nodes/s)",
+        total_nodes,
+        total_time,
+        total_nodes as f64/total_time.as_secs_f64()
+    );
+
+    Ok(())
+}
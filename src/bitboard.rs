@@ -0,0 +1,149 @@
+//! Bitboards displayed as 8x8 grids, so that they can be read in test
+//! failures and debug output, and spelled out in tests.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::board::{Board, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::error::Error;
+
+/// A bitboard, displayed as a grid with the 8th rank at the top and the
+/// a-file on the left, with `1` for the squares in it and `.` for the others,
+/// e.g. the white pawns at the start:
+///
+/// ```text
+/// 8 . . . . . . . .
+/// 7 . . . . . . . .
+/// 6 . . . . . . . .
+/// 5 . . . . . . . .
+/// 4 . . . . . . . .
+/// 3 . . . . . . . .
+/// 2 1 1 1 1 1 1 1 1
+/// 1 . . . . . . . .
+///   a b c d e f g h
+/// ```
+///
+/// It parses from the same grid, with or without the coordinates.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bb(pub u64);
+
+impl Bb {
+    /// Bitboard of `squares` in algebraic notation, e.g. `["e4", "d5"]`.
+    pub fn from_squares(squares: &[&str]) -> Result<Self, Error> {
+        squares.iter().try_fold(Self(0), |bb, square| {
+            let square_index = Board::square_from_algebraic(square)
+                .ok_or_else(|| Error::InvalidBitboard(format!("invalid square '{}'", square)))?;
+            Ok(Self(bb.0 | 1 << square_index))
+        })
+    }
+}
+
+impl From<u64> for Bb {
+    fn from(bb: u64) -> Self {
+        Self(bb)
+    }
+}
+
+impl FromStr for Bb {
+    type Err = Error;
+
+    /// Parse a grid of 8 rows of 8 squares each, `1` or `.`, from the 8th
+    /// rank down. Rank numbers in front of the rows and a line of files are
+    /// skipped.
+    fn from_str(grid: &str) -> Result<Self, Self::Err> {
+        let mut rows = Vec::new();
+        for line in grid.lines() {
+            let mut squares: Vec<&str> = line.split_whitespace().collect();
+            if squares.is_empty() || squares.iter().all(|square| matches!(square.as_bytes(), [b'a'..=b'h'])) {
+                continue;
+            }
+            if squares.len() == BOARD_WIDTH + 1 {
+                squares.remove(0);
+            }
+            if squares.len() != BOARD_WIDTH {
+                return Err(Error::InvalidBitboard(format!("row '{}' isn't 8 squares", line.trim())));
+            }
+            rows.push(squares);
+        }
+        if rows.len() != BOARD_HEIGHT {
+            return Err(Error::InvalidBitboard(format!("{} rows instead of 8", rows.len())));
+        }
+
+        let mut bb = 0u64;
+        for (row, squares) in rows.iter().enumerate() {
+            let rank = BOARD_HEIGHT - 1 - row;
+            for (file_from_a, square) in squares.iter().enumerate() {
+                match *square {
+                    "1" => bb |= 1 << (rank*BOARD_WIDTH + BOARD_WIDTH - 1 - file_from_a),
+                    "." => {},
+                    _ => return Err(Error::InvalidBitboard(format!("invalid square '{}', expected 1 or .", square))),
+                }
+            }
+        }
+
+        Ok(Self(bb))
+    }
+}
+
+impl fmt::Display for Bb {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for rank in (0..BOARD_HEIGHT).rev() {
+            write!(fmt, "{}", rank + 1)?;
+            // Files are indexed from the h-file
+            for file in (0..BOARD_WIDTH).rev() {
+                let in_bb = self.0 & (1 << (rank*BOARD_WIDTH + file)) != 0;
+                write!(fmt, " {}", if in_bb { '1' } else { '.' })?;
+            }
+            writeln!(fmt)?;
+        }
+        write!(fmt, "  a b c d e f g h")
+    }
+}
+
+// The grid on the lines after the value, e.g. in `assert_eq` failures
+impl fmt::Debug for Bb {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Bb({:#018x})\n{}", self.0, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bb() {
+        let pawns = Bb(0xff00);
+        let grid = "8 . . . . . . . .\n\
+                    7 . . . . . . . .\n\
+                    6 . . . . . . . .\n\
+                    5 . . . . . . . .\n\
+                    4 . . . . . . . .\n\
+                    3 . . . . . . . .\n\
+                    2 1 1 1 1 1 1 1 1\n\
+                    1 . . . . . . . .\n  \
+                    a b c d e f g h";
+        assert_eq!(pawns.to_string(), grid);
+        assert_eq!(format!("{:?}", pawns), format!("Bb(0x000000000000ff00)\n{}", grid));
+        assert_eq!(grid.parse::<Bb>().unwrap(), pawns);
+
+        // Without the coordinates, the h1 corner is the lowest bit
+        let corners: Bb = "
+            1 . . . . . . 1
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            1 . . . . . . 1
+        ".parse().unwrap();
+        assert_eq!(corners, Bb(0x8100_0000_0000_0081));
+        assert_eq!(Bb::from_squares(&["a1", "h1", "a8", "h8"]).unwrap(), corners);
+
+        assert!(matches!(Bb::from_squares(&["i9"]), Err(Error::InvalidBitboard(_))));
+        assert!(matches!("1 . .".parse::<Bb>(), Err(Error::InvalidBitboard(_))));
+        assert!(matches!(grid.replacen('1', "x", 2).parse::<Bb>(), Err(Error::InvalidBitboard(_))));
+        assert!(matches!(grid.lines().skip(1).collect::<Vec<_>>().join("\n").parse::<Bb>(), Err(Error::InvalidBitboard(_))));
+    }
+}
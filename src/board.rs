@@ -1,6 +1,10 @@
 use std::fmt;
 use std::ops::{Index, IndexMut};
+use std::str::FromStr;
 
+use crate::error::Error;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
 use crate::r#move::{Move, MoveType, RANKS, FILES};
 
 pub const BOARD_WIDTH: usize = 8;
@@ -21,12 +25,16 @@ impl Side {
     pub fn opposite(self) -> Self {
         Self::VALUES[1usize - (self as usize)]
     }
+}
+
+impl FromStr for Side {
+    type Err = Error;
 
-    pub fn from_str(string: &str) -> Self {
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
         match string {
-            "white" => Side::White,
-            "black" => Side::Black,
-            _ => panic!("invalid side {}", string)
+            "white" => Ok(Side::White),
+            "black" => Ok(Side::Black),
+            _ => Err(Error::InvalidSide(string.to_owned())),
         }
     }
 }
@@ -74,6 +82,42 @@ impl Piece {
         self_idx > 1 && self_idx < 5
     }
 
+    /// Piece and side of a FEN piece letter, uppercase for white.
+    pub fn from_fen_char(c: char) -> Option<(Self, Side)> {
+        let piece = match c.to_ascii_lowercase() {
+            'p' => Piece::Pawn,
+            'n' => Piece::Knight,
+            'b' => Piece::Bishop,
+            'r' => Piece::Rook,
+            'q' => Piece::Queen,
+            'k' => Piece::King,
+            _ => return None,
+        };
+        let side = if c.is_ascii_uppercase() {
+            Side::White
+        } else {
+            Side::Black
+        };
+
+        Some((piece, side))
+    }
+
+    /// Conventional value of the piece in pawns for counting material, zero
+    /// for the king.
+    pub fn points(self) -> i32 {
+        [1, 3, 3, 5, 9, 0][self as usize]
+    }
+
+    /// FEN piece letter of the piece for `side`, uppercase for white.
+    pub fn fen_char(&self, side: Side) -> char {
+        let c = ['p', 'n', 'b', 'r', 'q', 'k'][*self as usize];
+        if side == Side::White {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }
+    }
+
     pub fn ascii(&self, side: Side) -> &str {
         if side == Side::White {
             match self {
@@ -111,6 +155,64 @@ impl fmt::Display for Piece {
     }
 }
 
+/// The rules a game is played by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// Checking the opponent for the third time also wins, see
+    /// `Board::checks`
+    ThreeCheck,
+    /// Captured pieces go to the capturer's hand and can be dropped back on
+    /// the board as a move, see `Board::hands`
+    Crazyhouse,
+}
+
+impl Variant {
+    /// Number of checks that win a game of three-check.
+    pub const CHECKS_TO_WIN: u8 = 3;
+
+    /// Most pieces of a type a side can have in its hand in crazyhouse, all
+    /// pawns of both sides.
+    pub const MAX_IN_HAND: u8 = 16;
+}
+
+impl FromStr for Variant {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "standard" => Ok(Variant::Standard),
+            "three-check" | "3check" => Ok(Variant::ThreeCheck),
+            "crazyhouse" => Ok(Variant::Crazyhouse),
+            _ => Err(Error::InvalidVariant(string.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Variant::Standard => "standard",
+            Variant::ThreeCheck => "three-check",
+            Variant::Crazyhouse => "crazyhouse",
+        })
+    }
+}
+
+/// How `Board::to_ascii_with_style` draws the pieces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoardStyle {
+    /// Chess glyphs, black's in red
+    #[default]
+    Unicode,
+    /// Chess glyphs without colors, for terminals without ANSI colors
+    Monochrome,
+    /// FEN piece letters, uppercase for white, without colors for terminals
+    /// and fonts that don't render the glyphs or escape codes well
+    Letters,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Board {
     pub pawns: [u64; Side::N_SIDES],
@@ -123,12 +225,27 @@ pub struct Board {
     pub side_to_move: Side,
     pub castling_right_long: [bool; Side::N_SIDES],
     pub castling_right_short: [bool; Side::N_SIDES],
-    pub en_passant_square: usize,
+    pub en_passant_square: Option<usize>,
+
+    pub variant: Variant,
+    // Checks each side gave in three-check, counted by `GameState` as moves
+    // are made
+    pub checks: [u8; Side::N_SIDES],
+    // Pieces each side captured and can drop in crazyhouse, by piece type
+    // (never kings)
+    pub hands: [[u8; Piece::N_PIECES]; Side::N_SIDES],
+    // Pieces promoted from pawns in crazyhouse, which go to the capturer's
+    // hand as pawns
+    pub promoted: u64,
+
+    // Piece on every square, kept in sync with the bitboards by `make_move`
+    // and `undo_move` for looking up pieces by square
+    mailbox: [Option<(Piece, Side)>; N_SQUARES],
 }
 
 impl Default for Board {
     fn default() -> Self {
-        Board {
+        let mut board = Board {
             pawns: [
                 // White pawns
                 0b0000000000000000000000000000000000000000000000001111111100000000,
@@ -168,10 +285,20 @@ impl Default for Board {
 
             side_to_move: Side::White,
 
-            en_passant_square: 0usize,
+            en_passant_square: None,
             castling_right_long: [true, true],
             castling_right_short: [true, true],
-        }
+
+            variant: Variant::Standard,
+            checks: [0; Side::N_SIDES],
+            hands: [[0; Piece::N_PIECES]; Side::N_SIDES],
+            promoted: 0,
+
+            mailbox: [None; N_SQUARES],
+        };
+        board.update_mailbox();
+
+        board
     }
 }
 
@@ -226,37 +353,32 @@ impl Board {
         self.update_castling_rights(m);
 
         match m.move_type {
-            MoveType::CastleShort => {
-                // Move king
-                self[(Piece::King, m.side)] >>= 2;
-
-                // Move rook
-                if m.side == Side::White {
-                    self[(Piece::Rook, m.side)] &= !(1 << 0);
-                    self[(Piece::Rook, m.side)] |= 1 << 2;
-                } else {
-                    self[(Piece::Rook, m.side)] &= !(1 << (BOARD_WIDTH*(BOARD_HEIGHT - 1)));
-                    self[(Piece::Rook, m.side)] |= 1 << (BOARD_WIDTH*(BOARD_HEIGHT - 1) + 2);
-                }
+            MoveType::CastleShort | MoveType::CastleLong => {
+                // Move king and rook
+                let (rook_square, rook_target) = m.castling_rook_squares().unwrap();
+                self[(Piece::King, m.side)] ^= (1 << m.from_square) | (1 << m.to_square);
+                self[(Piece::Rook, m.side)] ^= (1 << rook_square) | (1 << rook_target);
+
+                self.mailbox[m.from_square] = None;
+                self.mailbox[rook_square] = None;
+                self.mailbox[m.to_square] = Some((Piece::King, m.side));
+                self.mailbox[rook_target] = Some((Piece::Rook, m.side));
             },
-            MoveType::CastleLong => {
-                // Move king
-                self[(Piece::King, m.side)] <<= 2;
-
-                // Move rook
-                if m.side == Side::White {
-                    self[(Piece::Rook, m.side)] &= !(1 << (BOARD_WIDTH - 1));
-                    self[(Piece::Rook, m.side)] |= 1 << (BOARD_WIDTH - 1 - 3);
-                } else {
-                    self[(Piece::Rook, m.side)] &= !(1 << (BOARD_WIDTH*BOARD_HEIGHT - 1));
-                    self[(Piece::Rook, m.side)] |= 1 << (BOARD_WIDTH*BOARD_HEIGHT - 1 - 3)
-                }
+            MoveType::Drop => {
+                // Put the piece from the hand on the target square
+                self[(m.piece, m.side)] |= 1 << m.to_square;
+                self.mailbox[m.to_square] = Some((m.piece, m.side));
+                self.hands[m.side as usize][m.piece as usize] -= 1;
             },
             _ => {
                 // Quiet, capture, and promotion moves
+                if self.variant == Variant::Crazyhouse {
+                    self.update_hands(m);
+                }
 
                 // Remove piece from its current square
                 self[(m.piece, m.side)] &= !(1 << m.from_square);
+                self.mailbox[m.from_square] = None;
 
                 // Move piece to target square
                 // In the case of pawn promotion, we create the respective promotion 
@@ -267,6 +389,7 @@ impl Board {
                     _ => m.piece
                 };
                 self[(new_square_piece_type, m.side)] |= 1 << m.to_square;
+                self.mailbox[m.to_square] = Some((new_square_piece_type, m.side));
 
                 // Capture case, also remove the captured piece from enemy board
                 match m.move_type {
@@ -275,6 +398,7 @@ impl Board {
                     MoveType::EnPassantCapture(captured_piece) => {
                         let enemy_pawn_square = (m.to_square as i64 + (((m.side as i64)*2 - 1)*(BOARD_WIDTH as i64))) as usize;
                         self[(captured_piece, m.side.opposite())] &= !(1 << enemy_pawn_square);
+                        self.mailbox[enemy_pawn_square] = None;
                     }
                     _ => {}
                 }
@@ -287,46 +411,47 @@ impl Board {
         m: &Move,
         castling_right_long: &[bool; Side::N_SIDES],
         castling_right_short: &[bool; Side::N_SIDES],
+        en_passant_square: Option<usize>,
+        promoted: u64,
     ) {
         // Restore from values the caller saved
         self.castling_right_short = *castling_right_short;
         self.castling_right_long = *castling_right_long;
+        self.en_passant_square = en_passant_square;
+        self.promoted = promoted;
 
         // Other side to move
         self.side_to_move = self.side_to_move.opposite();
 
-        self.en_passant_square = 0;
-
         match m.move_type {
-            MoveType::CastleShort => {
-                // Move king back
-                self[(Piece::King, m.side)] <<= 2;
-
-                // Move rook back
-                if m.side == Side::White {
-                    self[(Piece::Rook, m.side)] &= !(1 << 2);
-                    self[(Piece::Rook, m.side)] |= 1 << 0;
-                } else {
-                    self[(Piece::Rook, m.side)] &= !(1 << (BOARD_WIDTH*(BOARD_HEIGHT - 1) + 2));
-                    self[(Piece::Rook, m.side)] |= 1 << (BOARD_WIDTH*(BOARD_HEIGHT - 1));
-                }
+            MoveType::CastleShort | MoveType::CastleLong => {
+                // Move king and rook back
+                let (rook_square, rook_target) = m.castling_rook_squares().unwrap();
+                self[(Piece::King, m.side)] ^= (1 << m.from_square) | (1 << m.to_square);
+                self[(Piece::Rook, m.side)] ^= (1 << rook_square) | (1 << rook_target);
+
+                self.mailbox[m.to_square] = None;
+                self.mailbox[rook_target] = None;
+                self.mailbox[m.from_square] = Some((Piece::King, m.side));
+                self.mailbox[rook_square] = Some((Piece::Rook, m.side));
             },
-            MoveType::CastleLong => {
-                // Move king back
-                self[(Piece::King, m.side)] >>= 2;
-
-                // Move rook back
-                if m.side == Side::White {
-                    self[(Piece::Rook, m.side)] &= !(1 << (BOARD_WIDTH - 1 - 3));
-                    self[(Piece::Rook, m.side)] |= 1 << (BOARD_WIDTH - 1);
-                } else {
-                    self[(Piece::Rook, m.side)] &= !(1 << (BOARD_WIDTH*BOARD_HEIGHT - 1 - 3));
-                    self[(Piece::Rook, m.side)] |= 1 << (BOARD_WIDTH*BOARD_HEIGHT - 1);
-                }
+            MoveType::Drop => {
+                // Put the piece back in the hand
+                self[(m.piece, m.side)] &= !(1 << m.to_square);
+                self.mailbox[m.to_square] = None;
+                self.hands[m.side as usize][m.piece as usize] += 1;
             },
             _ => {
                 // Quiet, capture, and promotion moves
 
+                // Take the captured piece back out of the hand, the promoted
+                // pieces are those from before the move again
+                if self.variant == Variant::Crazyhouse {
+                    if let Some(hand_piece) = self.hand_piece(m) {
+                        self.hands[m.side as usize][hand_piece as usize] -= 1;
+                    }
+                }
+
                 // Pawn promotion, remove the promoted piece from the target square
                 if let MoveType::Promotion(promoted_piece) | MoveType::CapturePromotion(_, promoted_piece) = m.move_type  {
                     self[(promoted_piece, m.side)] &= !(1 << m.to_square);
@@ -337,25 +462,59 @@ impl Board {
 
                 // Move piece back to from square
                 self[(m.piece, m.side)] |= 1 << m.from_square;
+                self.mailbox[m.from_square] = Some((m.piece, m.side));
+                self.mailbox[m.to_square] = None;
 
                 // Capture case, also put the captured piece back into enemy board
                 if let MoveType::Capture(captured_piece) | MoveType::CapturePromotion(captured_piece, _) = m.move_type {
                     self[(captured_piece, m.side.opposite())] |= 1 << m.to_square;
+                    self.mailbox[m.to_square] = Some((captured_piece, m.side.opposite()));
                 } else if let MoveType::EnPassantCapture(captured_piece) = m.move_type {
                     // Put back en passant-captured pawn
                     let enemy_pawn_square = (m.to_square as i64 + (((m.side as i64)*2 - 1)*(BOARD_WIDTH as i64))) as usize;
                     self[(captured_piece, m.side.opposite())] |= 1 << enemy_pawn_square;
-
-                    // Re-set en passant square flag
-                    self.en_passant_square = m.to_square;
+                    self.mailbox[enemy_pawn_square] = Some((captured_piece, m.side.opposite()));
                 }
             }
         }
     }
 
+    /// Put the piece captured by `m` in the capturer's hand and move the
+    /// promoted piece marks along with the pieces, in crazyhouse.
+    fn update_hands(&mut self, m: &Move) {
+        if let Some(hand_piece) = self.hand_piece(m) {
+            self.hands[m.side as usize][hand_piece as usize] += 1;
+        }
+
+        let moves_promoted = self.promoted & (1 << m.from_square) != 0 || m.is_promotion();
+        self.promoted &= !(1 << m.from_square) & !(1 << m.to_square);
+        if moves_promoted {
+            self.promoted |= 1 << m.to_square;
+        }
+    }
+
+    /// The piece the capture `m` puts in the capturer's hand in crazyhouse,
+    /// a pawn for promoted pieces, given the promoted pieces before `m`.
+    fn hand_piece(&self, m: &Move) -> Option<Piece> {
+        match m.move_type {
+            MoveType::Capture(_) | MoveType::CapturePromotion(_, _) if self.promoted & (1 << m.to_square) != 0 =>
+                Some(Piece::Pawn),
+            MoveType::Capture(captured_piece)
+            | MoveType::CapturePromotion(captured_piece, _)
+            | MoveType::EnPassantCapture(captured_piece) => Some(captured_piece),
+            _ => None,
+        }
+    }
+
+    /// Number of `piece`s `side` has in its hand in crazyhouse.
+    #[inline]
+    pub fn in_hand(&self, piece: Piece, side: Side) -> u8 {
+        self.hands[side as usize][piece as usize]
+    }
+
     pub fn update_en_passant_flag(&mut self, m: &Move) {
         // Reset first
-        self.en_passant_square = 0;
+        self.en_passant_square = None;
 
         // Then set if applicable
         if m.piece == Piece::Pawn && m.move_type == MoveType::Quiet {
@@ -363,16 +522,24 @@ impl Board {
             if n_ranks_moved.abs() == (BOARD_WIDTH * 2) as i64 {
                 // Pawn moved two ranks forward, mark to_square as the en passant square
                 self.en_passant_square =
-                    (m.to_square as i64 - (n_ranks_moved.signum())*(BOARD_WIDTH as i64)) as usize;
+                    Some((m.to_square as i64 - (n_ranks_moved.signum())*(BOARD_WIDTH as i64)) as usize);
             }
         }
     }
 
+    pub const KING_SQUARES: [usize; Side::N_SIDES] = [3, BOARD_WIDTH*(BOARD_HEIGHT - 1) + 3];
     pub const ROOK_SHORT_SQUARES: [usize; Side::N_SIDES] = [0, BOARD_WIDTH*(BOARD_HEIGHT - 1)];
     pub const ROOK_LONG_SQUARES: [usize; Side::N_SIDES] = [
         BOARD_WIDTH - 1,
         BOARD_WIDTH*BOARD_HEIGHT - 1,
     ];
+
+    // Squares the king and rook end up on after castling
+    pub const KING_SHORT_CASTLE_SQUARES: [usize; Side::N_SIDES] = [1, BOARD_WIDTH*(BOARD_HEIGHT - 1) + 1];
+    pub const KING_LONG_CASTLE_SQUARES: [usize; Side::N_SIDES] = [5, BOARD_WIDTH*(BOARD_HEIGHT - 1) + 5];
+    pub const ROOK_SHORT_CASTLE_SQUARES: [usize; Side::N_SIDES] = [2, BOARD_WIDTH*(BOARD_HEIGHT - 1) + 2];
+    pub const ROOK_LONG_CASTLE_SQUARES: [usize; Side::N_SIDES] = [4, BOARD_WIDTH*(BOARD_HEIGHT - 1) + 4];
+
     pub fn update_castling_rights(&mut self, m: &Move) {
         match m.piece {
             Piece::King => {
@@ -393,6 +560,31 @@ impl Board {
             },
             _ => {}
         }
+
+        // A rook captured on its starting square can't castle anymore, even
+        // if another rook (or in crazyhouse a dropped one) takes its place
+        let enemy = m.side.opposite() as usize;
+        if m.is_capture() {
+            if m.to_square == Self::ROOK_SHORT_SQUARES[enemy] {
+                self.castling_right_short[enemy] = false;
+            } else if m.to_square == Self::ROOK_LONG_SQUARES[enemy] {
+                self.castling_right_long[enemy] = false;
+            }
+        }
+    }
+
+    /// Whether `side` has any pieces other than pawns and the king. Without
+    /// them zugzwang is common, which breaks null move pruning.
+    ///
+    /// * `side`: Side to check
+    pub fn has_non_pawn_material(&self, side: Side) -> bool {
+        (self[(Piece::Knight, side)] | self[(Piece::Bishop, side)]
+            | self[(Piece::Rook, side)] | self[(Piece::Queen, side)]) != 0
+    }
+
+    /// Material of `side` in pawns, see `Piece::points`.
+    pub fn material_points(&self, side: Side) -> i32 {
+        Piece::ALL_BUT_KING.iter().map(|&piece| self[(piece, side)].count_ones() as i32*piece.points()).sum()
     }
 
     /// Generate bitboard of all pieces of one side in which 1 indicates a square
@@ -409,7 +601,367 @@ impl Board {
         occ_bb
     }
 
+    /// Piece and side on `square`, if any.
+    ///
+    /// * `square`: Square index
+    #[inline]
+    pub fn piece_on(&self, square: usize) -> Option<(Piece, Side)> {
+        self.mailbox[square]
+    }
+
+    /// Fill in the piece on every square from the bitboards.
+    fn update_mailbox(&mut self) {
+        self.mailbox = [None; N_SQUARES];
+        for piece in Piece::VALUES {
+            for side in Side::VALUES {
+                let mut piece_bb = self[(piece, side)];
+                while piece_bb != 0 {
+                    let square = piece_bb.trailing_zeros() as usize;
+                    self.mailbox[square] = Some((piece, side));
+
+                    // clear square bit
+                    piece_bb &= piece_bb - 1;
+                }
+            }
+        }
+    }
+
+    /// The position with the ranks flipped and the colors swapped, so white
+    /// plays black's game and the other way around. Evaluations of the
+    /// flipped position are negated.
+    pub fn flipped(&self) -> Self {
+        let flip = |bbs: [u64; Side::N_SIDES]| [bbs[1].swap_bytes(), bbs[0].swap_bytes()];
+
+        let mut board = Board {
+            pawns: flip(self.pawns),
+            knights: flip(self.knights),
+            bishops: flip(self.bishops),
+            rooks: flip(self.rooks),
+            queens: flip(self.queens),
+            king: flip(self.king),
+
+            side_to_move: self.side_to_move.opposite(),
+
+            en_passant_square: self.en_passant_square.map(|square| square ^ (BOARD_WIDTH*(BOARD_HEIGHT - 1))),
+            castling_right_long: [self.castling_right_long[1], self.castling_right_long[0]],
+            castling_right_short: [self.castling_right_short[1], self.castling_right_short[0]],
+
+            variant: self.variant,
+            checks: [self.checks[1], self.checks[0]],
+            hands: [self.hands[1], self.hands[0]],
+            promoted: self.promoted.swap_bytes(),
+
+            mailbox: [None; N_SQUARES],
+        };
+        board.update_mailbox();
+
+        board
+    }
+
+    /// The position with the files mirrored, the a-file becomes the h-file.
+    /// The kings leave their starting squares, so castling rights are lost.
+    pub fn mirrored(&self) -> Self {
+        let mirror = |bbs: [u64; Side::N_SIDES]| bbs.map(|bb| bb.reverse_bits().swap_bytes());
+
+        let mut board = Board {
+            pawns: mirror(self.pawns),
+            knights: mirror(self.knights),
+            bishops: mirror(self.bishops),
+            rooks: mirror(self.rooks),
+            queens: mirror(self.queens),
+            king: mirror(self.king),
+
+            side_to_move: self.side_to_move,
+
+            en_passant_square: self.en_passant_square.map(|square| square ^ (BOARD_WIDTH - 1)),
+            castling_right_long: [false, false],
+            castling_right_short: [false, false],
+
+            variant: self.variant,
+            checks: self.checks,
+            hands: self.hands,
+            promoted: self.promoted.reverse_bits().swap_bytes(),
+
+            mailbox: [None; N_SQUARES],
+        };
+        board.update_mailbox();
+
+        board
+    }
+
+    /// Check that the position is one that can come up in a game: a single
+    /// king per side, no pawns on the back ranks, no square occupied twice,
+    /// the king and rook on their starting squares for every castling right
+    /// and the side that just moved not left in check.
+    pub fn validate(&self) -> Result<(), Error> {
+        for side in Side::VALUES {
+            if self[(Piece::King, side)].count_ones() != 1 {
+                return Err(Error::IllegalPosition(format!("expected a single {:?} king", side)));
+            }
+        }
+
+        let back_ranks = 0xff000000000000ffu64;
+        if (self[(Piece::Pawn, Side::White)] | self[(Piece::Pawn, Side::Black)]) & back_ranks != 0 {
+            return Err(Error::IllegalPosition("pawns on the first or last rank".to_string()));
+        }
+
+        let mut occ = 0u64;
+        for piece in Piece::VALUES {
+            for side in Side::VALUES {
+                let overlap = occ & self[(piece, side)];
+                if overlap != 0 {
+                    let square = overlap.trailing_zeros() as usize;
+                    return Err(Error::IllegalPosition(format!("square {} is occupied twice", Self::square_to_algebraic(square))));
+                }
+                occ |= self[(piece, side)];
+            }
+        }
+
+        for side in Side::VALUES {
+            let king_on_start = self[(Piece::King, side)] & (1 << Self::KING_SQUARES[side as usize]) != 0;
+            let rights = [
+                (self.castling_right_short[side as usize], Self::ROOK_SHORT_SQUARES[side as usize]),
+                (self.castling_right_long[side as usize], Self::ROOK_LONG_SQUARES[side as usize]),
+            ];
+            for (right, rook_square) in rights {
+                if right && (!king_on_start || self[(Piece::Rook, side)] & (1 << rook_square) == 0) {
+                    return Err(Error::IllegalPosition(format!("{:?} can't castle without its king and rook on their starting squares", side)));
+                }
+            }
+        }
+
+        // The side to move could capture the enemy king
+        let pl_moves = &PSEUDO_LEGAL_MOVES;
+        let magics = MagicBitboard::get();
+        let side = self.side_to_move;
+        let king_square = self[(Piece::King, side.opposite())].trailing_zeros() as usize;
+        let rook_rays = magics.get_rook_moves(king_square, occ & pl_moves.get_piece_blocker_mask(Piece::Rook, king_square));
+        let bishop_rays = magics.get_bishop_moves(king_square, occ & pl_moves.get_piece_blocker_mask(Piece::Bishop, king_square));
+        let checkers = pl_moves.pawn_capture_moves[side.opposite() as usize][king_square] & self[(Piece::Pawn, side)]
+            | pl_moves.knight_moves[king_square] & self[(Piece::Knight, side)]
+            | pl_moves.king_moves[king_square] & self[(Piece::King, side)]
+            | rook_rays & (self[(Piece::Rook, side)] | self[(Piece::Queen, side)])
+            | bishop_rays & (self[(Piece::Bishop, side)] | self[(Piece::Queen, side)]);
+        if checkers != 0 {
+            return Err(Error::IllegalPosition(format!("{:?} is in check with {:?} to move", side.opposite(), side)));
+        }
+
+        Ok(())
+    }
+
+    /// Square index of a square in algebraic notation, e.g. `e4`.
+    pub fn square_from_algebraic(square: &str) -> Option<usize> {
+        let (file_char, rank_char) = match square.as_bytes() {
+            [file_char, rank_char] => (*file_char, *rank_char),
+            _ => return None,
+        };
+
+        let file_from_a = file_char.checked_sub(b'a')? as usize;
+        let rank = rank_char.checked_sub(b'1')? as usize;
+        if file_from_a >= BOARD_WIDTH || rank >= BOARD_HEIGHT {
+            return None;
+        }
+
+        // Files are indexed from the h-file
+        Some(rank*BOARD_WIDTH + BOARD_WIDTH - 1 - file_from_a)
+    }
+
+    /// Algebraic name of a square, e.g. "e4".
+    ///
+    /// * `square`: Square index
+    pub fn square_to_algebraic(square: usize) -> String {
+        format!("{}{}", FILES[square % BOARD_WIDTH], RANKS[square / BOARD_WIDTH])
+    }
+
+    /// Parse the piece placement, side to move, castling rights and en
+    /// passant square of a FEN string. The move counters, if present, are
+    /// left to `GameState::from_fen`. Crazyhouse positions have the pieces
+    /// in hand after the placement, e.g. `[Pn]`, and promoted pieces marked
+    /// with a `~`.
+    ///
+    /// * `fen`: Position in Forsyth-Edwards Notation
+    pub fn from_fen(fen: &str) -> Result<Self, Error> {
+        let mut fields = fen.split_whitespace();
+        let mut builder = BoardBuilder::new();
+
+        // Piece placement, from the 8th rank down and from the a-file to the
+        // h-file within a rank
+        let placement = fields.next().ok_or_else(|| Error::InvalidFen("empty FEN".to_owned()))?;
+        let (placement, hand) = match placement.split_once('[') {
+            Some((placement, hand)) => {
+                let hand = hand.strip_suffix(']')
+                    .ok_or_else(|| Error::InvalidFen(format!("invalid pieces in hand '[{}'", hand)))?;
+                (placement, Some(hand))
+            },
+            None => (placement, None),
+        };
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != BOARD_HEIGHT {
+            return Err(Error::InvalidFen(format!("expected {} ranks, got {}", BOARD_HEIGHT, ranks.len())));
+        }
+
+        for (i, rank_str) in ranks.iter().enumerate() {
+            let rank = BOARD_HEIGHT - 1 - i;
+            let mut file_from_a = 0;
+
+            for c in rank_str.chars() {
+                if let Some(n_empty) = c.to_digit(10) {
+                    file_from_a += n_empty as usize;
+                } else if c == '~' && file_from_a > 0 {
+                    // The piece before was promoted
+                    builder = builder.promoted(rank*BOARD_WIDTH + BOARD_WIDTH - file_from_a);
+                } else {
+                    let (piece, side) = Piece::from_fen_char(c)
+                        .ok_or_else(|| Error::InvalidFen(format!("invalid piece '{}'", c)))?;
+                    if file_from_a >= BOARD_WIDTH {
+                        return Err(Error::InvalidFen(format!("too many squares on rank {}", RANKS[rank])));
+                    }
+
+                    let square = rank*BOARD_WIDTH + BOARD_WIDTH - 1 - file_from_a;
+                    builder = builder.piece(square, piece, side);
+                    file_from_a += 1;
+                }
+            }
+
+            if file_from_a != BOARD_WIDTH {
+                return Err(Error::InvalidFen(format!("expected {} squares on rank {}", BOARD_WIDTH, RANKS[rank])));
+            }
+        }
+
+        if let Some(hand) = hand {
+            let mut hands = [[0u8; Piece::N_PIECES]; Side::N_SIDES];
+            for c in hand.chars() {
+                match Piece::from_fen_char(c) {
+                    Some((piece, side)) if piece != Piece::King => {
+                        let n = &mut hands[side as usize][piece as usize];
+                        *n = n.saturating_add(1);
+                    },
+                    _ => return Err(Error::InvalidFen(format!("invalid piece in hand '{}'", c))),
+                }
+            }
+            builder = builder.crazyhouse(hands);
+        }
+
+        builder = match fields.next() {
+            Some("w") | None => builder.side_to_move(Side::White),
+            Some("b") => builder.side_to_move(Side::Black),
+            Some(side) => return Err(Error::InvalidFen(format!("invalid side to move '{}'", side))),
+        };
+
+        let castling = fields.next().unwrap_or("-");
+        let mut castling_right_short = [false; Side::N_SIDES];
+        let mut castling_right_long = [false; Side::N_SIDES];
+        for c in castling.chars() {
+            match c {
+                'K' => castling_right_short[Side::White as usize] = true,
+                'Q' => castling_right_long[Side::White as usize] = true,
+                'k' => castling_right_short[Side::Black as usize] = true,
+                'q' => castling_right_long[Side::Black as usize] = true,
+                '-' => (),
+                _ => return Err(Error::InvalidFen(format!("invalid castling rights '{}'", castling))),
+            }
+        }
+        for side in Side::VALUES {
+            builder = builder.castling(side, castling_right_short[side as usize], castling_right_long[side as usize]);
+        }
+
+        match fields.next() {
+            Some("-") | None => (),
+            Some(square) => builder = builder.ep(Self::square_from_algebraic(square)
+                .ok_or_else(|| Error::InvalidFen(format!("invalid en passant square '{}'", square)))?),
+        };
+
+        // Three-check adds the checks each side has left to give, e.g. `3+3`
+        if let Some(remaining) = fields.next().filter(|field| field.contains('+')) {
+            let invalid = || Error::InvalidFen(format!("invalid remaining checks '{}'", remaining));
+            let given = |n: &str| {
+                n.parse::<u8>().ok().and_then(|n| Variant::CHECKS_TO_WIN.checked_sub(n)).ok_or_else(invalid)
+            };
+            let (white, black) = remaining.split_once('+').ok_or_else(invalid)?;
+            builder = builder.three_check([given(white)?, given(black)?]);
+        }
+
+        builder.build()
+    }
+
+    /// The piece placement, side to move, castling rights and en passant
+    /// square in Forsyth-Edwards Notation, the remaining checks in
+    /// three-check and the pieces in hand in crazyhouse, without the move
+    /// counters (see `GameState::to_fen`).
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..BOARD_HEIGHT).rev() {
+            let mut n_empty = 0;
+            for file_from_a in 0..BOARD_WIDTH {
+                let square = rank*BOARD_WIDTH + BOARD_WIDTH - 1 - file_from_a;
+                match self.piece_on(square) {
+                    Some((piece, side)) => {
+                        if n_empty > 0 {
+                            fen.push_str(&n_empty.to_string());
+                            n_empty = 0;
+                        }
+                        fen.push(piece.fen_char(side));
+                        if self.promoted & (1 << square) != 0 {
+                            fen.push('~');
+                        }
+                    },
+                    None => n_empty += 1,
+                }
+            }
+
+            if n_empty > 0 {
+                fen.push_str(&n_empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        if self.variant == Variant::Crazyhouse {
+            fen.push('[');
+            for side in Side::VALUES {
+                for &piece in Piece::ALL_BUT_KING.iter().rev() {
+                    for _ in 0..self.in_hand(piece, side) {
+                        fen.push(piece.fen_char(side));
+                    }
+                }
+            }
+            fen.push(']');
+        }
+
+        fen.push_str(if self.side_to_move == Side::White { " w " } else { " b " });
+
+        let castling_rights = [
+            (self.castling_right_short[Side::White as usize], 'K'),
+            (self.castling_right_long[Side::White as usize], 'Q'),
+            (self.castling_right_short[Side::Black as usize], 'k'),
+            (self.castling_right_long[Side::Black as usize], 'q'),
+        ];
+        let castling: String = castling_rights.iter().filter(|(right, _)| *right).map(|(_, c)| c).collect();
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
+
+        fen.push(' ');
+        match self.en_passant_square {
+            Some(square) => fen.push_str(&Self::square_to_algebraic(square)),
+            None => fen.push('-'),
+        }
+
+        if self.variant == Variant::ThreeCheck {
+            let [white, black] = self.checks.map(|checks| Variant::CHECKS_TO_WIN.saturating_sub(checks));
+            fen.push_str(&format!(" {}+{}", white, black));
+        }
+
+        fen
+    }
+
     pub fn to_ascii(&self, play_side: Side) -> String {
+        self.to_ascii_with_style(play_side, BoardStyle::Unicode)
+    }
+
+    /// The board as text with `play_side` at the bottom, with the pieces
+    /// drawn in `style`.
+    pub fn to_ascii_with_style(&self, play_side: Side, style: BoardStyle) -> String {
         let mut fmt = String::new();
 
         for mut j in 0..BOARD_HEIGHT {
@@ -427,14 +979,13 @@ impl Board {
                 let mut no_piece = true;
                 'piece: for piece in Piece::VALUES {
                     for side in Side::VALUES {
-                        let is_black = side == Side::Black;
                         if pos & self[(piece, side)] != 0 {
-                            if is_black {
-                                fmt.push_str("\x1b[31m");
-                            }
-                            fmt.push_str(piece.ascii(side));
-                            if is_black {
-                                fmt.push_str("\x1b[0m");
+                            match style {
+                                BoardStyle::Unicode if side == Side::Black => {
+                                    fmt.push_str(&format!("\x1b[31m{}\x1b[0m", piece.ascii(side)));
+                                },
+                                BoardStyle::Unicode | BoardStyle::Monochrome => fmt.push_str(piece.ascii(side)),
+                                BoardStyle::Letters => fmt.push(piece.fen_char(side)),
                             }
 
                             no_piece = false;
@@ -444,15 +995,15 @@ impl Board {
                 }
 
                 if no_piece {
-                    fmt.push_str(".");
+                    fmt.push('.');
                 }
 
                 if (play_side == Side::White && i != BOARD_WIDTH - 1) || (play_side == Side::Black && i != 0) {
-                    fmt.push_str(" ");
+                    fmt.push(' ');
                 }
             }
             if (play_side == Side::White && j != 7) || (play_side == Side::Black && j != 0) {
-                fmt.push_str("\n");
+                fmt.push('\n');
             }
         }
 
@@ -463,8 +1014,272 @@ impl Board {
             }
             fmt.push_str(&format!("{} ", FILES[BOARD_WIDTH - j - 1]));
         }
-        fmt.push_str("\n");
+        fmt.push('\n');
 
         fmt
     }
+
+    /// The board as text with `play_side` at the bottom and squares three
+    /// characters wide, checkered with background colors in
+    /// `BoardStyle::Unicode` and with a dot on the empty dark squares
+    /// otherwise. With `framed`, it has a border with the ranks and files.
+    pub fn to_ascii_large(&self, play_side: Side, style: BoardStyle, framed: bool) -> String {
+        // 256-color backgrounds, and the pieces of both sides in the solid
+        // glyphs told apart by their color
+        const LIGHT_SQUARE: &str = "\x1b[48;5;180m";
+        const DARK_SQUARE: &str = "\x1b[48;5;137m";
+        const WHITE_PIECE: &str = "\x1b[1;97m";
+        const BLACK_PIECE: &str = "\x1b[1;30m";
+        const RESET: &str = "\x1b[0m";
+
+        let (horizontal, vertical, corners) = if style == BoardStyle::Letters {
+            ("-", "|", ["+", "+", "+", "+"])
+        } else {
+            ("─", "│", ["┌", "┐", "└", "┘"])
+        };
+        let edge = horizontal.repeat(3*BOARD_WIDTH);
+
+        let mut fmt = String::new();
+        if framed {
+            fmt.push_str(&format!("   {}{}{}\n", corners[0], edge, corners[1]));
+        }
+
+        for row in 0..BOARD_HEIGHT {
+            let rank = if play_side == Side::White { BOARD_HEIGHT - 1 - row } else { row };
+            if framed {
+                fmt.push_str(&format!(" {} {}", RANKS[rank], vertical));
+            }
+
+            for column in 0..BOARD_WIDTH {
+                let file = if play_side == Side::White { BOARD_WIDTH - 1 - column } else { column };
+                let square = rank*BOARD_WIDTH + file;
+                let light = (rank + file) % 2 == 0;
+
+                match (style, self.piece_on(square)) {
+                    (BoardStyle::Unicode, piece) => {
+                        fmt.push_str(if light { LIGHT_SQUARE } else { DARK_SQUARE });
+                        match piece {
+                            Some((piece, side)) => {
+                                let color = if side == Side::White { WHITE_PIECE } else { BLACK_PIECE };
+                                fmt.push_str(&format!("{} {} ", color, piece.ascii(Side::White)));
+                            },
+                            None => fmt.push_str("   "),
+                        }
+                    },
+                    (BoardStyle::Monochrome, Some((piece, side))) => fmt.push_str(&format!(" {} ", piece.ascii(side))),
+                    (BoardStyle::Letters, Some((piece, side))) => fmt.push_str(&format!(" {} ", piece.fen_char(side))),
+                    (_, None) => fmt.push_str(if light { "   " } else { " . " }),
+                }
+            }
+            if style == BoardStyle::Unicode {
+                fmt.push_str(RESET);
+            }
+
+            if framed {
+                fmt.push_str(vertical);
+            }
+            fmt.push('\n');
+        }
+
+        if framed {
+            fmt.push_str(&format!("   {}{}{}\n    ", corners[2], edge, corners[3]));
+            for column in 0..BOARD_WIDTH {
+                let file = if play_side == Side::White { BOARD_WIDTH - 1 - column } else { column };
+                fmt.push_str(&format!(" {} ", FILES[file]));
+            }
+            fmt.push('\n');
+        }
+
+        fmt
+    }
+}
+
+/// Builds a board piece by piece, starting from an empty board with white to
+/// move and no castling rights or en passant square.
+pub struct BoardBuilder {
+    board: Board,
+    error: Option<String>,
+}
+
+impl Default for BoardBuilder {
+    fn default() -> Self {
+        BoardBuilder {
+            board: Board {
+                pawns: [0; Side::N_SIDES],
+                knights: [0; Side::N_SIDES],
+                bishops: [0; Side::N_SIDES],
+                rooks: [0; Side::N_SIDES],
+                queens: [0; Side::N_SIDES],
+                king: [0; Side::N_SIDES],
+
+                side_to_move: Side::White,
+
+                en_passant_square: None,
+                castling_right_long: [false, false],
+                castling_right_short: [false, false],
+
+                variant: Variant::Standard,
+                checks: [0; Side::N_SIDES],
+                hands: [[0; Piece::N_PIECES]; Side::N_SIDES],
+                promoted: 0,
+
+                mailbox: [None; N_SQUARES],
+            },
+            error: None,
+        }
+    }
+}
+
+impl BoardBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put a piece on an empty square.
+    ///
+    /// * `square`: Square index
+    /// * `piece`: Piece type
+    /// * `side`: Side of the piece
+    pub fn piece(mut self, square: usize, piece: Piece, side: Side) -> Self {
+        if square >= N_SQUARES {
+            self.set_error(format!("invalid square {}", square));
+        } else if self.board.piece_on(square).is_some() {
+            self.set_error(format!("square {} is already occupied", Board::square_to_algebraic(square)));
+        } else {
+            self.board[(piece, side)] |= 1 << square;
+            self.board.mailbox[square] = Some((piece, side));
+        }
+
+        self
+    }
+
+    pub fn side_to_move(mut self, side: Side) -> Self {
+        self.board.side_to_move = side;
+        self
+    }
+
+    /// Set the castling rights of `side`.
+    ///
+    /// * `side`: Side whose castling rights to set
+    /// * `short`: Whether `side` may castle king side
+    /// * `long`: Whether `side` may castle queen side
+    pub fn castling(mut self, side: Side, short: bool, long: bool) -> Self {
+        self.board.castling_right_short[side as usize] = short;
+        self.board.castling_right_long[side as usize] = long;
+        self
+    }
+
+    /// Set the square a pawn skipped over by moving two ranks forward on the
+    /// last move, which must be on the 3rd or 6th rank.
+    ///
+    /// * `square`: Square index
+    pub fn ep(mut self, square: usize) -> Self {
+        if square >= N_SQUARES || (square / BOARD_WIDTH != 2 && square / BOARD_WIDTH != BOARD_HEIGHT - 3) {
+            self.set_error(format!("invalid en passant square {}", square));
+        } else {
+            self.board.en_passant_square = Some(square);
+        }
+
+        self
+    }
+
+    /// Play three-check, with `checks` given by white and black so far.
+    pub fn three_check(mut self, checks: [u8; Side::N_SIDES]) -> Self {
+        if checks.iter().any(|&n| n > Variant::CHECKS_TO_WIN) {
+            self.set_error(format!("more than {} checks", Variant::CHECKS_TO_WIN));
+        } else {
+            self.board.variant = Variant::ThreeCheck;
+            self.board.checks = checks;
+        }
+
+        self
+    }
+
+    /// Play crazyhouse, with `hands` holding the number of pieces of each
+    /// type white and black have in hand.
+    pub fn crazyhouse(mut self, hands: [[u8; Piece::N_PIECES]; Side::N_SIDES]) -> Self {
+        if hands.iter().any(|hand| hand[Piece::King as usize] > 0) {
+            self.set_error("king in hand".to_owned());
+        } else if hands.iter().flatten().any(|&n| n > Variant::MAX_IN_HAND) {
+            self.set_error(format!("more than {} pieces of a type in hand", Variant::MAX_IN_HAND));
+        } else {
+            self.board.variant = Variant::Crazyhouse;
+            self.board.hands = hands;
+        }
+
+        self
+    }
+
+    /// Mark the piece on `square` as promoted from a pawn, in crazyhouse.
+    ///
+    /// * `square`: Square index
+    pub fn promoted(mut self, square: usize) -> Self {
+        match self.board.mailbox.get(square).copied().flatten() {
+            Some((piece, _)) if Piece::PROMOTION_PIECES.contains(&piece) => self.board.promoted |= 1 << square,
+            _ => self.set_error(format!("no piece to mark as promoted on square {}", square)),
+        }
+
+        self
+    }
+
+    /// The board, or the first error made building it or the reason it's
+    /// not a valid position (see `Board::validate`).
+    pub fn build(self) -> Result<Board, Error> {
+        if let Some(error) = self.error {
+            return Err(Error::IllegalPosition(error));
+        }
+        if self.board.promoted != 0 && self.board.variant != Variant::Crazyhouse {
+            return Err(Error::IllegalPosition("promoted pieces outside of crazyhouse".to_owned()));
+        }
+
+        self.board.validate()?;
+        Ok(self.board)
+    }
+
+    fn set_error(&mut self, error: String) {
+        self.error.get_or_insert(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ascii_with_style() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let letters = board.to_ascii_with_style(Side::White, BoardStyle::Letters);
+        assert!(letters.starts_with("8  . . . . k . . .\n"));
+        assert!(letters.contains("\n2  . . . . P . . .\n1  . . . . K . . .\n"));
+        assert!(!letters.contains('\x1b'));
+
+        // From black's side, in glyphs with black's in red
+        let unicode = board.to_ascii(Side::Black);
+        assert!(unicode.starts_with("1  . . . ♚ . . . ."));
+        assert!(unicode.contains("\x1b[31m♔\x1b[0m"));
+        let monochrome = board.to_ascii_with_style(Side::Black, BoardStyle::Monochrome);
+        assert_eq!(monochrome, unicode.replace("\x1b[31m", "").replace("\x1b[0m", ""));
+    }
+
+    #[test]
+    fn test_to_ascii_large() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let letters = board.to_ascii_large(Side::White, BoardStyle::Letters, false);
+        let lines: Vec<&str> = letters.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "    .     .  k  .     . ");
+        assert_eq!(lines[7], " .     .     K     .    ");
+
+        // Framed with the ranks and files, from black's side
+        let framed = board.to_ascii_large(Side::Black, BoardStyle::Letters, true);
+        let lines: Vec<&str> = framed.lines().collect();
+        assert_eq!(lines[0], format!("   +{}+", "-".repeat(24)));
+        assert_eq!(lines[1], " 1 |    .     K     .     . |");
+        assert_eq!(lines[10], "     h  g  f  e  d  c  b  a ");
+
+        // Checkered backgrounds instead of dots
+        let unicode = board.to_ascii_large(Side::White, BoardStyle::Unicode, true);
+        assert!(unicode.contains("\x1b[48;5;180m\x1b[1;30m ♚ "));
+        assert!(!unicode.contains(" . "));
+    }
 }
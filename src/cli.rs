@@ -1,11 +1,334 @@
-use clap::Parser;
+use clap::{Args as ClapArgs, Parser, Subcommand};
 
+use pawndropper::annotation::AnnotationThresholds;
+use pawndropper::board::{Side, Variant};
+use pawndropper::clock::{Clock, TimeControl};
+use pawndropper::opponent::Opponent;
+use pawndropper::personality::Personality;
+use pawndropper::spsa::ParamGroup;
+
+use crate::output::{BoardLayout, ColorChoice, OutputFormat, View};
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// Without a command the options of `play` can be given directly, e.g.
+// `pawndropper -d 8` is `pawndropper play -d 8`
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 pub struct Args {
+    /// Write the diagnostic log (see RUST_LOG) as JSON lines
+    #[arg(long, global = true)]
+    pub log_json: bool,
+
+    /// Print the games and analysis as text or as JSON lines
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Draw the boards with piece letters and without colors
+    #[arg(long, global = true)]
+    pub no_unicode: bool,
+
+    /// Draw the boards in color, by default if the terminal supports it
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Draw the boards with a character per square, or larger with checkered squares and optionally a border
+    #[arg(long, global = true, value_enum, default_value_t = BoardLayout::Compact)]
+    pub board: BoardLayout,
+
+    /// Read option defaults from this file instead of ~/.config/pawndropper/config.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<String>,
+
+    #[command(flatten)]
+    pub play: PlayArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// Options of a game against the engine in the terminal
+#[derive(ClapArgs, Debug)]
+pub struct PlayArgs {
     #[arg(short, long, default_value = "black")]
-    pub cpu_side: String,
+    pub cpu_side: Side,
+
+    /// Search depth, by default the deepest this machine searches in about two seconds (see `speedtest`)
+    #[arg(short, long)]
+    pub depth: Option<usize>,
+
+    /// Stop searching after visiting this many nodes
+    #[arg(short, long)]
+    pub nodes: Option<u64>,
+
+    /// Keep searching on the user's time, assuming the predicted reply
+    #[arg(short, long)]
+    pub ponder: bool,
+
+    /// Play with clocks of base minutes plus increment seconds, e.g. 5+3
+    #[arg(short, long)]
+    pub tc: Option<TimeControl>,
+
+    /// Think this many milliseconds per move instead of searching to a depth
+    #[arg(long, value_name = "MS", conflicts_with = "tc")]
+    pub movetime: Option<u64>,
+
+    /// Append the moves and the engine's search details to this file
+    #[arg(short, long)]
+    pub log: Option<String>,
+
+    /// Learn from the finished games in this file, avoiding lines lost repeatedly
+    #[arg(long, value_name = "FILE")]
+    pub learn: Option<String>,
+
+    /// Playing strength, from 0 (beginner) to 20 (full strength)
+    #[arg(short, long, default_value_t = 20, value_parser = clap::value_parser!(u8).range(0..=20))]
+    pub skill: u8,
+
+    /// Search depth of the computer's first move
+    #[arg(long, value_name = "DEPTH", default_value_t = 4)]
+    pub opening_depth: usize,
+
+    /// Centipawns a random first move may score below the best
+    #[arg(long, value_name = "CP", default_value_t = 25)]
+    pub opening_margin: i32,
+
+    /// Size of the transposition table in megabytes
+    #[arg(long, value_name = "MB", default_value_t = 64)]
+    pub hash: usize,
+
+    /// Centipawns your move must lose to be annotated as an inaccuracy, mistake and blunder
+    #[arg(long, value_name = "CP,CP,CP", default_value = "50,100,300")]
+    pub annotate: AnnotationThresholds,
+
+    /// Side at the bottom of the board, by default the side you play
+    #[arg(long, value_enum, default_value_t = View::Auto)]
+    pub view: View,
+
+    /// Rules to play by, standard, three-check or crazyhouse
+    #[arg(long, default_value_t = Variant::Standard)]
+    pub variant: Variant,
+
+    /// Who plays the computer's moves: engine, random, greedy or shallow
+    #[arg(long, default_value_t = Opponent::Engine)]
+    pub opponent: Opponent,
+
+    /// Style of the engine: default, aggressive, solid, gambit or endgame-grinder
+    #[arg(long, default_value_t = Personality::Default)]
+    pub personality: Personality,
+
+    /// Milliseconds of the clock kept in reserve per move for transmission delays
+    #[arg(long, value_name = "MS", default_value_t = Clock::DEFAULT_MOVE_OVERHEAD.as_millis() as u64)]
+    pub move_overhead: u64,
+
+    /// Play in a full-screen terminal interface
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    pub tui: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Play a game against the engine in the terminal (the default)
+    Play(PlayArgs),
+    /// Analyze a position, printing the evaluation and principal variation of each depth
+    Analyze {
+        /// Position to analyze
+        #[arg(short, long, default_value = START_FEN)]
+        fen: String,
+
+        /// Moves to play from the position first, in coordinate notation (e.g. `e2e4 e7e5`)
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        moves: Vec<String>,
+
+        /// Search depth
+        #[arg(short, long, default_value_t = 12)]
+        depth: usize,
+
+        /// Search for this many milliseconds instead of to a depth
+        #[arg(long, value_name = "MS")]
+        movetime: Option<u64>,
+
+        /// Stop searching after visiting this many nodes
+        #[arg(short, long)]
+        nodes: Option<u64>,
+
+        /// Size of the transposition table in megabytes
+        #[arg(long, value_name = "MB", default_value_t = 64)]
+        hash: usize,
+
+        /// Split the root moves across these workers (see `worker`) and search them to the depth
+        #[arg(short, long, value_name = "HOST:PORT", num_args = 1.., value_delimiter = ',')]
+        workers: Vec<String>,
+    },
+    /// Search the root moves a coordinator (see `analyze --workers`) sends over TCP
+    Worker {
+        /// Address to accept coordinators on
+        #[arg(short, long, value_name = "HOST:PORT", default_value = "0.0.0.0:7420")]
+        listen: String,
+
+        /// Size of the transposition table in megabytes
+        #[arg(long, value_name = "MB", default_value_t = 64)]
+        hash: usize,
+    },
+    /// Benchmark the search with a fixed-depth search of a set of positions
+    Bench {
+        /// Search depth
+        #[arg(short, long, default_value_t = 8)]
+        depth: usize,
+    },
+    /// Measure the search speed of this machine and the time per move of each depth
+    Speedtest,
+    /// Play a game of the engine against itself
+    Selfplay {
+        /// Position to play from
+        #[arg(short, long, default_value = START_FEN)]
+        fen: String,
+
+        /// Moves to play from the position first, in coordinate notation (e.g. `e2e4 e7e5`)
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        moves: Vec<String>,
+
+        /// Search depth of every move
+        #[arg(short, long, default_value_t = 6)]
+        depth: usize,
+
+        /// Think this many milliseconds per move instead of searching to a depth
+        #[arg(long, value_name = "MS")]
+        movetime: Option<u64>,
+
+        /// Stop searching after visiting this many nodes
+        #[arg(short, long)]
+        nodes: Option<u64>,
+
+        /// Learn from the game in this file, avoiding lines lost repeatedly
+        #[arg(long, value_name = "FILE")]
+        learn: Option<String>,
+
+        /// Play a match of this many games, printing the results and a running Elo estimate
+        #[arg(short, long, default_value_t = 1)]
+        games: usize,
+
+        /// Opponent of the engine in a match: engine, random, greedy or shallow
+        #[arg(long, default_value_t = Opponent::Engine)]
+        opponent: Opponent,
+    },
+    /// Speak the Universal Chess Interface protocol on stdin and stdout, for chess GUIs
+    Uci,
+    /// Run the built-in tactical test suite and report the solve rate
+    Testsuite {
+        /// Search depths to run the test suite at
+        #[arg(short, long, value_delimiter = ',', default_values_t = [4, 6])]
+        depths: Vec<usize>,
+
+        /// Stop searching after visiting this many nodes
+        #[arg(short, long)]
+        nodes: Option<u64>,
+    },
+    /// Solve puzzles, with the attempts checked by the engine
+    Puzzle {
+        /// File with a FEN, or EPD with the solution as `bm`, per line (built-in puzzles by default)
+        file: Option<String>,
+
+        /// Search depth to verify the attempts at
+        #[arg(short, long, default_value_t = 6)]
+        depth: usize,
+
+        /// Number of attempts per puzzle
+        #[arg(short, long, default_value_t = 3)]
+        attempts: usize,
+    },
+    /// Find puzzles in saved games, or in self-play games, and write them as EPD
+    GeneratePuzzles {
+        /// Saved games to look for puzzles in (self-play games by default)
+        files: Vec<String>,
+
+        /// Number of self-play games to play without saved games
+        #[arg(short, long, default_value_t = 10)]
+        games: usize,
+
+        /// Search depth of every move of the self-play games
+        #[arg(short, long, default_value_t = 4)]
+        depth: usize,
+
+        /// Search depth to evaluate every position of the games at
+        #[arg(long, default_value_t = 4)]
+        scan_depth: usize,
+
+        /// Search depth to verify that a single move wins at
+        #[arg(long, default_value_t = 8)]
+        verify_depth: usize,
+
+        /// File to write the puzzles to, printed by default
+        #[arg(long, value_name = "FILE")]
+        out: Option<String>,
+    },
+    /// Tune the evaluation weights on a dataset of positions labeled with game results
+    Tune {
+        /// File with a FEN and game result (e.g. `1-0` or `[0.5]`) per line
+        dataset: String,
+
+        /// File to write the tuned weights to, as Rust source
+        #[arg(short, long, default_value = "tuned_eval.rs")]
+        output: String,
+
+        /// Maximum number of passes over all weights
+        #[arg(short, long, default_value_t = 100)]
+        passes: usize,
+
+        /// Add the color-flipped and file-mirrored copies of the positions
+        #[arg(short, long)]
+        augment: bool,
+    },
+    /// Tune search parameters and piece values with SPSA by playing quick self-play games
+    Spsa {
+        /// Parameters to tune: search (reductions and margins), pieces (piece values) or both
+        #[arg(short, long, num_args = 1.., value_delimiter = ',', default_values_t = [ParamGroup::Search, ParamGroup::Pieces])]
+        params: Vec<ParamGroup>,
+
+        /// Number of iterations
+        #[arg(short, long, default_value_t = 1000)]
+        iterations: usize,
+
+        /// Games per iteration, played in pairs with both colors
+        #[arg(short, long, default_value_t = 2)]
+        games: usize,
+
+        /// Search depth of every move
+        #[arg(short, long, default_value_t = 4)]
+        depth: usize,
+
+        /// Position to play the games from, after a few random moves
+        #[arg(short, long, default_value = START_FEN)]
+        fen: String,
+
+        /// File to write the tuned parameters to, as Rust source
+        #[arg(short, long, value_name = "FILE", default_value = "tuned_params.rs")]
+        out: String,
+    },
+    /// Count the leaf nodes of the legal move tree to validate the move generator
+    Perft {
+        /// Number of plies to count
+        depth: usize,
+
+        /// Position to count from
+        #[arg(short, long, default_value = START_FEN)]
+        fen: String,
+
+        /// Moves to play from the position first, in coordinate notation (e.g. `e2e4 e7e5`)
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        moves: Vec<String>,
+    },
+    /// Benchmark the move generator with perft on positions with known node counts
+    PerftBench,
+    /// Search new magics for the slider move tables
+    Magics {
+        /// Seed of the magic search, random by default
+        #[arg(short, long)]
+        seed: Option<u64>,
 
-    #[arg(short, long, default_value_t = 6)]
-    pub depth: usize,
+        /// File to write the magics to, as Rust source
+        #[arg(short, long, default_value = "magics.rs")]
+        output: String,
+    },
 }
@@ -0,0 +1,170 @@
+//! Chess clocks for games against the user, and how much of its time the
+//! engine spends on a move.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::board::Side;
+use crate::error::Error;
+
+/// Time each side starts with and the time added after each of its moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = Error;
+
+    /// Parse a time control of the base time in minutes and the increment in
+    /// seconds, e.g. `5+3` or `0.5+0`. The increment is optional.
+    fn from_str(tc: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidTimeControl(tc.to_owned());
+        let (base, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+        let base: f64 = base.parse().map_err(|_| invalid())?;
+        let increment: f64 = increment.parse().map_err(|_| invalid())?;
+        if !(base > 0.0 && increment >= 0.0 && base.is_finite() && increment.is_finite()) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            base: Duration::from_secs_f64(base*60.0),
+            increment: Duration::from_secs_f64(increment),
+        })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{}", self.base.as_secs_f64()/60.0, self.increment.as_secs_f64())
+    }
+}
+
+/// Remaining time of both sides.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clock {
+    time_control: TimeControl,
+    remaining: [Duration; Side::N_SIDES],
+}
+
+impl Clock {
+    // Expected number of moves left in the game, over which the remaining
+    // time is spread
+    const MOVES_TO_GO: u32 = 30;
+
+    /// Time kept in reserve by default for the overhead of making the move,
+    /// see `move_time`.
+    pub const DEFAULT_MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+    pub fn new(time_control: TimeControl) -> Self {
+        Self {
+            time_control,
+            remaining: [time_control.base; Side::N_SIDES],
+        }
+    }
+
+    /// Clocks of a game in progress, with `remaining` time for white and
+    /// black.
+    pub fn with_remaining(time_control: TimeControl, remaining: [Duration; Side::N_SIDES]) -> Self {
+        Self {
+            time_control,
+            remaining,
+        }
+    }
+
+    pub fn time_control(&self) -> TimeControl {
+        self.time_control
+    }
+
+    pub fn remaining(&self, side: Side) -> Duration {
+        self.remaining[side as usize]
+    }
+
+    /// Charge `side` for a move that took `elapsed` and add the increment.
+    /// Returns false if the side ran out of time.
+    pub fn punch(&mut self, side: Side, elapsed: Duration) -> bool {
+        let remaining = &mut self.remaining[side as usize];
+        match remaining.checked_sub(elapsed) {
+            Some(left) => {
+                *remaining = left + self.time_control.increment;
+                true
+            },
+            None => {
+                *remaining = Duration::ZERO;
+                false
+            },
+        }
+    }
+
+    /// Time for `side` to spend on its next move: an even share of the
+    /// remaining time plus most of the increment, leaving `move_overhead` in
+    /// reserve for the time it takes the move to reach the clock, e.g. over
+    /// the network.
+    pub fn move_time(&self, side: Side, move_overhead: Duration) -> Duration {
+        let remaining = self.remaining(side);
+        let move_time = remaining/Self::MOVES_TO_GO + self.time_control.increment*3/4;
+        move_time.min(remaining/2).saturating_sub(move_overhead).max(Duration::from_millis(1))
+    }
+}
+
+/// `duration` as minutes, seconds and tenths, e.g. `4:59.3`.
+pub fn format_duration(duration: Duration) -> String {
+    let tenths = duration.as_millis()/100;
+    format!("{}:{:02}.{}", tenths/600, tenths/10 % 60, tenths % 10)
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "White {} | Black {}",
+            format_duration(self.remaining(Side::White)),
+            format_duration(self.remaining(Side::Black))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_control() {
+        let tc: TimeControl = "5+3".parse().unwrap();
+        assert_eq!(tc, TimeControl { base: Duration::from_secs(300), increment: Duration::from_secs(3) });
+        assert_eq!(tc.to_string(), "5+3");
+        let tc: TimeControl = "0.5".parse().unwrap();
+        assert_eq!(tc, TimeControl { base: Duration::from_secs(30), increment: Duration::ZERO });
+        assert_eq!(tc.to_string(), "0.5+0");
+
+        for tc in ["", "5+", "+3", "0+3", "-1+0", "5+-1", "five+3"] {
+            assert!(tc.parse::<TimeControl>().is_err(), "{}", tc);
+        }
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut clock = Clock::new("1+2".parse().unwrap());
+        assert_eq!(clock.to_string(), "White 1:00.0 | Black 1:00.0");
+
+        assert!(clock.punch(Side::White, Duration::from_millis(10500)));
+        assert_eq!(clock.remaining(Side::White), Duration::from_millis(51500));
+        assert_eq!(clock.to_string(), "White 0:51.5 | Black 1:00.0");
+
+        // An even share of the time plus most of the increment
+        assert_eq!(clock.move_time(Side::Black, Clock::DEFAULT_MOVE_OVERHEAD), Duration::from_millis(2000 + 1500 - 50));
+        assert_eq!(clock.move_time(Side::Black, Duration::from_millis(1000)), Duration::from_millis(2000 + 1500 - 1000));
+
+        // Never more than half the remaining time
+        assert!(clock.punch(Side::Black, Duration::from_secs(59)));
+        assert_eq!(clock.move_time(Side::Black, Clock::DEFAULT_MOVE_OVERHEAD), Duration::from_millis(1500 - 50));
+
+        // An overhead beyond the time left still leaves a moment to move
+        assert_eq!(clock.move_time(Side::Black, Duration::from_secs(5)), Duration::from_millis(1));
+
+        assert!(!clock.punch(Side::White, Duration::from_secs(52)));
+        assert_eq!(clock.remaining(Side::White), Duration::ZERO);
+    }
+}
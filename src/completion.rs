@@ -0,0 +1,74 @@
+//! Tab completion of the REPL input: the legal moves of the position in
+//! algebraic notation and the REPL's commands, e.g. `N<Tab>` offers the
+//! knight moves.
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use crate::repl::COMMANDS;
+
+/// Completes the input with the moves set with `set_moves` and the commands.
+#[derive(Default)]
+pub struct MoveCompleter {
+    moves: Vec<String>,
+}
+
+impl MoveCompleter {
+    /// Complete with `moves` from now on, e.g. the legal moves after a move.
+    pub fn set_moves(&mut self, moves: Vec<String>) {
+        self.moves = moves;
+    }
+}
+
+impl Completer for MoveCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let prefix = &line[..pos];
+        // Commands with arguments complete up to the space before them
+        let commands = COMMANDS.iter().map(|(name, args, _)| if args.is_empty() {
+            name.to_string()
+        } else {
+            format!("{} ", name)
+        });
+        let candidates = self.moves.iter()
+            .cloned()
+            .chain(commands)
+            .filter(|candidate| candidate.starts_with(prefix))
+            .collect();
+
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for MoveCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for MoveCompleter {}
+
+impl Validator for MoveCompleter {}
+
+impl Helper for MoveCompleter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustyline::history::DefaultHistory;
+
+    #[test]
+    fn test_complete() {
+        let mut completer = MoveCompleter::default();
+        completer.set_moves(["e4", "Nf3", "Nc3", "O-O"].map(str::to_owned).to_vec());
+        let history = DefaultHistory::new();
+        let ctx = Context::new(&history);
+
+        assert_eq!(completer.complete("N", 1, &ctx).unwrap(), (0, vec!["Nf3".to_owned(), "Nc3".to_owned()]));
+        assert_eq!(completer.complete("e", 1, &ctx).unwrap(), (0, vec!["e4".to_owned(), "eval".to_owned(), "evals ".to_owned()]));
+        assert_eq!(completer.complete("offer", 5, &ctx).unwrap(), (0, vec!["offer draw".to_owned()]));
+        assert!(completer.complete("Q", 1, &ctx).unwrap().1.is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+//! Configuration file with defaults for the command line options, read from
+//! `--config` or from `pawndropper/config.toml` in `$XDG_CONFIG_HOME`
+//! (`~/.config` by default) if it exists. Options given on the command line
+//! take precedence over the file:
+//!
+//! ```toml
+//! cpu_side = "white"
+//! depth = 8
+//! skill = 15
+//! nodes = 500000
+//! ponder = true
+//! hash = 128
+//! tc = "5+3"
+//! movetime = 1000
+//! move_overhead = 50
+//! log = "games.log"
+//! learn = "learning.txt"
+//! log_json = false
+//! output = "text"
+//! no_unicode = false
+//! color = "auto"
+//! board = "compact"
+//! opening_depth = 4
+//! opening_margin = 25
+//! view = "auto"
+//! annotate = "50,100,300"
+//! variant = "standard"
+//! opponent = "engine"
+//! personality = "default"
+//! ```
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use serde::Deserialize;
+
+use crate::cli::{Args, Command};
+use crate::output::{BoardLayout, ColorChoice, OutputFormat, View};
+
+/// Values of the config file, named like the command line options.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    cpu_side: Option<String>,
+    depth: Option<usize>,
+    nodes: Option<u64>,
+    ponder: Option<bool>,
+    tc: Option<String>,
+    movetime: Option<u64>,
+    move_overhead: Option<u64>,
+    log: Option<String>,
+    learn: Option<String>,
+    log_json: Option<bool>,
+    output: Option<String>,
+    no_unicode: Option<bool>,
+    color: Option<String>,
+    board: Option<String>,
+    skill: Option<u8>,
+    hash: Option<usize>,
+    opening_depth: Option<usize>,
+    opening_margin: Option<i32>,
+    view: Option<String>,
+    annotate: Option<String>,
+    variant: Option<String>,
+    opponent: Option<String>,
+    personality: Option<String>,
+}
+
+/// Path of the config file read when there's no `--config`.
+fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("pawndropper").join("config.toml"))
+}
+
+impl Config {
+    /// Read the config file `path`, or the default config file if there is
+    /// one. Without either every option keeps its command line default.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path).map_err(|e| format!("can't read '{}': {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("invalid config '{}': {}", path.display(), e))
+    }
+
+    /// Set the options of `args` that weren't given on the command line to
+    /// the config's values. The game options apply to `play`, or to the game
+    /// played without a command.
+    ///
+    /// * `args`: Parsed command line options
+    /// * `matches`: Matches `args` was parsed from, to tell given options from defaults
+    pub fn merge_into(self, args: &mut Args, matches: &ArgMatches) -> Result<(), String> {
+        if let Some(log_json) = self.log_json.filter(|_| matches.value_source("log_json") != Some(ValueSource::CommandLine)) {
+            args.log_json = log_json;
+        }
+        if let Some(output) = self.output.filter(|_| matches.value_source("output") != Some(ValueSource::CommandLine)) {
+            args.output = OutputFormat::from_str(&output, false).map_err(|e| format!("config: invalid output: {}", e))?;
+        }
+        if let Some(no_unicode) = self.no_unicode.filter(|_| matches.value_source("no_unicode") != Some(ValueSource::CommandLine)) {
+            args.no_unicode = no_unicode;
+        }
+        if let Some(color) = self.color.filter(|_| matches.value_source("color") != Some(ValueSource::CommandLine)) {
+            args.color = ColorChoice::from_str(&color, false).map_err(|e| format!("config: invalid color: {}", e))?;
+        }
+        if let Some(board) = self.board.filter(|_| matches.value_source("board") != Some(ValueSource::CommandLine)) {
+            args.board = BoardLayout::from_str(&board, false).map_err(|e| format!("config: invalid board: {}", e))?;
+        }
+
+        let (args, matches) = match (&mut args.command, matches.subcommand()) {
+            (Some(Command::Play(play)), Some(("play", play_matches))) => (play, play_matches),
+            (None, _) => (&mut args.play, matches),
+            _ => return Ok(()),
+        };
+        let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if let Some(cpu_side) = self.cpu_side.filter(|_| unset("cpu_side")) {
+            args.cpu_side = cpu_side.parse().map_err(|e| format!("config: {}", e))?;
+        }
+        if let Some(depth) = self.depth.filter(|_| unset("depth")) {
+            args.depth = Some(depth);
+        }
+        if let Some(nodes) = self.nodes.filter(|_| unset("nodes")) {
+            args.nodes = Some(nodes);
+        }
+        if let Some(ponder) = self.ponder.filter(|_| unset("ponder")) {
+            args.ponder = ponder;
+        }
+        if let Some(tc) = self.tc.filter(|_| unset("tc")) {
+            args.tc = Some(tc.parse().map_err(|e| format!("config: {}", e))?);
+        }
+        if let Some(movetime) = self.movetime.filter(|_| unset("movetime") && unset("tc")) {
+            args.movetime = Some(movetime);
+        }
+        if let Some(move_overhead) = self.move_overhead.filter(|_| unset("move_overhead")) {
+            args.move_overhead = move_overhead;
+        }
+        if let Some(log) = self.log.filter(|_| unset("log")) {
+            args.log = Some(log);
+        }
+        if let Some(learn) = self.learn.filter(|_| unset("learn")) {
+            args.learn = Some(learn);
+        }
+        if let Some(skill) = self.skill.filter(|_| unset("skill")) {
+            if skill > 20 {
+                return Err(format!("config: invalid skill {}, expected 0 to 20", skill));
+            }
+            args.skill = skill;
+        }
+        if let Some(hash) = self.hash.filter(|_| unset("hash")) {
+            args.hash = hash;
+        }
+        if let Some(opening_depth) = self.opening_depth.filter(|_| unset("opening_depth")) {
+            args.opening_depth = opening_depth;
+        }
+        if let Some(opening_margin) = self.opening_margin.filter(|_| unset("opening_margin")) {
+            args.opening_margin = opening_margin;
+        }
+        if let Some(view) = self.view.filter(|_| unset("view")) {
+            args.view = View::from_str(&view, false).map_err(|e| format!("config: invalid view: {}", e))?;
+        }
+        if let Some(thresholds) = self.annotate.filter(|_| unset("annotate")) {
+            args.annotate = thresholds.parse().map_err(|e| format!("config: {}", e))?;
+        }
+        if let Some(variant) = self.variant.filter(|_| unset("variant")) {
+            args.variant = variant.parse().map_err(|e| format!("config: {}", e))?;
+        }
+        if let Some(opponent) = self.opponent.filter(|_| unset("opponent")) {
+            args.opponent = opponent.parse().map_err(|e| format!("config: {}", e))?;
+        }
+        if let Some(personality) = self.personality.filter(|_| unset("personality")) {
+            args.personality = personality.parse().map_err(|e| format!("config: {}", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+    use pawndropper::board::{Side, Variant};
+    use pawndropper::opponent::Opponent;
+    use pawndropper::personality::Personality;
+
+    fn merged(config: &str, cli_args: &[&str]) -> Result<Args, String> {
+        let matches = Args::command().get_matches_from(["pawndropper"].iter().chain(cli_args));
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        let config: Config = toml::from_str(config).map_err(|e| e.to_string())?;
+        config.merge_into(&mut args, &matches)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn test_merge() {
+        let config = "cpu_side = \"white\"\ndepth = 8\ntc = \"5+3\"\nhash = 16\nponder = true\n";
+        let args = merged(config, &["--depth", "3", "--hash", "64"]).unwrap().play;
+        assert_eq!(args.cpu_side, Side::White);
+        assert_eq!(args.depth, Some(3));
+        assert_eq!(args.hash, 64);
+        assert!(args.ponder);
+        assert_eq!(args.tc, Some("5+3".parse().unwrap()));
+        assert_eq!(args.skill, 20);
+
+        // A default given explicitly on the command line wins too
+        assert_eq!(merged(config, &["--cpu-side", "black"]).unwrap().play.cpu_side, Side::Black);
+
+        // The game options apply to the play command too, not to others
+        let args = merged(config, &["play", "--log-json"]).unwrap();
+        let Some(Command::Play(play)) = &args.command else { panic!("expected play") };
+        assert_eq!(play.depth, Some(8));
+        assert!(args.log_json);
+        assert!(merged("depth = 8\nlog_json = true", &["bench"]).unwrap().log_json);
+        assert_eq!(merged("output = \"json\"", &["analyze"]).unwrap().output, OutputFormat::Json);
+        assert_eq!(merged("output = \"json\"", &["--output", "text"]).unwrap().output, OutputFormat::Text);
+        assert!(merged("no_unicode = true", &["play"]).unwrap().no_unicode);
+        assert_eq!(merged("color = \"never\"", &[]).unwrap().color, ColorChoice::Never);
+        assert!(merged("color = \"sometimes\"", &[]).is_err());
+        assert_eq!(merged("board = \"framed\"", &["play"]).unwrap().board, BoardLayout::Framed);
+        assert!(merged("board = \"huge\"", &[]).is_err());
+
+        assert!(merged("depth = \"deep\"", &[]).is_err());
+        assert!(merged("threads = 4", &[]).is_err());
+        assert!(merged("cpu_side = \"blue\"", &[]).is_err());
+        assert!(merged("skill = 21", &[]).is_err());
+        assert!(merged("output = \"xml\"", &[]).is_err());
+        assert_eq!(merged("view = \"white\"", &[]).unwrap().play.view, View::White);
+        assert_eq!(merged("view = \"white\"", &["--view", "auto"]).unwrap().play.view, View::Auto);
+        assert!(merged("view = \"sideways\"", &[]).is_err());
+        assert_eq!(merged("annotate = \"40,80,200\"", &[]).unwrap().play.annotate.blunder, 200);
+        assert!(merged("annotate = \"40,80\"", &[]).is_err());
+        assert_eq!(merged("variant = \"three-check\"", &[]).unwrap().play.variant, Variant::ThreeCheck);
+        assert!(merged("variant = \"atomic\"", &[]).is_err());
+        assert_eq!(merged("opponent = \"greedy\"", &[]).unwrap().play.opponent, Opponent::Greedy);
+        assert!(merged("opponent = \"grandmaster\"", &[]).is_err());
+        assert_eq!(merged("personality = \"gambit\"", &[]).unwrap().play.personality, Personality::Gambit);
+        assert_eq!(merged("move_overhead = 300", &[]).unwrap().play.move_overhead, 300);
+        assert_eq!(merged("move_overhead = 300", &["--move-overhead", "0"]).unwrap().play.move_overhead, 0);
+        assert_eq!(merged("learn = \"learning.txt\"", &[]).unwrap().play.learn.as_deref(), Some("learning.txt"));
+    }
+}
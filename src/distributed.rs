@@ -0,0 +1,326 @@
+//! Distributed analysis over TCP: worker processes, on this or other
+//! machines, search the root moves a coordinator assigns them, and the
+//! coordinator picks the best of their results. Every worker gets a share of
+//! the root moves. When a worker can't be reached or drops the connection,
+//! its moves go to the workers left, or are searched locally without any. So
+//! do the moves of a worker that doesn't answer in time.
+//!
+//! A connection carries a request line and the response line:
+//!
+//! ```text
+//! search 12 <start FEN> moves e2e4 e7e5 searchmoves g1f3 b1c3
+//! result 35 1234567 g1f3 b8c6 f1b5
+//! ```
+//!
+//! The position is sent as the start of the game with its moves, so the
+//! workers know which positions were repeated. The result is the evaluation
+//! from white's perspective, the number of nodes searched and the principal
+//! variation.
+
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::board::Side;
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::r#move::Move;
+use crate::search::SearchAsync;
+
+// A worker that doesn't accept the connection by then counts as lost, and
+// so does a peer that takes longer to send a request or take one
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A worker that hasn't answered by then counts as lost. Long enough for a
+// deep search of its share of the moves, which a worker can't interrupt
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A search of some of the root moves of a position, as sent to a worker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchRequest {
+    pub depth: usize,
+    /// FEN of the start of the game
+    pub fen: String,
+    /// Moves of the game in coordinate notation
+    pub moves: Vec<String>,
+    /// Root moves to search in coordinate notation
+    pub search_moves: Vec<String>,
+}
+
+impl SearchRequest {
+    /// A search of `search_moves` in `game`'s position to `depth`.
+    pub fn new(game: &GameState, depth: usize, search_moves: &[Move]) -> Self {
+        let mut start = game.clone();
+        while start.undo_move().is_some() {}
+
+        Self {
+            depth,
+            fen: start.to_fen(),
+            moves: game.played_moves().map(|m| m.to_coordinate()).collect(),
+            search_moves: search_moves.iter().map(|m| m.to_coordinate()).collect(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidWorkerMessage(line.to_owned());
+        let rest = line.trim().strip_prefix("search ").ok_or_else(invalid)?;
+        let (depth, rest) = rest.split_once(' ').ok_or_else(invalid)?;
+        let (position, search_moves) = rest.split_once(" searchmoves ").ok_or_else(invalid)?;
+        let (fen, moves) = position.split_once(" moves ").unwrap_or((position, ""));
+
+        Ok(Self {
+            depth: depth.parse().map_err(|_| invalid())?,
+            fen: fen.to_owned(),
+            moves: moves.split_whitespace().map(str::to_owned).collect(),
+            search_moves: search_moves.split_whitespace().map(str::to_owned).collect(),
+        })
+    }
+
+    pub fn to_line(&self) -> String {
+        let moves = if self.moves.is_empty() {
+            String::new()
+        } else {
+            format!(" moves {}", self.moves.join(" "))
+        };
+        format!("search {} {}{} searchmoves {}", self.depth, self.fen, moves, self.search_moves.join(" "))
+    }
+
+    /// Search the request with `searcher`.
+    fn search(
+        &self,
+        pl_moves: &'static MoveBitboards,
+        magics: &'static MagicBitboard,
+        searcher: &mut SearchAsync,
+    ) -> Result<WorkerResult, Error> {
+        let mut game = GameState::from_fen(pl_moves, magics, &self.fen)?;
+        game.apply_moves(&self.moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+        let search_moves = self.search_moves.iter().map(|m| game.parse_move(m)).collect::<Result<Vec<_>, _>>()?;
+        if search_moves.is_empty() {
+            return Err(Error::InvalidWorkerMessage(self.to_line()));
+        }
+
+        let (eval, _, pv) = searcher.find_best_legal_move(&mut game, self.depth, &search_moves);
+        Ok(WorkerResult { eval, nodes: searcher.nodes(), pv })
+    }
+}
+
+/// The result of searching some of the root moves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkerResult {
+    /// Evaluation from white's perspective
+    pub eval: i32,
+    pub nodes: u64,
+    /// Principal variation, starting with the best of the searched moves
+    pub pv: Vec<Move>,
+}
+
+impl WorkerResult {
+    /// Parse the response to a search of `game`'s position.
+    pub fn parse(game: &GameState, line: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidWorkerMessage(line.to_owned());
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some("result") {
+            return Err(invalid());
+        }
+        let eval = fields.next().and_then(|eval| eval.parse().ok()).ok_or_else(invalid)?;
+        let nodes = fields.next().and_then(|nodes| nodes.parse().ok()).ok_or_else(invalid)?;
+
+        let mut after = game.clone();
+        let mut pv = vec![];
+        for move_str in fields {
+            let m = after.parse_move(move_str)?;
+            after.make_move(&m);
+            pv.push(m);
+        }
+        if pv.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self { eval, nodes, pv })
+    }
+
+    pub fn to_line(&self) -> String {
+        let pv: Vec<String> = self.pv.iter().map(|m| m.to_coordinate()).collect();
+        format!("result {} {} {}", self.eval, self.nodes, pv.join(" "))
+    }
+}
+
+/// Serve the search requests of coordinators on `listener`, one connection
+/// at a time, until the process is stopped.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `listener`: Socket to accept the connections of coordinators on
+/// * `hash_mb`: Size of the transposition table in megabytes
+pub fn serve(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    listener: TcpListener,
+    hash_mb: usize,
+) -> Result<(), Error> {
+    let mut searcher = SearchAsync::with_hash_size(hash_mb);
+    for stream in listener.incoming() {
+        // A coordinator that goes away doesn't stop the worker
+        let served = stream.map_err(Error::Network).and_then(|stream| {
+            // Nor does one that connects and sends nothing, the connections
+            // are served one at a time
+            stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(Error::Network)?;
+            stream.set_write_timeout(Some(CONNECT_TIMEOUT)).map_err(Error::Network)?;
+            let mut reader = BufReader::new(stream.try_clone().map_err(Error::Network)?);
+            let mut line = String::new();
+            reader.read_line(&mut line).map_err(Error::Network)?;
+            info!("Request: {}", line.trim());
+
+            let response = match SearchRequest::parse(&line).and_then(|request| request.search(pl_moves, magics, &mut searcher)) {
+                Ok(result) => result.to_line(),
+                Err(err) => format!("error {}", err),
+            };
+            writeln!(&stream, "{}", response).map_err(Error::Network)
+        });
+
+        if let Err(err) = served {
+            warn!("Connection failed: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Send the search of `search_moves` in `game`'s position to the worker at
+/// `addr` and wait up to `timeout` for its result.
+fn request(addr: &str, game: &GameState, depth: usize, search_moves: &[Move], timeout: Duration) -> Result<WorkerResult, Error> {
+    let socket_addr = addr.to_socket_addrs().map_err(Error::Network)?.next()
+        .ok_or_else(|| Error::Network(ErrorKind::NotFound.into()))?;
+    let stream = TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT).map_err(Error::Network)?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT)).map_err(Error::Network)?;
+    stream.set_read_timeout(Some(timeout)).map_err(Error::Network)?;
+    writeln!(&stream, "{}", SearchRequest::new(game, depth, search_moves).to_line()).map_err(Error::Network)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).map_err(Error::Network)?;
+    WorkerResult::parse(game, &line)
+}
+
+/// Search `game`'s position to `depth` with its root moves split across the
+/// `workers`, given as `host:port`. Returns the best of their results with
+/// the nodes of all of them, or None without legal moves.
+///
+/// * `game`: Position to search
+/// * `depth`: Search depth of the workers
+/// * `workers`: Addresses of the workers, see `serve`
+pub fn search(game: &GameState, depth: usize, workers: &[String]) -> Option<WorkerResult> {
+    search_with_timeout(game, depth, workers, RESPONSE_TIMEOUT)
+}
+
+/// Like `search`, with workers that don't answer within `timeout` counting
+/// as lost.
+fn search_with_timeout(game: &GameState, depth: usize, workers: &[String], timeout: Duration) -> Option<WorkerResult> {
+    let (legal_moves, _) = game.get_legal_moves();
+    if legal_moves.is_empty() {
+        return None;
+    }
+
+    // Moves not searched yet, a share per worker
+    let n_shares = workers.len().clamp(1, legal_moves.len());
+    let shares: Vec<Vec<Move>> = (0..n_shares)
+        .map(|i| legal_moves.iter().skip(i).step_by(n_shares).copied().collect())
+        .collect();
+    let pending = Mutex::new(shares);
+    let results = Mutex::new(vec![]);
+
+    thread::scope(|scope| {
+        for addr in workers {
+            let (pending, results) = (&pending, &results);
+            scope.spawn(move || {
+                loop {
+                    // The lock isn't held while searching
+                    let share = pending.lock().unwrap().pop();
+                    let Some(share) = share else { break };
+                    match request(addr, game, depth, &share, timeout) {
+                        Ok(result) => results.lock().unwrap().push(result),
+                        Err(err) => {
+                            warn!("Lost worker {}: {}", addr, err);
+                            pending.lock().unwrap().push(share);
+                            break;
+                        },
+                    }
+                }
+            });
+        }
+    });
+
+    // What the workers couldn't search is searched here
+    let left: Vec<Move> = pending.into_inner().unwrap().into_iter().flatten().collect();
+    let mut results = results.into_inner().unwrap();
+    if !left.is_empty() {
+        let mut searcher = SearchAsync::new();
+        let (eval, _, pv) = searcher.find_best_legal_move(&mut game.clone(), depth, &left);
+        results.push(WorkerResult { eval, nodes: searcher.nodes(), pv });
+    }
+
+    let nodes = results.iter().map(|result| result.nodes).sum();
+    let mult = if game.board.side_to_move == Side::White { 1 } else { -1 };
+    let best = results.into_iter().max_by_key(|result| mult*result.eval)?;
+    Some(WorkerResult { nodes, ..best })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+    use crate::r#move::MoveResult;
+
+    #[test]
+    fn test_messages() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4", "e7e5"]).unwrap();
+        let search_moves = [game.parse_move("g1f3").unwrap(), game.parse_move("b1c3").unwrap()];
+
+        let request = SearchRequest::new(&game, 6, &search_moves);
+        assert_eq!(request.fen, GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS).to_fen());
+        let line = request.to_line();
+        assert!(line.ends_with(" moves e2e4 e7e5 searchmoves g1f3 b1c3"));
+        assert_eq!(SearchRequest::parse(&line).unwrap(), request);
+        assert!(matches!(SearchRequest::parse("search six"), Err(Error::InvalidWorkerMessage(_))));
+
+        let mut after = game.clone();
+        after.make_move(&search_moves[0]);
+        let result = WorkerResult { eval: -20, nodes: 1234, pv: vec![search_moves[0], after.parse_move("b8c6").unwrap()] };
+        assert_eq!(result.to_line(), "result -20 1234 g1f3 b8c6");
+        assert_eq!(WorkerResult::parse(&game, &result.to_line()).unwrap(), result);
+        assert!(WorkerResult::parse(&game, "error illegal move").is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(|| serve(&PSEUDO_LEGAL_MOVES, &MAGICS, listener, 1));
+
+        // Mate in one, with a worker that can't be reached whose moves go to
+        // the other one
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let unreachable = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let result = search(&game, 3, &[addr, unreachable.clone()]).unwrap();
+        assert_eq!(game.clone().make_move(&result.pv[0]).0, Some(MoveResult::Checkmate));
+        assert!(result.nodes > 0);
+
+        // Without workers it's searched here
+        let result = search(&game, 3, &[unreachable]).unwrap();
+        assert_eq!(game.clone().make_move(&result.pv[0]).0, Some(MoveResult::Checkmate));
+
+        // A worker that accepts the connection but never answers loses its
+        // moves once it times out
+        let hung = TcpListener::bind("127.0.0.1:0").unwrap();
+        let hung_addr = hung.local_addr().unwrap().to_string();
+        let result = search_with_timeout(&game, 3, &[hung_addr], Duration::from_millis(100)).unwrap();
+        assert_eq!(game.clone().make_move(&result.pv[0]).0, Some(MoveResult::Checkmate));
+    }
+}
@@ -0,0 +1,129 @@
+//! Elo difference estimates from the results of a match, with the 95%
+//! confidence interval and the likelihood of superiority (LOS), the chance
+//! that the player is stronger at all given its wins and losses.
+
+use std::fmt;
+
+use crate::board::Side;
+
+/// Wins, draws and losses of a player in a match.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    // Standard normal quantile of the 95% confidence interval
+    const Z_95: f64 = 1.959964;
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Count the result of a game the player played as `side`, won by
+    /// `winner` or drawn.
+    pub fn record(&mut self, side: Side, winner: Option<Side>) {
+        match winner {
+            Some(winner) if winner == side => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+
+    /// Fraction of the points scored.
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64/2.0)/self.games().max(1) as f64
+    }
+
+    /// Elo difference to the opponent, None without games or with all games
+    /// won or all lost, as it's unbounded then.
+    pub fn elo(&self) -> Option<f64> {
+        let score = self.score();
+        (self.games() > 0 && score > 0.0 && score < 1.0).then(|| elo_of_score(score))
+    }
+
+    /// Half the width of the 95% confidence interval of `elo`, from the
+    /// standard error of the score.
+    pub fn elo_margin(&self) -> Option<f64> {
+        let elo = self.elo()?;
+        let games = self.games() as f64;
+        let score = self.score();
+        let variance = (self.wins as f64*(1.0 - score).powi(2)
+            + self.draws as f64*(0.5 - score).powi(2)
+            + self.losses as f64*score.powi(2))/games;
+        let deviation = Self::Z_95*(variance/games).sqrt();
+
+        // The interval is asymmetric in Elo, the wider side is taken
+        let high = (score + deviation).min(1.0 - f64::EPSILON);
+        let low = (score - deviation).max(f64::EPSILON);
+        Some((elo_of_score(high) - elo).max(elo - elo_of_score(low)))
+    }
+
+    /// Likelihood of superiority, the probability that the player is
+    /// stronger, None before anything was won or lost.
+    pub fn los(&self) -> Option<f64> {
+        let decisive = (self.wins + self.losses) as f64;
+        (decisive > 0.0).then(|| 0.5*(1.0 + erf((self.wins as f64 - self.losses as f64)/(2.0*decisive).sqrt())))
+    }
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "+{} ={} -{}", self.wins, self.draws, self.losses)?;
+        match (self.elo(), self.elo_margin()) {
+            (Some(elo), Some(margin)) => write!(fmt, ", Elo {:+.0} ± {:.0}", elo, margin)?,
+            _ => write!(fmt, ", Elo -")?,
+        }
+        match self.los() {
+            Some(los) => write!(fmt, ", LOS {:.1}%", 100.0*los),
+            None => write!(fmt, ", LOS -"),
+        }
+    }
+}
+
+/// Elo difference at which the expected score is `score`.
+fn elo_of_score(score: f64) -> f64 {
+    -400.0*(1.0/score - 1.0).log10()
+}
+
+/// Error function, by the approximation of Abramowitz and Stegun 7.1.26,
+/// accurate to 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let t = 1.0/(1.0 + 0.3275911*x.abs());
+    let polynomial = t*(0.254829592 + t*(-0.284496736 + t*(1.421413741 + t*(-1.453152027 + t*1.061405429))));
+    (1.0 - polynomial*(-x*x).exp()).copysign(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_score() {
+        let mut score = MatchScore::default();
+        assert_eq!(score.elo(), None);
+        assert_eq!(score.los(), None);
+        assert_eq!(score.to_string(), "+0 =0 -0, Elo -, LOS -");
+
+        score.record(Side::White, Some(Side::White));
+        score.record(Side::Black, None);
+        assert_eq!(score, MatchScore { wins: 1, draws: 1, losses: 0 });
+        score.record(Side::Black, Some(Side::White));
+
+        // Even scores are no difference, with a 50% chance either is stronger
+        assert_eq!(score.elo(), Some(0.0));
+        assert!((score.los().unwrap() - 0.5).abs() < 1e-9);
+
+        // 75% is about 191 Elo
+        let score = MatchScore { wins: 60, draws: 30, losses: 10 };
+        assert!((score.elo().unwrap() - 190.8).abs() < 0.1);
+        assert!(score.elo_margin().unwrap() > 40.0 && score.elo_margin().unwrap() < 90.0);
+        assert!(score.los().unwrap() > 0.999);
+        assert_eq!(MatchScore { wins: 3, draws: 0, losses: 0 }.elo(), None);
+
+        assert!((erf(1.0) - 0.8427008).abs() < 1e-6);
+        assert!((erf(-0.5) + 0.5204999).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,422 @@
+//! Facade over the game state and the search, for embedding the engine.
+
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+use crate::r#move::{Move, MoveResult};
+use crate::search::{SearchAsync, SearchHandle, MAX_SEARCH_DEPTH};
+use crate::skill::Skill;
+
+/// Limits of a search, without any the search runs until it's stopped with
+/// the engine's `SearchHandle` or reaches the maximum depth.
+///
+/// * `depth`: Maximum depth in plies
+/// * `nodes`: Maximum number of nodes
+/// * `time`: Maximum time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+/// Result of a search.
+///
+/// * `best_move`: Move to play
+/// * `eval`: Evaluation in centipawns from white's perspective, see `search::format_score`
+/// * `pv`: Principal variation, starting with the best move
+/// * `depth`: Depth of the last completed iteration
+/// * `nodes`: Number of nodes searched
+/// * `hashfull`: Per mille of the transposition table in use after the search
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Move,
+    pub eval: i32,
+    pub pv: Vec<Move>,
+    pub depth: usize,
+    pub nodes: u64,
+    pub hashfull: usize,
+}
+
+/// Receives the engine's game and search events, e.g. to show them in a
+/// front-end. All methods do nothing by default.
+pub trait EngineObserver: Send {
+    /// The move `m` is played in `game`, which is still in the position
+    /// before the move.
+    fn move_played(&mut self, _game: &GameState, _m: &Move) {}
+
+    /// The game ended with `result` in the position `game`.
+    fn game_over(&mut self, _game: &GameState, _result: &MoveResult) {}
+
+    /// A search completed an iteration of `depth`, with the evaluation from
+    /// white's perspective and the principal variation.
+    fn iteration_completed(&mut self, _depth: usize, _eval: i32, _pv: &[Move]) {}
+}
+
+/// A game and a searcher to find moves in it.
+///
+/// ```
+/// use pawndropper::{Engine, SearchLimits};
+///
+/// let mut engine = Engine::new();
+/// engine.set_position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &[]).unwrap();
+/// let result = engine.search(&SearchLimits { depth: Some(2), ..Default::default() });
+/// assert_eq!(result.best_move.to_coordinate(), "a1a8");
+/// engine.make_move("a1a8").unwrap();
+/// ```
+pub struct Engine {
+    game: GameState,
+    searcher: SearchAsync,
+
+    // Limits of searches that don't set them
+    default_limits: SearchLimits,
+
+    observers: Vec<Box<dyn EngineObserver>>,
+}
+
+/// Configures an engine, see `Engine::builder`.
+///
+/// ```
+/// use pawndropper::Engine;
+///
+/// let engine = Engine::builder().hash_mb(16).depth(8).skill(15).build();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EngineBuilder {
+    hash_mb: Option<usize>,
+    skill: Option<u8>,
+    default_limits: SearchLimits,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Size of the transposition table in megabytes, rounded down to a power
+    /// of two.
+    pub fn hash_mb(mut self, hash_mb: usize) -> Self {
+        self.hash_mb = Some(hash_mb);
+        self
+    }
+
+    /// Playing strength from 0 to 20, see `Skill`.
+    pub fn skill(mut self, level: u8) -> Self {
+        self.skill = Some(level);
+        self
+    }
+
+    /// Depth of searches without a depth limit.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.default_limits.depth = Some(depth);
+        self
+    }
+
+    /// Node limit of searches without one.
+    pub fn nodes(mut self, nodes: u64) -> Self {
+        self.default_limits.nodes = Some(nodes);
+        self
+    }
+
+    /// Time limit of searches without one.
+    pub fn time(mut self, time: Duration) -> Self {
+        self.default_limits.time = Some(time);
+        self
+    }
+
+    /// The engine, with the game at the starting position.
+    pub fn build(self) -> Engine {
+        let mut searcher = match self.hash_mb {
+            Some(hash_mb) => SearchAsync::with_hash_size(hash_mb),
+            None => SearchAsync::new(),
+        };
+        if let Some(level) = self.skill {
+            searcher.set_skill(Skill::new(level));
+        }
+
+        Engine {
+            game: GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get()),
+            searcher,
+            default_limits: self.default_limits,
+            observers: vec![],
+        }
+    }
+}
+
+impl Engine {
+    /// An engine with the game at the starting position and the default
+    /// configuration.
+    pub fn new() -> Self {
+        EngineBuilder::new().build()
+    }
+
+    /// Configure an engine.
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    /// Set up the position `fen` and play `moves` from it.
+    ///
+    /// * `fen`: Position in Forsyth-Edwards Notation
+    /// * `moves`: Moves in coordinate notation, e.g. `e2e4` or `e7e8q`
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> Result<(), Error> {
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), fen)?;
+        game.apply_moves(moves)?;
+        self.game = game;
+        Ok(())
+    }
+
+    /// Notify `observer` of the engine's events from now on.
+    pub fn subscribe(&mut self, observer: Box<dyn EngineObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// The current game.
+    pub fn game(&self) -> &GameState {
+        &self.game
+    }
+
+    /// Legal moves of the side to move.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.game.get_legal_moves().0
+    }
+
+    /// Play the move `m` in coordinate notation, and return whether it gives
+    /// check or ends the game.
+    pub fn make_move(&mut self, m: &str) -> Result<Option<MoveResult>, Error> {
+        let legal_move = self.legal_moves()
+            .into_iter()
+            .find(|legal_move| legal_move.to_coordinate() == m)
+            .ok_or_else(|| Error::IllegalMove(m.to_owned()))?;
+
+        for observer in &mut self.observers {
+            observer.move_played(&self.game, &legal_move);
+        }
+
+        let (move_result, _) = self.game.make_move(&legal_move);
+        if let Some(result @ (MoveResult::Checkmate | MoveResult::ThirdCheck | MoveResult::Draw(_))) = &move_result {
+            for observer in &mut self.observers {
+                observer.game_over(&self.game, result);
+            }
+        }
+
+        Ok(move_result)
+    }
+
+    /// Forget what earlier searches learned, before analyzing an unrelated
+    /// position.
+    pub fn clear_hash(&mut self) {
+        self.searcher.clear_hash();
+    }
+
+    /// Handle to stop a running search from another thread.
+    pub fn handle(&self) -> SearchHandle {
+        self.searcher.handle()
+    }
+
+    /// Search the current position within `limits`, or the engine's default
+    /// limits (see `EngineBuilder`) for the ones `limits` doesn't set.
+    pub fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        self.searcher.handle().clear();
+        self.run_search(limits)
+    }
+
+    /// Like `search`, but on a thread of its own, which the engine moves to
+    /// until the search is joined. Meanwhile other threads can stop the
+    /// search or follow its progress with the search's handle.
+    pub fn spawn_search(mut self, limits: SearchLimits) -> BackgroundSearch {
+        // Cleared here, a stop right after spawning the search counts
+        let handle = self.handle();
+        handle.clear();
+        let thread = thread::spawn(move || {
+            let result = self.run_search(&limits);
+            (self, result)
+        });
+
+        BackgroundSearch { handle, thread }
+    }
+
+    fn run_search(&mut self, limits: &SearchLimits) -> SearchResult {
+        self.searcher.set_node_limit(limits.nodes.or(self.default_limits.nodes));
+        self.searcher.set_time_limit(limits.time.or(self.default_limits.time));
+
+        let depth = limits.depth.or(self.default_limits.depth).unwrap_or(MAX_SEARCH_DEPTH);
+
+        // Search on another thread, and pass the completed iterations on to
+        // the observers on this one
+        let (sender, receiver) = mpsc::channel();
+        self.searcher.set_iteration_sender(Some(sender));
+        let (searcher, game, observers) = (&mut self.searcher, &mut self.game, &mut self.observers);
+        let (eval, best_move, pv) = thread::scope(|scope| {
+            let search = scope.spawn(move || {
+                let result = searcher.find_best_legal_move(game, depth, &[]);
+                searcher.set_iteration_sender(None);
+                result
+            });
+
+            for (depth, eval, pv) in receiver {
+                for observer in observers.iter_mut() {
+                    observer.iteration_completed(depth, eval, &pv);
+                }
+            }
+
+            search.join().unwrap()
+        });
+
+        SearchResult {
+            best_move,
+            eval,
+            pv,
+            depth: self.searcher.completed_depth(),
+            nodes: self.searcher.nodes(),
+            hashfull: self.searcher.hashfull(),
+        }
+    }
+}
+
+/// A search running on a thread of its own, see `Engine::spawn_search`. Its
+/// handle can be cloned to other threads to follow or stop the search.
+pub struct BackgroundSearch {
+    handle: SearchHandle,
+    thread: JoinHandle<(Engine, SearchResult)>,
+}
+
+impl BackgroundSearch {
+    /// Handle to stop the search or follow its progress.
+    pub fn handle(&self) -> SearchHandle {
+        self.handle.clone()
+    }
+
+    /// Whether the search is done, so `join` won't block.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the search to finish, and take back the engine with the
+    /// search result.
+    pub fn join(self) -> (Engine, SearchResult) {
+        self.thread.join().unwrap()
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::search::format_score;
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.legal_moves().len(), 20);
+
+        engine.set_position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &["g1g2", "g8h8"]).unwrap();
+        let result = engine.search(&SearchLimits { depth: Some(3), nodes: Some(100_000), time: None });
+        assert_eq!(result.best_move.to_coordinate(), "a1a8");
+        assert_eq!(result.pv.first(), Some(&result.best_move));
+        assert_eq!(format_score(result.eval), "#1");
+        assert_eq!(result.depth, 3);
+
+        assert!(matches!(engine.make_move("a1a9"), Err(Error::IllegalMove(_))));
+        assert_eq!(engine.make_move("a1a8").unwrap(), Some(MoveResult::Checkmate));
+        assert!(engine.legal_moves().is_empty());
+
+        assert!(matches!(engine.set_position("not a fen", &[]), Err(Error::InvalidFen(_))));
+        assert!(engine.set_position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &["a1a9"]).is_err());
+    }
+
+    #[test]
+    fn test_hashfull_clear_hash() {
+        let mut engine = Engine::builder().hash_mb(1).build();
+        engine.set_position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4", &[]).unwrap();
+        let deep = engine.search(&SearchLimits { depth: Some(6), ..Default::default() });
+        assert!(deep.hashfull > 0 && deep.hashfull <= 1000);
+
+        // A shallow search after clearing uses a fraction of the table
+        engine.clear_hash();
+        let shallow = engine.search(&SearchLimits { depth: Some(1), ..Default::default() });
+        assert!(shallow.hashfull < deep.hashfull);
+    }
+
+    #[derive(Default)]
+    struct Events(Arc<Mutex<Vec<String>>>);
+
+    impl EngineObserver for Events {
+        fn move_played(&mut self, game: &GameState, m: &Move) {
+            let (legal_moves, _) = game.get_legal_moves();
+            self.0.lock().unwrap().push(m.to_algebraic_with_state(&legal_moves));
+        }
+
+        fn game_over(&mut self, _game: &GameState, result: &MoveResult) {
+            self.0.lock().unwrap().push(format!("{:?}", result));
+        }
+
+        fn iteration_completed(&mut self, depth: usize, _eval: i32, pv: &[Move]) {
+            self.0.lock().unwrap().push(format!("depth {} {}", depth, pv[0].to_coordinate()));
+        }
+    }
+
+    #[test]
+    fn test_observers() {
+        let events = Events::default();
+        let log = Arc::clone(&events.0);
+        let mut engine = Engine::new();
+        engine.subscribe(Box::new(events));
+
+        engine.set_position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &[]).unwrap();
+        engine.search(&SearchLimits { depth: Some(2), ..Default::default() });
+        engine.make_move("a1a8").unwrap();
+        assert_eq!(*log.lock().unwrap(), ["depth 1 a1a8", "depth 2 a1a8", "Ra8", "Checkmate"]);
+    }
+
+    #[test]
+    fn test_spawn_search() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        fn assert_send<T: Send>() {}
+        assert_send_sync::<SearchHandle>();
+        assert_send::<Engine>();
+        assert_send::<BackgroundSearch>();
+
+        // Stopped from another thread, once it's underway
+        let mut engine = Engine::builder().hash_mb(1).build();
+        engine.set_position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4", &[]).unwrap();
+        let search = engine.spawn_search(SearchLimits::default());
+        let handle = search.handle();
+        thread::spawn(move || {
+            while handle.completed_depth() < 2 {
+                thread::yield_now();
+            }
+            handle.stop();
+        }).join().unwrap();
+
+        let handle = search.handle();
+        let (mut engine, result) = search.join();
+        assert!(!handle.is_searching());
+        assert!(result.depth >= 2 && result.depth < MAX_SEARCH_DEPTH);
+        assert_eq!(handle.nodes(), result.nodes);
+
+        // The engine is back, and searches again after the stop
+        assert_eq!(engine.search(&SearchLimits { depth: Some(1), ..Default::default() }).depth, 1);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut engine = Engine::builder().hash_mb(1).depth(2).nodes(1_000_000).build();
+        assert_eq!(engine.search(&SearchLimits::default()).depth, 2);
+        assert_eq!(engine.search(&SearchLimits { depth: Some(3), ..Default::default() }).depth, 3);
+
+        let result = engine.search(&SearchLimits { depth: Some(20), nodes: Some(2000), time: None });
+        assert!(result.depth < 20);
+    }
+}
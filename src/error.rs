@@ -0,0 +1,105 @@
+//! Errors of the engine's fallible APIs, e.g. parsing positions and moves or
+//! reading and writing files.
+
+use std::io;
+use std::path::PathBuf;
+
+#[cfg(feature = "cli")]
+use rustyline::error::ReadlineError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A side other than `white` or `black`
+    #[error("invalid side '{0}', expected white or black")]
+    InvalidSide(String),
+
+    /// A variant other than `standard` or `three-check`
+    #[error("invalid variant '{0}', expected standard, three-check or crazyhouse")]
+    InvalidVariant(String),
+
+    /// An opponent other than those of `Opponent`
+    #[error("invalid opponent '{0}', expected engine, random, greedy or shallow")]
+    InvalidOpponent(String),
+
+    /// A personality other than those of `Personality`
+    #[error("invalid personality '{0}', expected default, aggressive, solid, gambit or endgame-grinder")]
+    InvalidPersonality(String),
+
+    /// A bitboard grid or square that can't be parsed, see `Bb`
+    #[error("invalid bitboard: {0}")]
+    InvalidBitboard(String),
+
+    /// A FEN string that can't be parsed
+    #[error("invalid FEN: {0}")]
+    InvalidFen(String),
+
+    /// A position that can't come up in a game, see `Board::validate`
+    #[error("illegal position: {0}")]
+    IllegalPosition(String),
+
+    /// A move that isn't in coordinate notation
+    #[error("invalid move '{0}'")]
+    InvalidMove(String),
+
+    /// A move that isn't legal in the position
+    #[error("illegal move '{0}'")]
+    IllegalMove(String),
+
+    /// A time control that can't be parsed, see `TimeControl::from_str`
+    #[error("invalid time control '{0}', expected e.g. 5+3")]
+    InvalidTimeControl(String),
+
+    /// Annotation thresholds that can't be parsed, see
+    /// `AnnotationThresholds::from_str`
+    #[error("invalid annotation thresholds '{0}', expected increasing centipawns of an inaccuracy, mistake and blunder, e.g. 50,100,300")]
+    InvalidAnnotationThresholds(String),
+
+    /// An EPD line without a best move or without operations
+    #[error("{reason} in EPD line '{line}'")]
+    InvalidEpd { line: String, reason: String },
+
+    /// A saved game with missing or invalid tags
+    #[error("invalid saved game: {0}")]
+    InvalidSavedGame(String),
+
+    /// A line of a learning file that isn't a learned position, see
+    /// `LearningStore`
+    #[error("invalid learning file line '{0}'")]
+    InvalidLearningFile(String),
+
+    /// A group of tuned parameters other than those of `ParamGroup`
+    #[error("invalid parameter group '{0}', expected search or pieces")]
+    InvalidParamGroup(String),
+
+    /// A tuning dataset without positions or with a line without a result
+    #[error("invalid dataset: {0}")]
+    InvalidDataset(String),
+
+    /// A puzzle without a solution
+    #[error("puzzle {id}: {reason}")]
+    InvalidPuzzle { id: String, reason: String },
+
+    /// A message of the distributed search protocol that can't be parsed, see
+    /// `SearchRequest` and `WorkerResult`
+    #[error("invalid worker message '{0}'")]
+    InvalidWorkerMessage(String),
+
+    /// A connection between a coordinator and a worker that failed
+    #[error("network error: {0}")]
+    Network(io::Error),
+
+    /// A perft count that differs from the known result
+    #[error("{fen} depth {depth}: expected {expected} nodes, got {nodes}")]
+    PerftMismatch { fen: String, depth: usize, expected: u64, nodes: u64 },
+
+    #[error("can't read '{}': {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("can't write '{}': {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    #[cfg(feature = "cli")]
+    #[error(transparent)]
+    Readline(#[from] ReadlineError),
+}
@@ -1,17 +1,23 @@
-use crate::game::GameState;
-use crate::board::{Piece, Side, N_SQUARES, BOARD_WIDTH, BOARD_HEIGHT};
-use crate::move_bitboards::{rank, file, FILE_MASKS};
-
-// Middlegame evaluations
-const PIECES_VALUES_MG: [i32; Piece::N_PIECES] = [
-    95, // Pawn
-    310, // Knight
-    330, // Bishop
-    490, // Rook
-    920, // Queen
-    0, // King
+use std::fmt;
+
+use crate::game::{GameState, PositionInfo};
+use crate::score::{Score, S};
+use crate::search::format_score;
+use crate::board::{Board, Piece, Side, N_SQUARES, BOARD_WIDTH, BOARD_HEIGHT};
+use crate::r#move::Move;
+use crate::move_bitboards::{rank, file, FILE_MASKS, RANK_MASKS};
+
+const PIECE_VALUES: [Score; Piece::N_PIECES] = [
+    S(95, 110), // Pawn
+    S(310, 260), // Knight
+    S(330, 280), // Bishop
+    S(490, 490), // Rook
+    S(920, 890), // Queen
+    S(0, 0), // King
 ];
-pub const PIECE_PLACEMENT_VALUES_MG: [[i32; N_SQUARES]; Piece::N_PIECES] = [
+
+// Middlegame piece placement values
+const PIECE_PLACEMENT_VALUES_MG: [[i32; N_SQUARES]; Piece::N_PIECES] = [
     // Pawn
     [
         0,    0,   0,  0,  0,  0,  0,  0,
@@ -80,16 +86,8 @@ pub const PIECE_PLACEMENT_VALUES_MG: [[i32; N_SQUARES]; Piece::N_PIECES] = [
     ]
 ];
 
-// Endgame evaluations
-const PIECES_VALUES_EG: [i32; Piece::N_PIECES] = [
-    110, // Pawn
-    260, // Knight
-    280, // Bishop
-    490, // Rook
-    890, // Queen
-    0, // King
-];
-pub const PIECE_PLACEMENT_VALUES_EG: [[i32; N_SQUARES]; Piece::N_PIECES] = [
+// Endgame piece placement values
+const PIECE_PLACEMENT_VALUES_EG: [[i32; N_SQUARES]; Piece::N_PIECES] = [
     // Pawn
     [
         0,    0,   0,  0,  0,  0,  0,  0,
@@ -158,112 +156,1118 @@ pub const PIECE_PLACEMENT_VALUES_EG: [[i32; N_SQUARES]; Piece::N_PIECES] = [
     ]
 ];
 
-const PHASES: [f64; 6] = [
-    0.0, // Pawn
-    1.0, // Knight phase
-    1.0, // Bishop phase
-    2.0, // Rook phase
-    4.0, // Queen phase
-    0.0 // unused
+const PIECE_PLACEMENT_VALUES: [[Score; N_SQUARES]; Piece::N_PIECES] =
+    pack_placement_values(&PIECE_PLACEMENT_VALUES_MG, &PIECE_PLACEMENT_VALUES_EG);
+
+/// Pack the middlegame and endgame piece placement values into scores.
+const fn pack_placement_values(
+    mg: &[[i32; N_SQUARES]; Piece::N_PIECES],
+    eg: &[[i32; N_SQUARES]; Piece::N_PIECES],
+) -> [[Score; N_SQUARES]; Piece::N_PIECES] {
+    let mut packed = [[Score::ZERO; N_SQUARES]; Piece::N_PIECES];
+
+    let mut piece = 0;
+    while piece < Piece::N_PIECES {
+        let mut square = 0;
+        while square < N_SQUARES {
+            packed[piece][square] = S(mg[piece][square], eg[piece][square]);
+            square += 1;
+        }
+        piece += 1;
+    }
+
+    packed
+}
+
+const PHASES: [i32; 6] = [
+    0, // Pawn
+    1, // Knight phase
+    1, // Bishop phase
+    2, // Rook phase
+    4, // Queen phase
+    0 // unused
+];
+const TOTAL_PHASE: i32 = 16*PHASES[0] + 4*(PHASES[1] + PHASES[2] + PHASES[3]) + 2*PHASES[4];
+
+const BISHOP_PAIR_BONUS: Score = S(35, 50);
+
+// Penalty per own pawn on the bishop's square color, multiplied by one plus
+// the number of own blocked pawns in the center that wall the bishop in
+const BAD_BISHOP_PENALTY: Score = S(-2, -5);
+
+// Evaluations are scaled by a factor out of `SCALE_NORMAL` in drawish endgames
+const SCALE_NORMAL: i32 = 64;
+// Endgames that can't be won, or only with the help of the defender
+const SCALE_DRAW: i32 = 0;
+// No pawns and at most a minor piece up, e.g. rook and bishop against rook or
+// rook against a minor piece: the defender can usually hold
+const SCALE_NO_PAWNS_MINOR_UP: i32 = 8;
+// Bishops of opposite colors with only pawns left are very hard to win, even a
+// pawn or two up, since the defending bishop can blockade on its own color
+const SCALE_OPPOSITE_BISHOPS: i32 = 24;
+// Winning chances are better with more than one extra pawn
+const SCALE_OPPOSITE_BISHOPS_PAWNS_UP: i32 = 40;
+
+// Mop-up evaluation against a bare king: bonus per square the losing king is
+// away from the center (or for KBN-vs-K, closer to the right corner), and per
+// square the kings are closer to each other
+const MOP_UP_EDGE_WEIGHT: i32 = 10;
+const MOP_UP_CORNER_WEIGHT: i32 = 20;
+const MOP_UP_KING_PROXIMITY_WEIGHT: i32 = 4;
+const MAX_KING_DISTANCE: i32 = 14;
+
+// Penalty for a piece pinned to its own king, which can't move off the pin
+// ray and can't defend. The more valuable the pinned piece, the more the pin
+// is worth to the opponent
+const PINNED_PIECE_PENALTY: [Score; Piece::N_PIECES] = [
+    S(0, 0), // Pawn
+    S(-15, -10), // Knight
+    S(-15, -10), // Bishop
+    S(-25, -20), // Rook
+    S(-40, -30), // Queen
+    S(0, 0), // King
+];
+
+// Penalty for a piece attacked by the opponent and not defended by any of its
+// own pieces, by the type of the hanging piece
+const HANGING_PIECE_PENALTY: [Score; Piece::N_PIECES] = [
+    S(-5, -10), // Pawn
+    S(-25, -20), // Knight
+    S(-25, -20), // Bishop
+    S(-35, -30), // Rook
+    S(-45, -40), // Queen
+    S(0, 0), // King
+];
+
+// Attack units per square of the enemy king ring attacked by a piece, and the
+// non-linear king safety penalty (in middlegame centipawns) by the total number
+// of attack units, so several attackers weigh much more than a single one
+const KING_ATTACK_WEIGHTS: [i32; Piece::N_PIECES] = [
+    0, // Pawn
+    2, // Knight
+    2, // Bishop
+    3, // Rook
+    5, // Queen
+    0, // King
+];
+// A single piece attacking the king ring is no real threat
+const KING_ATTACK_MIN_ATTACKERS: u32 = 2;
+const KING_SAFETY_TABLE: [i32; 100] = [
+      0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+     18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+     68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
+
+// Square h1 (index 0) is light
+const LIGHT_SQUARES: u64 = 0xaa55aa55aa55aa55;
+// c-, d-, e- and f-file
+const CENTER_FILES: u64 = FILE_MASKS[2] | FILE_MASKS[3] | FILE_MASKS[4] | FILE_MASKS[5];
+// d-, e- and f-file, where the king is left when it hasn't castled either way
+const KING_CENTER_FILES: u64 = FILE_MASKS[2] | FILE_MASKS[3] | FILE_MASKS[4];
+
+const DOUBLED_PAWNS_PENALTY: Score = S(-2, -10);
+
+// Mobility bonus of a knight or slider that covers every square its moves
+// could reach from its square: knights on the 16 center squares, sliders with
+// all their rays open
+const FULL_MOBILITY_BONUS: Score = S(25, 10);
+// c3 to f6, the squares with eight knight moves
+const FULL_KNIGHT_MOBILITY_SQUARES: u64 = 0x00003c3c3c3c0000;
+
+// Once a side can't castle anymore: penalty for a king left on the center files
+// and bonus for a king tucked away on a wing with its rook freed, as it is after
+// castling. Middlegame only, in the endgame the king belongs in the center
+const UNCASTLED_KING_PENALTY: Score = S(-40, 0);
+const CASTLED_KING_BONUS: Score = S(25, 0);
+
+// Opening development: penalty per minor piece still on its starting square,
+// for bringing out the queen while two or more minor pieces are undeveloped,
+// and per tempo spent moving the same pieces or pawns again during the first
+// `OPENING_MOVES` moves. Middlegame only
+const UNDEVELOPED_MINOR_PENALTY: Score = S(-10, 0);
+const EARLY_QUEEN_PENALTY: Score = S(-20, 0);
+const WASTED_TEMPO_PENALTY: Score = S(-8, 0);
+const OPENING_MOVES: usize = 10;
+
+// Bonus per own pawn on d4, e4, d5 or e5
+const CENTER_PAWN_BONUS: Score = S(15, 0);
+
+const CENTER_SQUARES: u64 = (1 << 27) | (1 << 28) | (1 << 35) | (1 << 36);
+
+// Bonus per friendly pawn defending a pawn, and for pawns with a friendly pawn
+// next to them (phalanx), indexed by the rank relative to the pawn's side
+const CONNECTED_PAWN_BONUS: [Score; BOARD_HEIGHT] = [
+    S(0, 0), S(0, 0), S(4, 2), S(6, 5), S(10, 10), S(18, 20), S(30, 40), S(0, 0),
 ];
-const TOTAL_PHASE: f64 = 16.0*PHASES[0] + 4.0*(PHASES[1] + PHASES[2] + PHASES[3]) + 2.0*PHASES[4];
 
-const BISHOP_PAIR_BONUS_MG: i32 = 35;
-const BISHOP_PAIR_BONUS_EG: i32 = 50;
+/// Tunable evaluation weights, the constants above gathered as data so the
+/// tuners (see `src/tuner.rs` and `src/spsa.rs`) can evaluate positions with
+/// modified copies. The engine itself evaluates with `EvalParams::DEFAULT`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    pub piece_values: [Score; Piece::N_PIECES],
+    pub piece_placement_values: [[Score; N_SQUARES]; Piece::N_PIECES],
+    pub bishop_pair_bonus: Score,
+    pub bad_bishop_penalty: Score,
+    pub doubled_pawns_penalty: Score,
+    pub uncastled_king_penalty: Score,
+    pub castled_king_bonus: Score,
+    pub undeveloped_minor_penalty: Score,
+    pub early_queen_penalty: Score,
+    pub wasted_tempo_penalty: Score,
+    pub center_pawn_bonus: Score,
+    pub connected_pawn_bonus: [Score; BOARD_HEIGHT],
+    pub pinned_piece_penalty: [Score; Piece::N_PIECES],
+    pub hanging_piece_penalty: [Score; Piece::N_PIECES],
+}
+
+impl EvalParams {
+    pub const DEFAULT: Self = Self {
+        piece_values: PIECE_VALUES,
+        piece_placement_values: PIECE_PLACEMENT_VALUES,
+        bishop_pair_bonus: BISHOP_PAIR_BONUS,
+        bad_bishop_penalty: BAD_BISHOP_PENALTY,
+        doubled_pawns_penalty: DOUBLED_PAWNS_PENALTY,
+        uncastled_king_penalty: UNCASTLED_KING_PENALTY,
+        castled_king_bonus: CASTLED_KING_BONUS,
+        undeveloped_minor_penalty: UNDEVELOPED_MINOR_PENALTY,
+        early_queen_penalty: EARLY_QUEEN_PENALTY,
+        wasted_tempo_penalty: WASTED_TEMPO_PENALTY,
+        center_pawn_bonus: CENTER_PAWN_BONUS,
+        connected_pawn_bonus: CONNECTED_PAWN_BONUS,
+        pinned_piece_penalty: PINNED_PIECE_PENALTY,
+        hanging_piece_penalty: HANGING_PIECE_PENALTY,
+    };
+
+    fn scores_mut(&mut self) -> Vec<&mut Score> {
+        let mut scores: Vec<&mut Score> = vec![];
+        scores.extend(self.piece_values.iter_mut());
+        scores.extend(self.piece_placement_values.iter_mut().flatten());
+        scores.push(&mut self.bishop_pair_bonus);
+        scores.push(&mut self.bad_bishop_penalty);
+        scores.push(&mut self.doubled_pawns_penalty);
+        scores.push(&mut self.uncastled_king_penalty);
+        scores.push(&mut self.castled_king_bonus);
+        scores.push(&mut self.undeveloped_minor_penalty);
+        scores.push(&mut self.early_queen_penalty);
+        scores.push(&mut self.wasted_tempo_penalty);
+        scores.push(&mut self.center_pawn_bonus);
+        scores.extend(self.connected_pawn_bonus.iter_mut());
+        scores.extend(self.pinned_piece_penalty.iter_mut());
+        scores.extend(self.hanging_piece_penalty.iter_mut());
+        scores
+    }
+
+    /// Middlegame and endgame values of all weights in a fixed order, for
+    /// optimizing them one by one.
+    pub fn weights(&self) -> Vec<i32> {
+        self.clone()
+            .scores_mut()
+            .iter()
+            .flat_map(|score| [score.mg(), score.eg()])
+            .collect()
+    }
+
+    /// Set all weights from values in the order of `weights`.
+    pub fn set_weights(&mut self, weights: &[i32]) {
+        for (score, values) in self.scores_mut().into_iter().zip(weights.chunks(2)) {
+            *score = S(values[0], values[1]);
+        }
+    }
+}
+
+/// Value of the pieces of `side` other than pawns and the king.
+fn non_pawn_material(board: &Board, side: Side) -> i32 {
+    [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen].iter()
+        .map(|&piece| board[(piece, side)].count_ones() as i32*PIECE_VALUES[piece as usize].eg())
+        .sum()
+}
+
+/// Whether the only winning try of `strong_side` is pawns on a single rook file
+/// with bishops that don't control the promotion square, and the defending king
+/// has reached that corner. The king can't be driven out of it, so it's a draw.
+fn wrong_bishop_rook_pawns(board: &Board, strong_side: Side) -> bool {
+    let pawns = board[(Piece::Pawn, strong_side)];
+    let bishops = board[(Piece::Bishop, strong_side)];
+    if pawns == 0
+        || bishops == 0
+        || board[(Piece::Knight, strong_side)] | board[(Piece::Rook, strong_side)] | board[(Piece::Queen, strong_side)] != 0
+    {
+        return false;
+    }
+
+    // All bishops on the same square color
+    let light_bishops = bishops & LIGHT_SQUARES != 0;
+    if light_bishops && bishops & !LIGHT_SQUARES != 0 {
+        return false;
+    }
+
+    let pawn_file = if pawns & !FILE_MASKS[0] == 0 {
+        0
+    } else if pawns & !FILE_MASKS[BOARD_WIDTH - 1] == 0 {
+        BOARD_WIDTH - 1
+    } else {
+        return false;
+    };
+
+    let promotion_rank = if strong_side == Side::White { BOARD_HEIGHT - 1 } else { 0 };
+    let promotion_square = promotion_rank*BOARD_WIDTH + pawn_file;
+    if (LIGHT_SQUARES & (1 << promotion_square) != 0) == light_bishops {
+        return false;
+    }
+
+    let weak_king = board[(Piece::King, strong_side.opposite())].trailing_zeros() as usize;
+    (file(weak_king) as i32 - pawn_file as i32).abs() <= 1
+        && (rank(weak_king) as i32 - promotion_rank as i32).abs() <= 1
+}
+
+/// Factor (out of `SCALE_NORMAL`) to scale the evaluation by, lower for
+/// endgames that are drawish despite the material advantage of `strong_side`,
+/// the side the evaluation favors.
+fn scale_factor(game: &GameState, strong_side: Side) -> i32 {
+    let board = &game.board;
+    let weak_side = strong_side.opposite();
+
+    if !game.rules().endgame_knowledge() {
+        return SCALE_NORMAL;
+    }
+
+    if board[(Piece::Pawn, strong_side)] == 0 {
+        let strong_material = non_pawn_material(board, strong_side);
+        if strong_material <= PIECE_VALUES[Piece::Bishop as usize].eg() {
+            // A lone minor piece can't mate
+            return SCALE_DRAW;
+        }
+        if strong_material - non_pawn_material(board, weak_side) <= PIECE_VALUES[Piece::Bishop as usize].eg() {
+            return SCALE_NO_PAWNS_MINOR_UP;
+        }
+    }
+
+    if wrong_bishop_rook_pawns(board, strong_side) {
+        return SCALE_DRAW;
+    }
+
+    let white_bishops = board[(Piece::Bishop, Side::White)];
+    let black_bishops = board[(Piece::Bishop, Side::Black)];
+
+    let only_bishops_and_pawns = Side::VALUES.iter().all(|&side| {
+        board[(Piece::Knight, side)] | board[(Piece::Rook, side)] | board[(Piece::Queen, side)] == 0
+    });
+    let opposite_colored_bishops = white_bishops.count_ones() == 1
+        && black_bishops.count_ones() == 1
+        && ((white_bishops & LIGHT_SQUARES) == 0) != ((black_bishops & LIGHT_SQUARES) == 0);
+
+    if only_bishops_and_pawns && opposite_colored_bishops {
+        let pawn_difference = (board[(Piece::Pawn, Side::White)].count_ones() as i32
+            - board[(Piece::Pawn, Side::Black)].count_ones() as i32).abs();
+        if pawn_difference <= 1 {
+            SCALE_OPPOSITE_BISHOPS
+        } else {
+            SCALE_OPPOSITE_BISHOPS_PAWNS_UP
+        }
+    } else {
+        SCALE_NORMAL
+    }
+}
+
+/// Penalty for bishops of `side` obstructed by their own
+/// pawns: pawns on the bishop's square color limit its scope, even more so
+/// when the center is locked by blocked pawns.
+fn bad_bishops(game: &GameState, params: &EvalParams, side: Side) -> Score {
+    let pawns = game.board[(Piece::Pawn, side)];
+    let occupied = game.board.occupation_board(Side::White) | game.board.occupation_board(Side::Black);
+
+    // Pawns that can't advance because the square in front is occupied
+    let blocked_pawns = if side == Side::White {
+        pawns & (occupied >> BOARD_WIDTH)
+    } else {
+        pawns & (occupied << BOARD_WIDTH)
+    };
+    let blocked_center_pawns = (blocked_pawns & CENTER_FILES).count_ones() as i32;
+
+    let mut penalty = Score::ZERO;
+
+    let mut bishop_bb = game.board[(Piece::Bishop, side)];
+    while bishop_bb != 0 {
+        let square = bishop_bb.trailing_zeros() as usize;
+        let same_color_squares = if LIGHT_SQUARES & (1 << square) != 0 {
+            LIGHT_SQUARES
+        } else {
+            !LIGHT_SQUARES
+        };
+        let same_color_pawns = (pawns & same_color_squares).count_ones() as i32;
+
+        penalty += params.bad_bishop_penalty*(same_color_pawns*(1 + blocked_center_pawns));
+
+        // clear square bit
+        bishop_bb &= bishop_bb - 1;
+    }
+
+    penalty
+}
+
+/// Bonus for the pawns of `side` that are defended by
+/// friendly pawns or stand next to one, which makes them hard to attack. The
+/// further a pawn is advanced, the more it's worth.
+fn connected_pawns(game: &GameState, params: &EvalParams, side: Side) -> Score {
+    let pawns = game.board[(Piece::Pawn, side)];
+    let mut bonus = Score::ZERO;
+
+    let mut pawn_bb = pawns;
+    while pawn_bb != 0 {
+        let square = pawn_bb.trailing_zeros() as usize;
+        let pawn_file = file(square);
+        let pawn_rank = rank(square);
+        let relative_rank = if side == Side::White {
+            pawn_rank
+        } else {
+            BOARD_HEIGHT - 1 - pawn_rank
+        };
+
+        // Pawns of `side` defending the square are those that a pawn of the
+        // other side on the square could capture
+        let supporters = game.pl_moves.pawn_capture_moves[side.opposite() as usize][square] & pawns;
+
+        let mut adjacent_files = 0u64;
+        if pawn_file > 0 {
+            adjacent_files |= FILE_MASKS[pawn_file - 1];
+        }
+        if pawn_file < BOARD_WIDTH - 1 {
+            adjacent_files |= FILE_MASKS[pawn_file + 1];
+        }
+        let is_phalanx = adjacent_files & RANK_MASKS[pawn_rank] & pawns != 0;
+
+        let n_connections = supporters.count_ones() as i32 + is_phalanx as i32;
+        bonus += params.connected_pawn_bonus[relative_rank]*n_connections;
+
+        // clear square bit
+        pawn_bb &= pawn_bb - 1;
+    }
+
+    bonus
+}
+
+/// Penalty for the king of `side` stuck in the center after losing its castling
+/// rights, or bonus for a king that has castled. The board doesn't record
+/// castling itself, so a king on a wing of its back rank counts as castled
+/// unless one of its own rooks is shut in between the king and the corner, as
+/// happens when the king walks there instead.
+fn king_castling(game: &GameState, params: &EvalParams, side: Side) -> Score {
+    let board = &game.board;
+    if board.castling_right_long[side as usize] || board.castling_right_short[side as usize] {
+        return Score::ZERO;
+    }
+
+    let king_bb = board[(Piece::King, side)];
+    if king_bb & KING_CENTER_FILES != 0 {
+        return params.uncastled_king_penalty;
+    }
+
+    let back_rank = RANK_MASKS[if side == Side::White { 0 } else { BOARD_HEIGHT - 1 }];
+    if king_bb & back_rank == 0 {
+        return Score::ZERO;
+    }
+
+    // Back rank squares between the king and the corner of its wing
+    let king_square = king_bb.trailing_zeros() as usize;
+    let corner_side = if file(king_square) < BOARD_WIDTH/2 {
+        king_bb - 1
+    } else {
+        !(king_bb | (king_bb - 1))
+    };
+
+    if board[(Piece::Rook, side)] & back_rank & corner_side != 0 {
+        Score::ZERO
+    } else {
+        params.castled_king_bonus
+    }
+}
+
+/// Penalty for lagging development of `side`: minor pieces left on their
+/// starting squares and, in the opening, an early queen sortie and tempi lost
+/// by moving pieces more than once. The board doesn't record the moves played,
+/// so the lost tempi are the moves `side` has made minus the number of its
+/// pieces and pawns that have left their starting squares.
+fn development(game: &GameState, params: &EvalParams, side: Side) -> Score {
+    let board = &game.board;
+    let start = Board::default();
+    let on_start_square = |piece| board[(piece, side)] & start[(piece, side)];
+
+    let undeveloped_minors = (on_start_square(Piece::Knight) | on_start_square(Piece::Bishop)).count_ones() as i32;
+    let mut score = params.undeveloped_minor_penalty*undeveloped_minors;
+
+    if game.move_number > OPENING_MOVES {
+        return score;
+    }
+
+    if undeveloped_minors >= 2 && board[(Piece::Queen, side)] & !start[(Piece::Queen, side)] != 0 {
+        score += params.early_queen_penalty;
+    }
+
+    let moves_played = if side == Side::White {
+        game.half_move_number/2
+    } else {
+        (game.half_move_number - 1)/2
+    };
+    let moved_units: usize = Piece::VALUES.iter()
+        .map(|&piece| (start[(piece, side)] & !board[(piece, side)]).count_ones() as usize)
+        .sum();
+    score + params.wasted_tempo_penalty*moves_played.saturating_sub(moved_units) as i32
+}
+
+/// Bonus for the pawns of `side` occupying the center.
+fn center_pawns(game: &GameState, params: &EvalParams, side: Side) -> Score {
+    let n_center_pawns = (game.board[(Piece::Pawn, side)] & CENTER_SQUARES).count_ones() as i32;
+    params.center_pawn_bonus*n_center_pawns
+}
+
+/// Penalty for the pieces of `side` pinned to their king.
+fn pinned_pieces(game: &GameState, params: &EvalParams, info: &PositionInfo, side: Side) -> Score {
+    let pinned = info.pinned[side as usize];
+    if pinned == 0 {
+        return Score::ZERO;
+    }
+
+    Piece::ALL_BUT_KING.iter().fold(Score::ZERO, |penalty, &piece| {
+        let n_pinned = (pinned & game.board[(piece, side)]).count_ones() as i32;
+        penalty + params.pinned_piece_penalty[piece as usize]*n_pinned
+    })
+}
+
+/// Penalty for the pieces of `side` that are attacked by the opponent and not
+/// defended.
+fn hanging_pieces(game: &GameState, params: &EvalParams, info: &PositionInfo, side: Side) -> Score {
+    let hanging = game.board.occupation_board(side)
+        & info.attacks[side.opposite() as usize]
+        & !info.attacks[side as usize];
+    if hanging == 0 {
+        return Score::ZERO;
+    }
+
+    Piece::ALL_BUT_KING.iter().fold(Score::ZERO, |penalty, &piece| {
+        let n_hanging = (hanging & game.board[(piece, side)]).count_ones() as i32;
+        penalty + params.hanging_piece_penalty[piece as usize]*n_hanging
+    })
+}
+
+/// Penalty for the king of `side` being attacked: every enemy piece attacking
+/// the squares around the king adds attack units by piece type and number of
+/// squares attacked, and the total is mapped through `KING_SAFETY_TABLE`.
+fn king_safety(game: &GameState, side: Side) -> Score {
+    let king_square = game.board[(Piece::King, side)].trailing_zeros() as usize;
+    let king_ring = game.pl_moves.king_moves[king_square] | (1 << king_square);
+    let enemy = side.opposite();
+
+    let mut n_attackers = 0;
+    let mut attack_units = 0;
+    let extra_attackers = game.rules().extra_king_attackers(game, side);
+
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        let mut piece_bb = game.board[(piece, enemy)];
+        while piece_bb != 0 {
+            let square = piece_bb.trailing_zeros() as usize;
+            let attacks = if piece.is_slider() {
+                game.slider_moves(piece, square)
+            } else {
+                game.pl_moves.knight_moves[square]
+            };
+
+            let ring_attacks = (attacks & king_ring).count_ones();
+            if ring_attacks > 0 {
+                n_attackers += 1;
+                attack_units += KING_ATTACK_WEIGHTS[piece as usize]*ring_attacks as i32;
+            }
+
+            // clear square bit
+            piece_bb &= piece_bb - 1;
+        }
+
+        // Pieces the variant counts as attacking, as if they attacked the ring
+        let n_extra = extra_attackers[piece as usize];
+        n_attackers += n_extra;
+        attack_units += KING_ATTACK_WEIGHTS[piece as usize]*n_extra as i32;
+    }
+
+    if n_attackers < KING_ATTACK_MIN_ATTACKERS {
+        return Score::ZERO;
+    }
+
+    let penalty = KING_SAFETY_TABLE[(attack_units as usize).min(KING_SAFETY_TABLE.len() - 1)];
+    S(-penalty, 0)
+}
+
+/// Manhattan distance between two squares.
+fn distance(a: usize, b: usize) -> i32 {
+    (file(a) as i32 - file(b) as i32).abs() + (rank(a) as i32 - rank(b) as i32).abs()
+}
+
+/// Manhattan distance of a square to the nearest of the four center squares.
+fn center_distance(square: usize) -> i32 {
+    let f = file(square) as i32;
+    let r = rank(square) as i32;
+    (3 - f).max(f - 4) + (3 - r).max(r - 4)
+}
+
+/// Evaluation for positions where one side only has its king left and the
+/// other side has the material to mate without pawns: instead of piece
+/// placement, reward driving the bare king to the edge and bringing the
+/// attacking king closer, so elementary mates are converted quickly. With
+/// bishop and knight the king has to be driven to a corner of the bishop's
+/// color. Returns `None` for all other positions.
+fn mop_up(game: &GameState, params: &EvalParams) -> Option<i32> {
+    let board = &game.board;
+    if !game.rules().endgame_knowledge() {
+        return None;
+    }
+
+    let (strong_side, weak_side) = if board.occupation_board(Side::Black) == board[(Piece::King, Side::Black)] {
+        (Side::White, Side::Black)
+    } else if board.occupation_board(Side::White) == board[(Piece::King, Side::White)] {
+        (Side::Black, Side::White)
+    } else {
+        return None;
+    };
+
+    let n_pieces = |piece| board[(piece, strong_side)].count_ones();
+    if n_pieces(Piece::Pawn) > 0 {
+        return None;
+    }
+
+    let heavy_pieces = n_pieces(Piece::Queen) + n_pieces(Piece::Rook) > 0;
+    let bishop_and_knight = n_pieces(Piece::Bishop) >= 1 && n_pieces(Piece::Knight) >= 1;
+    let bishop_pair = n_pieces(Piece::Bishop) >= 2;
+    if !(heavy_pieces || bishop_and_knight || bishop_pair) {
+        return None;
+    }
+
+    let strong_king = board[(Piece::King, strong_side)].trailing_zeros() as usize;
+    let weak_king = board[(Piece::King, weak_side)].trailing_zeros() as usize;
+
+    let mut score: i32 = Piece::ALL_BUT_KING.iter()
+        .map(|&piece| n_pieces(piece) as i32*params.piece_values[piece as usize].eg())
+        .sum();
+
+    score += (MAX_KING_DISTANCE - distance(strong_king, weak_king))*MOP_UP_KING_PROXIMITY_WEIGHT;
+
+    if bishop_and_knight && !heavy_pieces && !bishop_pair {
+        // Only the corners of the bishop's square color can be mated in
+        let corners: [usize; 2] = if board[(Piece::Bishop, strong_side)] & LIGHT_SQUARES != 0 {
+            [0, N_SQUARES - 1]
+        } else {
+            [BOARD_WIDTH - 1, N_SQUARES - BOARD_WIDTH]
+        };
+        let corner_distance = corners.iter().map(|&corner| distance(weak_king, corner)).min().unwrap();
+        score += (MAX_KING_DISTANCE - corner_distance)*MOP_UP_CORNER_WEIGHT;
+    } else {
+        score += center_distance(weak_king)*MOP_UP_EDGE_WEIGHT;
+    }
+
+    Some(if strong_side == Side::White { score } else { -score })
+}
+
+/// Index of a bitboard square in the piece placement values arrays above,
+/// which are from white's side with a8 first.
+fn placement_square(side: Side, square: usize) -> usize {
+    let square = N_SQUARES - 1 - square;
+    if side == Side::Black {
+        // Flip the rank for black
+        BOARD_WIDTH*(BOARD_HEIGHT - 1 - rank(square)) + file(square)
+    } else {
+        square
+    }
+}
+
+/// Material and placement value of a piece, from white's perspective.
+fn piece_square_score(params: &EvalParams, piece: Piece, side: Side, square: usize) -> Score {
+    let piece_idx = piece as usize;
+
+    // intrinsic piece value and absolute piece placement value
+    let score = params.piece_values[piece_idx]
+        + params.piece_placement_values[piece_idx][placement_square(side, square)];
+
+    if side == Side::White {
+        score
+    } else {
+        -score
+    }
+}
+
+/// Material and piece placement score of all pieces on the board, from white's
+/// perspective.
+pub fn psqt_score(board: &Board, params: &EvalParams) -> Score {
+    let mut score = Score::ZERO;
+
+    for side in Side::VALUES {
+        for piece in Piece::VALUES {
+            let mut piece_bb = board[(piece, side)];
+            while piece_bb != 0 {
+                let square = piece_bb.trailing_zeros() as usize;
+                score += piece_square_score(params, piece, side, square);
+
+                // clear square bit
+                piece_bb &= piece_bb - 1;
+            }
+        }
+    }
+
+    score
+}
+
+/// Change of `psqt_score` with the default weights by making move `m`, so the
+/// score can be kept up to date incrementally instead of summing all pieces in
+/// every evaluation.
+///
+/// * `m`: Move to make
+pub fn psqt_score_delta(m: &Move) -> Score {
+    m.piece_changes().iter().fold(Score::ZERO, |score, &(piece, side, square, sign)| {
+        score + piece_square_score(&EvalParams::DEFAULT, piece, side, square)*sign
+    })
+}
+
+/// Terms of the evaluation, as listed by `EvalReport`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvalTerm {
+    Material,
+    Placement,
+    Mobility,
+    BishopPair,
+    BadBishops,
+    DoubledPawns,
+    ConnectedPawns,
+    KingSafety,
+    PinnedPieces,
+    HangingPieces,
+    Castling,
+    Development,
+    CenterPawns,
+    Checks,
+    Hand,
+}
+
+impl EvalTerm {
+    pub const N_TERMS: usize = 15;
+    pub const VALUES: [Self; Self::N_TERMS] = [
+        Self::Material,
+        Self::Placement,
+        Self::Mobility,
+        Self::BishopPair,
+        Self::BadBishops,
+        Self::DoubledPawns,
+        Self::ConnectedPawns,
+        Self::KingSafety,
+        Self::PinnedPieces,
+        Self::HangingPieces,
+        Self::Castling,
+        Self::Development,
+        Self::CenterPawns,
+        Self::Checks,
+        Self::Hand,
+    ];
+}
+
+/// Breakdown of the evaluation of a position, see `evaluate`, e.g. for showing
+/// it in a GUI or debugging the evaluation.
+///
+/// * `terms`: Score of every term for each side, from the side's own perspective
+/// * `phase`: Game phase the middlegame and endgame values are blended with,
+///   from 0 (opening) to 256 (endgame)
+/// * `scale_factor`: Factor (out of `SCALE_NORMAL`) the blended score is scaled by
+/// * `mop_up`: Mop-up evaluation used instead of the terms, if any
+/// * `score`: Final evaluation in centipawns from white's perspective
+#[derive(Clone, Debug, Default)]
+pub struct EvalReport {
+    pub terms: [[Score; Side::N_SIDES]; EvalTerm::N_TERMS],
+    pub phase: i32,
+    pub scale_factor: i32,
+    pub mop_up: Option<i32>,
+    pub score: i32,
+}
+
+impl EvalReport {
+    fn add(&mut self, term: EvalTerm, side: Side, score: Score) {
+        self.terms[term as usize][side as usize] += score;
+    }
+
+    /// What `term` adds to the score, in centipawns from white's perspective:
+    /// its middlegame and endgame values of both sides blended by the phase
+    /// and scaled like the score. Zero with a mop-up evaluation.
+    pub fn term_score(&self, term: EvalTerm) -> i32 {
+        if self.mop_up.is_some() {
+            return 0;
+        }
+
+        let [white, black] = self.terms[term as usize];
+        let total = white - black;
+        (total.mg()*(256 - self.phase) + total.eg()*self.phase)/256*self.scale_factor/SCALE_NORMAL
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(mop_up_eval) = self.mop_up {
+            writeln!(fmt, "Mop-up evaluation against a bare king")?;
+            return write!(fmt, "Score: {} (white's perspective)", format_score(mop_up_eval));
+        }
+
+        writeln!(fmt, "{:<16}|{:^15}|{:^15}|{:^15}", "Term", "White", "Black", "Total")?;
+        writeln!(fmt, "{:<16}|{:>7}{:>7} |{:>7}{:>7} |{:>7}{:>7}", "", "MG", "EG", "MG", "EG", "MG", "EG")?;
+        writeln!(fmt, "{:-<16}+{:-<15}+{:-<15}+{:-<15}", "", "", "", "")?;
+        for term in EvalTerm::VALUES {
+            let [white, black] = self.terms[term as usize];
+            let total = white - black;
+            writeln!(
+                fmt,
+                "{:<16}|{:>7}{:>7} |{:>7}{:>7} |{:>7}{:>7}",
+                format!("{:?}", term),
+                white.mg(),
+                white.eg(),
+                black.mg(),
+                black.eg(),
+                total.mg(),
+                total.eg()
+            )?;
+        }
+        writeln!(fmt)?;
+        writeln!(fmt, "Phase: {}/256 (0 = opening, 256 = endgame)", self.phase)?;
+        writeln!(fmt, "Scale factor: {}/{}", self.scale_factor, SCALE_NORMAL)?;
+        write!(fmt, "Score: {} (white's perspective)", format_score(self.score))
+    }
+}
+
+/// Static evaluation of the position in centipawns, from white's perspective.
+/// Material and piece placement are kept up to date by `GameState` as moves
+/// are made, only the other terms are computed here.
+pub fn eval(game: &GameState) -> i32 {
+    eval_with_info(game, &game.position_info())
+}
+
+/// Like `eval`, with the attacks and pins of the position already computed
+/// for generating its moves.
+///
+/// * `info`: Attacks and pins of the position, see `GameState::position_info`
+pub fn eval_with_info(game: &GameState, info: &PositionInfo) -> i32 {
+    debug_assert_eq!(game.psqt_score, psqt_score(&game.board, &EvalParams::DEFAULT));
+    debug_assert_eq!(*info, game.position_info());
+    eval_from_psqt_score(game, &EvalParams::DEFAULT, info, game.psqt_score, None)
+}
+
+/// Like `eval`, but with the evaluation weights of `params`. Material and
+/// piece placement are computed from scratch.
+pub fn eval_with_params(game: &GameState, params: &EvalParams) -> i32 {
+    eval_with_info_and_params(game, &game.position_info(), params)
+}
+
+/// Like `eval_with_params`, with the attacks and pins of the position already
+/// computed, see `eval_with_info`.
+pub fn eval_with_info_and_params(game: &GameState, info: &PositionInfo, params: &EvalParams) -> i32 {
+    debug_assert_eq!(*info, game.position_info());
+    eval_from_psqt_score(game, params, info, psqt_score(&game.board, params), None)
+}
+
+/// Evaluate the position like `eval`, and break the evaluation down into its
+/// terms and the game phase.
+pub fn evaluate(game: &GameState) -> EvalReport {
+    let params = &EvalParams::DEFAULT;
+    let mut report = EvalReport::default();
+
+    for side in Side::VALUES {
+        for piece in Piece::VALUES {
+            let mut piece_bb = game.board[(piece, side)];
+            while piece_bb != 0 {
+                let square = piece_bb.trailing_zeros() as usize;
 
-const DOUBLED_PAWNS_PENALTY_MG: i32 = -2;
-const DOUBLED_PAWNS_PENALTY_EG: i32 = -10;
+                report.add(EvalTerm::Material, side, params.piece_values[piece as usize]);
+                report.add(
+                    EvalTerm::Placement,
+                    side,
+                    params.piece_placement_values[piece as usize][placement_square(side, square)],
+                );
+
+                // clear square bit
+                piece_bb &= piece_bb - 1;
+            }
+        }
+    }
+
+    report.score = eval_from_psqt_score(game, params, &game.position_info(), game.psqt_score, Some(&mut report));
+    report
+}
+
+fn eval_from_psqt_score(
+    game: &GameState,
+    params: &EvalParams,
+    info: &PositionInfo,
+    psqt_score: Score,
+    mut report: Option<&mut EvalReport>,
+) -> i32 {
+    if let Some(mop_up_eval) = mop_up(game, params) {
+        if let Some(report) = report {
+            report.mop_up = Some(mop_up_eval);
+        }
+        return mop_up_eval;
+    }
 
-pub fn eval(game: &GameState) -> f64 {
-    let mut piece_counts = [[0i32; Piece::N_PIECES]; Side::N_SIDES];
-    let mut doubled_pawns = [0i32; Side::N_SIDES];
     let mut phase = TOTAL_PHASE;
-    let mut mg_eval = 0i32;
-    let mut eg_eval = 0i32;
-    let mut side_mg_eval;
-    let mut side_eg_eval;
+    let mut score = psqt_score;
     const MULTIPLIERS: [i32; 2] = [1, -1]; // multiply by -1 for black's piece evaluation
 
     for side in Side::VALUES {
-        let side_idx = side as usize;
         let multiplier = MULTIPLIERS[side as usize];
 
-        side_mg_eval = 0;
-        side_eg_eval = 0;
+        let mut side_score = Score::ZERO;
+        let mut add_term = |term: EvalTerm, term_score: Score| {
+            side_score += term_score;
+            if let Some(report) = report.as_deref_mut() {
+                report.add(term, side, term_score);
+            }
+        };
 
         for piece in Piece::VALUES {
-            let piece_idx = piece as usize;
+            phase -= PHASES[piece as usize]*game.board[(piece, side)].count_ones() as i32;
+        }
+
+        // Knights only depend on their square, sliders on their open rays
+        let mut n_full_mobility = (game.board[(Piece::Knight, side)] & FULL_KNIGHT_MOBILITY_SQUARES).count_ones() as i32;
+        for piece in [Piece::Bishop, Piece::Rook, Piece::Queen] {
             let mut piece_bb = game.board[(piece, side)];
             while piece_bb != 0 {
                 let one_pos = piece_bb.trailing_zeros() as usize;
                 let square = u64::BITS as usize - 1 - one_pos;
-                let mut corrected_square = square;
-                if side == Side::Black {
-                    // Piece placement values arrays above are from white's side,
-                    // flip the rank for black
-                    let flipped_square_rank = BOARD_HEIGHT - 1 - rank(square);
-                    let square_file = file(square);
-                    corrected_square = BOARD_WIDTH*flipped_square_rank + square_file;
+                let all_rays = game.pl_moves.get_comp_rays(piece)[square];
+                if game.slider_moves(piece, square) & all_rays == all_rays {
+                    n_full_mobility += 1;
                 }
 
-                side_mg_eval += 
-                    // intrinsic piece value
-                    PIECES_VALUES_MG[piece_idx]
-                    // absolute piece placement value
-                    + PIECE_PLACEMENT_VALUES_MG[piece_idx][corrected_square];
-                side_eg_eval += 
-                    PIECES_VALUES_EG[piece_idx]
-                    + PIECE_PLACEMENT_VALUES_EG[piece_idx][corrected_square];
-
-                // Count the fraction of squares that a knight or slider piece 
-                // covers compared to the maximum amount of possible coverage
-                // for a given square
-                let frac_ray_occupied = if piece.is_slider() {
-                    let moves_bb = game.slider_moves(piece, square);
-                    let all_rays = game.pl_moves.get_comp_rays(piece)[square];
-                    let ray_occupied = moves_bb & all_rays;
-                    ((ray_occupied.count_ones() as f64)/(all_rays.count_ones() as f64)) as i32 * 100
-                } else if piece == Piece::Knight {
-                    let moves_bb = game.pl_moves[(piece, side, square)];
-                    (moves_bb.count_ones() as f64 / 8.0) as i32 * 100
-                } else {
-                    0
-                };
-
-                
-                side_mg_eval += frac_ray_occupied/4;
-                side_eg_eval += frac_ray_occupied/10;
-
-                piece_counts[side_idx][piece_idx] += 1;
-
-                phase -= PHASES[piece_idx];
-
                 // clear square bit
-                piece_bb &= piece_bb - (1 << one_pos);
+                piece_bb &= piece_bb - 1;
             }
         }
 
+        add_term(EvalTerm::Mobility, FULL_MOBILITY_BONUS*n_full_mobility);
+
         // Bishop pair bonus
-        if piece_counts[side_idx][Piece::Bishop as usize] >= 2 {
-            side_mg_eval += BISHOP_PAIR_BONUS_MG;
-            side_eg_eval += BISHOP_PAIR_BONUS_EG;
+        if game.board[(Piece::Bishop, side)].count_ones() >= 2 {
+            add_term(EvalTerm::BishopPair, params.bishop_pair_bonus);
         }
 
-        // Doubled pawns
-        for file in FILE_MASKS {
-            let pawns_on_file = (file & game.board[(Piece::Pawn, side)]).count_ones() as i32;
-            if pawns_on_file > 1 {
-                doubled_pawns[side_idx] += pawns_on_file - 1;
-            }
+        add_term(EvalTerm::BadBishops, bad_bishops(game, params, side));
+
+        // Doubled pawns: pawns beyond the first on each file, the files with
+        // pawns are counted by folding the pawns onto one rank
+        let pawns = game.board[(Piece::Pawn, side)];
+        let mut pawn_files = pawns;
+        pawn_files |= pawn_files >> 32;
+        pawn_files |= pawn_files >> 16;
+        pawn_files |= pawn_files >> 8;
+        let doubled_pawns = pawns.count_ones() as i32 - (pawn_files & 0xff).count_ones() as i32;
+
+        add_term(EvalTerm::DoubledPawns, params.doubled_pawns_penalty*doubled_pawns);
+
+        add_term(EvalTerm::ConnectedPawns, connected_pawns(game, params, side));
+
+        add_term(EvalTerm::KingSafety, king_safety(game, side));
+
+        add_term(EvalTerm::PinnedPieces, pinned_pieces(game, params, info, side));
+
+        add_term(EvalTerm::HangingPieces, hanging_pieces(game, params, info, side));
+
+        add_term(EvalTerm::Castling, king_castling(game, params, side));
+
+        add_term(EvalTerm::Development, development(game, params, side));
+
+        add_term(EvalTerm::CenterPawns, center_pawns(game, params, side));
+
+        if let Some((term, term_score)) = game.rules().eval_term(game, params, side) {
+            add_term(term, term_score);
         }
 
-        side_mg_eval += doubled_pawns[side as usize]*DOUBLED_PAWNS_PENALTY_MG;
-        side_eg_eval += doubled_pawns[side as usize]*DOUBLED_PAWNS_PENALTY_EG;
+        score += side_score*multiplier;
+    }
+
+    phase = (phase*256 + (TOTAL_PHASE / 2))/TOTAL_PHASE;
+    let blended_eval = (score.mg()*(256 - phase) + score.eg()*phase)/256;
 
-        mg_eval += multiplier*side_mg_eval;
-        eg_eval += multiplier*side_eg_eval;
+    let strong_side = if blended_eval >= 0 { Side::White } else { Side::Black };
+    let scale = scale_factor(game, strong_side);
+    if let Some(report) = report {
+        report.phase = phase;
+        report.scale_factor = scale;
     }
 
-    phase = (phase*256.0 + (TOTAL_PHASE / 2.0))/TOTAL_PHASE;
-    (((mg_eval as f64)*(256.0 - phase) + (eg_eval as f64)*phase)/256.0)/100.0
+    blended_eval*scale/SCALE_NORMAL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+    use crate::r#move::MoveType;
+
+    #[test]
+    fn test_evaluate() {
+        let fen = "r1bqk2r/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP3PPP/R1BQKB1R w KQkq - 0 7";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        let report = evaluate(&game);
+        assert_eq!(report.score, eval(&game));
+        assert_eq!(report.mop_up, None);
+
+        // Material and placement add up to the incrementally updated scores
+        let psqt_score = [EvalTerm::Material, EvalTerm::Placement].iter()
+            .fold(Score::ZERO, |score, &term| score + report.terms[term as usize][0] - report.terms[term as usize][1]);
+        assert_eq!(psqt_score, game.psqt_score);
+
+        // Material is even
+        let [white_material, black_material] = report.terms[EvalTerm::Material as usize];
+        assert_eq!(white_material, black_material);
+        assert_eq!(report.term_score(EvalTerm::Material), 0);
+
+        // The terms add up to the score, give or take rounding
+        let terms_score: i32 = EvalTerm::VALUES.iter().map(|&term| report.term_score(term)).sum();
+        assert!((terms_score - report.score).abs() <= EvalTerm::N_TERMS as i32);
+
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "8/8/3k4/8/8/8/8/KQ6 w - - 0 1").unwrap();
+        let report = evaluate(&game);
+        assert_eq!(report.mop_up, Some(report.score));
+        assert!(report.score > 0);
+    }
+
+    #[test]
+    fn test_eval_symmetry() {
+        // Flipping the ranks and colors negates the evaluation. Past the
+        // opening, as the wasted tempo penalty depends on who moved first
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 20",
+            "r1bqk2r/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP3PPP/R1BQKB1R w KQkq - 0 20",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 20",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 20",
+            "8/8/3k4/8/8/8/8/KQ6 w - - 0 20",
+        ] {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            let flipped_fen = format!("{} 0 20", game.board.flipped().to_fen());
+            let flipped = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, &flipped_fen).unwrap();
+            assert_eq!(eval(&flipped), -eval(&game), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_king_safety() {
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(king_safety(&game, Side::White), Score::ZERO);
+        assert_eq!(king_safety(&game, Side::Black), Score::ZERO);
+
+        // The queen alone on h5 doesn't count, with the knight on g5 it does
+        let fen = "rnbqkb1r/pppp1p1p/5n2/4p1pQ/4P3/8/PPPP1PPP/RNB1KBNR w KQkq - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(king_safety(&game, Side::Black), Score::ZERO);
+
+        let fen = "rnbqkb1r/pppp1p1p/5n2/4p1NQ/4P3/8/PPPP1PPP/RNB1KB1R w KQkq - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        let penalty = king_safety(&game, Side::Black);
+        assert!(penalty.mg() < 0);
+        assert_eq!(king_safety(&game, Side::White), Score::ZERO);
+    }
+
+    #[test]
+    fn test_hanging_pieces() {
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(hanging_pieces(&game, &EvalParams::DEFAULT, &game.position_info(), Side::White), Score::ZERO);
+        assert_eq!(hanging_pieces(&game, &EvalParams::DEFAULT, &game.position_info(), Side::Black), Score::ZERO);
+
+        // The knight on e5 is attacked by the knight on c6 and undefended, the
+        // one on f6 is attacked by the queen but defended
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4N3/4P3/5Q2/PPPP1PPP/RNB1KB1R w KQkq - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(
+            hanging_pieces(&game, &EvalParams::DEFAULT, &game.position_info(), Side::White),
+            HANGING_PIECE_PENALTY[Piece::Knight as usize],
+        );
+        assert_eq!(hanging_pieces(&game, &EvalParams::DEFAULT, &game.position_info(), Side::Black), Score::ZERO);
+    }
+
+    #[test]
+    fn test_king_castling() {
+        let params = &EvalParams::DEFAULT;
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(king_castling(&game, params, Side::White), Score::ZERO);
+
+        // White castled short, black lost its rights with the king on e8
+        let fen = "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 w - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(king_castling(&game, params, Side::White), CASTLED_KING_BONUS);
+        assert_eq!(king_castling(&game, params, Side::Black), UNCASTLED_KING_PENALTY);
+
+        // Kings that walked to the wing, shutting in their rooks
+        let fen = "rnbq2kr/pppp1ppp/5n2/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQ2KR w - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(king_castling(&game, params, Side::White), Score::ZERO);
+        assert_eq!(king_castling(&game, params, Side::Black), Score::ZERO);
+
+        // Castled long
+        let fen = "2kr1bnr/pppqpppp/2n5/3p1b2/3P1B2/2N5/PPPQPPPP/2KR1BNR w - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(king_castling(&game, params, Side::White), CASTLED_KING_BONUS);
+        assert_eq!(king_castling(&game, params, Side::Black), CASTLED_KING_BONUS);
+    }
+
+    #[test]
+    fn test_scale_factor() {
+        let scale = |fen: &str, strong_side: Side| {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            scale_factor(&game, strong_side)
+        };
+
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(scale_factor(&game, Side::White), SCALE_NORMAL);
+
+        // Rook and bishop against rook
+        assert_eq!(scale("8/8/3k4/8/2r5/8/3BR3/4K3 w - - 0 1", Side::White), SCALE_NO_PAWNS_MINOR_UP);
+        // A bishop alone against pawns can't win
+        assert_eq!(scale("8/5p2/3k4/8/8/8/3B4/4K3 w - - 0 1", Side::White), SCALE_DRAW);
+
+        // Light-squared bishop and h-pawn, black king in the dark h8 corner
+        assert_eq!(scale("7k/8/8/7P/8/8/3B4/4K3 w - - 0 1", Side::White), SCALE_NORMAL);
+        assert_eq!(scale("7k/8/8/7P/8/8/4B3/4K3 w - - 0 1", Side::White), SCALE_DRAW);
+        assert_eq!(scale("8/8/3k4/7P/8/8/4B3/4K3 w - - 0 1", Side::White), SCALE_NORMAL);
+        // Same for black with the a-pawn, queening on a dark square
+        assert_eq!(scale("4k3/3b4/8/8/p7/8/8/1K6 b - - 0 1", Side::Black), SCALE_DRAW);
+
+        // Opposite colored bishops
+        assert_eq!(scale("8/4kp2/8/4b3/8/3B4/4KPP1/8 w - - 0 1", Side::White), SCALE_OPPOSITE_BISHOPS);
+    }
+
+    #[test]
+    fn test_development() {
+        let params = &EvalParams::DEFAULT;
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(development(&game, params, Side::White), UNDEVELOPED_MINOR_PENALTY*4);
+        assert_eq!(center_pawns(&game, params, Side::White), Score::ZERO);
+
+        // 1. e4 e5 2. Qh5 Nc6: an early queen, and a center pawn each
+        let moves = [(Piece::Pawn, 11, 27), (Piece::Pawn, 51, 35), (Piece::Queen, 4, 32), (Piece::Knight, 62, 45)];
+        for (piece, from_square, to_square) in moves {
+            let side = game.board.side_to_move;
+            game.update_board_with_move(&Move { from_square, to_square, move_type: MoveType::Quiet, piece, side });
+        }
+        assert_eq!(development(&game, params, Side::White), UNDEVELOPED_MINOR_PENALTY*4 + EARLY_QUEEN_PENALTY);
+        assert_eq!(development(&game, params, Side::Black), UNDEVELOPED_MINOR_PENALTY*3);
+        assert_eq!(center_pawns(&game, params, Side::White), CENTER_PAWN_BONUS);
+
+        // 3. Qd1 Nb8: both sides lost two tempi
+        let moves = [(Piece::Queen, 32, 4), (Piece::Knight, 45, 62)];
+        for (piece, from_square, to_square) in moves {
+            let side = game.board.side_to_move;
+            game.update_board_with_move(&Move { from_square, to_square, move_type: MoveType::Quiet, piece, side });
+        }
+        for side in Side::VALUES {
+            assert_eq!(development(&game, params, side), UNDEVELOPED_MINOR_PENALTY*4 + WASTED_TEMPO_PENALTY*2);
+        }
+    }
 }
@@ -0,0 +1,171 @@
+//! The engine's evaluation after every move of a game against the user, for
+//! a graph of how the game went and for exporting it to CSV to plot. The
+//! evaluations are those of the engine's searches: the user's move is
+//! evaluated by the search for the reply, and the engine's move by its own
+//! search, so both moves of a turn have the same evaluation.
+
+use std::fs;
+use std::path::Path;
+
+use crate::board::Side;
+use crate::error::Error;
+use crate::game::GameState;
+use crate::search::format_score;
+
+/// Evaluation of the position after a move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalPoint {
+    /// Half move number of the move, starting from 1
+    pub ply: usize,
+    /// The side that moved
+    pub side: Side,
+    /// The move in algebraic notation
+    pub algebraic: String,
+    /// Evaluation in centipawns from white's perspective
+    pub eval: i32,
+}
+
+#[derive(Default)]
+pub struct EvalHistory {
+    points: Vec<EvalPoint>,
+}
+
+impl EvalHistory {
+    // Evaluation at the top and bottom of the graph, anything beyond is
+    // drawn at the edge
+    const GRAPH_SCALE: i32 = 300;
+    // Rows of the graph above and below the zero line
+    const GRAPH_HALF_HEIGHT: i32 = 3;
+    // Games longer than this many half moves are sampled to fit
+    const GRAPH_MAX_WIDTH: usize = 72;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn points(&self) -> &[EvalPoint] {
+        &self.points
+    }
+
+    /// Record the evaluation `eval` of the position after the last move of
+    /// `game`, from white's perspective. Nothing is recorded without moves.
+    pub fn record(&mut self, game: &GameState, eval: i32) {
+        let mut before = game.clone();
+        let Some(m) = before.undo_move() else {
+            return;
+        };
+        let (legal_moves, _) = before.get_legal_moves();
+
+        self.points.push(EvalPoint {
+            ply: before.half_move_number,
+            side: before.board.side_to_move,
+            algebraic: m.to_algebraic_with_state(&legal_moves),
+            eval,
+        });
+    }
+
+    /// Forget the evaluations from half move `ply` on, for taken back moves.
+    pub fn truncate(&mut self, ply: usize) {
+        self.points.retain(|point| point.ply < ply);
+    }
+
+    /// Forget all evaluations, for a new game.
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// The evaluations as a graph in ASCII, with a column per half move
+    /// (sampled in long games), white's advantage up and black's down, e.g.
+    ///
+    /// ```text
+    /// +3.00 |      *
+    ///       |    **
+    ///       |  **
+    /// +0.00 |**-------
+    ///       |         *
+    ///       |
+    /// -3.00 |
+    /// ```
+    pub fn graph(&self) -> String {
+        let width = self.points.len().min(Self::GRAPH_MAX_WIDTH);
+        let levels: Vec<i32> = (0..width)
+            .map(|column| {
+                let eval = self.points[column*self.points.len()/width].eval.clamp(-Self::GRAPH_SCALE, Self::GRAPH_SCALE);
+                (eval as f64*Self::GRAPH_HALF_HEIGHT as f64/Self::GRAPH_SCALE as f64).round() as i32
+            })
+            .collect();
+
+        let mut lines = Vec::new();
+        for level in (-Self::GRAPH_HALF_HEIGHT..=Self::GRAPH_HALF_HEIGHT).rev() {
+            let label = match level {
+                0 => format_score(0),
+                _ if level.abs() == Self::GRAPH_HALF_HEIGHT => format_score(level.signum()*Self::GRAPH_SCALE),
+                _ => String::new(),
+            };
+            let row: String = levels.iter()
+                .map(|&point| if point == level { '*' } else if level == 0 { '-' } else { ' ' })
+                .collect();
+            lines.push(format!("{:>5} |{}", label, row).trim_end().to_owned());
+        }
+
+        lines.join("\n")
+    }
+
+    /// The evaluations as CSV with a header, a line per half move with the
+    /// evaluation in centipawns from white's perspective and as a score,
+    /// where mates are e.g. `#3`.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "ply,side,move,eval,score\n".to_owned();
+        for point in &self.points {
+            let side = if point.side == Side::White { "white" } else { "black" };
+            csv.push_str(&format!("{},{},{},{},{}\n", point.ply, side, point.algebraic, point.eval, format_score(point.eval)));
+        }
+        csv
+    }
+
+    /// Write the evaluations as CSV, see `to_csv`, to the file `path`.
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        fs::write(path, self.to_csv()).map_err(|source| Error::Write { path: path.to_owned(), source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    fn history(moves: &[&str], evals: &[i32]) -> EvalHistory {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        let mut history = EvalHistory::new();
+        for (m, &eval) in moves.iter().zip(evals) {
+            game.apply_moves(&[m]).unwrap();
+            history.record(&game, eval);
+        }
+        history
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut history = history(&["e2e4", "e7e5", "g1f3", "b8c6"], &[30, 30, -120, -120]);
+        assert_eq!(
+            history.to_csv(),
+            "ply,side,move,eval,score\n1,white,e4,30,+0.30\n2,black,e5,30,+0.30\n3,white,Nf3,-120,-1.20\n4,black,Nc6,-120,-1.20\n"
+        );
+
+        history.truncate(3);
+        assert_eq!(history.points().len(), 2);
+        history.clear();
+        assert_eq!(history.to_csv(), "ply,side,move,eval,score\n");
+    }
+
+    #[test]
+    fn test_graph() {
+        let history = history(&["e2e4", "e7e5", "g1f3", "b8c6", "f1c4"], &[0, 100, 200, 5000, -100]);
+        assert_eq!(
+            history.graph(),
+            "+3.00 |   *\n      |  *\n      | *\n+0.00 |*----\n      |    *\n      |\n-3.00 |"
+        );
+    }
+}
@@ -1,29 +1,45 @@
-use std::collections::HashMap;
+use std::fmt;
 
-use crate::board::{Board, Piece, Side, BOARD_HEIGHT, BOARD_WIDTH, N_SQUARES};
+use crate::bitboard::Bb;
+use crate::board::{Board, Piece, Side, Variant, BOARD_HEIGHT, BOARD_WIDTH, N_SQUARES};
+use crate::error::Error;
+use crate::eval::{psqt_score, psqt_score_delta, EvalParams};
 use crate::magic::MagicBitboard;
 use crate::move_bitboards::{file, rank, MoveBitboards, RayDirection};
 use crate::r#move::{Move, MoveType, MoveResult, DrawReason};
-use crate::zobrist::ZobristHasher;
+use crate::rules::Rules;
+use crate::score::Score;
+use crate::zobrist::{ZobristHasher, ZOBRIST};
 
 /// GameState holds the state of a game on a turn.
 ///
 /// * `pl_moves`: Pseudo-legal move bitboards for all pieces and all squares
 /// * `magics`: Magic bitboards used for looking up slider rays w.r.t blockers
+///
+/// The move tables are statics, so a game state owns everything else and can
+/// be sent to, or cloned for, other threads.
+///
 /// * `board`: Pieces bitboards of current turn
 /// * `occupation_boards`: Sum (OR) of all piece bitboards of each side
 /// * `comp_occupation_board`: Sum (OR) of both occupation boards
 /// * `side_to_move`: What side to move
 /// * `castling_right_long`: Long castling rights for both sides
 /// * `castling_right_short`: Short castling rights for both sides
-/// * `en_passant_square`: Tracks possibl en passant capture square
+/// * `en_passant_square`: Square a pawn can be captured en passant on, if any
 /// * `half_move_number`: Number of half moves, i.e. the sum of black and white moves
 /// * `move_number`: Current move number, i.e. the number of black and white moves
-/// * `half_move_of_last_capture`: Half move number at which last capture occured
+/// * `half_move_of_last_irreversible`: Half move number at which the last capture or pawn move
+///   occured, the start of the fifty move rule count
+/// * `psqt_score`: Material and piece placement score from white's perspective, kept up to date
+///   as moves are made for the evaluation
+/// * `hash_stack`: Hashes of the positions of the game up to the current one, scanned back to the
+///   last irreversible move for repetitions
+/// * `promoted_stack`: Promoted pieces before each move made, for undoing it in crazyhouse
+/// * `history`: Moves played with `make_move` or `apply_moves`, with the state to take them back
 #[derive(Clone)]
-pub struct GameState<'a> {
-    pub pl_moves: &'a MoveBitboards,
-    magics: &'a MagicBitboard,
+pub struct GameState {
+    pub pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
 
     pub board: Board,
     occupation_boards: [u64; Side::N_SIDES],
@@ -31,19 +47,62 @@ pub struct GameState<'a> {
 
     pub half_move_number: usize,
     pub move_number: usize,
-    pub half_move_of_last_capture: usize,
+    pub half_move_of_last_irreversible: usize,
     pub threefold_repetition: bool,
 
-    pub pos_hash: u128,
-    position_occurance_counter: HashMap<u128, usize>,
-    zobrist_hasher: ZobristHasher
+    pub psqt_score: Score,
+
+    pub pos_hash: u64,
+    hash_stack: Vec<u64>,
+    zobrist_hasher: &'static ZobristHasher,
+    promoted_stack: Vec<u64>,
+
+    history: Vec<HistoryEntry>,
 }
 
-impl<'a> GameState<'a> {
-    const POSITION_OCCURANCE_CAPACITY: usize = 500;
+/// A played move and the state before it that can't be derived from the
+/// position after it, for taking the move back.
+#[derive(Clone, Copy)]
+struct HistoryEntry {
+    m: Move,
+    pos_hash: u64,
+    castling_right_long: [bool; Side::N_SIDES],
+    castling_right_short: [bool; Side::N_SIDES],
+    en_passant_square: Option<usize>,
+    half_move_of_last_irreversible: usize,
+}
+
+impl GameState {
+    const HASH_STACK_CAPACITY: usize = 500;
     const MOVES_RESERVE_SIZE: usize = 40;
+    const PROMOTION_RANKS: u64 = 0xff000000000000ff;
+    /// Order of piece types to generate moves for when in check, getting out
+    /// of check with king moves first.
+    const IN_CHECK_PIECE_ORDER: [Piece; Piece::N_PIECES] = [
+        Piece::King,
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ];
+
+    /// Number of half moves without captures or pawn moves after which the
+    /// a draw can be claimed by the fifty move rule.
+    pub const FIFTY_MOVE_RULE_HALF_MOVES: usize = 100;
 
-    pub fn new(pl_moves: &'a MoveBitboards, magics: &'a MagicBitboard) -> Self {
+    /// Number of occurrences of a position after which a draw can be claimed.
+    pub const THREEFOLD_REPETITIONS: usize = 3;
+
+    /// Number of half moves without captures or pawn moves after which the
+    /// game is drawn automatically, without a claim.
+    pub const SEVENTY_FIVE_MOVE_RULE_HALF_MOVES: usize = 150;
+
+    /// Number of occurrences of a position after which the game is drawn
+    /// automatically, without a claim.
+    pub const FIVEFOLD_REPETITIONS: usize = 5;
+
+    pub fn new(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard) -> Self {
         let mut gs = Self {
             pl_moves,
             magics,
@@ -53,20 +112,27 @@ impl<'a> GameState<'a> {
             comp_occupation_board: 0u64,
             half_move_number: 1,
             move_number: 1,
-            half_move_of_last_capture: 0,
+            half_move_of_last_irreversible: 1,
             threefold_repetition: false,
 
-            pos_hash: 0u128,
-            position_occurance_counter: HashMap::with_capacity(Self::POSITION_OCCURANCE_CAPACITY),
-            zobrist_hasher: ZobristHasher::new(),
+            psqt_score: Score::ZERO,
+
+            pos_hash: 0u64,
+            hash_stack: Vec::with_capacity(Self::HASH_STACK_CAPACITY),
+            zobrist_hasher: &ZOBRIST,
+            promoted_stack: Vec::new(),
+
+            history: Vec::new(),
         };
 
         gs.update_occupation_boards();
+        gs.psqt_score = psqt_score(&gs.board, &EvalParams::DEFAULT);
+        gs.init_position_hash();
 
         gs
     }
 
-    pub fn from_board(pl_moves: &'a MoveBitboards, magics: &'a MagicBitboard, board: Board) -> Self {
+    pub fn from_board(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard, board: Board) -> Self {
         let mut gs = Self {
             pl_moves,
             magics,
@@ -77,21 +143,165 @@ impl<'a> GameState<'a> {
 
             half_move_number: 1,
             move_number: 1,
-            half_move_of_last_capture: 0,
+            half_move_of_last_irreversible: 1,
             threefold_repetition: false,
 
-            pos_hash: 0u128,
-            position_occurance_counter: HashMap::with_capacity(Self::POSITION_OCCURANCE_CAPACITY),
-            zobrist_hasher: ZobristHasher::new(),
+            psqt_score: Score::ZERO,
+
+            pos_hash: 0u64,
+            hash_stack: Vec::with_capacity(Self::HASH_STACK_CAPACITY),
+            zobrist_hasher: &ZOBRIST,
+            promoted_stack: Vec::new(),
+
+            history: Vec::new(),
         };
 
         gs.update_occupation_boards();
+        gs.clear_unusable_en_passant_square();
+        gs.psqt_score = psqt_score(&gs.board, &EvalParams::DEFAULT);
+        gs.init_position_hash();
 
         gs
     }
 
+    /// Start a game of `variant` from the starting position.
+    pub fn with_variant(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard, variant: Variant) -> Self {
+        Self::from_board(pl_moves, magics, variant.rules().start_board())
+    }
+
+    /// Set up a game from a position in Forsyth-Edwards Notation. The
+    /// halfmove clock and fullmove number are optional. The remaining checks
+    /// of three-check follow the en passant square, e.g. `3+3`.
+    ///
+    /// * `fen`: Position in Forsyth-Edwards Notation
+    pub fn from_fen(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard, fen: &str) -> Result<Self, Error> {
+        let board = Board::from_fen(fen)?;
+        let side_to_move = board.side_to_move;
+        let mut gs = Self::from_board(pl_moves, magics, board);
+
+        let n_board_fields = gs.rules().n_fen_board_fields();
+        let mut counters = fen.split_whitespace().skip(n_board_fields);
+        let half_move_clock: usize = match counters.next() {
+            Some(clock) => clock.parse().map_err(|_| Error::InvalidFen(format!("invalid halfmove clock '{}'", clock)))?,
+            None => 0,
+        };
+        let move_number: usize = match counters.next() {
+            Some(number) => number.parse().map_err(|_| Error::InvalidFen(format!("invalid fullmove number '{}'", number)))?,
+            None => 1,
+        };
+
+        // The first half move is 1, and black's half moves are even
+        gs.move_number = move_number.max(1);
+        gs.half_move_number = 2*gs.move_number - 1 + side_to_move as usize;
+        gs.half_move_of_last_irreversible = gs.half_move_number.saturating_sub(half_move_clock);
+
+        Ok(gs)
+    }
+
+    /// The position in Forsyth-Edwards Notation, including the halfmove clock
+    /// and fullmove number.
+    pub fn to_fen(&self) -> String {
+        format!("{} {} {}", self.board.to_fen(), self.half_move_clock(), self.move_number)
+    }
+
+    /// A copy of the position for searching it on its own, e.g. on another
+    /// thread. Unlike `clone` it leaves out the moves to take back and the
+    /// hashes of positions that can't repeat anymore, so it stays a few
+    /// hundred bytes however long the game is.
+    pub fn snapshot(&self) -> Self {
+        let n_reversible = self.half_move_clock().min(self.hash_stack.len().saturating_sub(1));
+        Self {
+            board: self.board.clone(),
+            hash_stack: self.hash_stack[self.hash_stack.len().saturating_sub(n_reversible + 1)..].to_vec(),
+            promoted_stack: Vec::new(),
+            history: Vec::new(),
+            ..*self
+        }
+    }
+
+    /// Replace the position with `board`, keeping the move counters. The
+    /// repetition history is reset, for evaluating unrelated positions with
+    /// the same game (e.g. when tuning the evaluation).
+    ///
+    /// * `board`: New position
+    pub fn set_board(&mut self, board: Board) {
+        self.board = board;
+        self.update_occupation_boards();
+        self.clear_unusable_en_passant_square();
+        self.psqt_score = psqt_score(&self.board, &EvalParams::DEFAULT);
+
+        self.hash_stack.clear();
+        self.promoted_stack.clear();
+        self.threefold_repetition = false;
+        self.history.clear();
+        self.init_position_hash();
+    }
+
+    /// Play `moves` in coordinate notation (e.g. `e2e4`, `e1g1` or `e7e8q`)
+    /// from the current position, as in the UCI `position ... moves ...`
+    /// command. Repetitions and the halfmove clock are tracked as for any
+    /// other move. Nothing is played if one of the moves isn't legal.
+    ///
+    /// * `moves`: Moves in coordinate notation
+    pub fn apply_moves(&mut self, moves: &[&str]) -> Result<(), Error> {
+        let mut game = self.clone();
+        for move_str in moves {
+            let m = game.parse_move(move_str)?;
+            game.play_move(&m);
+        }
+
+        *self = game;
+        Ok(())
+    }
+
+    /// The legal move of a move in coordinate notation, e.g. `e2e4`, `e1g1`,
+    /// `e7e8q` or the crazyhouse drop `N@f3`.
+    ///
+    /// * `move_str`: Move in coordinate notation
+    pub fn parse_move(&self, move_str: &str) -> Result<Move, Error> {
+        let invalid = || Error::InvalidMove(move_str.to_owned());
+        if !move_str.is_ascii() || !(4..=5).contains(&move_str.len()) {
+            return Err(invalid());
+        }
+
+        if let Some((piece, square)) = move_str.split_once('@') {
+            let piece = piece.chars().next().and_then(Piece::from_fen_char).ok_or_else(invalid)?.0;
+            let to_square = Board::square_from_algebraic(square).ok_or_else(invalid)?;
+            let (legal_moves, _) = self.get_legal_moves();
+            return legal_moves.into_iter()
+                .find(|m| m.move_type == MoveType::Drop && m.piece == piece && m.to_square == to_square)
+                .ok_or_else(|| Error::IllegalMove(move_str.to_owned()));
+        }
+
+        let from_square = Board::square_from_algebraic(&move_str[0..2]).ok_or_else(invalid)?;
+        let to_square = Board::square_from_algebraic(&move_str[2..4]).ok_or_else(invalid)?;
+        let promotion_piece = match move_str[4..].chars().next() {
+            Some(c) => match Piece::from_fen_char(c) {
+                Some((piece, _)) if Piece::PROMOTION_PIECES.contains(&piece) => Some(piece),
+                _ => return Err(invalid()),
+            },
+            None => None,
+        };
+
+        let (legal_moves, _) = self.get_legal_moves();
+        legal_moves.into_iter()
+            .find(|m| {
+                let m_promotion_piece = match m.move_type {
+                    MoveType::Promotion(piece) | MoveType::CapturePromotion(_, piece) => Some(piece),
+                    _ => None,
+                };
+                m.from_square == from_square && m.to_square == to_square && m_promotion_piece == promotion_piece
+            })
+            .ok_or_else(|| Error::IllegalMove(move_str.to_owned()))
+    }
+
+    fn init_position_hash(&mut self) {
+        self.pos_hash = self.zobrist_hasher.hash(&self.board);
+        self.hash_stack.push(self.pos_hash);
+    }
+
     pub fn make_move(&mut self, m: &Move) -> (Option<MoveResult>, Vec<Move>) {
-        self.update_board_with_move(m);
+        self.play_move(m);
 
         // Moves for other side, to be returned and passed to caller of this function
         // after checking the result of the previous move (e.g. check, checkmate, draw)
@@ -101,32 +311,124 @@ impl<'a> GameState<'a> {
         (move_result, legal_moves_opposite)
     }
 
+    /// Take back the last move played with `make_move` or `apply_moves`.
+    /// Returns the move taken back, or `None` if no moves were played.
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let entry = self.history.pop()?;
+        self.update_board_undo_move(
+            &entry.m,
+            entry.pos_hash,
+            &entry.castling_right_long,
+            &entry.castling_right_short,
+            entry.en_passant_square,
+            entry.half_move_of_last_irreversible,
+        );
+
+        Some(entry.m)
+    }
+
+    /// Number of moves that can be taken back with `undo_move`.
+    pub fn n_played_moves(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Moves that can be taken back with `undo_move`, in the order they were
+    /// played.
+    pub fn played_moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.history.iter().map(|entry| entry.m)
+    }
+
+    /// Pieces `side` captured in the moves that can be taken back with
+    /// `undo_move`, the most valuable first.
+    pub fn captured_pieces(&self, side: Side) -> Vec<Piece> {
+        let mut captured: Vec<Piece> = self.played_moves()
+            .filter(|m| m.side == side)
+            .filter_map(|m| match m.move_type {
+                MoveType::Capture(piece) | MoveType::CapturePromotion(piece, _) | MoveType::EnPassantCapture(piece) => Some(piece),
+                _ => None,
+            })
+            .collect();
+        captured.sort_by_key(|&piece| std::cmp::Reverse(piece as usize));
+        captured
+    }
+
+    /// The moves that can be taken back with `undo_move` in algebraic
+    /// notation, numbered in pairs, e.g. `1. e4 e5 2. Nf3`. A game starting
+    /// with black to move starts with `1. ..`.
+    pub fn move_history(&self) -> String {
+        self.move_history_with(|_| "")
+    }
+
+    /// Like `move_history`, with `suffix(i)` after the `i`th move that can be
+    /// taken back, e.g. its annotation.
+    pub fn move_history_with<'a>(&self, suffix: impl Fn(usize) -> &'a str) -> String {
+        let mut game = self.clone();
+        while game.undo_move().is_some() {}
+
+        let mut moves = Vec::with_capacity(self.history.len());
+        for (i, m) in self.played_moves().enumerate() {
+            let (legal_moves, _) = game.get_legal_moves();
+            let algebraic = format!("{}{}", m.to_algebraic_with_state(&legal_moves), suffix(i));
+            if game.board.side_to_move == Side::White {
+                moves.push(format!("{}. {}", game.move_number, algebraic));
+            } else if moves.is_empty() {
+                moves.push(format!("{}. ..{}", game.move_number, algebraic));
+            } else {
+                moves.push(algebraic);
+            }
+            game.update_board_with_move(&m);
+        }
+
+        moves.join(" ")
+    }
+
+    /// Make a move and remember how to take it back.
+    fn play_move(&mut self, m: &Move) {
+        self.history.push(HistoryEntry {
+            m: *m,
+            pos_hash: self.pos_hash,
+            castling_right_long: self.board.castling_right_long,
+            castling_right_short: self.board.castling_right_short,
+            en_passant_square: self.board.en_passant_square,
+            half_move_of_last_irreversible: self.half_move_of_last_irreversible,
+        });
+        self.update_board_with_move(m);
+    }
+
     pub fn update_board_with_move(&mut self, m: &Move) {
+        let state_hash_before = self.zobrist_hasher.state_hash(&self.board);
+        self.promoted_stack.push(self.board.promoted);
         self.board.make_move(m);
 
-        self.update_occupation_boards();
+        self.toggle_occupation_boards(m);
+
+        self.psqt_score += psqt_score_delta(m);
 
         // Track move number
         self.half_move_number += 1;
-        if self.half_move_number % 2 != 0 {
+        if !self.half_move_number.is_multiple_of(2) {
             self.move_number += 1;
         }
 
-        // Track last capture for fifty move rule
-        if m.is_capture() {
-            self.half_move_of_last_capture = self.half_move_number;
+        // Track last capture or pawn move for fifty move rule
+        if m.is_capture() || m.piece == Piece::Pawn {
+            self.half_move_of_last_irreversible = self.half_move_number;
         }
 
         // Other side to move
         self.board.side_to_move = self.board.side_to_move.opposite();
+        self.clear_unusable_en_passant_square();
 
-        // Update position occurance counter to track three-fold repetition
-        self.pos_hash = self.zobrist_hasher.hash(&self.board);
-        let n_occurances = self.position_occurance_counter.entry(self.pos_hash)
-            .and_modify(|c| *c += 1)
-            .or_insert(1);
+        self.rules().after_move(self, m);
 
-        if *n_occurances == 3 {
+        // Push the new position's hash to track three-fold repetition
+        self.pos_hash ^= self.zobrist_hasher.move_hash(m)
+            ^ state_hash_before
+            ^ self.zobrist_hasher.state_hash(&self.board);
+        debug_assert_eq!(self.pos_hash, self.zobrist_hasher.hash(&self.board));
+        self.hash_stack.push(self.pos_hash);
+
+        if self.repetitions() == Self::THREEFOLD_REPETITIONS {
             self.threefold_repetition = true;
         }
     }
@@ -134,56 +436,106 @@ impl<'a> GameState<'a> {
     pub fn update_board_undo_move(
         &mut self,
         m: &Move,
-        pos_hash: u128,
+        pos_hash: u64,
         castling_right_long: &[bool; Side::N_SIDES],
         castling_right_short: &[bool; Side::N_SIDES],
-        half_move_of_last_capture: usize
+        en_passant_square: Option<usize>,
+        half_move_of_last_irreversible: usize
     ) {
-        // Recalculate hash for previous position after decreasing position counter
-        // for the position resulting from the played move
-        self.position_occurance_counter.entry(pos_hash)
-            .and_modify(|c| *c -= 1 );
+        self.rules().before_undo(self, m);
+
+        // Restore hash for previous position after popping the hash of the
+        // position resulting from the played move
+        self.hash_stack.pop();
 
         self.threefold_repetition = false;
 
-        self.board.undo_move(m, castling_right_long, castling_right_short);
+        let promoted = self.promoted_stack.pop().unwrap_or(0);
+        self.board.undo_move(m, castling_right_long, castling_right_short, en_passant_square, promoted);
 
-        self.update_occupation_boards();
+        self.toggle_occupation_boards(m);
 
-        self.pos_hash = self.zobrist_hasher.hash(&self.board);
+        self.psqt_score -= psqt_score_delta(m);
+
+        self.pos_hash = pos_hash;
 
         // Restore move number
         self.half_move_number -= 1;
-        if self.half_move_number % 2 == 0 {
+        if self.half_move_number.is_multiple_of(2) {
             self.move_number -= 1;
         }
 
-        // Restore last capture for fifty move rule
-        self.half_move_of_last_capture = half_move_of_last_capture;
+        // Restore last capture or pawn move for fifty move rule
+        self.half_move_of_last_irreversible = half_move_of_last_irreversible;
+    }
+
+    /// Number of times the current position occurred in the game, counting
+    /// this one.
+    pub fn repetitions(&self) -> usize {
+        // Positions before the last capture or pawn move can't come back, and
+        // only every other one has the same side to move
+        let n_reversible = self.half_move_clock().min(self.hash_stack.len().saturating_sub(1));
+        self.hash_stack.iter()
+            .rev()
+            .take(n_reversible + 1)
+            .step_by(2)
+            .filter(|&&hash| hash == self.pos_hash)
+            .count()
+    }
+
+    /// Number of half moves since the last capture or pawn move.
+    pub fn half_move_clock(&self) -> usize {
+        self.half_move_number - self.half_move_of_last_irreversible
+    }
+
+    /// Pass the turn to the other side without moving, for null move pruning
+    /// in the search. Returns the en passant square to restore with
+    /// `undo_null_move`.
+    pub fn make_null_move(&mut self) -> Option<usize> {
+        let en_passant_square = self.board.en_passant_square;
+        let state_hash_before = self.zobrist_hasher.state_hash(&self.board);
+        self.board.en_passant_square = None;
+        self.board.side_to_move = self.board.side_to_move.opposite();
+        self.pos_hash ^= self.zobrist_hasher.null_move_hash()
+            ^ state_hash_before
+            ^ self.zobrist_hasher.state_hash(&self.board);
+        debug_assert_eq!(self.pos_hash, self.zobrist_hasher.hash(&self.board));
+        self.hash_stack.push(self.pos_hash);
+
+        en_passant_square
     }
 
-    pub fn get_move_result(&self, legal_moves_opposite: &Vec<Move>, in_check: bool) -> Option<MoveResult> {
-        let has_legal_moves = legal_moves_opposite.len() != 0;
+    pub fn undo_null_move(&mut self, pos_hash: u64, en_passant_square: Option<usize>) {
+        self.hash_stack.pop();
+        self.board.side_to_move = self.board.side_to_move.opposite();
+        self.board.en_passant_square = en_passant_square;
+        self.pos_hash = pos_hash;
+    }
 
-        let moves_since_last_capture =
-            (self.half_move_number - self.half_move_of_last_capture) / 2; // floor division
+    pub fn get_move_result(&self, legal_moves_opposite: &[Move], in_check: bool) -> Option<MoveResult> {
+        self.move_result(!legal_moves_opposite.is_empty(), in_check)
+    }
 
+    /// Like `get_move_result`, for when only whether the side to move has
+    /// any legal move is known, e.g. from `has_legal_moves`.
+    pub fn move_result(&self, has_legal_moves: bool, in_check: bool) -> Option<MoveResult> {
         // TODO: check for draw by:
         // - insufficient material:
         //   - king vs king
         //   - king and bishop vs king
         //   - king and knight vs king
         //   - king and bishop vs king and bishop (same color bishop)
-        if moves_since_last_capture == 50 {
-            Some(MoveResult::Draw(DrawReason::FiftyMoveRule))
-        } else if self.threefold_repetition {
-            Some(MoveResult::Draw(DrawReason::ThreeFoldRepetition))
+        if let Some(result) = self.variant_result() {
+            Some(result)
+        } else if in_check && !has_legal_moves {
+            // Checkmate on the last move before the seventy-five move rule still counts
+            Some(MoveResult::Checkmate)
+        } else if self.half_move_clock() >= Self::SEVENTY_FIVE_MOVE_RULE_HALF_MOVES {
+            Some(MoveResult::Draw(DrawReason::SeventyFiveMoveRule))
+        } else if self.repetitions() >= Self::FIVEFOLD_REPETITIONS {
+            Some(MoveResult::Draw(DrawReason::FiveFoldRepetition))
         } else if in_check {
-            if !has_legal_moves {
-                Some(MoveResult::Checkmate)
-            } else {
-                Some(MoveResult::Check)
-            }
+            Some(MoveResult::Check)
         } else if !has_legal_moves {
             Some(MoveResult::Draw(DrawReason::Stalemate))
         } else {
@@ -191,139 +543,352 @@ impl<'a> GameState<'a> {
         }
     }
 
+    /// The draw that can be claimed in the current position, by the fifty
+    /// move rule or threefold repetition. Unlike the seventy-five move rule
+    /// and fivefold repetition, these don't end the game by themselves.
+    pub fn claimable_draw(&self) -> Option<DrawReason> {
+        if self.half_move_clock() >= Self::FIFTY_MOVE_RULE_HALF_MOVES {
+            Some(DrawReason::FiftyMoveRule)
+        } else if self.repetitions() >= Self::THREEFOLD_REPETITIONS {
+            Some(DrawReason::ThreeFoldRepetition)
+        } else {
+            None
+        }
+    }
+
+    /// The rules of the variant being played.
+    #[inline]
+    pub fn rules(&self) -> &'static dyn Rules {
+        self.board.variant.rules()
+    }
+
+    /// The result of the game by the variant's own rules, e.g. the third
+    /// check in three-check, which the side to move lost.
+    #[inline]
+    pub fn variant_result(&self) -> Option<MoveResult> {
+        self.rules().result(self)
+    }
+
+    /// Whether the side to move is in check.
+    pub(crate) fn in_check(&self) -> bool {
+        let side = self.board.side_to_move;
+        let king_square = self.board[(Piece::King, side)].trailing_zeros() as usize;
+        self.is_square_attacked(king_square, side.opposite())
+    }
+
     /// Get a list of legal moves for the side who's to play.
     pub fn get_legal_moves(&self) -> (Vec<Move>, bool) {
-        let mut move_list: Vec<Move> = vec![];
-        move_list.reserve(Self::MOVES_RESERVE_SIZE);
+        let mut move_list: Vec<Move> = Vec::with_capacity(Self::MOVES_RESERVE_SIZE);
+        let in_check = self.legal_moves_into(self.legal_move_masks(None), &mut move_list);
 
-        // Get information about enemy pieces to determine checks and pins
-        let (enemy_attack_bb, checkers, pin_masks) = self.enemy_attacks();
-        let n_checkers = checkers.len();
-        let in_check = n_checkers > 0;
+        (move_list, in_check)
+    }
 
-        if !in_check {
-            // Not in check, generate moves as usual
-            for piece in Piece::VALUES {
-                self.get_legal_moves_for_piece(piece, &enemy_attack_bb, &pin_masks, &mut move_list);
+    /// Like `get_legal_moves`, but write the moves into `move_list` (which is
+    /// cleared first) and only return whether the side to move is in check,
+    /// so a buffer can be reused without allocating. The enemy attacks are
+    /// taken from `info` instead of being generated again.
+    ///
+    /// * `info`: Attacks and pins of the position, see `position_info`
+    /// * `move_list`: Moves vector to write the moves to
+    pub fn generate_legal_moves(&self, info: &PositionInfo, move_list: &mut Vec<Move>) -> bool {
+        self.legal_moves_into(self.legal_move_masks(Some(info)), move_list)
+    }
+
+    fn legal_moves_into(&self, masks: LegalMoveMasks, move_list: &mut Vec<Move>) -> bool {
+        move_list.clear();
+
+        for piece in masks.piece_order {
+            self.get_legal_moves_for_piece(piece, &masks, move_list);
+        }
+
+        // Castling only legal when not in check
+        if !masks.in_check {
+            move_list.extend(self.get_castling_moves(masks.enemy_attack_bb).into_iter().flatten());
+        }
+
+        self.get_drop_moves(&masks, move_list);
+
+        masks.in_check
+    }
+
+    /// Get a list of the legal tactical moves for the side who's to play:
+    /// captures, promotions and, if `checks` is set, moves that give check.
+    /// When in check all legal moves are generated, as they're the only ways
+    /// out of it.
+    ///
+    /// * `checks`: Whether to include quiet moves that give check
+    pub fn get_tactical_moves(&self, checks: bool) -> (Vec<Move>, bool) {
+        let mut move_list: Vec<Move> = Vec::with_capacity(Self::MOVES_RESERVE_SIZE);
+        let in_check = self.tactical_moves_into(checks, self.legal_move_masks(None), &mut move_list);
+
+        (move_list, in_check)
+    }
+
+    /// Like `get_tactical_moves`, but write the moves into `move_list` (which
+    /// is cleared first) and only return whether the side to move is in check.
+    /// The enemy attacks are taken from `info`.
+    ///
+    /// * `checks`: Whether to include quiet moves that give check
+    /// * `info`: Attacks and pins of the position, see `position_info`
+    /// * `move_list`: Moves vector to write the moves to
+    pub fn generate_tactical_moves(&self, checks: bool, info: &PositionInfo, move_list: &mut Vec<Move>) -> bool {
+        self.tactical_moves_into(checks, self.legal_move_masks(Some(info)), move_list)
+    }
+
+    fn tactical_moves_into(&self, checks: bool, masks: LegalMoveMasks, move_list: &mut Vec<Move>) -> bool {
+        move_list.clear();
+
+        if masks.in_check {
+            for piece in masks.piece_order {
+                self.get_legal_moves_for_piece(piece, &masks, move_list);
             }
+            self.get_drop_moves(&masks, move_list);
+            return true;
+        }
 
-            // Castling only legal when not in check
-            self.get_castling_moves(enemy_attack_bb, &mut move_list);
+        let side = self.board.side_to_move;
+        let (check_squares, discovering_pieces) = if checks {
+            (self.check_squares(side), self.slider_blockers(side.opposite(), side))
         } else {
-            // In check, handle check-related moves (e.g. moving out of check,
-            // capturing checking piece, blocking rays, etc.)
-
-            if n_checkers == 1 {
-                // Single check, filter generated moves for moves that get out of check
-                // In the case of double check, only king moves are legal and we do not consider
-                // other moves
-                let (checker_piece, checker_square, checker_bb) = checkers[0];
-                let mut king_ray_mask = 0xffffffffffffffffu64;
-
-                let move_mask = if checker_piece.is_slider() {
-                    // Slider check, look for capture of checking piece, and moves that
-                    // block the checker's ray.
-
-                    // Get the ray on which the check is
-                    let (checking_ray, _) = self.get_checking_ray(checker_square);
-                    king_ray_mask = !checking_ray | checker_bb;
-                    checker_bb & checking_ray | (1 << checker_square)
-                } else {
-                    // Non-sliders, i.e. pawns and knights, just check for captures
-                    1 << checker_square
-                };
+            ([0u64; Piece::N_PIECES], 0)
+        };
 
-                self.get_legal_moves_for_piece_with_mask(
-                    Piece::King,
-                    &enemy_attack_bb,
-                    &pin_masks,
-                    &mut move_list,
-                    king_ray_mask
-                );
-                for piece in Piece::ALL_BUT_KING {
-                    self.get_legal_moves_for_piece_with_mask(
-                        piece,
-                        &enemy_attack_bb,
-                        &pin_masks,
-                        &mut move_list,
-                        move_mask,
-                    );
-                }
-            } else {
-                // Double check, only king moves
-                let mut king_ray_mask = 0u64;
-
-                for (checker_piece, checker_square, checker_bb) in checkers {
-                    if checker_piece.is_slider() {
-                        let (checking_ray, _) = self.get_checking_ray(checker_square);
-                        king_ray_mask |= checking_ray | checker_bb;
-                    }
+        for piece in Piece::VALUES {
+            let mut targets_bb = self.occupation_boards[side.opposite() as usize] | check_squares[piece as usize];
+            if piece == Piece::Pawn {
+                targets_bb |= Self::PROMOTION_RANKS
+                    | self.board.en_passant_square.map_or(0, |square| 1 << square);
+            }
+
+            let mut piece_bb = self.board[(piece, side)];
+            while piece_bb != 0 {
+                let square = piece_bb.trailing_zeros() as usize;
+
+                let mut moves_bb = self.legal_move_targets(piece, square, &masks);
+                if discovering_pieces & (1 << square) == 0 {
+                    // Pieces blocking an own slider's ray to the enemy king
+                    // can give a discovered check from any square
+                    moves_bb &= targets_bb;
                 }
-                king_ray_mask = !king_ray_mask;
-
-                self.get_legal_moves_for_piece_with_mask(
-                    Piece::King,
-                    &enemy_attack_bb,
-                    &pin_masks,
-                    &mut move_list,
-                    king_ray_mask
-                );
+                self.generate_moves_from_bb(piece, square, moves_bb, move_list);
+
+                // clear square bit
+                piece_bb &= piece_bb - (1 << square);
             }
         }
 
-        (move_list, in_check)
+        if checks {
+            // Not every discovering move gives check, and castling can give
+            // check with the rook
+            move_list.extend(self.get_castling_moves(masks.enemy_attack_bb).into_iter().flatten());
+            move_list.retain(|m| m.is_capture() || m.is_promotion() || self.gives_check(m));
+        }
+
+        false
     }
 
-    fn get_legal_moves_for_piece(
-        &self,
-        piece: Piece,
-        enemy_attack_bb: &u64,
-        pins: &[u64; N_SQUARES],
-        move_list: &mut Vec<Move>,
-    ) {
-        self.get_legal_moves_for_piece_with_mask(
-            piece,
+    /// Whether the side who's to play has any legal move. Stops at the first
+    /// legal move found, so it's cheaper than `get_legal_moves` for telling
+    /// checkmate and stalemate apart from other positions.
+    pub fn has_legal_moves(&self) -> bool {
+        self.legal_moves_iter().next().is_some()
+    }
+
+    /// Get an iterator over the legal moves for the side who's to play, which
+    /// generates them on demand piece by piece. Cheaper than `get_legal_moves`
+    /// when only the first few moves or the number of moves is needed.
+    pub fn legal_moves_iter(&self) -> LegalMoves<'_> {
+        LegalMoves {
+            game: self,
+            masks: self.legal_move_masks(None),
+            next_piece: 0,
+            piece: Piece::Pawn,
+            piece_bb: 0,
+            square: 0,
+            moves_bb: 0,
+            promotion: None,
+            castling_moves: None,
+            next_drop: 0,
+            drop_piece: Piece::Pawn,
+            drops_bb: 0,
+        }
+    }
+
+    /// Determine the enemy attacks, pins and the squares the side to move's
+    /// pieces are restricted to by checks.
+    ///
+    /// * `info`: Attacks of the position if they're known, which also tells
+    ///   whether the king is in check without looking for checkers
+    fn legal_move_masks(&self, info: Option<&PositionInfo>) -> LegalMoveMasks {
+        let side = self.board.side_to_move;
+        let king_square = self.board[(Piece::King, side)].trailing_zeros() as usize;
+        let enemy_attack_bb = info.map(|info| info.attacks[side.opposite() as usize]);
+
+        // Some test positions leave out kings
+        let checkers = if enemy_attack_bb.is_some_and(|attacks| attacks & self.board[(Piece::King, side)] == 0) {
+            0
+        } else if king_square < N_SQUARES {
+            self.attackers_to(king_square, self.comp_occupation_board)
+                & self.occupation_boards[side.opposite() as usize]
+        } else {
+            0
+        };
+
+        if checkers != 0 {
+            return self.evasion_masks(king_square, checkers);
+        }
+
+        let (enemy_attack_bb, pin_masks) = self.enemy_attacks(enemy_attack_bb);
+        LegalMoveMasks {
+            enemy_attack_bb,
+            pin_masks,
+            mask: 0xffffffffffffffff,
+            in_check: false,
+            piece_order: Piece::VALUES,
+        }
+    }
+
+    /// Determine the masks for getting out of check. The king has to move to
+    /// a square the enemy doesn't attack, other pieces have to capture the
+    /// checking piece or block its ray. Pinned pieces can do neither, so only
+    /// the pins on the side to move's pieces are needed instead of the pin
+    /// rays of every enemy slider.
+    ///
+    /// * `king_square`: Square of the side to move's king
+    /// * `checkers`: Bitboard of the pieces checking the king
+    fn evasion_masks(&self, king_square: usize, checkers: u64) -> LegalMoveMasks {
+        let side = self.board.side_to_move;
+
+        // Look through the king, as it can't escape a slider's check by moving
+        // away from it along the checking ray
+        let enemy_attack_bb = self.attack_map(side.opposite(), 1 << king_square);
+
+        let mut pin_masks = [0xffffffffffffffffu64; N_SQUARES];
+        let mask = if checkers.count_ones() > 1 {
+            // Double check, only king moves
+            0
+        } else {
+            let mut pinned = self.pinned_pieces(side);
+            while pinned != 0 {
+                let square = pinned.trailing_zeros() as usize;
+                pin_masks[square] = 0;
+                pinned &= pinned - 1;
+            }
+
+            let checker_square = checkers.trailing_zeros() as usize;
+            let checker_is_slider = checkers & (
+                self.board[(Piece::Bishop, side.opposite())]
+                | self.board[(Piece::Rook, side.opposite())]
+                | self.board[(Piece::Queen, side.opposite())]
+            ) != 0;
+
+            if checker_is_slider {
+                checkers | self.squares_between(checker_square, king_square)
+            } else {
+                checkers
+            }
+        };
+
+        LegalMoveMasks {
             enemy_attack_bb,
-            pins,
-            move_list,
-            0xffffffffffffffff,
-        )
+            pin_masks,
+            mask,
+            in_check: true,
+            piece_order: Self::IN_CHECK_PIECE_ORDER,
+        }
     }
 
-    fn get_legal_moves_for_piece_with_mask(
+    fn get_legal_moves_for_piece(
         &self,
         piece: Piece,
-        enemy_attack_bb: &u64,
-        pin_masks: &[u64; N_SQUARES],
+        masks: &LegalMoveMasks,
         move_list: &mut Vec<Move>,
-        mask: u64,
     ) {
         let mut piece_bb = self.board[(piece, self.board.side_to_move)];
         while piece_bb != 0 {
             let square = piece_bb.trailing_zeros() as usize;
 
-            let mut moves_bb = if piece.is_slider() {
-                self.slider_moves(piece, square)
-            } else {
-                self.pl_moves[(piece, self.board.side_to_move, square)]
-            };
+            let moves_bb = self.legal_move_targets(piece, square, masks);
+            self.generate_moves_from_bb(piece, square, moves_bb, move_list);
 
-            if piece == Piece::Pawn {
-                self.pawn_moves(square, &mut moves_bb);
-            } else if piece == Piece::King {
-                // King cannot move into check
-                moves_bb &= !enemy_attack_bb;
+            // clear square bit
+            piece_bb &= piece_bb - (1 << square);
+        }
+    }
+
+    /// Add the legal drops of the side to move in crazyhouse to `move_list`.
+    ///
+    /// * `masks`: Enemy attacks, pins and check restrictions of the position
+    /// * `move_list`: Moves vector to push moves to
+    fn get_drop_moves(&self, masks: &LegalMoveMasks, move_list: &mut Vec<Move>) {
+        for piece in Piece::ALL_BUT_KING {
+            let mut drops_bb = self.drop_targets(piece, masks);
+            while drops_bb != 0 {
+                let square = drops_bb.trailing_zeros() as usize;
+                move_list.push(self.drop_move(piece, square));
+
+                // clear square bit
+                drops_bb &= drops_bb - 1;
             }
+        }
+    }
+
+    /// Bitboard of the squares a piece in the hand of the side to move can
+    /// be dropped on: those of the empty squares, or those blocking the
+    /// check when in check, that the variant's rules allow. Dropping a piece
+    /// can't expose the king, so pins don't matter.
+    ///
+    /// * `piece`: Piece type
+    /// * `masks`: Enemy attacks, pins and check restrictions of the position
+    fn drop_targets(&self, piece: Piece, masks: &LegalMoveMasks) -> u64 {
+        self.rules().drop_targets(self, piece, !self.comp_occupation_board & masks.mask)
+    }
 
-            moves_bb &= mask & pin_masks[square];
+    fn drop_move(&self, piece: Piece, square: usize) -> Move {
+        Move {
+            from_square: square,
+            to_square: square,
+            move_type: MoveType::Drop,
+            piece,
+            side: self.board.side_to_move,
+        }
+    }
 
-            self.remove_friendly_moves(&mut moves_bb);
+    /// Bitboard of the squares a piece can legally move to.
+    ///
+    /// * `piece`: Piece type
+    /// * `square`: Square of the piece
+    /// * `masks`: Enemy attacks, pins and check restrictions of the position
+    fn legal_move_targets(&self, piece: Piece, square: usize, masks: &LegalMoveMasks) -> u64 {
+        let mut moves_bb = if piece.is_slider() {
+            self.slider_moves(piece, square)
+        } else {
+            self.pl_moves[(piece, self.board.side_to_move, square)]
+        };
 
-            self.generate_moves_from_bb(piece, square, moves_bb, move_list);
+        if piece == Piece::Pawn {
+            self.pawn_moves(square, &mut moves_bb);
+        }
 
-            // clear square bit
-            piece_bb &= piece_bb - (1 << square);
+        if piece == Piece::King {
+            // King cannot move into check
+            moves_bb &= !masks.enemy_attack_bb;
+        } else if piece == Piece::Pawn && masks.in_check {
+            // Capturing a checking pawn en passant gets out of check as well,
+            // even though the en passant square isn't on the checker's square
+            let en_passant_bb = self.board.en_passant_square
+                .filter(|&square| masks.mask & (1 << self.en_passant_captured_square(square)) != 0)
+                .map_or(0, |square| 1 << square);
+            moves_bb &= masks.mask | en_passant_bb;
+        } else {
+            moves_bb &= masks.mask;
         }
+        moves_bb &= masks.pin_masks[square];
+
+        self.remove_friendly_moves(&mut moves_bb);
+
+        moves_bb
     }
 
     /// Generate moves from a move bitboard for a single piece on a single
@@ -343,81 +908,165 @@ impl<'a> GameState<'a> {
         while moves_bb != 0 {
             // Pop least significant 1 bits in moves bitboard to generate moves
             let target_square = moves_bb.trailing_zeros() as usize;
+            let captured_piece = self.captured_piece(target_square);
 
-            // We've removed potential "friendly captures", so any overlapping
-            // piece with the target square is an enemy's piece
-            let is_capture = self.comp_occupation_board & (1 << target_square) != 0;
-
-            // Determine the captured piece
-            let mut captured_piece = Piece::Pawn;
-            if is_capture {
-                for piece in Piece::ALL_BUT_KING {
-                    if (1 << target_square) & self.board[(piece, self.board.side_to_move.opposite())] != 0 {
-                        captured_piece = piece;
-                        break;
-                    }
+            if Self::is_promotion(piece, target_square) {
+                // Pawn move to 8th or 1st rank = multiple possible promotion moves
+                for promotion_piece in Piece::PROMOTION_PIECES {
+                    move_list.push(self.promotion_move(square, target_square, captured_piece, promotion_piece));
                 }
+            } else if let Some(m) = self.target_move(piece, square, target_square, captured_piece) {
+                move_list.push(m);
             }
 
-            let is_pawn = piece == Piece::Pawn;
-            let pawn_promotion_possible = target_square >= (BOARD_WIDTH * (BOARD_HEIGHT - 1))
-                || target_square <= (BOARD_WIDTH) - 1;
-            if is_pawn && pawn_promotion_possible  {
-                // Pawn move to 8th or 1st rank = multiple possible promotion moves
-                self.generate_promotion_moves(
-                    piece,
-                    square,
-                    target_square,
-                    is_capture,
-                    captured_piece,
-                    move_list,
-                );
-            } else {
-                let move_type = if is_pawn && target_square == self.board.en_passant_square {
-                    MoveType::EnPassantCapture(captured_piece)
-                } else if is_capture {
-                    MoveType::Capture(captured_piece)
-                } else {
-                    MoveType::Quiet
-                };
+            // clear move bit
+            moves_bb &= moves_bb - (1 << target_square);
+        }
+    }
 
-                move_list.push(Move {
-                    from_square: square,
-                    to_square: target_square,
-                    move_type,
-                    piece,
-                    side: self.board.side_to_move,
-                });
+    /// Number of moves `generate_moves_from_bb` would generate from a move
+    /// bitboard, without generating them.
+    ///
+    /// * `piece`: Piece type
+    /// * `square`: Square of the piece
+    /// * `moves_bb`: Moves bitboard
+    fn count_moves_from_bb(&self, piece: Piece, square: usize, moves_bb: u64) -> usize {
+        if piece != Piece::Pawn {
+            return moves_bb.count_ones() as usize;
+        }
+
+        let promotions_bb = moves_bb & Self::PROMOTION_RANKS;
+        let mut n_moves = (moves_bb & !promotions_bb).count_ones() as usize
+            + Piece::PROMOTION_PIECES.len()*promotions_bb.count_ones() as usize;
+        if let Some(en_passant_square) = self.board.en_passant_square {
+            if moves_bb & (1 << en_passant_square) != 0 && !self.en_passant_is_legal(square, en_passant_square) {
+                n_moves -= 1;
             }
+        }
 
-            // clear move bit
-            moves_bb &= moves_bb - (1 << target_square);
+        n_moves
+    }
+
+    /// Enemy piece on `target_square`, if any. Friendly moves have been
+    /// removed from the move bitboards, so any piece on a target square is
+    /// the enemy's.
+    fn captured_piece(&self, target_square: usize) -> Option<Piece> {
+        match self.board.piece_on(target_square) {
+            Some((piece, side)) if side != self.board.side_to_move && piece != Piece::King => Some(piece),
+            _ => None,
         }
     }
 
-    fn generate_promotion_moves(
+    fn is_promotion(piece: Piece, target_square: usize) -> bool {
+        piece == Piece::Pawn && Self::PROMOTION_RANKS & (1 << target_square) != 0
+    }
+
+    /// Move of `piece` from `square` to `target_square` that isn't a
+    /// promotion, or `None` if it's an en passant capture that leaves the
+    /// king in check.
+    ///
+    /// * `piece`: Piece type
+    /// * `square`: Square of the piece
+    /// * `target_square`: Square the piece moves to
+    /// * `captured_piece`: Enemy piece on the target square, if any
+    fn target_move(
         &self,
         piece: Piece,
         square: usize,
         target_square: usize,
-        is_capture: bool,
-        captured_piece: Piece,
-        move_list: &mut Vec<Move>,
-    ) {
-        for promotion_piece in Piece::PROMOTION_PIECES {
-            let move_type = if is_capture {
-                MoveType::CapturePromotion(captured_piece, promotion_piece)
-            } else {
-                MoveType::Promotion(promotion_piece)
-            };
-            move_list.push(Move {
-                from_square: square,
-                to_square: target_square,
-                move_type,
-                piece,
-                side: self.board.side_to_move,
-            });
-        }
+        captured_piece: Option<Piece>,
+    ) -> Option<Move> {
+        let move_type = if piece == Piece::Pawn && self.board.en_passant_square == Some(target_square) {
+            if !self.en_passant_is_legal(square, target_square) {
+                return None;
+            }
+            MoveType::EnPassantCapture(Piece::Pawn)
+        } else if let Some(captured_piece) = captured_piece {
+            MoveType::Capture(captured_piece)
+        } else {
+            MoveType::Quiet
+        };
+
+        Some(Move {
+            from_square: square,
+            to_square: target_square,
+            move_type,
+            piece,
+            side: self.board.side_to_move,
+        })
+    }
+
+    /// Whether an en passant capture doesn't leave the king in check. Pin
+    /// masks only cover the capturing pawn, but the captured pawn leaves its
+    /// square as well, which can expose the king along a rank (with both pawns
+    /// between the king and an enemy slider) or a diagonal.
+    ///
+    /// * `square`: Square of the capturing pawn
+    /// * `target_square`: En passant square
+    fn en_passant_is_legal(&self, square: usize, target_square: usize) -> bool {
+        let side = self.board.side_to_move;
+        let captured_square = self.en_passant_captured_square(target_square);
+        let occ = (self.comp_occupation_board & !(1 << square) & !(1 << captured_square)) | (1 << target_square);
+
+        let king_square = self.board[(Piece::King, side)].trailing_zeros() as usize;
+        let enemy_pieces = self.occupation_boards[side.opposite() as usize] & !(1 << captured_square);
+
+        self.attackers_to(king_square, occ) & enemy_pieces == 0
+    }
+
+    /// Clear the en passant square if the side to move can't capture en
+    /// passant, so it's only part of the position hash when it makes a
+    /// difference. Positions that differ only in an unusable en passant
+    /// square are the same position for repetitions, as in FIDE's rules.
+    fn clear_unusable_en_passant_square(&mut self) {
+        let target_square = match self.board.en_passant_square {
+            Some(target_square) => target_square,
+            None => return,
+        };
+
+        let side = self.board.side_to_move;
+        let mut capturers = self.pl_moves.pawn_capture_moves[side.opposite() as usize][target_square]
+            & self.board[(Piece::Pawn, side)];
+        while capturers != 0 {
+            let square = capturers.trailing_zeros() as usize;
+            if self.en_passant_is_legal(square, target_square) {
+                return;
+            }
+
+            // clear square bit
+            capturers &= capturers - 1;
+        }
+
+        self.board.en_passant_square = None;
+    }
+
+    /// Square of the pawn captured by the side to move en passant.
+    ///
+    /// * `target_square`: En passant square
+    fn en_passant_captured_square(&self, target_square: usize) -> usize {
+        let side = self.board.side_to_move;
+        (target_square as i64 + ((side as i64)*2 - 1)*(BOARD_WIDTH as i64)) as usize
+    }
+
+    fn promotion_move(
+        &self,
+        square: usize,
+        target_square: usize,
+        captured_piece: Option<Piece>,
+        promotion_piece: Piece,
+    ) -> Move {
+        let move_type = match captured_piece {
+            Some(captured_piece) => MoveType::CapturePromotion(captured_piece, promotion_piece),
+            None => MoveType::Promotion(promotion_piece),
+        };
+
+        Move {
+            from_square: square,
+            to_square: target_square,
+            move_type,
+            piece: Piece::Pawn,
+            side: self.board.side_to_move,
+        }
     }
 
     /// Update legal pawn moves as pawns movement and captures are different from
@@ -442,8 +1091,11 @@ impl<'a> GameState<'a> {
 
         // Check for captures
         let capture_squares = &self.pl_moves.pawn_capture_moves[self.board.side_to_move as usize][square];
-        *moves_bb |= capture_squares
-            & (self.occupation_boards[self.board.side_to_move.opposite() as usize] | (1 << self.board.en_passant_square));
+        let mut capturable = self.occupation_boards[self.board.side_to_move.opposite() as usize];
+        if let Some(en_passant_square) = self.board.en_passant_square {
+            capturable |= 1 << en_passant_square;
+        }
+        *moves_bb |= capture_squares & capturable;
     }
 
     /// Update legal slider moves, removing moves that are blocked by other pieces
@@ -452,30 +1104,125 @@ impl<'a> GameState<'a> {
     /// * `piece`: Type of piece
     /// * `square`: Square of the slider piece
     pub fn slider_moves(&self, piece: Piece, square: usize) -> u64 {
+        self.slider_attacks(piece, square, self.comp_occupation_board)
+    }
+
+    /// Squares attacked by a slider on `square`, with `occ` as the blockers
+    /// of its rays.
+    ///
+    /// * `piece`: Slider piece type
+    /// * `square`: Square of the slider
+    /// * `occ`: Occupancy bitboard to use for slider blockers
+    fn slider_attacks(&self, piece: Piece, square: usize, occ: u64) -> u64 {
         match piece {
             Piece::Rook => {
-                let blocker_mask =
-                    self.comp_occupation_board & self.pl_moves.get_piece_blocker_mask(piece, square);
-
-                self.magics.get_rook_moves(square, blocker_mask)
+                self.magics.get_rook_moves(square, occ & self.pl_moves.get_piece_blocker_mask(piece, square))
             }
             Piece::Bishop => {
-                let blocker_mask =
-                    self.comp_occupation_board & self.pl_moves.get_piece_blocker_mask(piece, square);
-                self.magics.get_bishop_moves(square, blocker_mask)
+                self.magics.get_bishop_moves(square, occ & self.pl_moves.get_piece_blocker_mask(piece, square))
             },
             Piece::Queen => {
-                let blocker_mask_bishop =
-                    self.comp_occupation_board & self.pl_moves.get_piece_blocker_mask(Piece::Bishop, square);
-                let blocker_mask_rook =
-                    self.comp_occupation_board & self.pl_moves.get_piece_blocker_mask(Piece::Rook, square);
-                self.magics.get_rook_moves(square, blocker_mask_rook)
-                    | self.magics.get_bishop_moves(square, blocker_mask_bishop)
+                self.magics.get_rook_moves(square, occ & self.pl_moves.get_piece_blocker_mask(Piece::Rook, square))
+                    | self.magics.get_bishop_moves(square, occ & self.pl_moves.get_piece_blocker_mask(Piece::Bishop, square))
             }
             _ => unreachable!(),
         }
     }
 
+    /// Attacks and pins of both sides, to share between the move generation
+    /// and the evaluation of the position.
+    pub fn position_info(&self) -> PositionInfo {
+        PositionInfo {
+            attacks: [self.attacks(Side::White), self.attacks(Side::Black)],
+            pinned: [self.pinned_pieces(Side::White), self.pinned_pieces(Side::Black)],
+        }
+    }
+
+    /// Bitboards of the position as the legal move generation of the side to
+    /// move sees them, for debugging it: the pieces, the occupancy, the
+    /// squares the enemy attacks, the squares that get out of check and the
+    /// squares each pinned piece can move to.
+    pub fn bitboards(&self) -> Bitboards {
+        let side = self.board.side_to_move;
+        let masks = self.legal_move_masks(None);
+
+        let mut boards = Vec::new();
+        for side in Side::VALUES {
+            for piece in Piece::VALUES {
+                boards.push((format!("{:?} {:?}", side, piece), self.board[(piece, side)]));
+            }
+        }
+        boards.push(("White occupancy".to_owned(), self.occupation_boards[Side::White as usize]));
+        boards.push(("Black occupancy".to_owned(), self.occupation_boards[Side::Black as usize]));
+        boards.push(("Occupancy".to_owned(), self.comp_occupation_board));
+        boards.push((format!("Attacked by {:?}", side.opposite()), masks.enemy_attack_bb));
+        if masks.in_check {
+            boards.push(("Check evasions".to_owned(), masks.mask));
+        }
+
+        // Squares without a pinned piece can move anywhere
+        let mut pieces = self.occupation_boards[side as usize];
+        while pieces != 0 {
+            let square = pieces.trailing_zeros() as usize;
+            if masks.pin_masks[square] != u64::MAX {
+                boards.push((format!("Pin of {}", Board::square_to_algebraic(square)), masks.pin_masks[square]));
+            }
+
+            // clear square bit
+            pieces &= pieces - 1;
+        }
+
+        Bitboards { boards }
+    }
+
+    /// Generate a bitboard of all squares attacked by the pieces of `side`.
+    ///
+    /// * `side`: Side whose attacks to generate
+    pub fn attacks(&self, side: Side) -> u64 {
+        self.attack_map(side, 0)
+    }
+
+    /// Generate a bitboard of all squares attacked by the pieces of `side`,
+    /// with the pieces on `exclude` removed from the blockers of slider rays.
+    ///
+    /// * `side`: Side whose attacks to generate
+    /// * `exclude`: Bitboard of pieces sliders look through
+    fn attack_map(&self, side: Side, exclude: u64) -> u64 {
+        let occ = self.comp_occupation_board & !exclude;
+        let mut attacks_bb = 0u64;
+
+        for piece in Piece::VALUES {
+            let mut piece_bb = self.board[(piece, side)];
+            while piece_bb != 0 {
+                let square = piece_bb.trailing_zeros() as usize;
+
+                attacks_bb |= self.piece_attacks(piece, side, square, occ);
+
+                // clear square bit
+                piece_bb &= piece_bb - 1;
+            }
+        }
+
+        attacks_bb
+    }
+
+    /// Squares attacked by a piece of `side` on `square`, with `occ` as the
+    /// blockers of slider rays.
+    ///
+    /// * `piece`: Type of the piece
+    /// * `side`: Side of the piece
+    /// * `square`: Square of the piece
+    /// * `occ`: Occupancy bitboard to use for slider blockers
+    pub(crate) fn piece_attacks(&self, piece: Piece, side: Side, square: usize, occ: u64) -> u64 {
+        if piece.is_slider() {
+            self.slider_attacks(piece, square, occ)
+        } else if piece == Piece::Pawn {
+            self.pl_moves.pawn_capture_moves[side as usize][square]
+        } else {
+            self.pl_moves[(piece, side, square)]
+        }
+    }
+
     /// This function removes moves that are illegal because the target square
     /// is occupied by a friendly piece. Aditionally, in the case of pawns
     /// (who have capture moves different from movement moves), squares occupied
@@ -487,155 +1234,184 @@ impl<'a> GameState<'a> {
     }
 
     /// Generate a bitboard with all attacking trajectories of the opponent's
-    /// pieces and find all pieces that are currently checking the king as well as
-    /// determining pinned pieces and the ray along which they're pinned.
-    fn enemy_attacks(&self) -> (u64, Vec<(Piece, usize, u64)>, [u64; N_SQUARES]) {
-        let mut checkers: Vec<(Piece, usize, u64)> = vec![];
-        let mut pin_masks = [0xffffffffffffffffu64; N_SQUARES];
-        let mut attacks_bb = 0u64;
-        let king_pos = self.board[(Piece::King, self.board.side_to_move)];
-        let opposite_side = self.board.side_to_move.opposite();
+    /// pieces when not in check, determining pinned pieces and the ray along
+    /// which they're pinned.
+    ///
+    /// * `attacks_bb`: The opponent's attacks if they're already known
+    fn enemy_attacks(&self, attacks_bb: Option<u64>) -> (u64, [u64; N_SQUARES]) {
+        let side = self.board.side_to_move;
+        let enemy = side.opposite();
+        let attacks_bb = attacks_bb.unwrap_or_else(|| self.attacks(enemy));
 
-        for piece in Piece::VALUES {
-            let mut piece_bb = self.board[(piece, opposite_side)];
-            while piece_bb != 0 {
-                let square = piece_bb.trailing_zeros() as usize;
-                let is_slider = piece.is_slider();
-
-                let moves_bb = if is_slider {
-                    self.slider_moves(piece, square)
-                } else if piece == Piece::Pawn {
-                    self.pl_moves.pawn_capture_moves[opposite_side as usize][square]
-                } else {
-                    self.pl_moves[(piece, opposite_side, square)]
-                };
+        let mut pin_masks = [0xffffffffffffffffu64; N_SQUARES];
+        let king_square = self.board[(Piece::King, side)].trailing_zeros() as usize;
+        if king_square >= N_SQUARES {
+            // Some test positions leave out kings
+            return (attacks_bb, pin_masks);
+        }
 
-                // We do not care about friendly moves here, so no need to filter
+        // Enemy sliders seen from the king through exactly one of our pieces
+        let own_pieces = self.occupation_boards[side as usize];
+        let mut pinners = self.magics.xray_rook_attacks(king_square, self.comp_occupation_board, own_pieces)
+                & (self.board[(Piece::Rook, enemy)] | self.board[(Piece::Queen, enemy)])
+            | self.magics.xray_bishop_attacks(king_square, self.comp_occupation_board, own_pieces)
+                & (self.board[(Piece::Bishop, enemy)] | self.board[(Piece::Queen, enemy)]);
 
-                if moves_bb & king_pos != 0 {
-                    checkers.push((piece, square, moves_bb));
-                } else if is_slider {
-                    // Piece isn't checking king, check if it's pinning pieces
-                    self.enemy_attacks_piece_pins(piece, square, king_pos, &mut pin_masks);
-                }
+        while pinners != 0 {
+            let pinner_square = pinners.trailing_zeros() as usize;
 
-                attacks_bb |= moves_bb;
+            // Pinned piece can only move along the ray or capture the pinner
+            let pin_ray = self.squares_between(pinner_square, king_square);
+            let pinned_square = (pin_ray & own_pieces).trailing_zeros() as usize;
+            pin_masks[pinned_square] = pin_ray | (1 << pinner_square);
 
-                // clear square bit
-                piece_bb &= piece_bb - (1 << square);
-            }
+            // clear square bit
+            pinners &= pinners - 1;
         }
 
-        (attacks_bb, checkers, pin_masks)
+        (attacks_bb, pin_masks)
     }
 
-    fn enemy_attacks_piece_pins(
-        &self,
-        piece: Piece,
-        square: usize,
-        king_pos: u64,
-        pin_masks: &mut [u64; N_SQUARES],
-    ) {
-        let comp_ray = self.pl_moves.get_comp_rays(piece)[square];
-        let king_aligned = comp_ray & king_pos;
-
-        if king_aligned != 0 {
-            // Slider is aligned with king but not checking ---
-            // check and track pinned pieces
-            let (pin_ray, ray_direction) = self.get_checking_ray(square);
-
-            let king_square = king_pos.trailing_zeros() as usize;
-            let enemy_piece_king_ray = pin_ray
-                & !(self.pl_moves.rays[ray_direction as usize][king_square]);
-            let own_blocking_pieces = enemy_piece_king_ray
-              & self.occupation_boards[self.board.side_to_move.opposite() as usize];
-
-            if own_blocking_pieces == 0 {
-                // No friendly pieces in the way of a pin, check for
-                // number of enemy pieces on ray. When there's only a
-                // single enemy piece on the ray, that piece is pinned.
-                let enemy_blocking_pieces = enemy_piece_king_ray &
-                  & self.occupation_boards[self.board.side_to_move as usize]
-                  & !king_pos;
-                let n_blockers = enemy_blocking_pieces.count_ones();
-
-                if n_blockers == 1 {
-                    // Single piece between enemy slider and king, it's pinned
-                    let pinned_square = enemy_blocking_pieces.trailing_zeros() as usize;
-                    pin_masks[pinned_square] = pin_ray | (1 << square);
-                }
-            }
-        }
+    /// Squares strictly between `from` and `to`, which must be aligned on a
+    /// rank, file or diagonal.
+    fn squares_between(&self, from: usize, to: usize) -> u64 {
+        let direction = Self::ray_direction(from, to) as usize;
+        self.pl_moves.rays[direction][from] & !self.pl_moves.rays[direction][to] & !(1 << to)
     }
 
-    fn get_checking_ray(&self, checker_square: usize) -> (u64, RayDirection) {
-        let king_square = self.board[(Piece::King, self.board.side_to_move)].trailing_zeros() as usize;
-        let king_file = file(king_square);
-        let king_rank = rank(king_square);
+    /// Direction of the ray from square `from` towards square `to`, which
+    /// must be aligned on a rank, file or diagonal.
+    fn ray_direction(from: usize, to: usize) -> RayDirection {
+        let to_file = file(to);
+        let to_rank = rank(to);
 
-        let checker_file = file(checker_square);
-        let checker_rank = rank(checker_square);
+        let from_file = file(from);
+        let from_rank = rank(from);
 
-        let direction = if king_file == checker_file {
-            if king_rank < checker_rank {
+        if to_file == from_file {
+            if to_rank < from_rank {
                 RayDirection::South
             } else {
                 RayDirection::North
             }
-        } else if king_file > checker_file {
-            if king_rank == checker_rank {
+        } else if to_file > from_file {
+            if to_rank == from_rank {
                 RayDirection::West
-            } else if king_rank < checker_rank {
+            } else if to_rank < from_rank {
                 RayDirection::SouthWest
             } else {
                 RayDirection::NorthWest
             }
         } else {
-            if king_rank == checker_rank {
+            if to_rank == from_rank {
                 RayDirection::East
-            } else if king_rank < checker_rank {
+            } else if to_rank < from_rank {
                 RayDirection::SouthEast
             } else {
                 RayDirection::NorthEast
             }
-        };
+        }
+    }
 
-        (self.pl_moves.rays[direction as usize][checker_square], direction)
+    /// Generate a bitboard of the pieces of `side` that are pinned to their
+    /// king by an enemy slider, regardless of the side to move.
+    ///
+    /// * `side`: Side of the pinned pieces
+    pub fn pinned_pieces(&self, side: Side) -> u64 {
+        self.slider_blockers(side, side)
     }
 
+    /// Generate a bitboard of the pieces of `blocker_side` that are the only
+    /// piece between the king of `king_side` and an enemy slider. These are
+    /// pinned if they're of the king's side, and give a discovered check when
+    /// moving off the ray otherwise.
+    ///
+    /// * `king_side`: Side of the king
+    /// * `blocker_side`: Side of the blocking pieces
+    fn slider_blockers(&self, king_side: Side, blocker_side: Side) -> u64 {
+        let king_square = self.board[(Piece::King, king_side)].trailing_zeros() as usize;
+        let enemy = king_side.opposite();
+
+        // Enemy sliders behind exactly one of the blocking side's pieces
+        let blockers = self.occupation_boards[blocker_side as usize];
+        let mut snipers = self.magics.xray_rook_attacks(king_square, self.comp_occupation_board, blockers)
+                & (self.board[(Piece::Rook, enemy)] | self.board[(Piece::Queen, enemy)])
+            | self.magics.xray_bishop_attacks(king_square, self.comp_occupation_board, blockers)
+                & (self.board[(Piece::Bishop, enemy)] | self.board[(Piece::Queen, enemy)]);
+
+        let mut single_blockers = 0u64;
+        while snipers != 0 {
+            let sniper_square = snipers.trailing_zeros() as usize;
+            single_blockers |= self.squares_between(sniper_square, king_square) & blockers;
+
+            // clear square bit
+            snipers &= snipers - 1;
+        }
+
+        single_blockers
+    }
+
+    /// Squares a piece of each type of `side` gives a direct check to the
+    /// enemy king from.
+    ///
+    /// * `side`: Side of the checking pieces
+    fn check_squares(&self, side: Side) -> [u64; Piece::N_PIECES] {
+        let king_square = self.board[(Piece::King, side.opposite())].trailing_zeros() as usize;
+        let rook_rays = self.magics.get_rook_moves(
+            king_square,
+            self.comp_occupation_board & self.pl_moves.get_piece_blocker_mask(Piece::Rook, king_square),
+        );
+        let bishop_rays = self.magics.get_bishop_moves(
+            king_square,
+            self.comp_occupation_board & self.pl_moves.get_piece_blocker_mask(Piece::Bishop, king_square),
+        );
+
+        [
+            self.pl_moves.pawn_capture_moves[side.opposite() as usize][king_square],
+            self.pl_moves.knight_moves[king_square],
+            bishop_rays,
+            rook_rays,
+            bishop_rays | rook_rays,
+            0,
+        ]
+    }
+
+    // Squares between king and rook, which must be empty to castle
     const SHORT_CASTLE_MASKS: [u64; Side::N_SIDES] =
-        [0b00000110, 0b00000110 << (BOARD_WIDTH - 1) * BOARD_WIDTH];
+        [0b00000110, 0b00000110 << ((BOARD_WIDTH - 1) * BOARD_WIDTH)];
     const LONG_CASTLE_MASKS: [u64; Side::N_SIDES] = [
         0b01110000,
-        0b01110000 << (BOARD_WIDTH - 1) * BOARD_WIDTH,
+        0b01110000 << ((BOARD_WIDTH - 1) * BOARD_WIDTH),
     ];
-    const KING_STARTING_POS: [u64; Side::N_SIDES] = [
-        1 << 3,
-        1 << (BOARD_HEIGHT*(BOARD_HEIGHT - 1) + 3),
+    // Squares the king passes through or lands on when castling long, which
+    // must not be attacked. Unlike the rook, the king doesn't cross the b-file
+    const LONG_CASTLE_KING_PATH_MASKS: [u64; Side::N_SIDES] = [
+        0b00110000,
+        0b00110000 << ((BOARD_WIDTH - 1) * BOARD_WIDTH),
     ];
-
-    /// Determines legal castling moves, adding to `move_list` if legal.
+    /// Determines legal castling moves, short castling first and long
+    /// castling second, with `None` for the ones that aren't legal.
     ///
     /// * `enemy_attack_bb`: Bitboard of all squares that enemy pieces cover (attack)
-    /// * `move_list`: Moves vector to push castling moves to
-    fn get_castling_moves(&self, enemy_attack_bb: u64, move_list: &mut Vec<Move>) {
+    fn get_castling_moves(&self, enemy_attack_bb: u64) -> [Option<Move>; 2] {
+        let mut castling_moves = [None; 2];
         if self.board[(Piece::King, self.board.side_to_move)]
-            & Self::KING_STARTING_POS[self.board.side_to_move as usize] == 0 {
+            & (1 << Board::KING_SQUARES[self.board.side_to_move as usize]) == 0 {
             // King not on its starting square, don't look further for castling
             // legality
-            return;
+            return castling_moves;
         }
 
         if self.board.castling_right_short[self.board.side_to_move as usize] {
             let no_check_in_path = (Self::SHORT_CASTLE_MASKS[self.board.side_to_move as usize]
-                & (enemy_attack_bb | self.occupation_boards[self.board.side_to_move as usize])) == 0;
+                & (enemy_attack_bb | self.comp_occupation_board)) == 0;
             let rook_in_place = (1 << Board::ROOK_SHORT_SQUARES[self.board.side_to_move as usize])
                 & self.board[(Piece::Rook, self.board.side_to_move)] != 0;
 
             if no_check_in_path && rook_in_place {
-                move_list.push(Move {
-                    from_square: 0, // unused
-                    to_square: 0,   // unused
+                castling_moves[0] = Some(Move {
+                    from_square: Board::KING_SQUARES[self.board.side_to_move as usize],
+                    to_square: Board::KING_SHORT_CASTLE_SQUARES[self.board.side_to_move as usize],
                     move_type: MoveType::CastleShort,
                     piece: Piece::King,
                     side: self.board.side_to_move,
@@ -645,97 +1421,788 @@ impl<'a> GameState<'a> {
 
         if self.board.castling_right_long[self.board.side_to_move as usize] {
             let no_check_in_path = (Self::LONG_CASTLE_MASKS[self.board.side_to_move as usize]
-                & (enemy_attack_bb | self.occupation_boards[self.board.side_to_move as usize])) == 0;
+                & self.comp_occupation_board) == 0
+                && (Self::LONG_CASTLE_KING_PATH_MASKS[self.board.side_to_move as usize] & enemy_attack_bb) == 0;
             let rook_in_place = (1 << Board::ROOK_LONG_SQUARES[self.board.side_to_move as usize])
                 & self.board[(Piece::Rook, self.board.side_to_move)] != 0;
 
             if no_check_in_path && rook_in_place {
-                move_list.push(Move {
-                    from_square: 0, // unused
-                    to_square: 0,   // unused
+                castling_moves[1] = Some(Move {
+                    from_square: Board::KING_SQUARES[self.board.side_to_move as usize],
+                    to_square: Board::KING_LONG_CASTLE_SQUARES[self.board.side_to_move as usize],
                     move_type: MoveType::CastleLong,
                     piece: Piece::King,
                     side: self.board.side_to_move,
                 });
             }
         }
+
+        castling_moves
+    }
+
+    /// Whether any piece of `by_side` attacks `square` in the current position.
+    /// Cheaper than `enemy_attacks` when only a few squares need checking.
+    ///
+    /// * `square`: Target square
+    /// * `by_side`: Attacking side
+    pub fn is_square_attacked(&self, square: usize, by_side: Side) -> bool {
+        let board = &self.board;
+
+        // A pawn of `by_side` attacks the square if a pawn of the opposite
+        // side on that square could capture it
+        if self.pl_moves.pawn_capture_moves[by_side.opposite() as usize][square] & board[(Piece::Pawn, by_side)] != 0
+            || self.pl_moves.knight_moves[square] & board[(Piece::Knight, by_side)] != 0
+            || self.pl_moves.king_moves[square] & board[(Piece::King, by_side)] != 0
+        {
+            return true;
+        }
+
+        let queens = board[(Piece::Queen, by_side)];
+        let rooks = board[(Piece::Rook, by_side)] | queens;
+        if rooks != 0 && self.slider_moves(Piece::Rook, square) & rooks != 0 {
+            return true;
+        }
+
+        let bishops = board[(Piece::Bishop, by_side)] | queens;
+        bishops != 0 && self.slider_moves(Piece::Bishop, square) & bishops != 0
     }
 
-    fn update_occupation_boards(&mut self) {
-        self.comp_occupation_board = 0;
+    /// Generate a bitboard of all pieces (of both sides) attacking `square`,
+    /// given the occupancy `occ`. Sliders are looked up with `occ` as blockers
+    /// so pieces removed from `occ` reveal x-ray attackers behind them.
+    ///
+    /// * `square`: Target square
+    /// * `occ`: Occupancy bitboard to use for slider blockers
+    fn attackers_to(&self, square: usize, occ: u64) -> u64 {
+        let board = &self.board;
+        let rook_rays = self.magics.get_rook_moves(
+            square,
+            occ & self.pl_moves.get_piece_blocker_mask(Piece::Rook, square),
+        );
+        let bishop_rays = self.magics.get_bishop_moves(
+            square,
+            occ & self.pl_moves.get_piece_blocker_mask(Piece::Bishop, square),
+        );
+
+        let mut attackers = 0u64;
+        for side in Side::VALUES {
+            // A pawn of `side` attacks the square if a pawn of the opposite
+            // side on that square could capture it
+            attackers |= self.pl_moves.pawn_capture_moves[side.opposite() as usize][square]
+                & board[(Piece::Pawn, side)];
+            attackers |= self.pl_moves.knight_moves[square] & board[(Piece::Knight, side)];
+            attackers |= self.pl_moves.king_moves[square] & board[(Piece::King, side)];
+            attackers |= rook_rays
+                & (board[(Piece::Rook, side)] | board[(Piece::Queen, side)]);
+            attackers |= bishop_rays
+                & (board[(Piece::Bishop, side)] | board[(Piece::Queen, side)]);
+        }
+
+        attackers & occ
+    }
+
+    /// Whether `m` is legal in the current position. Cheaper than generating
+    /// all legal moves, for validating moves that don't come from the move
+    /// generator of this position (e.g. from a transposition table entry that
+    /// may belong to a different position).
+    ///
+    /// * `m`: Move to validate
+    pub fn is_legal(&self, m: &Move) -> bool {
+        let side = self.board.side_to_move;
+        if m.side != side {
+            return false;
+        }
+
+        if matches!(m.move_type, MoveType::CastleLong | MoveType::CastleShort) {
+            let king_square = self.board[(Piece::King, side)].trailing_zeros() as usize;
+            if self.is_square_attacked(king_square, side.opposite()) {
+                return false;
+            }
+
+            // Only the squares the king crosses matter for castling
+            let mut king_path = Self::SHORT_CASTLE_MASKS[side as usize] | Self::LONG_CASTLE_KING_PATH_MASKS[side as usize];
+            let mut attacked_path = 0u64;
+            while king_path != 0 {
+                let square = king_path.trailing_zeros() as usize;
+                if self.is_square_attacked(square, side.opposite()) {
+                    attacked_path |= 1 << square;
+                }
+                king_path &= king_path - 1;
+            }
+
+            return self.get_castling_moves(attacked_path).contains(&Some(*m));
+        }
+
+        if m.move_type == MoveType::Drop {
+            return m.piece != Piece::King && m.to_square < N_SQUARES
+                && self.drop_targets(m.piece, &self.legal_move_masks(None)) & (1 << m.to_square) != 0;
+        }
+
+        if m.from_square >= N_SQUARES || m.to_square >= N_SQUARES
+            || self.board[(m.piece, side)] & (1 << m.from_square) == 0 {
+            return false;
+        }
+
+        // The piece must be able to reach the target square, and the move
+        // type has to match what's on it
+        let mut moves_bb = if m.piece.is_slider() {
+            self.slider_moves(m.piece, m.from_square)
+        } else {
+            self.pl_moves[(m.piece, side, m.from_square)]
+        };
+        if m.piece == Piece::Pawn {
+            self.pawn_moves(m.from_square, &mut moves_bb);
+        }
+        self.remove_friendly_moves(&mut moves_bb);
+
+        let mut pseudo_legal_moves: Vec<Move> = vec![];
+        self.generate_moves_from_bb(
+            m.piece,
+            m.from_square,
+            moves_bb & (1 << m.to_square),
+            &mut pseudo_legal_moves,
+        );
+        if !pseudo_legal_moves.contains(m) {
+            return false;
+        }
+
+        // The move may not leave the own king in check
+        let (own_pieces, removed, occ) = self.pieces_after_move(m);
+        let king_square = own_pieces[Piece::King as usize].trailing_zeros() as usize;
+        let enemy = |piece| self.board[(piece, side.opposite())] & !removed;
+
+        let rook_rays = self.magics.get_rook_moves(
+            king_square,
+            occ & self.pl_moves.get_piece_blocker_mask(Piece::Rook, king_square),
+        );
+        let bishop_rays = self.magics.get_bishop_moves(
+            king_square,
+            occ & self.pl_moves.get_piece_blocker_mask(Piece::Bishop, king_square),
+        );
+
+        self.pl_moves.pawn_capture_moves[side as usize][king_square] & enemy(Piece::Pawn) == 0
+            && self.pl_moves.knight_moves[king_square] & enemy(Piece::Knight) == 0
+            && self.pl_moves.king_moves[king_square] & enemy(Piece::King) == 0
+            && rook_rays & (enemy(Piece::Rook) | enemy(Piece::Queen)) == 0
+            && bishop_rays & (enemy(Piece::Bishop) | enemy(Piece::Queen)) == 0
+    }
+
+    /// The pieces of the moving side, the squares vacated (including those of
+    /// captured pieces) and the occupancy of both sides after move `m`,
+    /// without making it.
+    ///
+    /// * `m`: Move of the side to move
+    fn pieces_after_move(&self, m: &Move) -> ([u64; Piece::N_PIECES], u64, u64) {
+        let mut own_pieces = [0u64; Piece::N_PIECES];
+        for piece in Piece::VALUES {
+            own_pieces[piece as usize] = self.board[(piece, m.side)];
+        }
+
+        let mut removed = 0u64;
+        let mut added = 0u64;
+        for (piece, piece_side, square, sign) in m.piece_changes() {
+            if sign == 0 {
+                continue;
+            }
+
+            if sign < 0 {
+                removed |= 1 << square;
+            } else {
+                added |= 1 << square;
+            }
+            if piece_side == m.side {
+                own_pieces[piece as usize] ^= 1 << square;
+            }
+        }
+
+        (own_pieces, removed, (self.comp_occupation_board & !removed) | added)
+    }
+
+    /// Why no legal move of the side to move goes from `from_square` to
+    /// `to_square`, or None if one does. The reason comes from the same
+    /// occupancy, attack, check and pin bitboards as the move generation, for
+    /// telling the user what's wrong with a move they entered.
+    ///
+    /// * `from_square`: Square of the piece to move
+    /// * `to_square`: Square to move it to
+    pub fn illegal_reason(&self, from_square: usize, to_square: usize) -> Option<IllegalReason> {
+        let (legal_moves, _) = self.get_legal_moves();
+        if legal_moves.iter().any(|m| m.move_type != MoveType::Drop && m.from_square == from_square && m.to_square == to_square) {
+            return None;
+        }
+
+        let side = self.board.side_to_move;
+        let enemy = side.opposite();
+        let Some((piece, piece_side)) = self.board.piece_on(from_square) else {
+            return Some(IllegalReason::NoPiece(from_square));
+        };
+        if piece_side != side {
+            return Some(IllegalReason::NotYourPiece(piece, from_square));
+        }
+        if let Some((target, target_side)) = self.board.piece_on(to_square) {
+            if target_side == side {
+                return Some(IllegalReason::OwnPieceOnTarget(target, to_square));
+            }
+        }
+
+        let to_bb = 1u64 << to_square;
+        let occ = self.comp_occupation_board;
+        let masks = self.legal_move_masks(None);
+
+        // Castling, the king moving two squares from its starting square
+        let castling = [
+            (true, Board::KING_SHORT_CASTLE_SQUARES, Board::ROOK_SHORT_SQUARES, Self::SHORT_CASTLE_MASKS, Self::SHORT_CASTLE_MASKS),
+            (false, Board::KING_LONG_CASTLE_SQUARES, Board::ROOK_LONG_SQUARES, Self::LONG_CASTLE_MASKS, Self::LONG_CASTLE_KING_PATH_MASKS),
+        ];
+        for (short, king_targets, rook_squares, path_masks, king_path_masks) in castling {
+            if piece != Piece::King || from_square != Board::KING_SQUARES[side as usize] || to_square != king_targets[side as usize] {
+                continue;
+            }
+
+            let right = if short { self.board.castling_right_short } else { self.board.castling_right_long };
+            let rook_in_place = self.board[(Piece::Rook, side)] & (1 << rook_squares[side as usize]) != 0;
+            let blockers = path_masks[side as usize] & occ;
+            let attacked = king_path_masks[side as usize] & masks.enemy_attack_bb;
+            return Some(if !right[side as usize] || !rook_in_place {
+                IllegalReason::NoCastlingRight(short)
+            } else if masks.in_check {
+                IllegalReason::CastlingInCheck
+            } else if blockers != 0 {
+                // The blocker nearest to the king, the path is on the king's rank
+                let square = if short { 63 - blockers.leading_zeros() } else { blockers.trailing_zeros() } as usize;
+                IllegalReason::CastlingPathBlocked(self.board.piece_on(square).unwrap().0, square)
+            } else if attacked != 0 {
+                IllegalReason::CastlingPathAttacked(attacked.trailing_zeros() as usize)
+            } else {
+                IllegalReason::ExposesKing
+            });
+        }
+
+        // Whether the piece moves that way at all, and isn't blocked
+        let nearest_blocker = |between: u64| {
+            let blockers = between & occ;
+            let square = if to_square > from_square { blockers.trailing_zeros() } else { 63 - blockers.leading_zeros() } as usize;
+            let (blocker, _) = self.board.piece_on(square).unwrap();
+            IllegalReason::Blocked(piece, from_square, blocker, square)
+        };
+        if piece == Piece::Pawn {
+            let en_passant = self.board.en_passant_square.map_or(0, |square| 1 << square);
+            if self.pl_moves.pawn_capture_moves[side as usize][from_square] & to_bb != 0 {
+                if (self.occupation_boards[enemy as usize] | en_passant) & to_bb == 0 {
+                    return Some(IllegalReason::NothingToCapture(from_square, to_square));
+                }
+            } else if self.pl_moves.pawn_moves[side as usize][from_square] & to_bb == 0 {
+                return Some(IllegalReason::CantMoveThere(piece, from_square, to_square));
+            } else if (self.squares_between(from_square, to_square) | to_bb) & occ != 0 {
+                return Some(nearest_blocker(self.squares_between(from_square, to_square) | to_bb));
+            }
+        } else if piece.is_slider() {
+            if self.slider_attacks(piece, from_square, 0) & to_bb == 0 {
+                return Some(IllegalReason::CantMoveThere(piece, from_square, to_square));
+            } else if self.slider_attacks(piece, from_square, occ) & to_bb == 0 {
+                return Some(nearest_blocker(self.squares_between(from_square, to_square)));
+            }
+        } else if self.piece_attacks(piece, side, from_square, occ) & to_bb == 0 {
+            return Some(IllegalReason::CantMoveThere(piece, from_square, to_square));
+        }
+
+        // The move is fine but for the king's safety
+        let (_, pin_masks) = self.enemy_attacks(Some(masks.enemy_attack_bb));
+        let pinner = pin_masks[from_square] & self.occupation_boards[enemy as usize];
+        Some(if piece == Piece::King {
+            if masks.enemy_attack_bb & to_bb != 0 {
+                IllegalReason::KingIntoCheck(to_square)
+            } else {
+                IllegalReason::ExposesKing
+            }
+        } else if masks.in_check && masks.mask == 0 {
+            IllegalReason::DoubleCheck
+        } else if pinner != 0 && pin_masks[from_square] & to_bb == 0 {
+            let pinner_square = pinner.trailing_zeros() as usize;
+            IllegalReason::Pinned(piece, from_square, self.board.piece_on(pinner_square).unwrap().0, pinner_square)
+        } else if masks.in_check {
+            IllegalReason::DoesntAddressCheck
+        } else {
+            // E.g. an en passant capture that uncovers a check along the rank
+            IllegalReason::ExposesKing
+        })
+    }
+
+    /// Whether a legal move puts the opponent's king in check, either directly
+    /// or by uncovering an attack of a slider behind the moved piece. Instead
+    /// of making the move, only the occupancy and the moving side's pieces are
+    /// updated with the squares the move changes: the arriving pieces give
+    /// direct checks, and the own sliders seeing the king through the vacated
+    /// squares discovered checks.
+    ///
+    /// * `m`: Legal move of the side to move
+    pub fn gives_check(&self, m: &Move) -> bool {
+        let side = m.side;
+        let enemy_king_square = self.board[(Piece::King, side.opposite())].trailing_zeros() as usize;
+
+        let (own_pieces, _, occ) = self.pieces_after_move(m);
+
+        // Direct checks by pawns and knights
+        if self.pl_moves.pawn_capture_moves[side.opposite() as usize][enemy_king_square] & own_pieces[Piece::Pawn as usize] != 0
+            || self.pl_moves.knight_moves[enemy_king_square] & own_pieces[Piece::Knight as usize] != 0
+        {
+            return true;
+        }
+
+        // Direct and discovered checks by sliders
+        let queens = own_pieces[Piece::Queen as usize];
+        let rook_rays = self.magics.get_rook_moves(
+            enemy_king_square,
+            occ & self.pl_moves.get_piece_blocker_mask(Piece::Rook, enemy_king_square),
+        );
+        let bishop_rays = self.magics.get_bishop_moves(
+            enemy_king_square,
+            occ & self.pl_moves.get_piece_blocker_mask(Piece::Bishop, enemy_king_square),
+        );
+
+        rook_rays & (own_pieces[Piece::Rook as usize] | queens) != 0
+            || bishop_rays & (own_pieces[Piece::Bishop as usize] | queens) != 0
+    }
+
+    const SEE_PIECE_VALUES: [i32; Piece::N_PIECES] = [100, 300, 300, 500, 900, 20000];
+
+    /// Static exchange evaluation of a capture: the material balance (in
+    /// centipawns, from the moving side's perspective) of the sequence of
+    /// captures on the target square, where both sides always recapture
+    /// with their least valuable attacker and may stop when it's unfavourable.
+    ///
+    /// * `m`: Capture move to evaluate
+    pub fn see(&self, m: &Move) -> i32 {
+        let target = m.to_square;
+        let mut gain = [0i32; 32];
+        let mut depth = 0;
+        let mut occ = self.comp_occupation_board;
+        let mut from_bb = 1u64 << m.from_square;
+        let mut attacker = m.piece;
+        let mut side = m.side;
+
+        gain[0] = match m.move_type {
+            MoveType::Capture(captured) | MoveType::CapturePromotion(captured, _) =>
+                Self::SEE_PIECE_VALUES[captured as usize],
+            MoveType::EnPassantCapture(captured) => {
+                let enemy_pawn_square = (target as i64
+                    + ((m.side as i64)*2 - 1)*(BOARD_WIDTH as i64)) as usize;
+                occ &= !(1 << enemy_pawn_square);
+                Self::SEE_PIECE_VALUES[captured as usize]
+            }
+            _ => return 0,
+        };
+
+        loop {
+            depth += 1;
+
+            // Speculative gain if the piece that just captured gets recaptured
+            gain[depth] = Self::SEE_PIECE_VALUES[attacker as usize] - gain[depth - 1];
+            if (-gain[depth - 1]).max(gain[depth]) < 0 {
+                // Neither side can improve by continuing the exchange
+                break;
+            }
+
+            occ &= !from_bb;
+            side = side.opposite();
+
+            // Find the least valuable attacker of the side to recapture
+            let attackers = self.attackers_to(target, occ);
+            let next_attacker = Piece::VALUES.iter()
+                .find(|&&piece| attackers & self.board[(piece, side)] != 0);
+
+            match next_attacker {
+                Some(&piece) => {
+                    let piece_attackers = attackers & self.board[(piece, side)];
+                    from_bb = 1 << piece_attackers.trailing_zeros();
+                    attacker = piece;
+                },
+                None => break,
+            }
+        }
+
+        while depth > 1 {
+            depth -= 1;
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        }
+
+        gain[0]
+    }
+
+    /// Toggle the squares changed by move `m` in the occupation boards, which
+    /// updates them for both making and undoing `m`.
+    ///
+    /// * `m`: Move made or undone
+    fn toggle_occupation_boards(&mut self, m: &Move) {
+        for (_, side, square, sign) in m.piece_changes() {
+            if sign != 0 {
+                self.occupation_boards[side as usize] ^= 1 << square;
+            }
+        }
+
+        self.comp_occupation_board = self.occupation_boards[Side::White as usize]
+            | self.occupation_boards[Side::Black as usize];
+
+        debug_assert_eq!(
+            self.occupation_boards,
+            [self.board.occupation_board(Side::White), self.board.occupation_board(Side::Black)]
+        );
+    }
+
+    fn update_occupation_boards(&mut self) {
+        self.comp_occupation_board = 0;
+
+        for side in Side::VALUES {
+            self.occupation_boards[side as usize] = self.board.occupation_board(side);
+
+            // Also keep track of a full composite board of both black and
+            // white's pieces
+            self.comp_occupation_board |= self.occupation_boards[side as usize];
+        }
+    }
+}
+
+/// Attack maps and pins of a position, computed once with
+/// `GameState::position_info` for both generating the moves and evaluating
+/// the position.
+///
+/// * `attacks`: Squares attacked by the pieces of each side
+/// * `pinned`: Pieces of each side pinned to their king
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PositionInfo {
+    pub attacks: [u64; Side::N_SIDES],
+    pub pinned: [u64; Side::N_SIDES],
+}
+
+/// Why a move the user entered isn't legal, see `GameState::illegal_reason`.
+/// Squares are those of the pieces involved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IllegalReason {
+    /// No piece on the origin square
+    NoPiece(usize),
+    /// The piece on the origin square belongs to the opponent
+    NotYourPiece(Piece, usize),
+    /// A piece of the side to move is on the target square
+    OwnPieceOnTarget(Piece, usize),
+    /// The piece doesn't move that way
+    CantMoveThere(Piece, usize, usize),
+    /// The piece on the first square is blocked by the piece on the second
+    Blocked(Piece, usize, Piece, usize),
+    /// A pawn moves diagonally to an empty square that isn't the en passant square
+    NothingToCapture(usize, usize),
+    /// No right to castle short (true) or long (false) any more
+    NoCastlingRight(bool),
+    /// Castling out of check
+    CastlingInCheck,
+    /// A piece stands between the king and the rook
+    CastlingPathBlocked(Piece, usize),
+    /// The king would pass through or land on an attacked square
+    CastlingPathAttacked(usize),
+    /// The king would move to an attacked square
+    KingIntoCheck(usize),
+    /// The piece on the first square is pinned to the king by the piece on the second
+    Pinned(Piece, usize, Piece, usize),
+    /// Double check, only the king can move
+    DoubleCheck,
+    /// The king is in check and the move neither captures the checker nor blocks it
+    DoesntAddressCheck,
+    /// The move leaves the king in check some other way
+    ExposesKing,
+}
+
+impl fmt::Display for IllegalReason {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let name = |piece: Piece| format!("{:?}", piece).to_lowercase();
+        let square = |square: usize| Board::square_to_algebraic(square);
+        match *self {
+            IllegalReason::NoPiece(from) => write!(fmt, "there is no piece on {}", square(from)),
+            IllegalReason::NotYourPiece(piece, from) => {
+                write!(fmt, "the {} on {} is not yours", name(piece), square(from))
+            },
+            IllegalReason::OwnPieceOnTarget(piece, to) => {
+                write!(fmt, "your own {} is on {}", name(piece), square(to))
+            },
+            IllegalReason::CantMoveThere(piece, from, to) => {
+                write!(fmt, "your {} on {} can't move to {}", name(piece), square(from), square(to))
+            },
+            IllegalReason::Blocked(piece, from, blocker, blocker_square) => write!(
+                fmt, "your {} on {} is blocked by the {} on {}",
+                name(piece), square(from), name(blocker), square(blocker_square)
+            ),
+            IllegalReason::NothingToCapture(from, to) => {
+                write!(fmt, "your pawn on {} has nothing to capture on {}", square(from), square(to))
+            },
+            IllegalReason::NoCastlingRight(short) => {
+                write!(fmt, "you can't castle {} any more", if short { "short" } else { "long" })
+            },
+            IllegalReason::CastlingInCheck => write!(fmt, "you can't castle out of check"),
+            IllegalReason::CastlingPathBlocked(piece, blocker_square) => {
+                write!(fmt, "the castling path is blocked by the {} on {}", name(piece), square(blocker_square))
+            },
+            IllegalReason::CastlingPathAttacked(attacked) => {
+                write!(fmt, "the castling path is attacked on {}", square(attacked))
+            },
+            IllegalReason::KingIntoCheck(to) => write!(fmt, "your king would be in check on {}", square(to)),
+            IllegalReason::Pinned(piece, from, pinner, pinner_square) => write!(
+                fmt, "your {} on {} is pinned to your king by the {} on {}",
+                name(piece), square(from), name(pinner), square(pinner_square)
+            ),
+            IllegalReason::DoubleCheck => write!(fmt, "you are in double check — only the king can move"),
+            IllegalReason::DoesntAddressCheck => write!(fmt, "you are in check — the move doesn't address it"),
+            IllegalReason::ExposesKing => write!(fmt, "the move would leave your king in check"),
+        }
+    }
+}
+
+/// Named bitboards of a position, see `GameState::bitboards`. They're
+/// displayed as 8x8 grids with the 8th rank at the top, a few side by side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bitboards {
+    pub boards: Vec<(String, u64)>,
+}
+
+impl Bitboards {
+    // Grids printed side by side, and the width of one
+    const GRIDS_PER_ROW: usize = 4;
+    const GRID_WIDTH: usize = 2 + 2*BOARD_WIDTH;
+
+    /// Bitboard named `name`, if any.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.boards.iter().find(|(board_name, _)| board_name == name).map(|&(_, bb)| bb)
+    }
+}
+
+impl fmt::Display for Bitboards {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines = Vec::new();
+        for (i, row) in self.boards.chunks(Self::GRIDS_PER_ROW).enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            let line = |cells: Vec<String>| {
+                cells.iter().map(|cell| format!("{:<width$}", cell, width = Self::GRID_WIDTH)).collect::<Vec<_>>().join(" ").trim_end().to_owned()
+            };
+
+            lines.push(line(row.iter().map(|(name, _)| name.clone()).collect()));
+            let grids: Vec<String> = row.iter().map(|&(_, bb)| Bb(bb).to_string()).collect();
+            let mut grid_lines: Vec<_> = grids.iter().map(|grid| grid.lines()).collect();
+            for _ in 0..=BOARD_HEIGHT {
+                lines.push(line(grid_lines.iter_mut().map(|grid| grid.next().unwrap_or_default().to_owned()).collect()));
+            }
+        }
+
+        write!(fmt, "{}", lines.join("\n"))
+    }
+}
+
+/// Restrictions on the legal moves of the side to move.
+///
+/// * `enemy_attack_bb`: Bitboard of all squares that enemy pieces cover (attack)
+/// * `pin_masks`: Squares the piece on each square may move to without exposing the king
+/// * `mask`: Squares other pieces may move to in check
+/// * `in_check`: Whether the king is in check
+/// * `piece_order`: Order of piece types to generate moves for
+struct LegalMoveMasks {
+    enemy_attack_bb: u64,
+    pin_masks: [u64; N_SQUARES],
+    mask: u64,
+    in_check: bool,
+    piece_order: [Piece; Piece::N_PIECES],
+}
+
+/// Iterator over the legal moves of a position that pops moves from the
+/// piece and move bitboards on demand, created by `GameState::legal_moves_iter`.
+/// Moves are generated in the same order as `GameState::get_legal_moves`.
+///
+/// * `next_piece`: Index in the piece order of the next piece type to generate moves for
+/// * `piece_bb`: Squares of the current piece type left to generate moves for
+/// * `square`: Square of the piece currently generating moves
+/// * `moves_bb`: Target squares left for the piece on `square`
+/// * `promotion`: Target square and captured piece of a promotion, and the index in
+///   `Piece::PROMOTION_PIECES` of the next promotion piece
+/// * `castling_moves`: Castling moves, determined once all other moves are generated
+/// * `next_drop`: Index in `Piece::ALL_BUT_KING` of the next piece type to generate drops for
+/// * `drop_piece`: Piece type currently generating drops
+/// * `drops_bb`: Squares left to drop `drop_piece` on
+pub struct LegalMoves<'g> {
+    game: &'g GameState,
+    masks: LegalMoveMasks,
+
+    next_piece: usize,
+    piece: Piece,
+    piece_bb: u64,
+    square: usize,
+    moves_bb: u64,
+    promotion: Option<(usize, Option<Piece>, usize)>,
+    castling_moves: Option<std::iter::Flatten<std::array::IntoIter<Option<Move>, 2>>>,
+    next_drop: usize,
+    drop_piece: Piece,
+    drops_bb: u64,
+}
+
+impl Iterator for LegalMoves<'_> {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        loop {
+            if let Some((target_square, captured_piece, i)) = self.promotion {
+                let promotion_piece = Piece::PROMOTION_PIECES[i];
+                self.promotion = (i + 1 < Piece::PROMOTION_PIECES.len())
+                    .then_some((target_square, captured_piece, i + 1));
+                return Some(self.game.promotion_move(self.square, target_square, captured_piece, promotion_piece));
+            }
+
+            if self.moves_bb != 0 {
+                let target_square = self.moves_bb.trailing_zeros() as usize;
+                self.moves_bb &= self.moves_bb - 1;
+
+                let captured_piece = self.game.captured_piece(target_square);
+                if GameState::is_promotion(self.piece, target_square) {
+                    self.promotion = Some((target_square, captured_piece, 0));
+                } else if let Some(m) = self.game.target_move(self.piece, self.square, target_square, captured_piece) {
+                    return Some(m);
+                }
+                continue;
+            }
+
+            if self.piece_bb != 0 {
+                self.square = self.piece_bb.trailing_zeros() as usize;
+                self.piece_bb &= self.piece_bb - 1;
+                self.moves_bb = self.game.legal_move_targets(self.piece, self.square, &self.masks);
+                continue;
+            }
+
+            if self.next_piece < Piece::N_PIECES {
+                self.piece = self.masks.piece_order[self.next_piece];
+                self.piece_bb = self.game.board[(self.piece, self.game.board.side_to_move)];
+                self.next_piece += 1;
+                continue;
+            }
+
+            // Castling only legal when not in check
+            if !self.masks.in_check {
+                let game = self.game;
+                let enemy_attack_bb = self.masks.enemy_attack_bb;
+                let castling_move = self.castling_moves
+                    .get_or_insert_with(|| game.get_castling_moves(enemy_attack_bb).into_iter().flatten())
+                    .next();
+                if castling_move.is_some() {
+                    return castling_move;
+                }
+            }
+
+            // Drops in crazyhouse last
+            if self.drops_bb != 0 {
+                let square = self.drops_bb.trailing_zeros() as usize;
+                self.drops_bb &= self.drops_bb - 1;
+                return Some(self.game.drop_move(self.drop_piece, square));
+            }
+
+            if self.next_drop < Piece::ALL_BUT_KING.len() {
+                self.drop_piece = Piece::ALL_BUT_KING[self.next_drop];
+                self.drops_bb = self.game.drop_targets(self.drop_piece, &self.masks);
+                self.next_drop += 1;
+                continue;
+            }
+
+            return None;
+        }
+    }
+
+    fn count(self) -> usize {
+        // Count the moves left from the move bitboards instead of generating
+        // them one by one
+        let mut n_moves = self.promotion.map_or(0, |(_, _, i)| Piece::PROMOTION_PIECES.len() - i);
+        n_moves += self.game.count_moves_from_bb(self.piece, self.square, self.moves_bb);
+
+        let count_piece_moves = |piece: Piece, mut piece_bb: u64| {
+            let mut n_moves = 0;
+            while piece_bb != 0 {
+                let square = piece_bb.trailing_zeros() as usize;
+                let moves_bb = self.game.legal_move_targets(piece, square, &self.masks);
+                n_moves += self.game.count_moves_from_bb(piece, square, moves_bb);
+                piece_bb &= piece_bb - 1;
+            }
+            n_moves
+        };
+
+        n_moves += count_piece_moves(self.piece, self.piece_bb);
+        for &piece in &self.masks.piece_order[self.next_piece..] {
+            n_moves += count_piece_moves(piece, self.game.board[(piece, self.game.board.side_to_move)]);
+        }
 
-        for side in Side::VALUES {
-            self.occupation_boards[side as usize] = self.board.occupation_board(side);
+        if !self.masks.in_check {
+            n_moves += match self.castling_moves {
+                Some(castling_moves) => castling_moves.count(),
+                None => self.game.get_castling_moves(self.masks.enemy_attack_bb).iter().flatten().count(),
+            };
+        }
 
-            // Also keep track of a full composite board of both black and
-            // white's pieces
-            self.comp_occupation_board |= self.occupation_boards[side as usize];
+        n_moves += self.drops_bb.count_ones() as usize;
+        for &piece in &Piece::ALL_BUT_KING[self.next_drop..] {
+            n_moves += self.game.drop_targets(piece, &self.masks).count_ones() as usize;
         }
+
+        n_moves
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use once_cell::sync::Lazy;
-
-    static PSEUDO_LEGAL_MOVES: Lazy<MoveBitboards> = Lazy::new(|| MoveBitboards::init_legal_moves());
-    static MAGICS: Lazy<MagicBitboard> = Lazy::new(|| MagicBitboard::init_precomputed(&*PSEUDO_LEGAL_MOVES));
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+    use crate::r#move::NULL_MOVE;
+    use crate::board::BoardBuilder;
+    use proptest::prelude::*;
+    use proptest::sample::{select, Index};
+    use proptest::test_runner::TestCaseError;
+
+    fn sq(square: &str) -> usize {
+        Board::square_from_algebraic(square).unwrap()
+    }
 
     #[test]
     fn test_legal_moves() {
-        let game = GameState::new(&*PSEUDO_LEGAL_MOVES, &*MAGICS);
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
         let (legal_moves, _) = game.get_legal_moves();
 
         assert_eq!(legal_moves.len(), 2 * 8 + 2 * 2);
 
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001110001100000000,
-                    // Black pawns
-                    0b0000000011101111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0001000000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
 
         let (legal_moves, _) = game.get_legal_moves();
@@ -749,6 +2216,95 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_legal_moves_iter() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            // Castling, pins and en passant
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1",
+            // Promotions
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            // Single and double check
+            "rnbqkbnr/ppp2ppp/8/1B1pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 1 3",
+            "4k3/8/8/8/8/5n2/8/3R2K1 w - - 0 1",
+            "3rk3/8/3N4/8/8/8/8/4R1K1 b - - 0 1",
+        ];
+
+        for fen in fens {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            let (legal_moves, _) = game.get_legal_moves();
+
+            let iter_moves: Vec<Move> = game.legal_moves_iter().collect();
+            assert_eq!(iter_moves, legal_moves, "{}", fen);
+            assert_eq!(game.legal_moves_iter().count(), legal_moves.len(), "{}", fen);
+
+            // Counting the rest of a partially consumed iterator
+            for n_taken in 0..=legal_moves.len() {
+                let mut iter = game.legal_moves_iter();
+                iter.by_ref().take(n_taken).for_each(drop);
+                assert_eq!(iter.count(), legal_moves.len() - n_taken, "{} {}", fen, n_taken);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tactical_moves() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            // In check
+            "rnbqkbnr/ppp2ppp/8/1B1pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 1 3",
+            // Discovered checks by a knight, king and pawn, castling with check
+            "3k4/8/8/3N4/8/8/8/3RK3 w - - 0 1",
+            "7k/8/8/4K3/8/8/1B6/8 w - - 0 1",
+            "7k/8/8/8/3P4/8/1B6/4K3 w - - 0 1",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+        ];
+
+        for fen in fens {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            let (legal_moves, in_check) = game.get_legal_moves();
+
+            for checks in [false, true] {
+                let (tactical_moves, tactical_in_check) = game.get_tactical_moves(checks);
+                assert_eq!(tactical_in_check, in_check);
+
+                let mut expected: Vec<Move> = legal_moves.iter()
+                    .filter(|m| in_check || m.is_capture() || m.is_promotion() || (checks && game.gives_check(m)))
+                    .copied()
+                    .collect();
+                let mut tactical_moves = tactical_moves;
+                let key = |m: &Move| (m.from_square, m.to_square, format!("{:?}", m.move_type));
+                expected.sort_by_key(key);
+                tactical_moves.sort_by_key(key);
+                assert_eq!(tactical_moves, expected, "{} {}", fen, checks);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_moves_into_buffer() {
+        // The buffer is cleared, whatever it held before
+        let mut move_list = vec![NULL_MOVE; 3];
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp2ppp/8/1B1pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 1 3",
+        ] {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+
+            let info = game.position_info();
+            let in_check = game.generate_legal_moves(&info, &mut move_list);
+            assert_eq!((move_list.clone(), in_check), game.get_legal_moves());
+
+            let in_check = game.generate_tactical_moves(true, &info, &mut move_list);
+            assert_eq!((move_list.clone(), in_check), game.get_tactical_moves(true));
+        }
+    }
+
     #[test]
     fn test_pins() {
         // RNBQKBNR
@@ -760,52 +2316,47 @@ mod tests {
         // ppppNppp
         // RNBQKBNR
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111011100000000,
-                    // Black pawns
-                    0b0000000011111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000100001000010,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000001000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0001000000000000000010000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("e2"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("e6"), Piece::Queen, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("h4"), Piece::Bishop, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, _) = game.get_legal_moves();
 
@@ -825,52 +2376,48 @@ mod tests {
         // ppppNppp
         // RNBQKBNR
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111011100000000,
-                    // Black pawns
-                    0b0000000011111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000100001000010,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000010000000000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000001000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0001000000000000000010000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("e3"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("e2"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("e6"), Piece::Queen, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("h4"), Piece::Bishop, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, _) = game.get_legal_moves();
 
@@ -888,53 +2435,45 @@ mod tests {
         // ........
         // pppppppp
         // RNBQKBNR
-        let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111111100000000,
-                    // Black pawns
-                    0b0000000011111011000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000001000010,
-                    // Black knights
-                    0b0000000000000000000000100000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000100000000000000000000000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000000000000100000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::Black,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+        let game = GameState::from_board(
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("h5"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("f7"), Piece::King, Side::Black)
+                .piece(sq("e8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g6"), Piece::Knight, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .side_to_move(Side::Black)
+                .castling(Side::White, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, _) = game.get_legal_moves();
 
@@ -949,55 +2488,45 @@ mod tests {
         // ........
         // .B......
         // ........
-        // pppBpppp
+        // pppppppp
         // RNBQKBNR
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001110111100000000,
-                    // Black pawns
-                    0b0000000011111011000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000001000010,
-                    // Black knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000001000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000001000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000000000000100000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("d2"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("f7"), Piece::King, Side::Black)
+                .piece(sq("e8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("b4"), Piece::Bishop, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, _) = game.get_legal_moves();
         assert!(legal_moves.contains(&Move {
@@ -1023,53 +2552,46 @@ mod tests {
         // ........
         // pppppppp
         // RNBQKBNR
-        let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000001000000100000000000001111111100000000,
-                    // Black pawns
-                    0b0000000010111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000001000010,
-                    // Black knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000001000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000010000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000000000000000001000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::Black,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+        let game = GameState::from_board(
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("h6"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("c4"), Piece::Pawn, Side::White)
+                .piece(sq("d5"), Piece::Pawn, Side::White)
+                .piece(sq("c6"), Piece::King, Side::Black)
+                .piece(sq("e8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .side_to_move(Side::Black)
+                .castling(Side::White, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, _) = game.get_legal_moves();
         let king_moves: Vec<&Move> = legal_moves.iter().filter(|m| m.piece == Piece::King).collect();
@@ -1094,6 +2616,34 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_pins_all_directions() {
+        // Pieces pinned to the king on d4 along both diagonals and the file,
+        // and pieces that aren't pinned because a second piece is in the way
+        let fen = "3r2k1/b5q1/3R4/2B1N3/rNPK4/2P1R3/1b3b2/8 w - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        let (legal_moves, _) = game.get_legal_moves();
+
+        let targets = |from: &str| {
+            let from_square = Board::square_from_algebraic(from).unwrap();
+            let mut targets: Vec<String> = legal_moves.iter()
+                .filter(|m| m.from_square == from_square)
+                .map(|m| Board::square_to_algebraic(m.to_square))
+                .collect();
+            targets.sort();
+            targets
+        };
+
+        assert_eq!(targets("d6"), ["d5", "d7", "d8"]);
+        assert_eq!(targets("c5"), ["a7", "b6"]);
+        assert!(targets("e5").is_empty());
+        assert!(targets("e3").is_empty());
+        assert!(targets("c3").is_empty());
+
+        // The knight on b4 isn't pinned with the pawn on c4 in the way too
+        assert_eq!(targets("b4"), ["a2", "a6", "c2", "c6", "d3", "d5"]);
+    }
+
     #[test]
     fn test_checkmate() {
         // RNBQKBNR
@@ -1105,52 +2655,45 @@ mod tests {
         // pppp..pp
         // RNBQKBNR
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111001100000000,
-                    // Black pawns
-                    0b0000000011111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000001000010,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000001000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0001000000000000000010000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("e6"), Piece::Queen, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("h4"), Piece::Bishop, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, in_check) = game.get_legal_moves();
 
@@ -1158,6 +2701,23 @@ mod tests {
         assert_eq!(legal_moves.len(), 0);
     }
 
+    #[test]
+    fn test_has_legal_moves() {
+        for (fen, move_result) in [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", None),
+            ("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3", Some(MoveResult::Checkmate)),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Some(MoveResult::Draw(DrawReason::Stalemate))),
+            // Only the king can move
+            ("7k/8/8/8/8/8/8/6K1 b - - 0 1", None),
+        ] {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            let (legal_moves, in_check) = game.get_legal_moves();
+            assert_eq!(game.has_legal_moves(), !legal_moves.is_empty(), "{}", fen);
+            assert_eq!(game.move_result(game.has_legal_moves(), in_check), move_result, "{}", fen);
+            assert_eq!(game.get_move_result(&legal_moves, in_check), move_result, "{}", fen);
+        }
+    }
+
     #[test]
     fn test_get_out_of_check() {
         // RNBQKBNR
@@ -1169,52 +2729,44 @@ mod tests {
         // pppp..pp
         // RNBQKBNR
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111001100000000,
-                    // Black pawns
-                    0b0000000011111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000001000010,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0001000000000000000000000000100000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("e5"), Piece::Queen, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, in_check) = game.get_legal_moves();
 
@@ -1264,53 +2816,46 @@ mod tests {
         // .....B..
         // pppppppp
         // RNBQKBNR
-        let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111111100000000,
-                    // Black pawns
-                    0b0000000010111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000001000010,
-                    // Black knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000001000001000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000001000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000100000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000000000000000001000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::Black,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+        let game = GameState::from_board(
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("d6"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("f3"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("c6"), Piece::King, Side::Black)
+                .piece(sq("e8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("b4"), Piece::Bishop, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .side_to_move(Side::Black)
+                .castling(Side::White, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, in_check) = game.get_legal_moves();
         assert!(in_check);
@@ -1351,53 +2896,47 @@ mod tests {
         // .....BB.
         // pppppppp
         // RNBQKBNR
-        let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111111100000000,
-                    // Black pawns
-                    0b0000000010111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000001000010,
-                    // Black knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000001100001000000100100,
-                    // Black bishops
-                    0b0010010000000000000000000000000001000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000100000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000010000,
-                    // Black queens
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000000000000000001000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::Black,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+        let game = GameState::from_board(
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("d1"), Piece::Queen, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("d6"), Piece::Rook, Side::White)
+                .piece(sq("f1"), Piece::Bishop, Side::White)
+                .piece(sq("c1"), Piece::Bishop, Side::White)
+                .piece(sq("g3"), Piece::Bishop, Side::White)
+                .piece(sq("f3"), Piece::Bishop, Side::White)
+                .piece(sq("g1"), Piece::Knight, Side::White)
+                .piece(sq("b1"), Piece::Knight, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("c6"), Piece::King, Side::Black)
+                .piece(sq("e8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("b4"), Piece::Bishop, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .side_to_move(Side::Black)
+                .castling(Side::White, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, in_check) = game.get_legal_moves();
 
@@ -1425,6 +2964,33 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_check_evasions() {
+        // Capturing the checking pawn en passant
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1").unwrap();
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert!(in_check);
+        assert_eq!(legal_moves.len(), 9);
+        assert!(legal_moves.contains(&find_algebraic(&game, "exd3")));
+
+        // The pinned knight can't block or capture, the bishop blocks and the
+        // king can't step back along the checking ray
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "6k1/8/8/b7/8/3B4/3N4/4K2q w - - 0 1").unwrap();
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert!(in_check);
+        let mut algebraic: Vec<String> = legal_moves.iter()
+            .map(|m| m.to_algebraic_with_state(&legal_moves))
+            .collect();
+        algebraic.sort();
+        assert_eq!(algebraic, ["Bf1", "Ke2", "Kf2"]);
+
+        // Double check, only king moves
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "3rk3/8/3N4/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert!(in_check);
+        assert!(legal_moves.iter().all(|m| m.piece == Piece::King));
+    }
+
     #[test]
     fn test_castling() {
         // RNBQKBNR
@@ -1436,60 +3002,48 @@ mod tests {
         // pppppppp
         // R...K..R
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001011111100000000,
-                    // Black pawns
-                    0b0000000011111111000000000000000000000000000000000100000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black queens
-                    0b0001000000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("b2"), Piece::Pawn, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
         let (legal_moves, in_check) = game.get_legal_moves();
 
         assert!(!in_check);
 
         assert!(legal_moves.contains(&Move {
-            from_square: 0,
-            to_square: 0,
+            from_square: 3,
+            to_square: 1,
             move_type: MoveType::CastleShort,
             piece: Piece::King,
             side: Side::White
@@ -1497,59 +3051,74 @@ mod tests {
 
         // no long castle, would move through pawn check
         assert!(!legal_moves.contains(&Move {
-            from_square: 0,
-            to_square: 0,
+            from_square: 3,
+            to_square: 5,
             move_type: MoveType::CastleLong,
             piece: Piece::King,
             side: Side::White
         }));
     }
 
+    #[test]
+    fn test_castling_squares() {
+        let mut game = GameState::from_fen(
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"
+        ).unwrap();
+        let (legal_moves, _) = game.get_legal_moves();
+        let board = game.board.clone();
+
+        // e8g8 with the rook from h8 to f8, e8c8 with the rook from a8 to d8
+        for (move_type, to_square, rook_squares) in [
+            (MoveType::CastleShort, 57, (56, 58)),
+            (MoveType::CastleLong, 61, (63, 60)),
+        ] {
+            let m = *legal_moves.iter().find(|m| m.move_type == move_type).unwrap();
+            assert_eq!((m.from_square, m.to_square), (59, to_square));
+            assert_eq!(m.castling_rook_squares(), Some(rook_squares));
+
+            let pos_hash = game.pos_hash;
+            let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
+            game.update_board_with_move(&m);
+            assert_eq!(game.board.king[Side::Black as usize], 1 << to_square);
+            assert_eq!(
+                game.board.rooks[Side::Black as usize],
+                (board.rooks[Side::Black as usize] & !(1 << rook_squares.0)) | (1 << rook_squares.1)
+            );
+
+            game.update_board_undo_move(
+                &m,
+                pos_hash,
+                &board.castling_right_long,
+                &board.castling_right_short,
+                None,
+                half_move_of_last_irreversible,
+            );
+            assert_eq!(game.board, board);
+        }
+    }
+
     #[test]
     fn test_pawn_promotion() {
-        // .B......
+        // .B.....k
         // ..p.....
         // ........
         // ........
         // ........
         // ........
         // ........
-        // ........
+        // .......K
         let game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000100000000000000000000000000000000000000000000000000000,
-                    // Black pawns
-                    0,
-                ],
-                knights: [
-                    0, 0,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black bishops
-                    0b0100000000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    0, 0
-                ],
-                queens: [
-                    0, 0
-                ],
-                king: [
-                    0, 0
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("h1"), Piece::King, Side::White)
+                .piece(sq("c7"), Piece::Pawn, Side::White)
+                .piece(sq("h8"), Piece::King, Side::Black)
+                .piece(sq("b8"), Piece::Bishop, Side::Black)
+                .build()
+                .unwrap()
         );
         let (legal_moves, _) = game.get_legal_moves();
 
@@ -1566,7 +3135,7 @@ mod tests {
 
     #[test]
     fn test_undo_move() {
-        let mut game = GameState::new(&*PSEUDO_LEGAL_MOVES, &*MAGICS);
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
 
         let board_initial = game.board.clone();
 
@@ -1578,67 +3147,44 @@ mod tests {
             side: Side::White,
         };
 
-        game.make_move(&m);
         let pos_hash = game.pos_hash;
         let castling_right_long = game.board.castling_right_long;
         let castling_right_short = game.board.castling_right_short;
-        let half_move_of_last_capture = game.half_move_of_last_capture;
+        let en_passant_square = game.board.en_passant_square;
+        let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
+        game.make_move(&m);
 
         game.update_board_undo_move(
             &m,
             pos_hash,
             &castling_right_long,
             &castling_right_short,
-            half_move_of_last_capture,
+            en_passant_square,
+            half_move_of_last_irreversible,
         );
 
         assert_eq!(game.half_move_number, 1);
         assert_eq!(game.move_number, 1);
         assert_eq!(game.board, board_initial);
 
-        // .B......
+        // .B.....k
         // ..p.....
         // ........
         // ........
         // ........
         // ........
         // ........
-        // ........
+        // .......K
         let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000100000000000000000000000000000000000000000000000000000,
-                    // Black pawns
-                    0,
-                ],
-                knights: [
-                    0, 0,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black bishops
-                    0b0100000000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    0, 0
-                ],
-                queens: [
-                    0, 0
-                ],
-                king: [
-                    0, 0
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("h1"), Piece::King, Side::White)
+                .piece(sq("c7"), Piece::Pawn, Side::White)
+                .piece(sq("h8"), Piece::King, Side::Black)
+                .piece(sq("b8"), Piece::Bishop, Side::Black)
+                .build()
+                .unwrap()
         );
 
         let board_initial = game.board.clone();
@@ -1654,10 +3200,11 @@ mod tests {
         let pos_hash = game.pos_hash;
         let castling_right_long = game.board.castling_right_long;
         let castling_right_short = game.board.castling_right_short;
-        let half_move_of_last_capture = game.half_move_of_last_capture;
+        let en_passant_square = game.board.en_passant_square;
+        let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
         game.make_move(&m);
 
-        game.update_board_undo_move(&m, pos_hash, &castling_right_long, &castling_right_short, half_move_of_last_capture);
+        game.update_board_undo_move(&m, pos_hash, &castling_right_long, &castling_right_short, en_passant_square, half_move_of_last_irreversible);
 
         assert_eq!(game.board, board_initial);
     }
@@ -1673,52 +3220,42 @@ mod tests {
         // pppppppp
         // R...K..R
         let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000100000000000000000000001111111100000000,
-                    // Black pawns
-                    0b0000000011111111000000000000000000000000000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black queens
-                    0b0001000000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::Black,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("b5"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .side_to_move(Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
 
         let m = Move {
@@ -1730,7 +3267,7 @@ mod tests {
         };
         game.make_move(&m);
 
-        assert_eq!(game.board.en_passant_square, 37 + BOARD_WIDTH);
+        assert_eq!(game.board.en_passant_square, Some(37 + BOARD_WIDTH));
 
         let (legal_moves, _) = game.get_legal_moves();
 
@@ -1746,103 +3283,81 @@ mod tests {
 
         game.make_move(&ep_move);
 
-        assert_eq!(game.board.en_passant_square, 0);
-
-        let board_after_ep = Board {
-            pawns: [
-                // White pawns
-                0b0000000000000000001000000000000000000000000000001111111100000000,
-                // Black pawns
-                0b0000000011011111000000000000000000000000000000000000000000000000,
-            ],
-            knights: [
-                // White knights
-                0b0000000000000000000000000000000000000000000000000000000000000000,
-                // Black knights
-                0b0100001000000000000000000000000000000000000000000000000000000000,
-            ],
-            bishops: [
-                // White bishops
-                0b0000000000000000000000000000000000000000000000000000000000000000,
-                // Black bishops
-                0b0010010000000000000000000000000000000000000000000000000000000000,
-            ],
-            rooks: [
-                // White rooks
-                0b0000000000000000000000000000000000000000000000000000000010000001,
-                // Black rooks
-                0b1000000100000000000000000000000000000000000000000000000000000000,
-            ],
-            queens: [
-                // White queens
-                0b0000000000000000000000000000000000000000000000000000000000000000,
-                // Black queens
-                0b0001000000000000000000000000000000000000000000000000000000000000,
-            ],
-            king: [
-                // White king
-                0b0000000000000000000000000000000000000000000000000000000000001000,
-                // Black king
-                0b0000100000000000000000000000000000000000000000000000000000000000,
-            ],
-
-            side_to_move: Side::Black,
-
-            en_passant_square: 0usize,
-            castling_right_long: [true, true],
-            castling_right_short: [true, true],
-        };
+        assert_eq!(game.board.en_passant_square, None);
+
+        let board_after_ep = BoardBuilder::new()
+            .piece(sq("e1"), Piece::King, Side::White)
+            .piece(sq("h1"), Piece::Rook, Side::White)
+            .piece(sq("a1"), Piece::Rook, Side::White)
+            .piece(sq("h2"), Piece::Pawn, Side::White)
+            .piece(sq("g2"), Piece::Pawn, Side::White)
+            .piece(sq("f2"), Piece::Pawn, Side::White)
+            .piece(sq("e2"), Piece::Pawn, Side::White)
+            .piece(sq("d2"), Piece::Pawn, Side::White)
+            .piece(sq("c2"), Piece::Pawn, Side::White)
+            .piece(sq("b2"), Piece::Pawn, Side::White)
+            .piece(sq("a2"), Piece::Pawn, Side::White)
+            .piece(sq("c6"), Piece::Pawn, Side::White)
+            .piece(sq("e8"), Piece::King, Side::Black)
+            .piece(sq("d8"), Piece::Queen, Side::Black)
+            .piece(sq("h8"), Piece::Rook, Side::Black)
+            .piece(sq("a8"), Piece::Rook, Side::Black)
+            .piece(sq("f8"), Piece::Bishop, Side::Black)
+            .piece(sq("c8"), Piece::Bishop, Side::Black)
+            .piece(sq("g8"), Piece::Knight, Side::Black)
+            .piece(sq("b8"), Piece::Knight, Side::Black)
+            .piece(sq("h7"), Piece::Pawn, Side::Black)
+            .piece(sq("g7"), Piece::Pawn, Side::Black)
+            .piece(sq("f7"), Piece::Pawn, Side::Black)
+            .piece(sq("e7"), Piece::Pawn, Side::Black)
+            .piece(sq("d7"), Piece::Pawn, Side::Black)
+            .piece(sq("b7"), Piece::Pawn, Side::Black)
+            .piece(sq("a7"), Piece::Pawn, Side::Black)
+            .side_to_move(Side::Black)
+            .castling(Side::White, true, true)
+            .castling(Side::Black, true, true)
+            .build()
+            .unwrap();
 
         assert_eq!(game.board, board_after_ep);
 
         // En-passant from black's side
         let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000001111111100000000,
-                    // Black pawns
-                    0b0000000011111111000000000000000000000010000000000000000000000000,
-                ],
-                knights: [
-                    // White knights
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black knights
-                    0b0100001000000000000000000000000000000000000000000000000000000000,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black bishops
-                    0b0010010000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    // White rooks
-                    0b0000000000000000000000000000000000000000000000000000000010000001,
-                    // Black rooks
-                    0b1000000100000000000000000000000000000000000000000000000000000000,
-                ],
-                queens: [
-                    // White queens
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black queens
-                    0b0001000000000000000000000000000000000000000000000000000000000000,
-                ],
-                king: [
-                    // White king
-                    0b0000000000000000000000000000000000000000000000000000000000001000,
-                    // Black king
-                    0b0000100000000000000000000000000000000000000000000000000000000000,
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("e1"), Piece::King, Side::White)
+                .piece(sq("h1"), Piece::Rook, Side::White)
+                .piece(sq("a1"), Piece::Rook, Side::White)
+                .piece(sq("h2"), Piece::Pawn, Side::White)
+                .piece(sq("g2"), Piece::Pawn, Side::White)
+                .piece(sq("f2"), Piece::Pawn, Side::White)
+                .piece(sq("e2"), Piece::Pawn, Side::White)
+                .piece(sq("d2"), Piece::Pawn, Side::White)
+                .piece(sq("c2"), Piece::Pawn, Side::White)
+                .piece(sq("b2"), Piece::Pawn, Side::White)
+                .piece(sq("a2"), Piece::Pawn, Side::White)
+                .piece(sq("e8"), Piece::King, Side::Black)
+                .piece(sq("d8"), Piece::Queen, Side::Black)
+                .piece(sq("h8"), Piece::Rook, Side::Black)
+                .piece(sq("a8"), Piece::Rook, Side::Black)
+                .piece(sq("f8"), Piece::Bishop, Side::Black)
+                .piece(sq("c8"), Piece::Bishop, Side::Black)
+                .piece(sq("g8"), Piece::Knight, Side::Black)
+                .piece(sq("b8"), Piece::Knight, Side::Black)
+                .piece(sq("g4"), Piece::Pawn, Side::Black)
+                .piece(sq("h7"), Piece::Pawn, Side::Black)
+                .piece(sq("g7"), Piece::Pawn, Side::Black)
+                .piece(sq("f7"), Piece::Pawn, Side::Black)
+                .piece(sq("e7"), Piece::Pawn, Side::Black)
+                .piece(sq("d7"), Piece::Pawn, Side::Black)
+                .piece(sq("c7"), Piece::Pawn, Side::Black)
+                .piece(sq("b7"), Piece::Pawn, Side::Black)
+                .piece(sq("a7"), Piece::Pawn, Side::Black)
+                .castling(Side::White, true, true)
+                .castling(Side::Black, true, true)
+                .build()
+                .unwrap()
         );
         let m = Move {
             from_square: 8,
@@ -1870,75 +3385,58 @@ mod tests {
         let pos_hash = game.pos_hash;
         let castling_right_long = game.board.castling_right_long;
         let castling_right_short = game.board.castling_right_short;
-        let half_move_of_last_capture = game.half_move_of_last_capture;
+        let en_passant_square = game.board.en_passant_square;
+        let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
 
         game.make_move(&ep_move);
 
-        assert_eq!(game.board.en_passant_square, 0);
-        assert_eq!(game.position_occurance_counter.get(&pos_hash).unwrap(), &1usize);
+        let pos_hash_after_ep = game.pos_hash;
+
+        assert_eq!(game.board.en_passant_square, None);
+        assert!(game.hash_stack.ends_with(&[pos_hash, pos_hash_after_ep]));
+        assert_eq!(game.repetitions(), 1);
 
         game.update_board_undo_move(
             &ep_move,
             pos_hash,
             &castling_right_long,
             &castling_right_short,
-            half_move_of_last_capture,
+            en_passant_square,
+            half_move_of_last_irreversible,
         );
 
         assert_eq!(game.board, board_before_ep);
         assert_eq!(game.board.en_passant_square, ep_square_before);
-        assert_eq!(game.position_occurance_counter.get(&pos_hash).unwrap(), &0usize);
+        assert_eq!(game.pos_hash, pos_hash);
+        assert_eq!(game.hash_stack.last(), Some(&pos_hash));
+        assert!(!game.hash_stack.contains(&pos_hash_after_ep));
+        assert_eq!(game.repetitions(), 1);
     }
 
     #[test]
     fn test_threefold_repetition() {
-        // .B......
-        // ..p.....
-        // ........
-        // ........
+        // q.......
         // ........
+        // .....k..
         // ........
         // ........
+        // ..K.....
         // ........
+        // .......Q
         let mut game = GameState::from_board(
-            &*PSEUDO_LEGAL_MOVES,
-            &*MAGICS,
-            Board {
-                pawns: [
-                    // White pawns
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black pawns
-                    0,
-                ],
-                knights: [
-                    0, 0,
-                ],
-                bishops: [
-                    // White bishops
-                    0b0000000000000000000000000000000000000000000000000000000000000000,
-                    // Black bishops
-                    0b0100000000000000000000000000000000000000000000000000000000000000,
-                ],
-                rooks: [
-                    0, 0
-                ],
-                queens: [
-                    0b0000000000000000000000000000000000000000000000000000000000000100,
-                    0b1000000000000000000000000000000000000000000000000000000000000000
-                ],
-                king: [
-                    0, 0
-                ],
-
-                side_to_move: Side::White,
-
-                en_passant_square: 0usize,
-                castling_right_long: [true, true],
-                castling_right_short: [true, true],
-            }
+            &PSEUDO_LEGAL_MOVES,
+            &MAGICS,
+            BoardBuilder::new()
+                .piece(sq("c3"), Piece::King, Side::White)
+                .piece(sq("h1"), Piece::Queen, Side::White)
+                .piece(sq("f6"), Piece::King, Side::Black)
+                .piece(sq("a8"), Piece::Queen, Side::Black)
+                .build()
+                .unwrap()
         );
 
-        for i in 0..3 {
+        // The starting position occurs for the third time after two cycles
+        for i in 0..2 {
             // White: Qg1
             game.make_move(&Move {
                 from_square: 0,
@@ -1975,15 +3473,875 @@ mod tests {
                 side: Side::Black,
             });
 
-            if i < 2 {
-                let (legal_moves, in_check) = game.get_legal_moves();
-                let move_result = game.get_move_result(&legal_moves, in_check);
-                assert_eq!(move_result, None);
+            if i < 1 {
+                assert_eq!(game.claimable_draw(), None);
+            }
+        }
+
+        // A draw can be claimed, but the game goes on
+        assert_eq!(game.claimable_draw(), Some(DrawReason::ThreeFoldRepetition));
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert_eq!(game.get_move_result(&legal_moves, in_check), None);
+    }
+
+    fn play_algebraic(game: &mut GameState, move_str: &str) -> Move {
+        let (legal_moves, _) = game.get_legal_moves();
+        let m = *legal_moves.iter()
+            .find(|m| m.to_algebraic_with_state(&legal_moves) == move_str)
+            .unwrap();
+        game.make_move(&m);
+        m
+    }
+
+    fn find_algebraic(game: &GameState, move_str: &str) -> Move {
+        let (legal_moves, _) = game.get_legal_moves();
+        *legal_moves.iter()
+            .find(|m| m.to_algebraic_with_state(&legal_moves) == move_str)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_from_fen() {
+        let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, start_fen).unwrap();
+        let start_game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(game.board, start_game.board);
+        assert_eq!(game.half_move_number, start_game.half_move_number);
+
+        // Same position as reached by playing the moves
+        let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/3P1N2/PPP2PPP/RNBQKB1R b KQkq e3 0 3";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        let mut played_game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        for move_str in ["Nf3", "d5", "d3", "d4", "e4"] {
+            play_algebraic(&mut played_game, move_str);
+        }
+        assert_eq!(game.board, played_game.board);
+        assert_eq!(game.move_number, played_game.move_number);
+        assert_eq!(game.half_move_number, played_game.half_move_number);
+        assert_eq!(game.half_move_clock(), played_game.half_move_clock());
+        assert!(find_algebraic(&game, "dxe3").is_capture());
+
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(played_game.to_fen(), fen);
+        assert_eq!(start_game.to_fen(), start_fen);
+
+        // The halfmove clock counts half moves since the last capture or pawn move
+        for move_str in ["Nc6", "Nc3", "Nb8"] {
+            play_algebraic(&mut played_game, move_str);
+        }
+        assert_eq!(played_game.to_fen(), "rnbqkbnr/ppp1pppp/8/8/3pP3/2NP1N2/PPP2PPP/R1BQKB1R w KQkq - 3 5");
+        play_algebraic(&mut played_game, "a3");
+        assert_eq!(played_game.half_move_clock(), 0);
+
+        let fen = "r3k2r/8/8/8/8/8/8/4K2R b Kq - 12 40";
+        assert_eq!(GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap().to_fen(), fen);
+
+        assert!(matches!(GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "8/8/8/8/8/8/8/8 w - - 0 1"), Err(Error::IllegalPosition(_))));
+        assert!(matches!(
+            GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1"),
+            Err(Error::InvalidFen(_))
+        ));
+    }
+
+    #[test]
+    fn test_board_builder() {
+        let board = BoardBuilder::new()
+            .piece(sq("e1"), Piece::King, Side::White)
+            .piece(sq("h1"), Piece::Rook, Side::White)
+            .piece(sq("e4"), Piece::Pawn, Side::White)
+            .piece(sq("e8"), Piece::King, Side::Black)
+            .piece(sq("d4"), Piece::Pawn, Side::Black)
+            .side_to_move(Side::Black)
+            .castling(Side::White, true, false)
+            .ep(sq("e3"))
+            .build()
+            .unwrap();
+        assert_eq!(board, Board::from_fen("4k3/8/8/8/3pP3/8/8/4K2R b K e3").unwrap());
+
+        assert!(BoardBuilder::new()
+            .piece(sq("e1"), Piece::King, Side::White)
+            .piece(sq("e1"), Piece::Queen, Side::White)
+            .build()
+            .is_err());
+        assert!(BoardBuilder::new().piece(N_SQUARES, Piece::King, Side::White).build().is_err());
+        assert!(BoardBuilder::new().ep(sq("e4")).build().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Board::default().validate().is_ok());
+        assert!(Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq -").is_ok());
+
+        for fen in [
+            // Missing or extra king
+            "8/8/8/8/8/8/8/4K3 w - -",
+            "4k3/8/8/8/8/8/8/3KK3 w - -",
+            // Pawn on the back rank
+            "4k2P/8/8/8/8/8/8/4K3 w - -",
+            // Castling rights without the rook or with the king moved
+            "r3k3/8/8/8/8/8/8/4K3 w k -",
+            "r3k2r/8/8/8/8/8/8/R2K3R w Q -",
+            // The side that just moved is in check
+            "4k3/8/8/8/8/8/8/r3K3 b - -",
+        ] {
+            assert!(Board::from_fen(fen).is_err(), "{}", fen);
+        }
+
+        let mut board = Board::default();
+        board.queens[Side::Black as usize] |= 1 << sq("e2");
+        assert!(board.validate().is_err());
+    }
+
+    #[test]
+    fn test_flipped_mirrored() {
+        let board = Board::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/3P1N2/PPP2PPP/RNBQKB1R b Kq e3").unwrap();
+        assert_eq!(board.flipped().to_fen(), "rnbqkb1r/ppp2ppp/3p1n2/3Pp3/8/8/PPP1PPPP/RNBQKBNR w Qk e6");
+        assert_eq!(board.mirrored().to_fen(), "rnbkqbnr/pppp1ppp/8/8/3Pp3/2N1P3/PPP2PPP/R1BKQBNR b - d3");
+        assert_eq!(board.flipped().flipped(), board);
+        assert_eq!(board.mirrored().flipped(), board.flipped().mirrored());
+    }
+
+    #[test]
+    fn test_apply_moves() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1"]).unwrap();
+        assert_eq!(game.to_fen(), "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4");
+
+        // Nothing is played when a move is illegal
+        assert!(matches!(game.apply_moves(&["f6e4", "e8e7"]), Err(Error::IllegalMove(m)) if m == "e8e7"));
+        assert!(matches!(game.apply_moves(&["e4"]), Err(Error::InvalidMove(_))));
+        assert_eq!(game.to_fen(), "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4");
+
+        // Repetitions count towards a threefold repetition
+        game.apply_moves(&["f6g8", "f3e1", "g8f6", "e1f3", "f6g8", "f3e1", "g8f6"]).unwrap();
+        assert!(!game.threefold_repetition);
+        game.apply_moves(&["e1f3"]).unwrap();
+        assert!(game.threefold_repetition);
+
+        // Promotions need the promotion piece
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "1n2k3/2P5/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(game.parse_move("c7c8").is_err());
+        assert_eq!(game.parse_move("c7b8n").unwrap().move_type, MoveType::CapturePromotion(Piece::Knight, Piece::Knight));
+        game.apply_moves(&["c7c8q"]).unwrap();
+        assert_eq!(game.to_fen(), "1nQ1k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4", "e7e5", "g1f3", "g8f6", "f3g1", "f6g8"]).unwrap();
+        let snapshot = game.snapshot();
+
+        assert_eq!(snapshot.to_fen(), game.to_fen());
+        assert_eq!(snapshot.pos_hash, game.pos_hash);
+        assert_eq!(snapshot.repetitions(), 2);
+        assert_eq!(snapshot.n_played_moves(), 0);
+        assert_eq!(snapshot.hash_stack.len(), snapshot.half_move_clock() + 1);
+        assert!(std::mem::size_of::<GameState>() < 512);
+
+        // Repetitions before the snapshot still count
+        let mut snapshot = snapshot;
+        snapshot.apply_moves(&["g1f3", "g8f6", "f3g1", "f6g8"]).unwrap();
+        assert!(snapshot.threefold_repetition);
+    }
+
+    #[test]
+    fn test_send_across_threads() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4", "e7e5"]).unwrap();
+
+        let handles: Vec<_> = ["g1f3", "d2d4"].into_iter().map(|m| {
+            let mut game = game.clone();
+            std::thread::spawn(move || {
+                game.apply_moves(&[m]).unwrap();
+                game.get_legal_moves().0.len()
+            })
+        }).collect();
+
+        let n_moves: Vec<usize> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(n_moves, [29, 30]);
+        assert_eq!(game.n_played_moves(), 2);
+    }
+
+    #[test]
+    fn test_take_back_moves() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        let start = game.clone();
+        assert_eq!(game.undo_move(), None);
+
+        // Captures, en passant, a promotion and castling
+        let moves = ["e2e4", "d7d5", "e4e5", "f7f5", "e5f6", "g8h6", "f6g7", "h6g8", "g7h8q", "g8h6", "g1f3", "e7e6", "f1e2", "b8c6", "e1g1"];
+        game.apply_moves(&moves).unwrap();
+        assert_eq!(game.n_played_moves(), moves.len());
+
+        let m = game.parse_move("h6g8").unwrap();
+        game.make_move(&m);
+        assert_eq!(game.undo_move(), Some(m));
+
+        for move_str in moves.iter().rev() {
+            let fen = game.to_fen();
+            let m = game.undo_move().unwrap();
+            assert_eq!(game.parse_move(move_str).unwrap(), m);
+
+            game.make_move(&m);
+            assert_eq!(game.to_fen(), fen);
+            game.undo_move();
+        }
+
+        assert_eq!(game.n_played_moves(), 0);
+        assert_eq!(game.board, start.board);
+        assert_eq!(game.to_fen(), start.to_fen());
+        assert_eq!(game.pos_hash, start.pos_hash);
+        assert_eq!(game.psqt_score, start.psqt_score);
+        assert_eq!(game.undo_move(), None);
+
+        // A new position can't be taken back to the old one
+        game.apply_moves(&["e2e4"]).unwrap();
+        game.set_board(Board::default());
+        assert_eq!(game.undo_move(), None);
+    }
+
+    #[test]
+    fn test_move_history() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(game.move_history(), "");
+        game.apply_moves(&["e2e4", "e7e5", "g1f3", "b8c6", "f1c4"]).unwrap();
+        assert_eq!(game.move_history(), "1. e4 e5 2. Nf3 Nc6 3. Bc4");
+        assert_eq!(game.move_history_with(|i| if i == 2 { "!" } else { "" }), "1. e4 e5 2. Nf3! Nc6 3. Bc4");
+
+        let fen = "4k3/1P6/8/8/8/8/8/R3K3 b Q - 0 40";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        game.apply_moves(&["e8d7", "e1c1", "d7c7", "b7b8q"]).unwrap();
+        assert_eq!(game.move_history(), "40. ..Kd7 41. 0-0-0 Kc7 42. b8=Q");
+    }
+
+    #[test]
+    fn test_captured_pieces() {
+        // Scandinavian with black's queen lost for a pawn, and a pawn captured
+        // en passant
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5d2", "c1d2", "c7c5", "f2f4", "c5c4", "b2b4", "c4b3"]).unwrap();
+        assert_eq!(game.captured_pieces(Side::White), vec![Piece::Queen, Piece::Pawn]);
+        assert_eq!(game.captured_pieces(Side::Black), vec![Piece::Pawn, Piece::Pawn, Piece::Pawn]);
+        assert_eq!(game.board.material_points(Side::White), 39 - 3);
+        assert_eq!(game.board.material_points(Side::Black), 39 - 9 - 1);
+
+        game.undo_move();
+        assert_eq!(game.captured_pieces(Side::Black), vec![Piece::Pawn, Piece::Pawn]);
+    }
+
+    #[test]
+    fn test_three_check() {
+        let mut game = GameState::with_variant(&PSEUDO_LEGAL_MOVES, &MAGICS, Variant::ThreeCheck);
+        game.apply_moves(&["e2e4", "e7e5", "f1c4"]).unwrap();
+        assert_eq!(game.board.checks, [0, 0]);
+        game.apply_moves(&["b8c6", "c4f7"]).unwrap();
+        assert_eq!(game.board.checks, [1, 0]);
+        assert_eq!(game.to_fen(), "r1bqkbnr/pppp1Bpp/2n5/4p3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 2+3 0 3");
+
+        game.undo_move();
+        assert_eq!(game.board.checks, [0, 0]);
+        assert_eq!(game.pos_hash, game.zobrist_hasher.hash(&game.board));
+
+        // The third check ends the game even though it can be answered
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 1+3 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.board.variant, Variant::ThreeCheck);
+        assert_eq!(game.to_fen(), fen);
+        game.apply_moves(&["a1a8"]).unwrap();
+        assert_eq!(game.variant_result(), Some(MoveResult::ThirdCheck));
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert_eq!(game.move_result(!legal_moves.is_empty(), in_check), Some(MoveResult::ThirdCheck));
+    }
+
+    #[test]
+    fn test_crazyhouse() {
+        // Captured pieces go to the hand and can be dropped
+        let mut game = GameState::with_variant(&PSEUDO_LEGAL_MOVES, &MAGICS, Variant::Crazyhouse);
+        game.apply_moves(&["e2e4", "d7d5", "e4d5", "d8d5"]).unwrap();
+        assert_eq!(game.to_fen(), "rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR[Pp] w KQkq - 0 3");
+
+        let m = game.parse_move("P@e6").unwrap();
+        assert_eq!(m.to_algebraic_with_state(&[]), "@e6");
+        assert_eq!(m.to_coordinate(), "P@e6");
+        game.apply_moves(&["P@e6"]).unwrap();
+        assert_eq!(game.board.in_hand(Piece::Pawn, Side::White), 0);
+        assert_eq!(game.board.piece_on(sq("e6")), Some((Piece::Pawn, Side::White)));
+        assert!(matches!(game.parse_move("P@d5"), Err(Error::IllegalMove(_))));
+
+        game.undo_move();
+        assert_eq!(game.board.in_hand(Piece::Pawn, Side::White), 1);
+        assert_eq!(game.pos_hash, game.zobrist_hasher.hash(&game.board));
+
+        // Pawns can't be dropped on the first and last ranks
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "4k3/8/8/8/8/8/8/4K3[P] w - - 0 1").unwrap();
+        let (legal_moves, _) = game.get_legal_moves();
+        assert_eq!(legal_moves.iter().filter(|m| m.move_type == MoveType::Drop).count(), 48);
+
+        // In check, pieces can only be dropped between the king and the checker
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1").unwrap();
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert!(in_check);
+        let mut drops: Vec<String> = legal_moves.iter()
+            .filter(|m| m.move_type == MoveType::Drop)
+            .map(|m| m.to_coordinate())
+            .collect();
+        drops.sort();
+        assert_eq!(drops, ["N@b1", "N@c1", "N@d1"]);
+        assert_eq!(game.legal_moves_iter().collect::<Vec<_>>(), legal_moves);
+        assert_eq!(game.legal_moves_iter().count(), legal_moves.len());
+        assert!(legal_moves.iter().all(|m| game.is_legal(m)));
+
+        // A captured promoted piece goes to the hand as a pawn
+        let fen = "r3k3/Q~7/8/8/8/8/8/4K3[] b - - 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.to_fen(), fen);
+        game.apply_moves(&["a8a7"]).unwrap();
+        assert_eq!(game.to_fen(), "4k3/r7/8/8/8/8/8/4K3[p] w - - 0 2");
+        game.undo_move();
+        assert_eq!(game.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_unusable_en_passant_square() {
+        // No black pawn can capture on e3, so the position after 1. e4 is
+        // repeated by moving the knights back and forth
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4"]).unwrap();
+        assert_eq!(game.board.en_passant_square, None);
+        assert_eq!(game.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+
+        game.apply_moves(&["g8f6", "g1f3", "f6g8", "f3g1", "g8f6", "g1f3", "f6g8"]).unwrap();
+        assert!(!game.threefold_repetition);
+        game.apply_moves(&["f3g1"]).unwrap();
+        assert!(game.threefold_repetition);
+
+        // The pawn on d4 can't capture en passant while pinned to its king,
+        // nor can the pawn on f4 while the king is in check by the bishop
+        for fen in ["3R4/8/8/8/3p4/8/4P3/K2k4 w - - 0 1", "8/8/8/7k/5p2/8/4P3/K2B4 w - - 0 1"] {
+            let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            game.apply_moves(&["e2e4"]).unwrap();
+            assert_eq!(game.board.en_passant_square, None, "{}", fen);
+        }
+
+        // The en passant square of a FEN is dropped as well
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.board.en_passant_square, None);
+        assert_eq!(game.pos_hash, game.zobrist_hasher.hash(&game.board));
+
+        // A usable en passant square is kept
+        let fen = "4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        game.apply_moves(&["e2e4"]).unwrap();
+        assert_eq!(game.board.en_passant_square, Some(sq("e3")));
+    }
+
+    #[test]
+    fn test_piece_on() {
+        let mailbox_matches = |board: &Board| (0..64).all(|square| {
+            let piece = Piece::VALUES.into_iter()
+                .flat_map(|piece| Side::VALUES.map(|side| (piece, side)))
+                .find(|&(piece, side)| board[(piece, side)] & (1 << square) != 0);
+            board.piece_on(square) == piece
+        });
+
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        assert_eq!(game.board.piece_on(sq("e1")), Some((Piece::King, Side::White)));
+        assert_eq!(game.board.piece_on(sq("d8")), Some((Piece::Queen, Side::Black)));
+        assert_eq!(game.board.piece_on(sq("e4")), None);
+
+        // Castling, promotions, en passant and captures, made and undone
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            assert!(mailbox_matches(&game.board));
+            assert!(mailbox_matches(&game.board.flipped()));
+            assert!(mailbox_matches(&game.board.mirrored()));
+
+            let board = game.board.clone();
+            let (legal_moves, _) = game.get_legal_moves();
+            for m in &legal_moves {
+                let pos_hash = game.pos_hash;
+                let castling_right_long = game.board.castling_right_long;
+                let castling_right_short = game.board.castling_right_short;
+                let en_passant_square = game.board.en_passant_square;
+                let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
+
+                game.update_board_with_move(m);
+                assert!(mailbox_matches(&game.board), "{} {:?}", fen, m);
+                game.update_board_undo_move(
+                    m,
+                    pos_hash,
+                    &castling_right_long,
+                    &castling_right_short,
+                    en_passant_square,
+                    half_move_of_last_irreversible,
+                );
+                assert_eq!(game.board, board);
+            }
+        }
+    }
+
+    #[test]
+    fn test_en_passant_square_none() {
+        // Without an en passant square, h1 isn't capturable
+        let fen = "4k3/8/8/8/8/8/6p1/4K3 b - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.board.en_passant_square, None);
+        let (legal_moves, _) = game.get_legal_moves();
+        assert!(legal_moves.iter().all(|m| m.to_square != 0));
+
+        // En passant onto the h-file
+        let fen = "4k3/8/8/8/6pP/8/8/4K3 b - h3 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.board.en_passant_square, Some(16));
+        let m = find_algebraic(&game, "gxh3");
+        assert_eq!(m.move_type, MoveType::EnPassantCapture(Piece::Pawn));
+    }
+
+    #[test]
+    fn test_is_square_attacked() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        let attacks = [game.attacks(Side::White), game.attacks(Side::Black)];
+        for square in 0..N_SQUARES {
+            for side in Side::VALUES {
+                assert_eq!(
+                    game.is_square_attacked(square, side),
+                    attacks[side as usize] & (1 << square) != 0,
+                    "{:?} attack on {}",
+                    side,
+                    Board::square_to_algebraic(square)
+                );
             }
         }
+    }
+
+    #[test]
+    fn test_null_move() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        play_algebraic(&mut game, "e4");
+
+        let board_before = game.board.clone();
+        let pos_hash = game.pos_hash;
+
+        let en_passant_square = game.make_null_move();
+        assert_eq!(game.board.side_to_move, Side::White);
+        assert_eq!(game.board.en_passant_square, None);
+        assert_ne!(game.pos_hash, pos_hash);
+
+        game.undo_null_move(pos_hash, en_passant_square);
+        assert_eq!(game.board, board_before);
+        assert_eq!(game.pos_hash, pos_hash);
+
+        assert!(game.board.has_non_pawn_material(Side::White));
+        let pawn_ending = Board::from_fen("8/4kp2/8/8/8/8/3PK3/8 w - - 0 1").unwrap();
+        assert!(!pawn_ending.has_non_pawn_material(Side::White));
+    }
+
+    #[test]
+    fn test_fifty_move_rule() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        play_algebraic(&mut game, "e4");
+        assert_eq!(game.half_move_clock(), 0);
+
+        play_algebraic(&mut game, "Nf6");
+        assert_eq!(game.half_move_clock(), 1);
+
+        play_algebraic(&mut game, "Nf3");
+        play_algebraic(&mut game, "Nxe4");
+        assert_eq!(game.half_move_clock(), 0);
+
+        // Without repeating the position, which would end the game by
+        // fivefold repetition first
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "8/8/4k3/8/8/8/3QK3/8 w - - 98 70").unwrap();
+        play_algebraic(&mut game, "Qd3");
+        assert_eq!(game.half_move_clock(), 99);
+        assert_eq!(game.claimable_draw(), None);
+
+        play_algebraic(&mut game, "Ke7");
+        assert_eq!(game.half_move_clock(), 100);
+        assert_eq!(game.claimable_draw(), Some(DrawReason::FiftyMoveRule));
+
+        // Only claimable, the game goes on
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert_eq!(game.get_move_result(&legal_moves, in_check), None);
+    }
+
+    #[test]
+    fn test_automatic_draws() {
+        // Past the fifty move rule the game only goes on until the
+        // seventy-five move rule ends it
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "8/8/4k3/8/8/8/3QK3/8 w - - 148 120").unwrap();
+        play_algebraic(&mut game, "Qd3");
+        assert_eq!(game.half_move_clock(), 149);
+        assert_eq!(game.claimable_draw(), Some(DrawReason::FiftyMoveRule));
+        let (legal_moves, in_check) = game.get_legal_moves();
+        assert_eq!(game.get_move_result(&legal_moves, in_check), None);
 
+        play_algebraic(&mut game, "Ke7");
+        assert_eq!(game.half_move_clock(), 150);
         let (legal_moves, in_check) = game.get_legal_moves();
-        let move_result = game.get_move_result(&legal_moves, in_check);
-        assert_eq!(move_result, Some(MoveResult::Draw(DrawReason::ThreeFoldRepetition)));
+        assert_eq!(
+            game.get_move_result(&legal_moves, in_check),
+            Some(MoveResult::Draw(DrawReason::SeventyFiveMoveRule))
+        );
+
+        // The starting position occurs for the fifth time after four cycles
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        for i in 0..4 {
+            for move_str in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+                play_algebraic(&mut game, move_str);
+            }
+
+            // A draw can be claimed from the third occurrence on, but only the
+            // fifth ends the game
+            let (legal_moves, in_check) = game.get_legal_moves();
+            let (claimable, expected) = match i {
+                0 => (None, None),
+                1 | 2 => (Some(DrawReason::ThreeFoldRepetition), None),
+                _ => (Some(DrawReason::ThreeFoldRepetition), Some(MoveResult::Draw(DrawReason::FiveFoldRepetition))),
+            };
+            assert_eq!(game.claimable_draw(), claimable);
+            assert_eq!(game.get_move_result(&legal_moves, in_check), expected);
+        }
+        assert_eq!(game.repetitions(), 5);
+    }
+
+    #[test]
+    fn test_is_legal() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        for move_str in ["e4", "e5", "Nf3", "Nc6", "Bb5"] {
+            let (legal_moves, _) = game.get_legal_moves();
+            assert!(legal_moves.iter().all(|m| game.is_legal(m)));
+            play_algebraic(&mut game, move_str);
+        }
+
+        assert!(!game.is_legal(&NULL_MOVE));
+
+        // The knight gets pinned once the d-pawn moves
+        let knight_move = find_algebraic(&game, "Nce7");
+        assert!(game.is_legal(&knight_move));
+        play_algebraic(&mut game, "d6");
+        let bishop_capture = find_algebraic(&game, "Bxc6");
+        play_algebraic(&mut game, "0-0");
+        assert!(!game.is_legal(&knight_move));
+
+        // Move of the other side
+        assert!(!game.is_legal(&bishop_capture));
+
+        play_algebraic(&mut game, "Bg4");
+        play_algebraic(&mut game, "d3");
+
+        // Wrong move type
+        let bishop_capture = find_algebraic(&game, "Bxf3");
+        assert!(game.is_legal(&bishop_capture));
+        assert!(!game.is_legal(&Move { move_type: MoveType::Quiet, ..bishop_capture }));
+
+        // Moves of other positions are only legal if they're legal here too
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "8/8/8/2k5/2pP4/8/B7/4K3 b - d3 0 1",
+            "8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1",
+        ];
+        let games: Vec<GameState> = fens.iter()
+            .map(|fen| GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap())
+            .collect();
+        let all_moves: Vec<Move> = games.iter().flat_map(|game| game.get_legal_moves().0).collect();
+        for game in &games {
+            let (legal_moves, _) = game.get_legal_moves();
+            for m in &all_moves {
+                assert_eq!(game.is_legal(m), legal_moves.contains(m), "{}: {:?}", game.to_fen(), m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gives_check() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        play_algebraic(&mut game, "e4");
+        play_algebraic(&mut game, "f5");
+
+        // Direct checks
+        assert!(game.gives_check(&find_algebraic(&game, "Qh5")));
+        assert!(!game.gives_check(&find_algebraic(&game, "Qg4")));
+        assert!(!game.gives_check(&find_algebraic(&game, "exf5")));
+
+        // Discovered checks by the queen on a4 once the knight on b5 moves
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        for move_str in ["e4", "d6", "Nc3", "a6", "Nb5", "h6", "c3", "h5", "Qa4", "g6"] {
+            play_algebraic(&mut game, move_str);
+        }
+        assert!(game.gives_check(&find_algebraic(&game, "Na3")));
+        assert!(game.gives_check(&find_algebraic(&game, "Nd4")));
+        assert!(!game.gives_check(&find_algebraic(&game, "Qb3")));
+
+        // Same as making the move for every move of positions with castling,
+        // promotions and en passant captures
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K2B b - d3 0 1",
+        ] {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            let (legal_moves, _) = game.get_legal_moves();
+            for m in &legal_moves {
+                let mut game_after = game.clone();
+                game_after.update_board_with_move(m);
+                let (_, in_check) = game_after.get_legal_moves();
+                assert_eq!(game.gives_check(m), in_check, "{}: {:?}", fen, m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_illegal_reason() {
+        let reason = |fen: &str, from: &str, to: &str| {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            game.illegal_reason(Board::square_from_algebraic(from).unwrap(), Board::square_from_algebraic(to).unwrap())
+        };
+        let sq = |square: &str| Board::square_from_algebraic(square).unwrap();
+
+        let fen = "4k3/8/8/8/8/4n3/4P3/4K3 w - - 0 1";
+        assert_eq!(reason(fen, "e2", "e4"), Some(IllegalReason::Blocked(Piece::Pawn, sq("e2"), Piece::Knight, sq("e3"))));
+        assert_eq!(reason(fen, "e2", "d3"), Some(IllegalReason::NothingToCapture(sq("e2"), sq("d3"))));
+        assert_eq!(reason(fen, "e1", "e2"), Some(IllegalReason::OwnPieceOnTarget(Piece::Pawn, sq("e2"))));
+        assert_eq!(reason(fen, "e1", "d1"), Some(IllegalReason::KingIntoCheck(sq("d1"))));
+        assert_eq!(reason(fen, "e8", "e7"), Some(IllegalReason::NotYourPiece(Piece::King, sq("e8"))));
+        assert_eq!(reason(fen, "a1", "a2"), Some(IllegalReason::NoPiece(sq("a1"))));
+
+        // Sliders
+        let fen = "4k3/8/8/8/7p/8/8/4K2R w - - 0 1";
+        assert_eq!(reason(fen, "h1", "h8"), Some(IllegalReason::Blocked(Piece::Rook, sq("h1"), Piece::Pawn, sq("h4"))));
+        assert_eq!(reason(fen, "h1", "g2"), Some(IllegalReason::CantMoveThere(Piece::Rook, sq("h1"), sq("g2"))));
+
+        // Pins and checks
+        let fen = "4k3/8/8/8/1b6/2N5/8/4K3 w - - 0 1";
+        assert_eq!(reason(fen, "c3", "d5"), Some(IllegalReason::Pinned(Piece::Knight, sq("c3"), Piece::Bishop, sq("b4"))));
+        let fen = "4k3/8/8/8/8/8/3P4/r3K3 w - - 0 1";
+        assert_eq!(reason(fen, "d2", "d3"), Some(IllegalReason::DoesntAddressCheck));
+        assert_eq!(reason(fen, "e1", "f1"), Some(IllegalReason::KingIntoCheck(sq("f1"))));
+        let fen = "4k3/8/8/8/8/5n2/3P4/r3K3 w - - 0 1";
+        assert_eq!(reason(fen, "d2", "d3"), Some(IllegalReason::DoubleCheck));
+
+        // Castling
+        assert_eq!(reason("4k3/8/8/8/8/8/5r2/4K2R w K - 0 1", "e1", "g1"), Some(IllegalReason::CastlingPathAttacked(sq("f1"))));
+        assert_eq!(reason("4k3/8/8/8/8/8/8/4KB1R w K - 0 1", "e1", "g1"), Some(IllegalReason::CastlingPathBlocked(Piece::Bishop, sq("f1"))));
+        assert_eq!(reason("4k3/8/8/8/8/8/8/r3K2R w K - 0 1", "e1", "g1"), Some(IllegalReason::CastlingInCheck));
+        assert_eq!(reason("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "e1", "c1"), Some(IllegalReason::NoCastlingRight(false)));
+
+        // Legal moves have no reason
+        assert_eq!(reason("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2", "e4"), None);
+    }
+    #[test]
+    fn test_see() {
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        play_algebraic(&mut game, "e4");
+        play_algebraic(&mut game, "d5");
+
+        // Pawn for pawn, black's queen recaptures
+        assert_eq!(game.see(&find_algebraic(&game, "exd5")), 0);
+
+        play_algebraic(&mut game, "Qh5");
+
+        // Undefended pawn
+        assert_eq!(game.see(&find_algebraic(&game, "dxe4")), 100);
+
+        play_algebraic(&mut game, "Nf6");
+
+        // Queen takes pawns defended by the rook/knight and the king
+        assert_eq!(game.see(&find_algebraic(&game, "Qxh7")), 100 - 900);
+        assert_eq!(game.see(&find_algebraic(&game, "Qxf7")), 100 - 900);
+
+        play_algebraic(&mut game, "a3");
+
+        // Undefended queen
+        assert_eq!(game.see(&find_algebraic(&game, "Nxh5")), 900);
+    }
+
+    #[test]
+    fn test_psqt_score() {
+        // Castling both ways, en passant and (capture) promotions available
+        let fen = "r3k2r/1P4pp/8/3pP3/8/8/6PP/R3K2R w KQkq d6 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        let (legal_moves, _) = game.get_legal_moves();
+
+        for m in &legal_moves {
+            let pos_hash = game.pos_hash;
+            let castling_right_long = game.board.castling_right_long;
+            let castling_right_short = game.board.castling_right_short;
+            let en_passant_square = game.board.en_passant_square;
+            let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
+            let score_before = game.psqt_score;
+
+            game.update_board_with_move(m);
+            assert_eq!(game.psqt_score, psqt_score(&game.board, &EvalParams::DEFAULT));
+
+            game.update_board_undo_move(
+                m,
+                pos_hash,
+                &castling_right_long,
+                &castling_right_short,
+                en_passant_square,
+                half_move_of_last_irreversible,
+            );
+            assert_eq!(game.psqt_score, score_before);
+        }
+    }
+
+    #[test]
+    fn test_pinned_pieces() {
+        // The knight on c6 is pinned by the bishop on b5 once the d-pawn moves,
+        // regardless of the side to move
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        for move_str in ["e4", "e5", "Nf3", "Nc6", "Bb5"] {
+            play_algebraic(&mut game, move_str);
+        }
+        assert_eq!(game.pinned_pieces(Side::Black), 0);
+
+        play_algebraic(&mut game, "d6");
+        assert_eq!(Bb(game.pinned_pieces(Side::Black)), Bb::from_squares(&["c6"]).unwrap());
+        assert_eq!(game.pinned_pieces(Side::White), 0);
+
+        // Two pieces between the slider and the king aren't pinned
+        let fen = "4k3/4r3/8/8/8/4N3/4B3/4K3 w - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.pinned_pieces(Side::White), 0);
+
+        // An enemy piece in between doesn't make a pin either
+        let fen = "4k3/4r3/8/8/8/4n3/4B3/4K3 b - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.pinned_pieces(Side::White), 0);
+        let fen = "4k3/4r3/8/8/8/8/4B3/4K3 b - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(Bb(game.pinned_pieces(Side::White)), Bb::from_squares(&["e2"]).unwrap());
+    }
+
+    #[test]
+    fn test_attacks() {
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        // Everything on the second and third rank, plus the squares around the
+        // back rank pieces that are covered by a neighbour
+        let white_attacks: Bb = "
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            . . . . . . . .
+            1 1 1 1 1 1 1 1
+            1 1 1 1 1 1 1 1
+            . 1 1 1 1 1 1 .
+        ".parse().unwrap();
+        assert_eq!(Bb(game.attacks(Side::White)), white_attacks);
+        assert_eq!(Bb(game.attacks(Side::Black)), Bb(white_attacks.0.swap_bytes()));
+
+        // The rook's ray stops at the king, unless the king is excluded
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "4r1k1/8/8/8/4K3/8/8/8 w - - 0 1").unwrap();
+        let king_square = Board::square_from_algebraic("e4").unwrap();
+        let behind_king = Board::square_from_algebraic("e3").unwrap();
+        assert_eq!(game.attacks(Side::Black) & (1 << behind_king), 0);
+        assert_ne!(game.attack_map(Side::Black, 1 << king_square) & (1 << behind_king), 0);
+    }
+
+    #[test]
+    fn test_bitboards() {
+        let squares = |squares: &[&str]| Some(Bb::from_squares(squares).unwrap());
+
+        // The bishop can only move along the rook's file, the ray includes its
+        // own square
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1").unwrap();
+        let bitboards = game.bitboards();
+        let get = |name| bitboards.get(name).map(Bb);
+        assert_eq!(get("White Bishop"), squares(&["e2"]));
+        assert_eq!(get("Occupancy"), squares(&["e8", "e7", "e2", "e1"]));
+        assert_eq!(get("Attacked by Black"), Some(Bb(game.attacks(Side::Black))));
+        assert_eq!(get("Pin of e2"), squares(&["e2", "e3", "e4", "e5", "e6", "e7"]));
+        assert_eq!(bitboards.get("Pin of e1"), None);
+        assert_eq!(bitboards.get("Check evasions"), None);
+
+        let display = bitboards.to_string();
+        let lines: Vec<&str> = display.lines().collect();
+        assert_eq!(lines[0], "White Pawn         White Knight       White Bishop       White Rook");
+        assert_eq!(lines[7], "2 . . . . . . . .  2 . . . . . . . .  2 . . . . 1 . . .  2 . . . . . . . .");
+        assert_eq!(lines[9], "  a b c d e f g h    a b c d e f g h    a b c d e f g h    a b c d e f g h");
+
+        // In check, other pieces have to capture the checker or block
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "4k3/8/8/8/8/8/3N4/r3K3 w - - 0 1").unwrap();
+        assert_eq!(game.bitboards().get("Check evasions").map(Bb), squares(&["a1", "b1", "c1", "d1"]));
+    }
+
+    // Positions with castling, en passant, promotions and checks for the
+    // random games below
+    const RANDOM_GAME_FENS: [&str; 7] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 3+3 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R[] w KQkq - 0 1",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N[] b - - 0 1",
+    ];
+
+    // The incrementally updated state of `game` against the state computed
+    // from its board from scratch
+    fn check_incremental_state(game: &GameState) -> Result<(), TestCaseError> {
+        prop_assert_eq!(game.pos_hash, game.zobrist_hasher.hash(&game.board));
+        prop_assert_eq!(game.hash_stack.last(), Some(&game.pos_hash));
+        prop_assert_eq!(game.psqt_score, psqt_score(&game.board, &EvalParams::DEFAULT));
+
+        let occupation_boards = [game.board.occupation_board(Side::White), game.board.occupation_board(Side::Black)];
+        prop_assert_eq!(game.occupation_boards, occupation_boards);
+        prop_assert_eq!(game.comp_occupation_board, occupation_boards[0] | occupation_boards[1]);
+
+        for square in 0..N_SQUARES {
+            let piece = Piece::VALUES.into_iter()
+                .flat_map(|piece| Side::VALUES.map(|side| (piece, side)))
+                .find(|&(piece, side)| game.board[(piece, side)] & (1 << square) != 0);
+            prop_assert_eq!(game.board.piece_on(square), piece);
+        }
+
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_random_games_make_undo(
+            fen in select(&RANDOM_GAME_FENS[..]),
+            choices in prop::collection::vec(any::<Index>(), 1..100),
+        ) {
+            let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            let mut history = Vec::new();
+
+            for choice in choices {
+                let (legal_moves, _) = game.get_legal_moves();
+                if legal_moves.is_empty() {
+                    break;
+                }
+
+                let m = *choice.get(&legal_moves);
+                history.push((game.board.clone(), game.pos_hash, game.psqt_score));
+                game.make_move(&m);
+                check_incremental_state(&game)?;
+            }
+
+            while let Some((board, pos_hash, psqt_score)) = history.pop() {
+                prop_assert!(game.undo_move().is_some());
+                prop_assert_eq!(&game.board, &board);
+                prop_assert_eq!(game.pos_hash, pos_hash);
+                prop_assert_eq!(game.psqt_score, psqt_score);
+                check_incremental_state(&game)?;
+            }
+        }
     }
 }
@@ -0,0 +1,252 @@
+//! Log of the games played in the REPL, for post-mortems. The log is in JSON
+//! Lines format, with an object per line for the start of a game, each move
+//! and the result:
+//!
+//! ```text
+//! {"event":"start","time":1700000000,"fen":"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1","cpu_side":"black","depth":6,"skill":20,"nodes":null,"ponder":false,"time_control":"5+3"}
+//! {"event":"move","ply":1,"side":"white","player":"user","move":"e4","time_ms":5120}
+//! {"event":"move","ply":2,"side":"black","player":"engine","move":"e5","time_ms":412,"depth":6,"score":"+0.02","nodes":36353,"pv":"1. ..e5 2. d4 exd4"}
+//! {"event":"annotation","ply":3,"move":"Ke2","annotation":"?!","loss":62}
+//! {"event":"result","result":"1-0","reason":"Checkmate"}
+//! ```
+//!
+//! An annotation of the user's move is logged once the engine's search for
+//! its reply judged the move, see `GameLog::annotation`.
+//!
+//! Moves played before the log started, e.g. of a loaded game, are logged
+//! without times. A game without a result line was abandoned.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::annotation::Annotation;
+use crate::board::Side;
+use crate::clock::TimeControl;
+use crate::error::Error;
+use crate::game::GameState;
+use crate::r#move::Move;
+use crate::savegame::Settings;
+use crate::search::format_score;
+
+/// The engine's search for a move.
+///
+/// * `depth`: Depth of the last completed iteration
+/// * `eval`: Evaluation from white's perspective
+/// * `pv`: Principal variation in numbered algebraic notation
+/// * `nodes`: Number of nodes searched
+/// * `time`: Time the search took
+/// * `hashfull`: Per mille of the transposition table in use after the search
+pub struct SearchReport {
+    pub depth: usize,
+    pub eval: i32,
+    pub pv: String,
+    pub nodes: u64,
+    pub time: Duration,
+    pub hashfull: usize,
+}
+
+/// Game log appended to a file, or discarding everything without a file.
+///
+/// * `file`: File the log is appended to, if any
+/// * `winner`: Result of the game since the last `start`, the winning side or
+///   None for a draw, once it's over
+pub struct GameLog {
+    file: Option<File>,
+    winner: Option<Option<Side>>,
+}
+
+fn side_name(side: Side) -> &'static str {
+    if side == Side::White { "white" } else { "black" }
+}
+
+impl GameLog {
+    /// Log to the end of the file `path`, if any.
+    pub fn new(path: Option<&str>) -> Result<Self, Error> {
+        let file = match path {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|source| Error::Write { path: path.into(), source })?
+            ),
+            None => None,
+        };
+
+        Ok(Self { file, winner: None })
+    }
+
+    fn write(&mut self, line: &str) {
+        if let Some(file) = &mut self.file {
+            if let Err(err) = writeln!(file, "{}", line) {
+                println!("Error: can't write the game log, logging stopped: {}", err);
+                self.file = None;
+            }
+        }
+    }
+
+    /// Log the start of `game`, from the position before the first move that
+    /// can be taken back, and the moves played since.
+    ///
+    /// * `game`: Game to log
+    /// * `cpu_side`: Side the computer plays
+    /// * `settings`: Engine settings
+    /// * `time_control`: Time control, if the game is played with clocks
+    pub fn start(&mut self, game: &GameState, cpu_side: Side, settings: &Settings, time_control: Option<TimeControl>) {
+        let mut start = game.clone();
+        while start.undo_move().is_some() {}
+
+        self.winner = None;
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let nodes = settings.nodes.map_or("null".to_owned(), |nodes| nodes.to_string());
+        let time_control = time_control.map_or("null".to_owned(), |tc| format!("\"{}\"", tc));
+        self.write(&format!(
+            "{{\"event\":\"start\",\"time\":{},\"fen\":\"{}\",\"cpu_side\":\"{}\",\"depth\":{},\"skill\":{},\"nodes\":{},\"ponder\":{},\"time_control\":{}}}",
+            time,
+            start.to_fen(),
+            side_name(cpu_side),
+            settings.depth,
+            settings.skill,
+            nodes,
+            settings.ponder,
+            time_control
+        ));
+
+        for m in game.played_moves() {
+            let line = format!("{}}}", Self::move_fields(&start, &m, cpu_side));
+            self.write(&line);
+            start.make_move(&m);
+        }
+    }
+
+    /// Log the user's move `m` in `game`'s position, which took `time`.
+    pub fn user_move(&mut self, game: &GameState, m: &Move, cpu_side: Side, time: Duration) {
+        let line = format!("{},\"time_ms\":{}}}", Self::move_fields(game, m, cpu_side), time.as_millis());
+        self.write(&line);
+    }
+
+    /// Log the computer's move `m` in `game`'s position, found by `search`.
+    pub fn engine_move(&mut self, game: &GameState, m: &Move, cpu_side: Side, search: &SearchReport) {
+        let line = format!(
+            "{},\"time_ms\":{},\"depth\":{},\"score\":\"{}\",\"nodes\":{},\"hashfull\":{},\"pv\":\"{}\"}}",
+            Self::move_fields(game, m, cpu_side),
+            search.time.as_millis(),
+            search.depth,
+            format_score(search.eval),
+            search.nodes,
+            search.hashfull,
+            search.pv.trim_end()
+        );
+        self.write(&line);
+    }
+
+    /// Log the annotation of the user's move, the last move of `game`, which
+    /// lost `loss` centipawns.
+    pub fn annotation(&mut self, game: &GameState, annotation: Annotation, loss: i32) {
+        let mut before = game.clone();
+        let Some(m) = before.undo_move() else {
+            return;
+        };
+        let (legal_moves, _) = before.get_legal_moves();
+        self.write(&format!(
+            "{{\"event\":\"annotation\",\"ply\":{},\"move\":\"{}\",\"annotation\":\"{}\",\"loss\":{}}}",
+            before.half_move_number,
+            m.to_algebraic_with_state(&legal_moves),
+            annotation,
+            loss
+        ));
+    }
+
+    /// Log taking back the last `n_moves` moves.
+    pub fn undo(&mut self, n_moves: usize) {
+        self.write(&format!("{{\"event\":\"undo\",\"moves\":{}}}", n_moves));
+    }
+
+    /// The winner of the game since the last `start`, or None for a draw.
+    /// None while the game isn't over.
+    pub fn winner(&self) -> Option<Option<Side>> {
+        self.winner
+    }
+
+    /// Log the end of the game, won by `winner` or drawn, for `reason`.
+    pub fn result(&mut self, winner: Option<Side>, reason: &str) {
+        self.winner = Some(winner);
+        let result = match winner {
+            Some(Side::White) => "1-0",
+            Some(Side::Black) => "0-1",
+            None => "1/2-1/2",
+        };
+        self.write(&format!("{{\"event\":\"result\",\"result\":\"{}\",\"reason\":\"{}\"}}", result, reason));
+    }
+
+    // The fields of a move line, without the closing brace
+    fn move_fields(game: &GameState, m: &Move, cpu_side: Side) -> String {
+        let (legal_moves, _) = game.get_legal_moves();
+        let side = game.board.side_to_move;
+        format!(
+            "{{\"event\":\"move\",\"ply\":{},\"side\":\"{}\",\"player\":\"{}\",\"move\":\"{}\"",
+            game.half_move_number,
+            side_name(side),
+            if side == cpu_side { "engine" } else { "user" },
+            m.to_algebraic_with_state(&legal_moves)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_game_log() {
+        let path = std::env::temp_dir().join(format!("pawndropper-test-{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let settings = Settings { depth: 6, skill: 20, nodes: None, ponder: false };
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4"]).unwrap();
+
+        let mut log = GameLog::new(Some(path)).unwrap();
+        log.start(&game, Side::White, &settings, Some("5+3".parse().unwrap()));
+
+        let move_of = |game: &GameState, coordinate: &str| {
+            game.get_legal_moves().0.into_iter().find(|m| m.to_coordinate() == coordinate).unwrap()
+        };
+        let m = move_of(&game, "h7h6");
+        log.user_move(&game, &m, Side::White, Duration::from_millis(1500));
+        game.make_move(&m);
+
+        let m = move_of(&game, "h2h3");
+        let search = SearchReport { depth: 4, eval: 35, pv: "2. h3 ".to_owned(), nodes: 1000, time: Duration::from_millis(20), hashfull: 12 };
+        log.engine_move(&game, &m, Side::White, &search);
+        log.result(Some(Side::Black), "Checkmate");
+        assert_eq!(log.winner(), Some(Some(Side::Black)));
+
+        let text = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("{\"event\":\"start\",\"time\":"));
+        assert!(lines[0].ends_with(
+            "\"fen\":\"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\",\"cpu_side\":\"white\",\
+             \"depth\":6,\"skill\":20,\"nodes\":null,\"ponder\":false,\"time_control\":\"5+3\"}"
+        ));
+        assert_eq!(lines[1], "{\"event\":\"move\",\"ply\":1,\"side\":\"white\",\"player\":\"engine\",\"move\":\"e4\"}");
+        assert_eq!(lines[2], "{\"event\":\"move\",\"ply\":2,\"side\":\"black\",\"player\":\"user\",\"move\":\"h6\",\"time_ms\":1500}");
+        assert_eq!(
+            lines[3],
+            "{\"event\":\"move\",\"ply\":3,\"side\":\"white\",\"player\":\"engine\",\"move\":\"h3\",\"time_ms\":20,\
+             \"depth\":4,\"score\":\"+0.35\",\"nodes\":1000,\"hashfull\":12,\"pv\":\"2. h3\"}"
+        );
+        assert_eq!(lines[4], "{\"event\":\"result\",\"result\":\"0-1\",\"reason\":\"Checkmate\"}");
+
+        // Without a file nothing is logged
+        GameLog::new(None).unwrap().result(None, "Stalemate");
+    }
+}
@@ -0,0 +1,38 @@
+//! Input typed while the engine is thinking. The line editor isn't reading
+//! then, so the terminal's own line editing applies and a line is only read
+//! once it's complete, which leaves anything typed after it to the editor.
+
+use std::io::{self, BufRead};
+use std::time::Duration;
+
+/// Wait up to `timeout` for a line of input. Returns None if there's none
+/// yet, or an empty string at the end of the input.
+pub fn poll_line(timeout: Duration) -> Option<String> {
+    if !stdin_ready(timeout) {
+        return None;
+    }
+
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(_) => Some(line),
+        Err(_) => Some(String::new()),
+    }
+}
+
+#[cfg(unix)]
+fn stdin_ready(timeout: Duration) -> bool {
+    let mut stdin = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `stdin` is a single valid pollfd, as poll is told
+    unsafe { libc::poll(&mut stdin, 1, timeout.as_millis().try_into().unwrap_or(libc::c_int::MAX)) > 0 }
+}
+
+// There's no input while thinking elsewhere
+#[cfg(not(unix))]
+fn stdin_ready(timeout: Duration) -> bool {
+    std::thread::sleep(timeout);
+    false
+}
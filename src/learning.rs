@@ -0,0 +1,258 @@
+//! Position learning: the positions of finished games against the user are
+//! kept in a file with how the games ended, so the computer stops steering
+//! into lines it lost again and again. A position is learned from the
+//! perspective of the side that moved into it, with its wins, draws, losses
+//! and a score that blends the results with the engine's evaluations of the
+//! position, one position per line:
+//!
+//! ```text
+//! standard 5b2c9a1e40f7d3c6 0 1 2 -143
+//! ```
+//!
+//! The position is identified by its Zobrist hash, which is the same across
+//! runs.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::board::{Side, Variant};
+use crate::error::Error;
+use crate::evalhistory::EvalPoint;
+use crate::game::GameState;
+use crate::r#move::Move;
+
+/// What the games through a position came to, for the side that moved into
+/// it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LearnedPosition {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Average of the results (as centipawns) blended with the evaluations
+    pub score: i32,
+}
+
+impl LearnedPosition {
+    // Losses after which a position with a losing score is avoided
+    const MIN_LOSSES: u32 = 2;
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Whether the position was lost repeatedly, and the evaluations don't
+    /// make up for it.
+    pub fn is_lost(&self) -> bool {
+        self.losses >= Self::MIN_LOSSES && self.score < 0
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LearningStore {
+    positions: HashMap<(Variant, u64), LearnedPosition>,
+}
+
+impl LearningStore {
+    // Score of a won game, the evaluations are capped at it so mate scores
+    // don't drown out the results
+    const RESULT_SCORE: i32 = 400;
+
+    // Only the positions of the first half moves of a game are learned,
+    // later ones hardly come up again
+    const MAX_PLY: usize = 40;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the store from the file `path`. There's nothing learned yet
+    /// without the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(source) => Err(Error::Read { path: path.to_owned(), source }),
+        }
+    }
+
+    /// Write the store to the file `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        fs::write(path, self.to_text()).map_err(|source| Error::Write { path: path.to_owned(), source })
+    }
+
+    fn parse(text: &str) -> Result<Self, Error> {
+        let mut store = Self::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let invalid = || Error::InvalidLearningFile(line.to_owned());
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [variant, hash, wins, draws, losses, score] = fields[..] else {
+                return Err(invalid());
+            };
+
+            let variant: Variant = variant.parse().map_err(|_| invalid())?;
+            let hash = u64::from_str_radix(hash, 16).map_err(|_| invalid())?;
+            let position = LearnedPosition {
+                wins: wins.parse().map_err(|_| invalid())?,
+                draws: draws.parse().map_err(|_| invalid())?,
+                losses: losses.parse().map_err(|_| invalid())?,
+                score: score.parse().map_err(|_| invalid())?,
+            };
+            store.positions.insert((variant, hash), position);
+        }
+
+        Ok(store)
+    }
+
+    fn to_text(&self) -> String {
+        // Sorted, so the file doesn't change needlessly between games
+        let mut positions: Vec<_> = self.positions.iter().collect();
+        positions.sort_by_key(|((variant, hash), _)| (variant.to_string(), *hash));
+
+        positions
+            .into_iter()
+            .map(|((variant, hash), position)| format!(
+                "{} {:016x} {} {} {} {}\n",
+                variant, hash, position.wins, position.draws, position.losses, position.score
+            ))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// What was learned about `game`'s position, if it came up before.
+    pub fn get(&self, game: &GameState) -> Option<&LearnedPosition> {
+        self.positions.get(&(game.board.variant, game.pos_hash))
+    }
+
+    /// Learn the positions of the finished `game`.
+    ///
+    /// * `game`: The game, with all its moves
+    /// * `winner`: Side that won the game, or None for a draw
+    /// * `evals`: Evaluations after the moves of the game from white's perspective, see `EvalHistory`
+    pub fn record_game(&mut self, game: &GameState, winner: Option<Side>, evals: &[EvalPoint]) {
+        let mut replay = game.clone();
+        while replay.undo_move().is_some() {}
+
+        for m in game.played_moves().take(Self::MAX_PLY) {
+            let ply = replay.half_move_number;
+            let side = replay.board.side_to_move;
+            replay.make_move(&m);
+
+            let (result, result_score) = match winner {
+                Some(winner) if winner == side => (1, Self::RESULT_SCORE),
+                Some(_) => (-1, -Self::RESULT_SCORE),
+                None => (0, 0),
+            };
+            let score = match evals.iter().find(|point| point.ply == ply) {
+                Some(point) => {
+                    let eval = if side == Side::White { point.eval } else { -point.eval };
+                    (result_score + eval.clamp(-Self::RESULT_SCORE, Self::RESULT_SCORE))/2
+                },
+                None => result_score,
+            };
+
+            let position = self.positions.entry((replay.board.variant, replay.pos_hash)).or_default();
+            let games = position.games() as i32;
+            position.score = (position.score*games + score)/(games + 1);
+            match result {
+                1 => position.wins += 1,
+                -1 => position.losses += 1,
+                _ => position.draws += 1,
+            }
+        }
+    }
+
+    /// The moves of `moves` not leading to positions the side to move lost
+    /// repeatedly, to restrict the root moves of a search to. Empty if no
+    /// move is avoided, or if every move is, as the search then considers all
+    /// moves.
+    ///
+    /// * `game`: Position the moves are made in
+    /// * `moves`: Legal moves of the position
+    pub fn search_moves(&self, game: &GameState, moves: &[Move]) -> Vec<Move> {
+        let mut after = game.clone();
+        let search_moves: Vec<Move> = moves
+            .iter()
+            .filter(|m| {
+                after.make_move(m);
+                let lost = self.get(&after).is_some_and(LearnedPosition::is_lost);
+                after.undo_move();
+                !lost
+            })
+            .copied()
+            .collect();
+
+        if search_moves.len() == moves.len() {
+            vec![]
+        } else {
+            search_moves
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evalhistory::EvalHistory;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_learning() {
+        let start = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        let (legal_moves, _) = start.get_legal_moves();
+
+        // Fool's mate, with black's mating move evaluated
+        let mut game = start.clone();
+        game.apply_moves(&["f2f3", "e7e5", "g2g4"]).unwrap();
+        let mut evals = EvalHistory::new();
+        evals.record(&game, -1000);
+        game.apply_moves(&["d8h4"]).unwrap();
+
+        let mut store = LearningStore::new();
+        store.record_game(&game, Some(Side::Black), evals.points());
+        assert_eq!(store.len(), 4);
+
+        let mut after_f3 = start.clone();
+        after_f3.apply_moves(&["f2f3"]).unwrap();
+        assert_eq!(store.get(&after_f3), Some(&LearnedPosition { wins: 0, draws: 0, losses: 1, score: -400 }));
+
+        // The evaluation is blended in, capped at the result's score
+        let mut after_g4 = after_f3.clone();
+        after_g4.apply_moves(&["e7e5", "g2g4"]).unwrap();
+        assert_eq!(store.get(&after_g4).unwrap().score, -400);
+
+        // Lost once isn't enough to avoid the line
+        assert!(store.search_moves(&start, &legal_moves).is_empty());
+
+        store.record_game(&game, Some(Side::Black), evals.points());
+        let search_moves = store.search_moves(&start, &legal_moves);
+        assert_eq!(search_moves.len(), legal_moves.len() - 1);
+        assert!(!search_moves.iter().any(|m| m.to_coordinate() == "f2f3"));
+
+        // A win through the position makes up for the losses
+        store.record_game(&game, Some(Side::White), &[]);
+        store.record_game(&game, Some(Side::White), &[]);
+        assert!(store.search_moves(&start, &legal_moves).is_empty());
+
+        let path = std::env::temp_dir().join(format!("pawndropper-test-learning-{}.txt", std::process::id()));
+        store.save(&path).unwrap();
+        let loaded = LearningStore::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.get(&after_f3), store.get(&after_f3));
+        assert_eq!(loaded.len(), store.len());
+
+        assert!(LearningStore::load(&path).unwrap().is_empty());
+        assert!(matches!(LearningStore::parse("standard 12 1 0 0"), Err(Error::InvalidLearningFile(_))));
+    }
+}
@@ -0,0 +1,47 @@
+//! pawndropper is a chess engine with a magic bitboard move generator and an
+//! alpha-beta search. The [`Engine`] type is the entry point for embedding it
+//! in other programs, the modules expose the building blocks it's made of.
+
+pub mod board;
+pub mod bitboard;
+pub mod move_bitboards;
+pub mod r#move;
+pub mod game;
+pub mod rules;
+pub mod eval;
+pub mod score;
+pub mod search;
+pub mod magic;
+pub mod zobrist;
+pub mod tt;
+mod trace;
+pub mod testsuite;
+#[cfg(feature = "cli")]
+pub mod puzzle;
+pub mod puzzlegen;
+pub mod tuner;
+pub mod spsa;
+pub mod perft;
+pub mod bench;
+pub mod speedtest;
+pub mod selfplay;
+pub mod distributed;
+pub mod uci;
+pub mod skill;
+pub mod opponent;
+pub mod personality;
+pub mod elo;
+pub mod savegame;
+pub mod adjudication;
+pub mod annotation;
+pub mod motifs;
+pub mod evalhistory;
+pub mod clock;
+pub mod gamelog;
+pub mod opening;
+pub mod learning;
+pub mod engine;
+pub mod error;
+
+pub use crate::engine::{BackgroundSearch, Engine, EngineBuilder, EngineObserver, SearchLimits, SearchResult};
+pub use crate::error::Error;
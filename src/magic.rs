@@ -1,19 +1,39 @@
-use crate::board::{N_SQUARES, Piece};
-use crate::move_bitboards::MoveBitboards;
+use std::fmt::Write;
+use std::fs;
+
+use crate::board::{BOARD_WIDTH, N_SQUARES, Piece};
+use crate::error::Error;
+use crate::move_bitboards::{MoveBitboards, PSEUDO_LEGAL_MOVES};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Slider move lookup tables, indexed by multiplying the blockers with a
+/// magic number. With the `pext` feature on x86-64 CPUs with BMI2 the tables
+/// are indexed with the PEXT instruction on the blocker masks instead, which
+/// is faster and doesn't need the magics. The tables of all squares are
+/// stored one after the other in a single table, each square getting an entry
+/// per index. The tables are generated at compile time, use
+/// `MagicBitboard::get` for the ones to use on this CPU.
+pub struct MagicBitboard {
+    rook_magics: [u64; N_SQUARES],
+    bishop_magics: [u64; N_SQUARES],
 
-use rand::Rng;
+    rook_masks: [u64; N_SQUARES],
+    bishop_masks: [u64; N_SQUARES],
+    use_pext: bool,
 
-use log::info;
+    table: [u64; MagicBitboard::TABLE_SIZE],
+}
 
-pub struct MagicBitboard {
-    pub rook_magics: [u64; N_SQUARES],
-    pub bishop_magics: [u64; N_SQUARES],
+/// Slider move tables indexed with the pre-computed magics.
+#[allow(long_running_const_eval)]
+pub static MAGICS: MagicBitboard = MagicBitboard::generate(&PSEUDO_LEGAL_MOVES, false);
 
-    // These are too big, need to be on heap instead
-    // https://github.com/rust-lang/rust/issues/53827
-    pub rook_table: Vec<[u64; 1 << 12]>,
-    pub bishop_table: Vec<[u64; 1 << 9]>
-}
+/// Slider move tables indexed with PEXT.
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+#[allow(long_running_const_eval)]
+static PEXT_MAGICS: MagicBitboard = MagicBitboard::generate(&PSEUDO_LEGAL_MOVES, true);
 
 impl MagicBitboard {
     const ROOK_SQUARE_BITS: [usize; N_SQUARES] = [
@@ -37,6 +57,12 @@ impl MagicBitboard {
         6, 5, 5, 5, 5, 5, 5, 6
     ];
 
+    // Start of every square's moves in the table, rooks first
+    const ROOK_OFFSETS: [usize; N_SQUARES] = Self::table_offsets(&Self::ROOK_SQUARE_BITS, 0);
+    const BISHOP_OFFSETS: [usize; N_SQUARES] =
+        Self::table_offsets(&Self::BISHOP_SQUARE_BITS, Self::table_len(&Self::ROOK_SQUARE_BITS));
+    const TABLE_SIZE: usize = Self::table_len(&Self::ROOK_SQUARE_BITS) + Self::table_len(&Self::BISHOP_SQUARE_BITS);
+
     // Pre-computed magics, save computation on start-up
     const PRECOMP_ROOK_MAGICS: [u64; N_SQUARES] = [
         36037800344256544, 18014699425783808, 612507141653135490, 180149585766776960, 9367522452253967440, 1224983496725373696, 4683814535262110208, 144126200376230433, 2379167241225650193, 141012542431234, 576601558536429568, 5584604309795934208, 9278822955159418880, 563018773611544, 422508817940608, 3518438291013888, 3518986966810624, 8092968805298341970, 141287512875016, 108227678435282944, 2307112395455072257, 4756083781126980608, 324351532416402961, 1441154079790273092, 36169674093903872, 234398289005380224, 35186520621184, 180231948173050242, 2319406606129299712, 2759018022366611468, 285885924901376, 5718018812302337, 143211397906528, 2534531085123584, 425030022340608, 1556910620823552, 72202731728668672, 147070718289380353, 11817463048833073442, 869335480108323072, 648845452124520448, 9385607195078443012, 1971012052779028, 10971912467835912224, 146648739894788640, 1153484506234224656, 6352332849544429576, 144115473842569220, 2342435873439629824, 2377971041252245632, 292734388104336512, 1153211777825112192, 292879184332062976, 9288682888497408, 649098922857499648, 9223381951992562176, 9268478951647367425, 126241601142923521, 2328431583863832713, 87965239871493, 4630544876231200811, 18296199905281537, 13873057185728823427, 4543598766867458
@@ -45,38 +71,33 @@ impl MagicBitboard {
         325407081031270657, 153135587946152352, 1235121311739805696, 9875273726504208384, 3127829380923981954, 9578954033758208, 432917397851340816, 577025912086463012, 10394312372512104608, 8967900660225, 1134722105352192, 4613829965284352, 9108553007122, 153123642000016384, 18024947088098304, 4510197839299778, 9010772709740806, 3118560251544576, 7066147987151400980, 1306189061865619456, 9391412647615794200, 1162491692473975808, 81223124108189698, 4683884633430163841, 9027283058955008, 1139094215723008, 16142046755679635472, 290271338700832, 13837037193442377744, 56297196651299329, 151183352660992, 9251521218163642624, 4516813648957504, 4611829126906515584, 6341420299485971460, 10088345192190443616, 4649968831463493888, 466198301114624, 36596146243700744, 5428301810237504, 2326391920001028096, 6918098171613683713, 9223653614978368000, 4684871574359140608, 142979629319170, 698093160976355393, 20363264588251280, 2306970577765664260, 1129766788796545, 285875775012864, 1297126861244203392, 162129587692773461, 703962592387072, 9512732779751161888, 2542075245760720, 2254001020207109, 563500800823312, 2305849614890436096, 288934339579219008, 206162760192, 2342022547243024640, 289365243984347648, 72274233530597888, 1443651139441673472
     ];
 
-    pub fn init(pl_moves: &MoveBitboards) -> Self {
-        let mut magic_bb = Self {
-            rook_magics: [0u64; N_SQUARES],
-            bishop_magics: [0u64; N_SQUARES],
-
-            rook_table: vec![[0u64; 1 << 12]; N_SQUARES],
-            bishop_table: vec![[0u64; 1 << 9]; N_SQUARES]
-        };
-
-        info!("Computing magics and calculating blocker moves");
-
-        magic_bb.compute_magics(pl_moves, Piece::Bishop);
-        magic_bb.compute_magics(pl_moves, Piece::Rook);
-
-        info!("Populating blocker move tables");
-
-        magic_bb.init_move_table(pl_moves, Piece::Bishop);
-        magic_bb.init_move_table(pl_moves, Piece::Rook);
+    /// Slider move tables to use on this CPU, the PEXT-indexed ones if it
+    /// supports them.
+    pub fn get() -> &'static Self {
+        #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+        if std::arch::is_x86_feature_detected!("bmi2") {
+            return &PEXT_MAGICS;
+        }
 
-        magic_bb
+        &MAGICS
     }
 
-    pub fn init_precomputed(pl_moves: &MoveBitboards) -> Self {
+    /// Generate the blocker move tables. Evaluated at compile time for
+    /// `MAGICS`.
+    ///
+    /// * `pl_moves`: Pseudo-legal move bitboards with the blocker masks
+    /// * `use_pext`: Whether to index the tables with PEXT instead of magics
+    const fn generate(pl_moves: &MoveBitboards, use_pext: bool) -> Self {
         let mut magic_bb = Self {
             rook_magics: Self::PRECOMP_ROOK_MAGICS,
             bishop_magics: Self::PRECOMP_BISHOP_MAGICS,
 
-            rook_table: vec![[0u64; 1 << 12]; N_SQUARES],
-            bishop_table: vec![[0u64; 1 << 9]; N_SQUARES]
-        };
+            rook_masks: pl_moves.rook_masks,
+            bishop_masks: pl_moves.bishop_masks,
+            use_pext,
 
-        info!("Initialising pre-calculated magics and populating blocker move tables");
+            table: [0u64; Self::TABLE_SIZE],
+        };
 
         magic_bb.init_move_table(pl_moves, Piece::Bishop);
         magic_bb.init_move_table(pl_moves, Piece::Rook);
@@ -84,116 +105,251 @@ impl MagicBitboard {
         magic_bb
     }
 
+    /// Number of random candidates to try for a magic with one index bit
+    /// less than the square's current number of bits.
+    const SHRINK_TRIES: usize = 1 << 16;
+
+    /// Search new magics for all squares and write them with their index
+    /// bits as Rust source to `output`, to paste over the constants in this
+    /// file. Every square gets a magic for its current number of index bits,
+    /// after which magics with fewer bits are searched for, which halve the
+    /// square's table each. Squares with fewer bits than their blocker mask
+    /// has squares can't be indexed with PEXT, see the `pext` feature.
+    ///
+    /// * `pl_moves`: Pseudo-legal move bitboards with the blocker masks
+    /// * `seed`: Seed of the random candidates, the same seed gives the same magics
+    /// * `output`: File to write the magics to
+    pub fn generate_magics(pl_moves: &MoveBitboards, seed: u64, output: &str) -> Result<(), Error> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (rook_magics, rook_bits) = Self::compute_magics(pl_moves, Piece::Rook, &mut rng);
+        let (bishop_magics, bishop_bits) = Self::compute_magics(pl_moves, Piece::Bishop, &mut rng);
+
+        let table_size = |bits: &[usize; N_SQUARES]| bits.iter().map(|&b| (1 << b)*size_of::<u64>()).sum::<usize>();
+        let (rook_size, bishop_size) = (table_size(&rook_bits), table_size(&bishop_bits));
+        println!(
+            "Rook tables: {} KiB, bishop tables: {} KiB, total: {} KiB",
+            rook_size/1024,
+            bishop_size/1024,
+            (rook_size + bishop_size)/1024
+        );
+
+        let mut source = String::new();
+        writeln!(source, "    // Generated with seed {}", seed).unwrap();
+        write_square_bits(&mut source, "ROOK_SQUARE_BITS", &rook_bits);
+        write_square_bits(&mut source, "BISHOP_SQUARE_BITS", &bishop_bits);
+        write_magics(&mut source, "PRECOMP_ROOK_MAGICS", &rook_magics);
+        write_magics(&mut source, "PRECOMP_BISHOP_MAGICS", &bishop_magics);
+        fs::write(output, source).map_err(|source| Error::Write { path: output.into(), source })?;
+
+        println!("Magics written to {}", output);
+
+        Ok(())
+    }
+
     pub fn get_rook_moves(&self, square: usize, blockers: u64) -> u64 {
+        #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+        if self.use_pext {
+            return self.table[Self::ROOK_OFFSETS[square] + Self::pext_index(blockers, self.rook_masks[square])];
+        }
+
         let magic = self.rook_magics[square];
-        let moves = &self.rook_table[square];
-        moves[self.magic_index(magic, blockers, Self::ROOK_SQUARE_BITS[square])]
+        self.table[Self::ROOK_OFFSETS[square] + Self::magic_index(magic, blockers, Self::ROOK_SQUARE_BITS[square])]
     }
 
     pub fn get_bishop_moves(&self, square: usize, blockers: u64) -> u64 {
+        #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+        if self.use_pext {
+            return self.table[Self::BISHOP_OFFSETS[square] + Self::pext_index(blockers, self.bishop_masks[square])];
+        }
+
         let magic = self.bishop_magics[square];
-        let moves = &self.bishop_table[square];
-        moves[self.magic_index(magic, blockers, Self::BISHOP_SQUARE_BITS[square])]
+        self.table[Self::BISHOP_OFFSETS[square] + Self::magic_index(magic, blockers, Self::BISHOP_SQUARE_BITS[square])]
     }
 
-    pub fn print_magics(&mut self) {
-        println!("ROOK MAGICS");
-        println!("----------------------");
-        for magic in &self.rook_magics {
-            print!("{}, ", magic);
-        }
+    /// Squares a rook on `square` attacks once the first of `blockers` on
+    /// each of its rays is removed, without the squares it attacks directly.
+    ///
+    /// * `square`: Square of the rook
+    /// * `occ`: Occupancy bitboard
+    /// * `blockers`: Pieces the rook may look through, usually a subset of `occ`
+    pub fn xray_rook_attacks(&self, square: usize, occ: u64, blockers: u64) -> u64 {
+        let mask = self.rook_masks[square];
+        let attacks = self.get_rook_moves(square, occ & mask);
+        let first_blockers = attacks & blockers;
+
+        attacks ^ self.get_rook_moves(square, (occ ^ first_blockers) & mask)
+    }
 
-        println!("BISHOP MAGICS");
-        println!("----------------------");
-        for magic in &self.bishop_magics {
-            print!("{}, ", magic);
-        }
+    /// Squares a bishop on `square` attacks once the first of `blockers` on
+    /// each of its rays is removed, without the squares it attacks directly.
+    ///
+    /// * `square`: Square of the bishop
+    /// * `occ`: Occupancy bitboard
+    /// * `blockers`: Pieces the bishop may look through, usually a subset of `occ`
+    pub fn xray_bishop_attacks(&self, square: usize, occ: u64, blockers: u64) -> u64 {
+        let mask = self.bishop_masks[square];
+        let attacks = self.get_bishop_moves(square, occ & mask);
+        let first_blockers = attacks & blockers;
+
+        attacks ^ self.get_bishop_moves(square, (occ ^ first_blockers) & mask)
     }
 
-    fn init_move_table(&mut self, pl_moves: &MoveBitboards, piece: Piece) {
-        let masks;
-        let square_bits;
-        let magics;
-        if piece == Piece::Bishop {
-            masks = &pl_moves.bishop_masks;
-            square_bits = &Self::BISHOP_SQUARE_BITS;
-            magics = &self.bishop_magics;
+    const fn init_move_table(&mut self, pl_moves: &MoveBitboards, piece: Piece) {
+        let is_bishop = matches!(piece, Piece::Bishop);
+        let (masks, square_bits, offsets, magics) = if is_bishop {
+            (&pl_moves.bishop_masks, &Self::BISHOP_SQUARE_BITS, &Self::BISHOP_OFFSETS, self.bishop_magics)
         } else {
             // Rook
-            masks = &pl_moves.rook_masks;
-            square_bits = &Self::ROOK_SQUARE_BITS;
-            magics = &self.rook_magics;
-        }
+            (&pl_moves.rook_masks, &Self::ROOK_SQUARE_BITS, &Self::ROOK_OFFSETS, self.rook_magics)
+        };
 
-        for square in 0..N_SQUARES {
-            for blocker_idx in 0..(1 << square_bits[square]) {
-                let blockers = self.get_blocker_from_idx(blocker_idx, masks[square]);
-                let index = self.magic_index(magics[square], blockers, square_bits[square]);
-                if piece == Piece::Bishop {
-                    self.bishop_table[square][index] = pl_moves.get_bishop_rays(square, blockers);
+        let mut square = 0;
+        while square < N_SQUARES {
+            assert!(
+                !self.use_pext || masks[square].count_ones() as usize <= square_bits[square],
+                "PEXT indexing needs an index bit per blocker mask square"
+            );
+
+            // Enumerate all subsets of the blocker mask in the order of their
+            // blocker index (see `get_blocker_from_idx`), which is cheaper to
+            // evaluate at compile time
+            let mut blocker_idx = 0;
+            let mut blockers = 0u64;
+            while blocker_idx < (1 << masks[square].count_ones()) {
+                // PEXT of the blockers on the mask gives back the blocker index
+                let index = if self.use_pext {
+                    blocker_idx
                 } else {
-                    self.rook_table[square][index] = pl_moves.get_rook_rays(square, blockers);
-                }
+                    Self::magic_index(magics[square], blockers, square_bits[square])
+                };
+                self.table[offsets[square] + index] = if is_bishop {
+                    pl_moves.get_bishop_rays(square, blockers)
+                } else {
+                    pl_moves.get_rook_rays(square, blockers)
+                };
+
+                blockers = blockers.wrapping_sub(masks[square]) & masks[square];
+                blocker_idx += 1;
             }
+            square += 1;
         }
     }
 
-    fn compute_magics(&mut self, pl_moves: &MoveBitboards, piece: Piece) {
-        let mut rng = rand::thread_rng();
-
-        let masks;
-        let square_bits;
-        let table_size;
-        if piece == Piece::Bishop {
-            masks = &pl_moves.bishop_masks;
-            square_bits = &Self::BISHOP_SQUARE_BITS;
-            table_size = 9;
+    /// Find a magic and its number of index bits for every square.
+    fn compute_magics(pl_moves: &MoveBitboards, piece: Piece, rng: &mut StdRng) -> ([u64; N_SQUARES], [usize; N_SQUARES]) {
+        let (masks, mut square_bits) = if piece == Piece::Bishop {
+            (&pl_moves.bishop_masks, Self::BISHOP_SQUARE_BITS)
         } else {
             // Rook
-            masks = &pl_moves.rook_masks;
-            square_bits = &Self::ROOK_SQUARE_BITS;
-            table_size = 12;
+            (&pl_moves.rook_masks, Self::ROOK_SQUARE_BITS)
+        };
+        let mut magics = [0u64; N_SQUARES];
+
+        for square in 0..N_SQUARES {
+            let mask = masks[square];
+            let (blockers, moves): (Vec<u64>, Vec<u64>) = (0..1 << mask.count_ones())
+                .map(|blocker_idx| {
+                    let blockers = Self::get_blocker_from_idx(blocker_idx, mask);
+                    let moves = if piece == Piece::Bishop {
+                        pl_moves.get_bishop_rays(square, blockers)
+                    } else {
+                        pl_moves.get_rook_rays(square, blockers)
+                    };
+                    (blockers, moves)
+                })
+                .unzip();
+
+            // A magic for the current number of bits always exists
+            magics[square] = Self::find_magic(rng, &blockers, &moves, square_bits[square], usize::MAX).unwrap();
+            while square_bits[square] > 1 {
+                match Self::find_magic(rng, &blockers, &moves, square_bits[square] - 1, Self::SHRINK_TRIES) {
+                    Some(magic) => {
+                        magics[square] = magic;
+                        square_bits[square] -= 1;
+                    },
+                    None => break,
+                }
+            }
         }
 
-        let mut occ_table;
+        (magics, square_bits)
+    }
 
-        // Then find magics
-        for square in 0..N_SQUARES {
-            'retry: loop {
-                let magic = rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>();
+    /// Try up to `tries` random candidates for a magic that indexes the moves
+    /// of all blocker sets with `bits` bits. Blocker sets may share an index
+    /// when they have the same moves.
+    ///
+    /// * `rng`: Source of the candidates
+    /// * `blockers`: All subsets of the blocker mask
+    /// * `moves`: Moves for each of `blockers`
+    /// * `bits`: Number of index bits
+    /// * `tries`: Maximum number of candidates to try
+    fn find_magic(rng: &mut StdRng, blockers: &[u64], moves: &[u64], bits: usize, tries: usize) -> Option<u64> {
+        let mut table = vec![0u64; 1 << bits];
+        let mut used = vec![false; 1 << bits];
+
+        'retry: for _ in 0..tries {
+            // Magics with few bits set index better
+            let magic = rng.gen::<u64>() & rng.gen::<u64>() & rng.gen::<u64>();
+            used.fill(false);
+
+            for (&blockers, &moves) in blockers.iter().zip(moves) {
+                let index = Self::magic_index(magic, blockers, bits);
+                if !used[index] {
+                    used[index] = true;
+                    table[index] = moves;
+                } else if table[index] != moves {
+                    // hash collision, retry
+                    continue 'retry;
+                }
+            }
 
-                occ_table = vec![false; 1 << table_size];
+            return Some(magic);
+        }
 
-                for blocker_idx in 0..(1 << square_bits[square]) {
-                    let blockers = self.get_blocker_from_idx(blocker_idx, masks[square]);
-                    let index = self.magic_index(magic, blockers, square_bits[square]);
+        None
+    }
 
-                    if !occ_table[index] {
-                        occ_table[index] = true;
-                    } else {
-                        // hash collision, retry
-                        continue 'retry;
-                    }
-                }
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    #[inline]
+    fn pext_index(blockers: u64, mask: u64) -> usize {
+        // SAFETY: only used by `PEXT_MAGICS`, which `get` only returns when
+        // the CPU supports BMI2
+        unsafe { std::arch::x86_64::_pext_u64(blockers, mask) as usize }
+    }
 
-                // Found perfect magic for this square, save to table
-                if piece == Piece::Bishop {
-                    self.bishop_magics[square] = magic;
-                } else {
-                    self.rook_magics[square] = magic;
-                }
+    /// Number of table entries for squares with `square_bits` index bits.
+    const fn table_len(square_bits: &[usize; N_SQUARES]) -> usize {
+        let mut len = 0;
+        let mut square = 0;
+        while square < N_SQUARES {
+            len += 1 << square_bits[square];
+            square += 1;
+        }
 
-                // Next square
-                break;
-            }
+        len
+    }
+
+    /// Start of every square's entries in the table, for squares with
+    /// `square_bits` index bits stored from `start` on.
+    const fn table_offsets(square_bits: &[usize; N_SQUARES], start: usize) -> [usize; N_SQUARES] {
+        let mut offsets = [0; N_SQUARES];
+        let mut offset = start;
+        let mut square = 0;
+        while square < N_SQUARES {
+            offsets[square] = offset;
+            offset += 1 << square_bits[square];
+            square += 1;
         }
+
+        offsets
     }
 
-    fn magic_index(&self, magic: u64, blockers: u64, bits: usize) -> usize {
+    const fn magic_index(magic: u64, blockers: u64, bits: usize) -> usize {
         ((blockers.wrapping_mul(magic)) >> (64 - bits)) as usize
     }
 
-    fn get_blocker_from_idx(&self, idx: usize, mut mask: u64) -> u64 {
+    const fn get_blocker_from_idx(idx: usize, mut mask: u64) -> u64 {
         let mut blockers = 0u64;
         let mut i = 0;
 
@@ -211,3 +367,97 @@ impl MagicBitboard {
         blockers
     }
 }
+
+fn write_square_bits(source: &mut String, name: &str, square_bits: &[usize; N_SQUARES]) {
+    writeln!(source, "    const {}: [usize; N_SQUARES] = [", name).unwrap();
+    for row in square_bits.chunks(BOARD_WIDTH) {
+        let row: Vec<String> = row.iter().map(|bits| bits.to_string()).collect();
+        writeln!(source, "        {},", row.join(", ")).unwrap();
+    }
+    writeln!(source, "    ];").unwrap();
+}
+
+fn write_magics(source: &mut String, name: &str, magics: &[u64; N_SQUARES]) {
+    writeln!(source, "    const {}: [u64; N_SQUARES] = [", name).unwrap();
+    for row in magics.chunks(BOARD_WIDTH/2) {
+        let row: Vec<String> = row.iter().map(|magic| format!("0x{:016x}", magic)).collect();
+        writeln!(source, "        {},", row.join(", ")).unwrap();
+    }
+    writeln!(source, "    ];").unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::Bb;
+
+    #[test]
+    fn test_slider_moves() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // The PEXT-indexed tables, if enabled and supported
+        for magics in [&MAGICS, MagicBitboard::get()] {
+            for square in 0..N_SQUARES {
+                for _ in 0..100 {
+                    let occ = rng.gen::<u64>() & rng.gen::<u64>();
+
+                    let rook_blockers = occ & PSEUDO_LEGAL_MOVES.get_piece_blocker_mask(Piece::Rook, square);
+                    assert_eq!(
+                        magics.get_rook_moves(square, rook_blockers),
+                        PSEUDO_LEGAL_MOVES.get_rook_rays(square, rook_blockers),
+                    );
+
+                    let bishop_blockers = occ & PSEUDO_LEGAL_MOVES.get_piece_blocker_mask(Piece::Bishop, square);
+                    assert_eq!(
+                        magics.get_bishop_moves(square, bishop_blockers),
+                        PSEUDO_LEGAL_MOVES.get_bishop_rays(square, bishop_blockers),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_magic() {
+        // Bishop on d4 (square 28)
+        let mask = PSEUDO_LEGAL_MOVES.bishop_masks[28];
+        let blockers: Vec<u64> = (0..1 << mask.count_ones())
+            .map(|blocker_idx| MagicBitboard::get_blocker_from_idx(blocker_idx, mask))
+            .collect();
+        let moves: Vec<u64> = blockers.iter().map(|&b| PSEUDO_LEGAL_MOVES.get_bishop_rays(28, b)).collect();
+
+        let find = |seed| MagicBitboard::find_magic(&mut StdRng::seed_from_u64(seed), &blockers, &moves, 9, usize::MAX).unwrap();
+        let magic = find(1);
+        assert_eq!(find(1), magic);
+
+        // Every index holds the moves of all blocker sets mapped to it
+        let mut table = [None; 1 << 9];
+        for (&b, &m) in blockers.iter().zip(&moves) {
+            let entry = &mut table[MagicBitboard::magic_index(magic, b, 9)];
+            assert!(entry.is_none_or(|entry_moves| entry_moves == m));
+            *entry = Some(m);
+        }
+
+        // Not enough bits for distinct moves
+        assert_eq!(MagicBitboard::find_magic(&mut StdRng::seed_from_u64(1), &blockers, &moves, 3, 1000), None);
+    }
+
+    #[test]
+    fn test_xray_attacks() {
+        // Rook on a1 (square 7) looking up the a-file through the piece on a3
+        // to the one on a6, and along the first rank through b1 to f1
+        let squares = |squares: &[&str]| Bb::from_squares(squares).unwrap();
+        let occ = squares(&["a3", "a6", "a8", "b1", "f1"]).0;
+        assert_eq!(
+            Bb(MAGICS.xray_rook_attacks(7, occ, squares(&["a3", "b1"]).0)),
+            squares(&["a4", "a5", "a6", "c1", "d1", "e1", "f1"]),
+        );
+        // Only the first blocker on a ray is looked through
+        assert_eq!(Bb(MAGICS.xray_rook_attacks(7, occ, squares(&["a6"]).0)), Bb(0));
+
+        // Bishop on c1 (square 5) through d2 to f4
+        let occ = squares(&["d2", "f4"]).0;
+        assert_eq!(Bb(MAGICS.xray_bishop_attacks(5, occ, squares(&["d2"]).0)), squares(&["e3", "f4"]));
+        assert_eq!(Bb(MAGICS.xray_bishop_attacks(5, occ, 0)), Bb(0));
+    }
+}
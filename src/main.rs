@@ -1,208 +1,1203 @@
-mod board;
-mod move_bitboards;
-mod r#move;
-mod game;
-mod eval;
-mod search;
-mod magic;
-mod zobrist;
 mod cli;
+mod completion;
+mod config;
+mod input;
+mod output;
+mod repl;
+#[cfg(feature = "tui")]
+mod tui;
 
-use crate::board::{Piece, Side};
-use crate::game::GameState;
-use crate::magic::MagicBitboard;
-use crate::move_bitboards::MoveBitboards;
-use crate::r#move::{Move, MoveType, MoveResult};
-use crate::search::SearchAsync;
+use pawndropper::{bench, distributed, perft, puzzle, puzzlegen, savegame, selfplay, spsa, testsuite, tuner, uci};
+use pawndropper::{Engine, EngineObserver, Error, SearchLimits};
+use pawndropper::adjudication::Adjudicator;
+use pawndropper::annotation::{Annotation, AnnotationThresholds};
+use pawndropper::evalhistory::EvalHistory;
+use pawndropper::board::{BoardStyle, Side, Variant};
+use pawndropper::clock::Clock;
+use pawndropper::eval::evaluate;
+use pawndropper::game::GameState;
+use pawndropper::gamelog::{GameLog, SearchReport};
+use pawndropper::learning::LearningStore;
+use pawndropper::magic::MagicBitboard;
+use pawndropper::motifs::find_motifs;
+use pawndropper::move_bitboards::{MoveBitboards, PSEUDO_LEGAL_MOVES};
+use pawndropper::opening::OpeningPolicy;
+use pawndropper::opponent::Opponent;
+use pawndropper::personality::Personality;
+use pawndropper::r#move::{DrawReason, Move, MoveResult};
+use pawndropper::savegame::{SavedGame, Settings};
+use pawndropper::search::{format_score, SearchAsync, SearchHandle, MAX_SEARCH_DEPTH};
+use pawndropper::skill::Skill;
+use pawndropper::speedtest::{self, Calibration};
 
-use std::collections::HashMap;
+use completion::MoveCompleter;
+use output::{format_principal_variation, Output, OutputFormat, View};
+use repl::Command;
 
-use rand::seq::SliceRandom;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, IsTerminal};
+use std::fs;
+use std::net::TcpListener;
 
-use log::info;
-use env_logger::Env;
+use tracing::{info, info_span, warn};
+use tracing_subscriber::EnvFilter;
 
 use rustyline::error::ReadlineError;
-use rustyline::{DefaultEditor, Result};
+use rustyline::history::DefaultHistory;
+use rustyline::{Editor, Result};
 
-use std::time::Instant;
+use std::sync::mpsc;
+use std::thread::{self, ScopedJoinHandle};
+use std::time::{Duration, Instant};
 
-fn print_legal_moves(side: Side, moves: &Vec<Move>) {
-    let mut moves_str = "".to_owned();
-    for m in moves {
-        moves_str.push_str(&m.to_algebraic_with_state(moves));
-        moves_str.push_str(" ");
-    }
-    info!("[{:?}] Legal moves: [{}]", side, moves_str);
+// Searcher handed back by the ponder thread along with its search result
+type PonderResult = (SearchAsync, (i32, Move, Vec<Move>));
+
+/// Time and depth limit of the engine's search for its move. With a clock or
+/// a fixed move time it searches until its time is up, otherwise to `depth`.
+/// The time on the clock is spent as the personality's time usage says, with
+/// `move_overhead` in reserve.
+fn search_limits(
+    clock: Option<&Clock>,
+    move_time: Option<Duration>,
+    cpu_side: Side,
+    depth: usize,
+    personality: Personality,
+    move_overhead: Duration,
+) -> (Option<Duration>, usize) {
+    let time_limit = clock
+        .map(|clock| {
+            let reserve = clock.remaining(cpu_side).saturating_sub(move_overhead)/2;
+            clock.move_time(cpu_side, move_overhead).mul_f64(personality.time_usage()).min(reserve)
+        })
+        .or(move_time);
+    (time_limit, if time_limit.is_some() { MAX_SEARCH_DEPTH } else { depth })
 }
 
-fn print_principal_variation(game: &GameState, pv: &Vec<Move>, mut legal_moves: Vec<Move>) {
-    let mut game_clone = game.clone();
-    let mut moves_str = "".to_owned();
+// Depth of the settings of a game on a clock or with a fixed move time
+// without a depth given, where the engine searches on time
+const DEFAULT_DEPTH: usize = 6;
 
-    if game.board.side_to_move.opposite() == Side::Black {
-        moves_str.push_str("..");
+/// Search depth of the engine's moves in a game against the user, and the
+/// calibration of this machine when it searches to a depth rather than on
+/// time. Without a depth given, it's the deepest that takes about
+/// `speedtest::TARGET_MOVE_TIME` per move.
+fn play_depth(args: &cli::PlayArgs) -> (usize, Option<Calibration>) {
+    if args.tc.is_some() || args.movetime.is_some() {
+        return (args.depth.unwrap_or(DEFAULT_DEPTH), None);
     }
 
-    for m in pv {
-        if (game_clone.half_move_number - 1) % 2 != 0 {
-            moves_str.push_str(&format!("{}. ", game_clone.move_number));
+    let calibration = Calibration::measure(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), speedtest::QUICK_BUDGET);
+    let depth = args.depth.unwrap_or_else(|| calibration.depth_for(speedtest::TARGET_MOVE_TIME));
+    info!(nps = calibration.nps, depth, "Calibrated the search speed");
+
+    (depth, Some(calibration))
+}
+
+/// Abort the ponder search, if any, and take back its searcher. The stop
+/// request is cleared, also one left by `stop` or Ctrl-C, as the search
+/// doesn't clear it and the next one would stop right away.
+fn stop_ponder(
+    ponder: &mut Option<(Move, ScopedJoinHandle<'_, PonderResult>)>,
+    searcher: &mut Option<SearchAsync>,
+    search_handle: &SearchHandle
+) {
+    if let Some((_, handle)) = ponder.take() {
+        search_handle.stop();
+        let (ponder_searcher, _) = handle.join().unwrap();
+        *searcher = Some(ponder_searcher);
+    }
+    search_handle.clear();
+}
+
+// How often input is checked for while the engine is thinking
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How the user ended the game while the engine was thinking.
+enum Interruption {
+    Quit,
+    Resign,
+    Draw,
+    ClaimDraw(DrawReason),
+}
+
+/// Wait for the engine's `search` to finish, handling the commands entered
+/// meanwhile: `stop` stops the search so the engine plays the best move found
+/// so far, like Ctrl-C, and `quit`, `resign`, an accepted draw offer or a
+/// claimed draw stop it and end the game. Any other input, e.g. the user's
+/// next move, is added to `typed_ahead` for after the engine's move.
+fn wait_for_search(
+    search: &ScopedJoinHandle<'_, PonderResult>,
+    typed_ahead: &mut VecDeque<String>,
+    search_handle: &SearchHandle,
+    game: &GameState,
+    adjudicator: &Adjudicator,
+) -> Option<Interruption> {
+    while !search.is_finished() {
+        let Some(line) = input::poll_line(INPUT_POLL_INTERVAL) else { continue };
+        // Nothing more to read until the engine moved, e.g. at the end of
+        // piped input
+        if line.is_empty() {
+            return None;
         }
-        moves_str.push_str(&m.to_algebraic_with_state(&legal_moves));
-        moves_str.push_str(" ");
 
-        (_, legal_moves) = game_clone.make_move(m);
+        let line = line.trim_end().to_owned();
+        let interruption = match repl::parse_command(&line) {
+            Ok(Command::Stop) => {
+                search_handle.stop();
+                continue;
+            },
+            Ok(Command::OfferDraw) => match game.claimable_draw() {
+                Some(reason) => Interruption::ClaimDraw(reason),
+                None if adjudicator.accepts_draw(game) => Interruption::Draw,
+                None => {
+                    println!("Draw offer declined");
+                    continue;
+                },
+            },
+            Ok(Command::Resign) => Interruption::Resign,
+            Ok(Command::Quit) => Interruption::Quit,
+            _ => {
+                typed_ahead.push_back(line);
+                continue;
+            },
+        };
+        search_handle.stop();
+        return Some(interruption);
     }
-    info!("Principal variation: {}", moves_str);
+
+    None
 }
 
-fn main() -> Result<()> {
-    env_logger::Builder::from_env(
-        Env::default().default_filter_or("pawndropper=info")
-    ).init();
+/// Tell the user if a draw can be claimed in `game`, which `offer draw` does.
+fn report_claimable_draw(game: &GameState) {
+    if let Some(reason) = game.claimable_draw() {
+        println!("A draw can be claimed: {:?}, `offer draw` claims it", reason);
+    }
+}
 
-    // Parse CLI args
-    use clap::Parser;
-    let args = cli::Args::parse();
+/// Set up a game of `variant` from the starting position, with the computer's
+/// first move made by the opening policy, or by a weak `opponent`, if it plays
+/// white, avoiding the lines `learning` lost repeatedly, and print it in
+/// `view`.
+/// Returns the game and the legal moves of the user.
+#[allow(clippy::too_many_arguments)]
+fn new_game(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    variant: Variant,
+    cpu_side: Side,
+    searcher: &mut SearchAsync,
+    opening: &OpeningPolicy,
+    opponent: Opponent,
+    learning: Option<&LearningStore>,
+    view: View,
+    output: Output,
+) -> (GameState, Vec<Move>) {
+    let mut game = GameState::with_variant(pl_moves, magics, variant);
 
-    // Initialise engine states (search thread pool, pseudo-legal moves, etc.)
-    let mut searcher = SearchAsync::new();
-    let pseudo_legal_moves = MoveBitboards::init_legal_moves();
-    let magics = MagicBitboard::init_precomputed(&pseudo_legal_moves);
-    //
-    // Uncomment to (re)generate magics
-    //MagicBitboard::init(&pseudo_legal_moves).print_magics();
-    let mut game = GameState::new(&pseudo_legal_moves, &magics);
-
-    let mut move_res: Option<MoveResult>;
     let (mut legal_moves, _) = game.get_legal_moves();
 
-    let cpu_side = Side::from_str(&args.cpu_side);
-
     // If the computer is white, make a white move before going in readline loop
     if cpu_side == Side::White {
-        // Only respectable moves, of course
-        let considered_moves = [
-            // e4
-            Move {
-                from_square: 11,
-                to_square: 27,
-                move_type: MoveType::Quiet,
-                piece: Piece::Pawn,
-                side: Side::White,
-            },
-            // d4
-            Move {
-                from_square: 12,
-                to_square: 28,
-                move_type: MoveType::Quiet,
-                piece: Piece::Pawn,
-                side: Side::White,
-            }
-        ];
+        let search_moves = learning.map_or(vec![], |learning| learning.search_moves(&game, &legal_moves));
+        let m = &match opponent {
+            Opponent::Engine => opening.pick_move(searcher, &mut game, &search_moves).unwrap(),
+            opponent => opponent.find_move(searcher, &mut game, MAX_SEARCH_DEPTH, &search_moves).1,
+        };
+
+        output.engine_move(&game, m, &legal_moves, None);
+        (_, legal_moves) = game.make_move(m);
+    }
+    output.position(&game, view.orientation(cpu_side));
+
+    (game, legal_moves)
+}
 
-        let m = considered_moves.choose(&mut rand::thread_rng()).unwrap();
+fn main() -> Result<()> {
+    // Parse CLI args, with the defaults of the options not given from the
+    // config file
+    use clap::{CommandFactory, FromArgMatches};
+    let matches = cli::Args::command().get_matches();
+    let mut args = cli::Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Err(err) = config::Config::load(args.config.as_deref()).and_then(|config| config.merge_into(&mut args, &matches)) {
+        println!("Error: {}", err);
+        return Ok(());
+    }
 
-        (_, legal_moves) = game.make_move(&m);
+    // Diagnostics go to stderr, filtered per module with RUST_LOG. They'd be
+    // drawn over the full-screen interface, which logs nothing by default
+    #[cfg(feature = "tui")]
+    let tui = match &args.command {
+        Some(cli::Command::Play(play)) => play.tui,
+        None => args.play.tui,
+        _ => false,
+    };
+    #[cfg(not(feature = "tui"))]
+    let tui = false;
+    let default_filter = if tui { "off" } else { "pawndropper=info" };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_filter));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    if args.log_json {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
 
-        println!("{}", game.board.to_ascii(cpu_side.opposite()));
+    // Initialise engine states (search thread pool, pseudo-legal moves, etc.)
+    let pseudo_legal_moves = &PSEUDO_LEGAL_MOVES;
+    let magics = MagicBitboard::get();
 
-        println!("1. {}", m.to_algebraic_with_state(&legal_moves));
+    let board_style = if args.no_unicode {
+        BoardStyle::Letters
+    } else if args.color.enabled() {
+        BoardStyle::Unicode
     } else {
-        println!("{}", game.board.to_ascii(cpu_side.opposite()));
+        BoardStyle::Monochrome
+    };
+    let output = Output { format: args.output, board_style, board_layout: args.board };
+
+    let result = match args.command {
+        Some(cli::Command::Play(play)) => return play_game(play, output),
+        None => return play_game(args.play, output),
+        Some(cli::Command::Analyze { fen, moves, depth, workers, .. }) if !workers.is_empty() => {
+            analyze_distributed(&fen, &moves, depth, &workers, output)
+        },
+        Some(cli::Command::Analyze { fen, moves, depth, movetime, nodes, hash, .. }) => {
+            let limits = match movetime {
+                Some(movetime) => SearchLimits { depth: None, nodes, time: Some(Duration::from_millis(movetime)) },
+                None => SearchLimits { depth: Some(depth), nodes, time: None },
+            };
+            analyze(&fen, &moves, &limits, hash, output)
+        },
+        Some(cli::Command::Worker { listen, hash }) => {
+            TcpListener::bind(&listen).map_err(Error::Network).and_then(|listener| {
+                println!("Listening on {}", listen);
+                distributed::serve(pseudo_legal_moves, magics, listener, hash)
+            })
+        },
+        Some(cli::Command::Bench { depth }) => bench::run(pseudo_legal_moves, magics, depth),
+        Some(cli::Command::Speedtest) => speedtest::run(pseudo_legal_moves, magics),
+        Some(cli::Command::Selfplay { fen, moves, depth, movetime, nodes, learn, games, opponent }) => {
+            let move_time = movetime.map(Duration::from_millis);
+
+            // What was learned is kept up to date with every game
+            learn.as_deref().map(LearningStore::load).transpose().and_then(|mut learning| {
+                if games > 1 || opponent != Opponent::Engine {
+                    let limits = SearchLimits { depth: Some(depth), nodes, time: move_time };
+                    selfplay::run_match(pseudo_legal_moves, magics, &fen, &moves, &limits, games, opponent, learning.as_mut())?;
+                } else {
+                    match output.format {
+                        OutputFormat::Text => selfplay::run(pseudo_legal_moves, magics, &fen, &moves, depth, move_time, nodes, learning.as_mut()),
+                        OutputFormat::Json => selfplay_json(&fen, &moves, depth, move_time, nodes, learning.as_mut()),
+                    }?;
+                }
+                match (&learning, &learn) {
+                    (Some(learning), Some(path)) => learning.save(path),
+                    _ => Ok(()),
+                }
+            })
+        },
+        Some(cli::Command::Uci) => {
+            uci::run(std::io::stdin().lock(), std::io::stdout())?;
+            Ok(())
+        },
+        Some(cli::Command::Testsuite { depths, nodes }) => {
+            testsuite::run(pseudo_legal_moves, magics, &depths, nodes);
+            Ok(())
+        },
+        Some(cli::Command::Puzzle { file, depth, attempts }) => {
+            puzzle::run(pseudo_legal_moves, magics, file.as_deref(), depth, attempts, board_style)
+        },
+        Some(cli::Command::GeneratePuzzles { files, games, depth, scan_depth, verify_depth, out }) => {
+            puzzlegen::run(pseudo_legal_moves, magics, &files, games, depth, scan_depth, verify_depth, out.as_deref())
+        },
+        Some(cli::Command::Tune { dataset, output, passes, augment }) => {
+            tuner::run(pseudo_legal_moves, magics, &dataset, &output, passes, augment)
+        },
+        Some(cli::Command::Spsa { params, iterations, games, depth, fen, out }) => {
+            spsa::run(pseudo_legal_moves, magics, &fen, &params, iterations, games, depth, &out)
+        },
+        Some(cli::Command::Perft { depth, fen, moves }) => perft::run(pseudo_legal_moves, magics, &fen, &moves, depth),
+        Some(cli::Command::PerftBench) => perft::bench(pseudo_legal_moves, magics),
+        Some(cli::Command::Magics { seed, output }) => {
+            MagicBitboard::generate_magics(pseudo_legal_moves, seed.unwrap_or_else(rand::random), &output)
+        },
+    };
+
+    if let Err(err) = result {
+        println!("Error: {}", err);
+    }
+
+    Ok(())
+}
+
+/// Search `fen` after `moves` within `limits`, printing every completed
+/// iteration.
+fn analyze(fen: &str, moves: &[String], limits: &SearchLimits, hash: usize, output: Output) -> std::result::Result<(), Error> {
+    let mut engine = Engine::builder().hash_mb(hash).build();
+    engine.set_position(fen, &moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+    output.position(engine.game(), engine.game().board.side_to_move);
+
+    let game = engine.game().clone();
+    engine.subscribe(Box::new(IterationPrinter { game, output }));
+    let result = engine.search(limits);
+    output.search_done(engine.game(), &result.best_move, result.nodes);
+
+    Ok(())
+}
+
+/// Search `fen` after `moves` to `depth` with the root moves split across
+/// `workers`, printing the result.
+fn analyze_distributed(fen: &str, moves: &[String], depth: usize, workers: &[String], output: Output) -> std::result::Result<(), Error> {
+    let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), fen)?;
+    game.apply_moves(&moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+    output.position(&game, game.board.side_to_move);
+
+    if let Some(result) = distributed::search(&game, depth, workers) {
+        output.iteration(&game, depth, result.eval, &result.pv);
+        output.search_done(&game, &result.pv[0], result.nodes);
+    }
+
+    Ok(())
+}
+
+/// Prints the evaluation and principal variation of each completed
+/// iteration of a search of `game`.
+struct IterationPrinter {
+    game: GameState,
+    output: Output,
+}
+
+impl EngineObserver for IterationPrinter {
+    fn iteration_completed(&mut self, depth: usize, eval: i32, pv: &[Move]) {
+        self.output.iteration(&self.game, depth, eval, pv);
+    }
+}
+
+/// Play a game of the engine against itself from `fen` after `moves`, like
+/// `selfplay::run`, printing the moves and the result as JSON lines.
+fn selfplay_json(
+    fen: &str,
+    moves: &[String],
+    depth: usize,
+    move_time: Option<Duration>,
+    node_limit: Option<u64>,
+    learning: Option<&mut LearningStore>,
+) -> std::result::Result<(), Error> {
+    let output = Output { format: OutputFormat::Json, ..Output::default() };
+    let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), fen)?;
+    game.apply_moves(&moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+
+    let result = selfplay::play(&mut game, [Opponent::Engine; Side::N_SIDES], depth, move_time, node_limit, learning, |game, m, legal_moves, eval| {
+        output.engine_move(game, m, legal_moves, Some(eval));
+    });
+    output.result(result.winner, &result.reason, "");
+    output.position(&game, Side::White);
+
+    Ok(())
+}
+
+/// Play games against the user in the terminal until they quit.
+fn play_game(args: cli::PlayArgs, mut output: Output) -> Result<()> {
+    let (depth, calibration) = play_depth(&args);
+
+    #[cfg(feature = "tui")]
+    if args.tui {
+        return Ok(tui::run(args, output.board_style, depth, calibration)?);
     }
 
-    // Readline instance for user input
-    let mut rl = DefaultEditor::new()?;
+    let pseudo_legal_moves = &PSEUDO_LEGAL_MOVES;
+    let magics = MagicBitboard::get();
 
-    loop {
-        let mut move_map = HashMap::<String, &Move>::new();
+    // Readline instance for user input, completing legal moves and commands
+    let mut rl = Editor::<MoveCompleter, DefaultHistory>::new()?;
+    rl.set_helper(Some(MoveCompleter::default()));
 
-        for m in &legal_moves {
-            let algebraic_notation = m.to_algebraic_with_state(&legal_moves);
-            move_map.insert(algebraic_notation, m);
+    // The input history is kept between runs, there's none on the first run
+    let history_path = repl::history_path();
+    if let Some(path) = &history_path {
+        let _ = rl.load_history(path);
+    }
+
+    // Offer to resume the game of the last run if it wasn't finished, e.g.
+    // after a crash
+    let autosave_path = savegame::autosave_path();
+    let mut resumed = None;
+    if let Some(Ok(saved)) = autosave_path.as_ref().map(SavedGame::load) {
+        if let Ok(saved_game) = saved.to_game(pseudo_legal_moves, magics) {
+            println!("Unfinished game: {}", saved_game.move_history());
+            if rl.readline("Resume it? [y/n] ").is_ok_and(|answer| answer.trim() == "y") {
+                resumed = Some((saved, saved_game));
+            }
         }
+    }
+
+    let mut settings = match &resumed {
+        Some((saved, _)) => saved.settings,
+        None => Settings {
+            depth,
+            skill: args.skill,
+            nodes: args.nodes,
+            ponder: args.ponder,
+        },
+    };
+    if let Some(calibration) = &calibration {
+        println!("Searching to depth {}, about {:.1?} per move on this machine", settings.depth, calibration.estimated_time(settings.depth));
+    }
 
-        print_legal_moves(game.board.side_to_move, &legal_moves);
+    let mut searcher = SearchAsync::with_hash_size(args.hash);
+    searcher.set_node_limit(settings.nodes);
+    searcher.set_skill(Skill::new(settings.skill));
+    args.personality.apply(&mut searcher);
+    let search_handle = searcher.handle();
 
-        let rl_str = if game.board.side_to_move == Side::White {
-            format!("move {}> ", game.move_number)
-        } else {
-            format!("move ..{}> ", game.move_number)
-        };
-        let readline = rl.readline(&rl_str);
-        match readline {
-            Ok(line) => {
-                match move_map.get(&line) {
-                    Some(m) => {
-                        // User move
-                        (move_res, legal_moves) = game.make_move(m);
-
-                        println!("{}", game.board.to_ascii(cpu_side.opposite()));
-
-                        match move_res {
-                            Some(MoveResult::Checkmate) => {
-                                println!("Checkmate --- computer loses");
+    // Ctrl-C while the engine is thinking stops the search and plays the best
+    // move found so far, like `stop`. While waiting for input, rustyline
+    // handles Ctrl-C
+    {
+        let search_handle = search_handle.clone();
+        ctrlc::set_handler(move || search_handle.stop())
+            .expect("failed to set Ctrl-C handler");
+    }
+
+    let opening = OpeningPolicy::new(args.opening_depth, args.opening_margin);
+
+    // Without a time control the engine searches to the set depth
+    let mut clock = args.tc.map(Clock::new);
+    let move_time = args.movetime.map(Duration::from_millis);
+
+    // What was learned from earlier games, to avoid the lines lost repeatedly
+    let mut learning = match args.learn.as_deref().map(LearningStore::load).transpose() {
+        Ok(learning) => learning,
+        Err(err) => {
+            println!("Error: {}", err);
+            return Ok(());
+        },
+    };
+
+    let mut cpu_side = args.cpu_side;
+    let (mut game, mut legal_moves) = match resumed {
+        Some((saved, saved_game)) => {
+            cpu_side = saved.cpu_side;
+            if saved.clock.is_some() {
+                clock = saved.clock;
+            }
+            output.position(&saved_game, args.view.orientation(cpu_side));
+            let (legal_moves, _) = saved_game.get_legal_moves();
+            (saved_game, legal_moves)
+        },
+        None => new_game(pseudo_legal_moves, magics, args.variant, cpu_side, &mut searcher, &opening, args.opponent, learning.as_ref(), args.view, output),
+    };
+
+    let mut game_log = match GameLog::new(args.log.as_deref()) {
+        Ok(game_log) => game_log,
+        Err(err) => {
+            println!("Error: {}", err);
+            return Ok(());
+        },
+    };
+    game_log.start(&game, cpu_side, &settings, clock.as_ref().map(Clock::time_control));
+    let mut adjudicator = Adjudicator::new();
+    let mut turn_start = Instant::now();
+
+    // Side at the bottom of the printed board, the user's side unless set
+    // with `--view`, `view` or `flip`
+    let mut view = args.view;
+
+    // The searcher is handed to the ponder thread while pondering and handed
+    // back when the thread is joined
+    let mut searcher = Some(searcher);
+
+    // The user's moves are annotated by the engine's evaluations before and
+    // after them, by index of the moves that can be taken back. There's no
+    // evaluation before the user's first move after the game was set up
+    let mut last_eval: Option<i32> = None;
+    let mut annotations = HashMap::<usize, Annotation>::new();
+    let mut evals = EvalHistory::new();
+
+    // Whether the user quit before the game was over
+    let mut abandoned = false;
+
+    // Input entered while the engine was thinking, for after its move
+    let mut typed_ahead = VecDeque::new();
+
+    thread::scope(|scope| {
+        let mut ponder: Option<(Move, ScopedJoinHandle<PonderResult>)> = None;
+
+        loop {
+            // Autosave the game in progress, there's nothing to resume without
+            // any moves
+            if let Some(path) = &autosave_path {
+                if game.n_played_moves() > 0 {
+                    let saved = SavedGame::new(&game, cpu_side, settings, clock.as_ref());
+                    let dir = path.parent().map_or(Ok(()), fs::create_dir_all)
+                        .map_err(|source| Error::Write { path: path.clone(), source });
+                    if let Err(err) = dir.and_then(|_| saved.save(path)) {
+                        warn!("Autosave failed: {}", err);
+                    }
+                } else {
+                    let _ = fs::remove_file(path);
+                }
+            }
+
+            rl.helper_mut().unwrap().set_moves(legal_moves.iter().map(|m| m.to_algebraic_with_state(&legal_moves)).collect());
+
+            output.legal_moves(&game, &legal_moves);
+            if let Some(clock) = &clock {
+                println!("{}", clock);
+            }
+
+            let rl_str = if game.board.side_to_move == Side::White {
+                format!("move {}> ", game.move_number)
+            } else {
+                format!("move ..{}> ", game.move_number)
+            };
+            let readline = match typed_ahead.pop_front() {
+                Some(line) => {
+                    if io::stdin().is_terminal() {
+                        println!("{}{}", rl_str, line);
+                    }
+                    Ok(line)
+                },
+                None => rl.readline(&rl_str),
+            };
+            match readline {
+                Ok(line) => {
+                    let _ = rl.add_history_entry(line.as_str());
+                    let command = match repl::parse_command(&line) {
+                        Ok(command) => command,
+                        Err(err) => {
+                            println!("{}", err);
+                            continue;
+                        },
+                    };
+
+                    let line = match command {
+                        Command::Move(line) => line,
+                        Command::Help => {
+                            print!("{}", repl::help());
+                            continue;
+                        },
+                        Command::Eval => {
+                            println!("{}", evaluate(&game));
+                            continue;
+                        },
+                        Command::Bitboards => {
+                            println!("{}", game.bitboards());
+                            continue;
+                        },
+                        Command::Motifs => {
+                            let motifs = find_motifs(&game);
+                            if motifs.is_empty() {
+                                println!("No tactical motifs");
+                            }
+                            motifs.iter().for_each(|motif| println!("{}", motif));
+                            continue;
+                        },
+                        Command::Show => {
+                            output.position(&game, view.orientation(cpu_side));
+                            continue;
+                        },
+                        Command::Flip => {
+                            view = View::from(view.orientation(cpu_side).opposite());
+                            output.position(&game, view.orientation(cpu_side));
+                            continue;
+                        },
+                        Command::View(new_view) => {
+                            view = new_view;
+                            output.position(&game, view.orientation(cpu_side));
+                            continue;
+                        },
+                        Command::Board(board_layout) => {
+                            output.board_layout = board_layout;
+                            output.position(&game, view.orientation(cpu_side));
+                            continue;
+                        },
+                        Command::Fen => {
+                            println!("{}", game.to_fen());
+                            continue;
+                        },
+                        Command::Evals(None) => {
+                            if evals.points().is_empty() {
+                                println!("No evaluations yet");
+                            } else {
+                                println!("{}", evals.graph());
+                            }
+                            continue;
+                        },
+                        Command::Evals(Some(path)) => {
+                            match evals.save_csv(&path) {
+                                Ok(()) => println!("Evaluations saved to {}", path),
+                                Err(err) => println!("Error: {}", err),
+                            }
+                            continue;
+                        },
+                        Command::History => {
+                            println!("{}", game.move_history_with(|i| annotations.get(&i).map_or("", |a| a.symbol())));
+                            continue;
+                        },
+                        // Search the position until the user presses enter,
+                        // printing the principal variation of each depth
+                        Command::Analyze => {
+                            // The ponder search is for the computer's next move
+                            stop_ponder(&mut ponder, &mut searcher, &search_handle);
+
+                            // Analysis is at full strength and without limits
+                            let mut analysis_searcher = searcher.take().unwrap();
+                            analysis_searcher.set_skill(Skill::default());
+                            analysis_searcher.set_node_limit(None);
+                            analysis_searcher.set_time_limit(None);
+                            let (sender, receiver) = mpsc::channel();
+                            analysis_searcher.set_iteration_sender(Some(sender));
+
+                            let mut analysis_game = game.clone();
+                            let analysis = scope.spawn(move || {
+                                analysis_searcher.find_best_legal_move(&mut analysis_game, MAX_SEARCH_DEPTH, &[]);
+                                analysis_searcher.set_iteration_sender(None);
+                                analysis_searcher
+                            });
+
+                            // The channel closes when the search is done
+                            let analysis_game = game.clone();
+                            let analysis_legal_moves = legal_moves.clone();
+                            let printer = scope.spawn(move || {
+                                for (depth, eval, pv) in receiver {
+                                    info!(
+                                        "Depth {}: {} {}",
+                                        depth,
+                                        format_score(eval),
+                                        format_principal_variation(&analysis_game, &pv, analysis_legal_moves.clone())
+                                    );
+                                }
+                            });
+
+                            // Any input, or Ctrl-C, stops the analysis
+                            let _ = rl.readline("");
+                            search_handle.stop();
+                            let mut analysis_searcher = analysis.join().unwrap();
+                            printer.join().unwrap();
+                            search_handle.clear();
+
+                            analysis_searcher.set_node_limit(settings.nodes);
+                            analysis_searcher.set_skill(Skill::new(settings.skill));
+                            searcher = Some(analysis_searcher);
+                            continue;
+                        },
+                        // The user plays the side to move of the new position
+                        Command::SetBoard(fen) => {
+                            match GameState::from_fen(pseudo_legal_moves, magics, &fen) {
+                                Ok(new_game) => {
+                                    // The ponder search is for the old position
+                                    stop_ponder(&mut ponder, &mut searcher, &search_handle);
+
+                                    game = new_game;
+                                    adjudicator.reset();
+                                    last_eval = None;
+                                    annotations.clear();
+                                    evals.clear();
+                                    clock = args.tc.map(Clock::new);
+                                    turn_start = Instant::now();
+                                    (legal_moves, _) = game.get_legal_moves();
+                                    cpu_side = game.board.side_to_move.opposite();
+                                    game_log.start(&game, cpu_side, &settings, clock.as_ref().map(Clock::time_control));
+                                    output.position(&game, view.orientation(cpu_side));
+                                },
+                                Err(err) => println!("Error: {}", err),
+                            }
+                            continue;
+                        },
+                        // `new` keeps the sides, `new white` or `new black`
+                        // sets the side the user plays
+                        Command::New(user_side) => {
+                            cpu_side = user_side.map_or(cpu_side, Side::opposite);
+
+                            // The ponder search is for the old game
+                            stop_ponder(&mut ponder, &mut searcher, &search_handle);
+
+                            (game, legal_moves) = new_game(
+                                pseudo_legal_moves,
+                                magics,
+                                game.board.variant,
+                                cpu_side,
+                                searcher.as_mut().unwrap(),
+                                &opening,
+                                args.opponent,
+                                learning.as_ref(),
+                                view,
+                                output
+                            );
+                            adjudicator.reset();
+                            last_eval = None;
+                            annotations.clear();
+                            evals.clear();
+                            clock = args.tc.map(Clock::new);
+                            turn_start = Instant::now();
+                            game_log.start(&game, cpu_side, &settings, clock.as_ref().map(Clock::time_control));
+                            continue;
+                        },
+                        Command::Save(path) => {
+                            match SavedGame::new(&game, cpu_side, settings, clock.as_ref()).save(&path) {
+                                Ok(()) => println!("Game saved to {}", path),
+                                Err(err) => println!("Error: {}", err),
+                            }
+                            continue;
+                        },
+                        // Continue a saved game with its settings and clocks
+                        Command::Load(path) => {
+                            let loaded = SavedGame::load(&path).and_then(|saved| {
+                                let game = saved.to_game(pseudo_legal_moves, magics)?;
+                                Ok((saved, game))
+                            });
+                            match loaded {
+                                Ok((saved, loaded_game)) => {
+                                    // The ponder search is for the old game
+                                    stop_ponder(&mut ponder, &mut searcher, &search_handle);
+
+                                    game = loaded_game;
+                                    adjudicator.reset();
+                                    last_eval = None;
+                                    annotations.clear();
+                                    evals.clear();
+                                    clock = saved.clock.or_else(|| args.tc.map(Clock::new));
+                                    turn_start = Instant::now();
+                                    (legal_moves, _) = game.get_legal_moves();
+                                    cpu_side = saved.cpu_side;
+
+                                    settings = saved.settings;
+                                    let searcher = searcher.as_mut().unwrap();
+                                    searcher.set_node_limit(settings.nodes);
+                                    searcher.set_skill(Skill::new(settings.skill));
+
+                                    game_log.start(&game, cpu_side, &settings, clock.as_ref().map(Clock::time_control));
+                                    output.position(&game, view.orientation(cpu_side));
+                                },
+                                Err(err) => println!("Error: {}", err),
+                            }
+                            continue;
+                        },
+                        Command::Resign => {
+                            output.result(Some(cpu_side), "Resignation", "You resign --- computer wins");
+                            game_log.result(Some(cpu_side), "Resignation");
+                            break;
+                        },
+                        Command::Stop => {
+                            println!("The computer isn't thinking");
+                            continue;
+                        },
+                        Command::OfferDraw => {
+                            if let Some(reason) = game.claimable_draw() {
+                                output.result(None, &format!("{:?}", reason), &format!("Draw claimed: {:?}", reason));
+                                game_log.result(None, &format!("{:?}", reason));
                                 break;
-                            },
-                            Some(MoveResult::Draw(reason)) => {
-                                println!("Draw: {:?}", reason);
+                            }
+                            if adjudicator.accepts_draw(&game) {
+                                output.result(None, "Agreement", "Draw offer accepted");
+                                game_log.result(None, "Agreement");
                                 break;
-                            },
-                            _ => {},
-                        }
+                            }
+                            println!("Draw offer declined");
+                            continue;
+                        },
+                        Command::ClearHash => {
+                            // The ponder search would fill it again
+                            stop_ponder(&mut ponder, &mut searcher, &search_handle);
+                            searcher.as_mut().unwrap().clear_hash();
+                            println!("Hash cleared");
+                            continue;
+                        },
+                        Command::Undo => {
+                            // Take back the user's last move and the computer's
+                            // reply, which leaves the computer's first move as
+                            // white as there's an odd number of moves then
+                            if game.n_played_moves() < 2 {
+                                println!("No moves to take back");
+                                continue;
+                            }
 
-                        // Computer move
-                        let start = Instant::now();
-                        let (best_eval, best_move, pv) =
-                            searcher.find_best_legal_move(&mut game, args.depth);
-                        let duration = start.elapsed();
+                            // The ponder search is for the position being taken back
+                            stop_ponder(&mut ponder, &mut searcher, &search_handle);
 
-                        info!("Search took {:?}", duration);
+                            game.undo_move();
+                            game.undo_move();
+                            game_log.undo(2);
+                            adjudicator.reset();
+                            last_eval = None;
+                            annotations.retain(|&i, _| i < game.n_played_moves());
+                            evals.truncate(game.half_move_number);
+                            turn_start = Instant::now();
+                            (legal_moves, _) = game.get_legal_moves();
 
-                        print_legal_moves(game.board.side_to_move, &legal_moves);
+                            output.position(&game, view.orientation(cpu_side));
+                            continue;
+                        },
+                        // Like Ctrl-D, the game can be resumed later
+                        Command::Quit => {
+                            abandoned = true;
+                            break;
+                        },
+                    };
 
-                        let move_str = if game.board.side_to_move == Side::White {
-                            format!("{}. {}", game.move_number, best_move.to_algebraic_with_state(&legal_moves))
-                        } else {
-                            format!("{}. ..{}", game.move_number, best_move.to_algebraic_with_state(&legal_moves))
-                        };
-                        println!("{}", move_str);
+                    // Input that can mean several moves, e.g. a promotion
+                    // without the piece, asks which one
+                    let chosen_move = match repl::matching_moves(&line, &legal_moves).as_slice() {
+                        [] => None,
+                        [m] => Some(*m),
+                        candidates => {
+                            let chosen = rl.readline(&repl::choice_prompt(candidates, &legal_moves))
+                                .ok()
+                                .and_then(|answer| repl::resolve_choice(&answer, candidates, &legal_moves));
+                            if chosen.is_none() {
+                                println!("No move made");
+                                continue;
+                            }
+                            chosen
+                        },
+                    };
 
-                        (move_res, legal_moves) = game.make_move(&best_move);
+                    match chosen_move {
+                        Some(m) => {
+                            // The user's time runs from the end of the
+                            // computer's move
+                            let user_time = turn_start.elapsed();
+                            if let Some(clock) = &mut clock {
+                                if !clock.punch(cpu_side.opposite(), user_time) {
+                                    output.result(Some(cpu_side), "Time forfeit", "Time out --- computer wins");
+                                    game_log.result(Some(cpu_side), "Time forfeit");
+                                    break;
+                                }
+                            }
+                            let start = Instant::now();
 
-                        println!("{}", game.board.to_ascii(cpu_side.opposite()));
-                        info!("Eval: {:.3}", best_eval);
-                        print_principal_variation(&game, &pv, legal_moves.clone());
+                            // Resolve pondering: on a ponder hit the ponder search
+                            // is exactly the search we need, so let it finish.
+                            // Otherwise abort it and search the actual position
+                            let mut ponder_hit = None;
+                            if let Some((ponder_move, handle)) = ponder.take() {
+                                if ponder_move == m {
+                                    info!("Ponder hit");
+                                    ponder_hit = Some(handle);
+                                } else {
+                                    search_handle.stop();
+                                    let (ponder_searcher, _) = handle.join().unwrap();
+                                    searcher = Some(ponder_searcher);
+                                }
+                            }
 
-                        let moves_since_capture =
-                            (game.half_move_number - game.half_move_of_last_capture) / 2;
-                        info!("Moves since last capture: {}", moves_since_capture);
+                            // User move
+                            info!(ply = game.half_move_number, r#move = %m.to_algebraic_with_state(&legal_moves), "User move");
+                            output.user_move(&game, &m, &legal_moves);
+                            game_log.user_move(&game, &m, cpu_side, user_time);
+                            let mut move_res: Option<MoveResult>;
+                            (move_res, legal_moves) = game.make_move(&m);
 
-                        match move_res {
-                            Some(MoveResult::Checkmate) => {
-                                println!("Checkmate --- computer wins");
-                                break;
-                            },
-                            Some(MoveResult::Draw(reason)) => {
-                                println!("Draw: {:?}", reason);
+                            output.position(&game, view.orientation(cpu_side));
+
+                            match move_res {
+                                Some(MoveResult::Checkmate) => {
+                                    output.result(Some(cpu_side.opposite()), "Checkmate", "Checkmate --- computer loses");
+                                    game_log.result(Some(cpu_side.opposite()), "Checkmate");
+                                    break;
+                                },
+                                Some(MoveResult::ThirdCheck) => {
+                                    output.result(Some(cpu_side.opposite()), "Third check", "Third check --- computer loses");
+                                    game_log.result(Some(cpu_side.opposite()), "Third check");
+                                    break;
+                                },
+                                Some(MoveResult::Draw(reason)) => {
+                                    output.result(None, &format!("{:?}", reason), &format!("Draw: {:?}", reason));
+                                    game_log.result(None, &format!("{:?}", reason));
+                                    break;
+                                },
+                                _ => {},
+                            }
+                            report_claimable_draw(&game);
+
+                            // Computer move, timed from the user's move on
+                            // as a ponder hit may still be searching
+                            let _span = info_span!("engine_move", ply = game.half_move_number).entered();
+                            search_handle.clear();
+                            let mut printer = None;
+                            let search = match ponder_hit {
+                                Some(handle) => handle,
+                                None => {
+                                    let mut searcher = searcher.take().unwrap();
+                                    let (time_limit, depth) = search_limits(clock.as_ref(), move_time, cpu_side, settings.depth, args.personality, Duration::from_millis(args.move_overhead));
+                                    searcher.set_time_limit(time_limit);
+
+                                    // Print every completed depth while thinking,
+                                    // the channel closes when the search is done
+                                    let (sender, receiver) = mpsc::channel();
+                                    searcher.set_iteration_sender(Some(sender));
+                                    let thinking_game = game.clone();
+                                    printer = Some(scope.spawn(move || {
+                                        for (depth, eval, pv) in receiver {
+                                            output.thinking(&thinking_game, depth, eval, &pv);
+                                        }
+                                    }));
+
+                                    let search_moves = learning.as_ref().map_or(vec![], |learning| learning.search_moves(&game, &legal_moves));
+                                    let mut search_game = game.clone();
+                                    let opponent = args.opponent;
+                                    scope.spawn(move || {
+                                        let result = opponent.find_move(&mut searcher, &mut search_game, depth, &search_moves);
+                                        searcher.set_iteration_sender(None);
+                                        (searcher, result)
+                                    })
+                                },
+                            };
+
+                            // The search runs in the background so the user
+                            // can stop it or end the game meanwhile
+                            let interruption = wait_for_search(&search, &mut typed_ahead, &search_handle, &game, &adjudicator);
+                            let (thinking_searcher, (best_eval, best_move, pv)) = search.join().unwrap();
+                            searcher = Some(thinking_searcher);
+                            if let Some(printer) = printer {
+                                printer.join().unwrap();
+                            }
+                            match interruption {
+                                Some(Interruption::Quit) => {
+                                    abandoned = true;
+                                    break;
+                                },
+                                Some(Interruption::Resign) => {
+                                    output.result(Some(cpu_side), "Resignation", "You resign --- computer wins");
+                                    game_log.result(Some(cpu_side), "Resignation");
+                                    break;
+                                },
+                                Some(Interruption::Draw) => {
+                                    output.result(None, "Agreement", "Draw offer accepted");
+                                    game_log.result(None, "Agreement");
+                                    break;
+                                },
+                                Some(Interruption::ClaimDraw(reason)) => {
+                                    output.result(None, &format!("{:?}", reason), &format!("Draw claimed: {:?}", reason));
+                                    game_log.result(None, &format!("{:?}", reason));
+                                    break;
+                                },
+                                None => {},
+                            }
+                            let duration = start.elapsed();
+
+                            info!("Search took {:?}", duration);
+
+                            let nodes = searcher.as_ref().unwrap().nodes();
+                            let nps = (nodes as f64/duration.as_secs_f64()) as u64;
+                            info!("Searched {} nodes ({} nps, hash {}‰ full)", nodes, nps, searcher.as_ref().unwrap().hashfull());
+
+                            // Judge the user's move by how the evaluation changed
+                            if let Some(eval_before) = last_eval {
+                                let loss = AnnotationThresholds::loss(cpu_side.opposite(), eval_before, best_eval);
+                                if let Some(annotation) = args.annotate.annotate(loss) {
+                                    output.annotation(&game, annotation, loss);
+                                    game_log.annotation(&game, annotation, loss);
+                                    annotations.insert(game.n_played_moves() - 1, annotation);
+                                }
+                            }
+                            evals.record(&game, best_eval);
+
+                            if let Some(clock) = &mut clock {
+                                if !clock.punch(cpu_side, duration) {
+                                    output.result(Some(cpu_side.opposite()), "Time forfeit", "Time out --- you win");
+                                    game_log.result(Some(cpu_side.opposite()), "Time forfeit");
+                                    break;
+                                }
+                            }
+
+                            // The evaluation is from white's perspective
+                            adjudicator.record_eval(if cpu_side == Side::White { best_eval } else { -best_eval });
+                            if adjudicator.should_resign() {
+                                output.result(Some(cpu_side.opposite()), "Resignation", "Computer resigns --- you win");
+                                game_log.result(Some(cpu_side.opposite()), "Resignation");
                                 break;
-                            },
-                            _ => {},
-                        }
-                    },
-                    _ => println!("Invalid or illegal move '{}'", line)
+                            }
+
+                            output.legal_moves(&game, &legal_moves);
+                            output.engine_move(&game, &best_move, &legal_moves, None);
+                            output.eval_bar(best_eval);
+                            let pv_str = format_principal_variation(&game, &pv, legal_moves.clone());
+                            info!("Principal variation: {}", pv_str);
+
+                            game_log.engine_move(&game, &best_move, cpu_side, &SearchReport {
+                                depth: searcher.as_ref().unwrap().completed_depth(),
+                                eval: best_eval,
+                                pv: pv_str,
+                                nodes,
+                                time: duration,
+                                hashfull: searcher.as_ref().unwrap().hashfull(),
+                            });
+                            (move_res, legal_moves) = game.make_move(&best_move);
+                            turn_start = Instant::now();
+                            last_eval = Some(best_eval);
+                            evals.record(&game, best_eval);
+
+                            output.position(&game, view.orientation(cpu_side));
+                            info!("Eval: {}", format_score(best_eval));
+
+                            info!("Halfmove clock: {}", game.half_move_clock());
+
+                            match move_res {
+                                Some(MoveResult::Checkmate) => {
+                                    output.result(Some(cpu_side), "Checkmate", "Checkmate --- computer wins");
+                                    game_log.result(Some(cpu_side), "Checkmate");
+                                    break;
+                                },
+                                Some(MoveResult::ThirdCheck) => {
+                                    output.result(Some(cpu_side), "Third check", "Third check --- computer wins");
+                                    game_log.result(Some(cpu_side), "Third check");
+                                    break;
+                                },
+                                Some(MoveResult::Draw(reason)) => {
+                                    output.result(None, &format!("{:?}", reason), &format!("Draw: {:?}", reason));
+                                    game_log.result(None, &format!("{:?}", reason));
+                                    break;
+                                },
+                                _ => {},
+                            }
+                            report_claimable_draw(&game);
+
+                            // Think on the user's time: search the position after
+                            // the reply predicted by the principal variation
+                            // while the user is typing their move, if the
+                            // engine plays its own moves
+                            if settings.ponder && args.opponent == Opponent::Engine && pv.len() >= 2 && legal_moves.contains(&pv[1]) {
+                                let ponder_move = pv[1];
+                                let mut ponder_game = game.clone();
+                                let (_, ponder_legal_moves) = ponder_game.make_move(&ponder_move);
+                                let search_moves = learning.as_ref().map_or(vec![], |learning| learning.search_moves(&ponder_game, &ponder_legal_moves));
+
+                                // With a clock, the ponder search gets the time
+                                // of the computer's next move on top of the
+                                // user's thinking time
+                                let mut ponder_searcher = searcher.take().unwrap();
+                                let (time_limit, depth) = search_limits(clock.as_ref(), move_time, cpu_side, settings.depth, args.personality, Duration::from_millis(args.move_overhead));
+                                ponder_searcher.set_time_limit(time_limit);
+                                let handle = scope.spawn(move || {
+                                    let result = ponder_searcher.find_best_legal_move(&mut ponder_game, depth, &search_moves);
+                                    (ponder_searcher, result)
+                                });
+                                ponder = Some((ponder_move, handle));
+                            }
+                        },
+                        None => {
+                            let legal_moves: Vec<String> = legal_moves.iter().map(|m| m.to_algebraic_with_state(&legal_moves)).collect();
+                            match (repl::illegal_reason(&line, &game), repl::suggest_moves(&line, &legal_moves).as_slice()) {
+                                (Some(reason), []) => println!("Illegal move '{}': {}", line, reason),
+                                (Some(reason), suggestions) => println!("Illegal move '{}': {}, did you mean {}?", line, reason, suggestions.join(" or ")),
+                                (None, []) => println!("Invalid or illegal move '{}', enter `help` for the commands", line),
+                                (None, suggestions) => println!("Invalid or illegal move '{}', did you mean {}?", line, suggestions.join(" or ")),
+                            }
+                        },
+                    }
+                },
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                    abandoned = true;
+                    break
+                },
+                Err(err) => {
+                    println!("Error: {:?}", err);
+                    abandoned = true;
+                    break
                 }
-            },
-            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
-                break
-            },
-            Err(err) => {
-                println!("Error: {:?}", err);
-                break
             }
         }
+
+        // Don't leave a ponder search running on exit
+        if let Some((_, handle)) = ponder.take() {
+            search_handle.stop();
+            handle.join().unwrap();
+        }
+    });
+
+    if let Some(path) = &history_path {
+        let saved = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| rl.save_history(path).map_err(io::Error::other));
+        if let Err(err) = saved {
+            warn!("Can't save the input history to '{}': {}", path.display(), err);
+        }
+    }
+
+    // Learn from the game if it was played to the end
+    if let (Some(learning), Some(path), Some(winner)) = (&mut learning, &args.learn, game_log.winner()) {
+        learning.record_game(&game, winner, evals.points());
+        if let Err(err) = learning.save(path) {
+            println!("Error: {}", err);
+        }
+    }
+
+    // Only an abandoned game can be resumed
+    if let Some(path) = autosave_path.as_ref().filter(|_| !abandoned) {
+        let _ = fs::remove_file(path);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pawndropper::r#move::NULL_MOVE;
+
+    /// Start a deep ponder search of the starting position, as after the
+    /// computer's move.
+    fn start_ponder<'scope>(
+        scope: &'scope thread::Scope<'scope, '_>,
+        searcher: &mut Option<SearchAsync>,
+    ) -> Option<(Move, ScopedJoinHandle<'scope, PonderResult>)> {
+        let mut ponder_searcher = searcher.take().unwrap();
+        let mut ponder_game = GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get());
+        let handle = scope.spawn(move || {
+            let result = ponder_searcher.find_best_legal_move(&mut ponder_game, MAX_SEARCH_DEPTH, &[]);
+            (ponder_searcher, result)
+        });
+
+        Some((NULL_MOVE, handle))
+    }
+
+    #[test]
+    fn test_search_after_ponder() {
+        let mut searcher = Some(SearchAsync::with_hash_size(1));
+        let search_handle = searcher.as_ref().unwrap().handle();
+        thread::scope(|scope| {
+            let mut ponder = start_ponder(scope, &mut searcher);
+            stop_ponder(&mut ponder, &mut searcher, &search_handle);
+        });
+
+        // The analysis isn't stopped by the stop request of the ponder search
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get());
+        let searcher = searcher.as_mut().unwrap();
+        searcher.find_best_legal_move(&mut game, 4, &[]);
+        assert_eq!(searcher.completed_depth(), 4);
+    }
+
+    #[test]
+    fn test_new_game_after_ponder() {
+        let mut searcher = Some(SearchAsync::with_hash_size(1));
+        let search_handle = searcher.as_ref().unwrap().handle();
+        thread::scope(|scope| {
+            let mut ponder = start_ponder(scope, &mut searcher);
+            stop_ponder(&mut ponder, &mut searcher, &search_handle);
+        });
+
+        // `new` with the computer playing white scores every first move,
+        // rather than stopping at the first poll of the stop request, after
+        // 1024 nodes
+        let searcher = searcher.as_mut().unwrap();
+        let (game, _) = new_game(
+            &PSEUDO_LEGAL_MOVES,
+            MagicBitboard::get(),
+            Variant::Standard,
+            Side::White,
+            searcher,
+            &OpeningPolicy::new(4, 0),
+            Opponent::Engine,
+            None,
+            View::Auto,
+            Output::default(),
+        );
+        assert_eq!(game.n_played_moves(), 1);
+        assert!(searcher.nodes() > 2048);
+    }
+}
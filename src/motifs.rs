@@ -0,0 +1,247 @@
+//! Static detection of tactical motifs in a position, for annotating it: pins,
+//! skewers, forks, discovered attacks and hanging pieces, with the pieces
+//! involved. Nothing is searched, so a motif is an opportunity or a threat
+//! rather than a winning tactic.
+
+use std::fmt;
+
+use crate::board::{Board, Piece, Side};
+use crate::game::GameState;
+
+/// Kind of tactical motif.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MotifKind {
+    /// A slider attacks a piece that can't move off the line without exposing
+    /// the king or a more valuable piece behind it
+    Pin,
+    /// A slider attacks the king or a valuable piece, which exposes a piece
+    /// behind it when moving away
+    Skewer,
+    /// A piece attacks two or more pieces at once
+    Fork,
+    /// Moving a piece off a slider's line uncovers an attack of the slider
+    DiscoveredAttack,
+    /// A piece is attacked and undefended
+    Hanging,
+}
+
+impl MotifKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            MotifKind::Pin => "Pin",
+            MotifKind::Skewer => "Skewer",
+            MotifKind::Fork => "Fork",
+            MotifKind::DiscoveredAttack => "Discovered attack",
+            MotifKind::Hanging => "Hanging piece",
+        }
+    }
+}
+
+/// A tactical motif in a position.
+///
+/// * `kind`: Kind of motif
+/// * `side`: Side that can exploit the motif
+/// * `pieces`: Pieces involved with their squares. The attacking piece comes
+///   first, except for a hanging piece, followed by the front and the back
+///   piece of a line for pins, skewers and discovered attacks, or the forked
+///   pieces
+#[derive(Clone, Debug, PartialEq)]
+pub struct Motif {
+    pub kind: MotifKind,
+    pub side: Side,
+    pub pieces: Vec<(Piece, Side, usize)>,
+}
+
+impl Motif {
+    /// Squares of the pieces involved, in the order of `pieces`.
+    pub fn squares(&self) -> Vec<usize> {
+        self.pieces.iter().map(|&(_, _, square)| square).collect()
+    }
+}
+
+/// A piece as text, e.g. `black Knight on c6`.
+fn describe((piece, side, square): (Piece, Side, usize)) -> String {
+    format!("{} {:?} on {}", format!("{:?}", side).to_lowercase(), piece, Board::square_to_algebraic(square))
+}
+
+impl fmt::Display for Motif {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let pieces: Vec<String> = self.pieces.iter().map(|&piece| describe(piece)).collect();
+        write!(fmt, "{} for {:?}: ", self.kind.name(), self.side)?;
+        match self.kind {
+            MotifKind::Pin => write!(fmt, "the {} pins the {} to the {}", pieces[0], pieces[1], pieces[2]),
+            MotifKind::Skewer => write!(fmt, "the {} skewers the {} and the {}", pieces[0], pieces[1], pieces[2]),
+            MotifKind::Fork => write!(fmt, "the {} attacks the {}", pieces[0], pieces[1..].join(" and the ")),
+            MotifKind::DiscoveredAttack => {
+                write!(fmt, "moving the {} uncovers an attack of the {} on the {}", pieces[1], pieces[0], pieces[2])
+            },
+            MotifKind::Hanging => write!(fmt, "the {} is attacked and undefended", pieces[0]),
+        }
+    }
+}
+
+/// Value of a piece for comparing targets, the king above everything.
+fn value(piece: Piece) -> i32 {
+    if piece == Piece::King { 100 } else { piece.points() }
+}
+
+/// Find the tactical motifs of `game`'s position, those the side to move can
+/// exploit first.
+pub fn find_motifs(game: &GameState) -> Vec<Motif> {
+    let board = &game.board;
+    let occ = board.occupation_board(Side::White) | board.occupation_board(Side::Black);
+    let attacks = [game.attacks(Side::White), game.attacks(Side::Black)];
+    let piece_at = |square: usize| {
+        let (piece, side) = board.piece_on(square).expect("square is occupied");
+        (piece, side, square)
+    };
+    let defended = |square: usize, side: Side| attacks[side as usize] & (1 << square) != 0;
+
+    let mut motifs = Vec::new();
+    let side_to_move = board.side_to_move;
+    for side in [side_to_move, side_to_move.opposite()] {
+        let enemy = side.opposite();
+        let motif = |kind, pieces| Motif { kind, side, pieces };
+
+        // Pins, skewers and discovered attacks, along the lines of the sliders
+        for piece in [Piece::Bishop, Piece::Rook, Piece::Queen] {
+            let mut sliders = board[(piece, side)];
+            while sliders != 0 {
+                let square = sliders.trailing_zeros() as usize;
+                let attacker = (piece, side, square);
+                let slider_bb = game.piece_attacks(piece, side, square, occ);
+
+                let mut fronts = slider_bb & occ;
+                while fronts != 0 {
+                    let front = piece_at(fronts.trailing_zeros() as usize);
+                    // Removing the front piece only extends its own ray, to
+                    // the first piece behind it
+                    let behind_bb = game.piece_attacks(piece, side, square, occ & !(1 << front.2)) & !slider_bb & occ;
+                    if behind_bb != 0 {
+                        let behind = piece_at(behind_bb.trailing_zeros() as usize);
+                        let (front_piece, front_side, _) = front;
+                        let (behind_piece, behind_side, behind_square) = behind;
+                        if front_side == enemy && behind_side == enemy {
+                            if behind_piece == Piece::King || value(behind_piece) > value(front_piece) {
+                                motifs.push(motif(MotifKind::Pin, vec![attacker, front, behind]));
+                            } else if value(front_piece) > value(behind_piece) && behind_piece != Piece::Pawn {
+                                motifs.push(motif(MotifKind::Skewer, vec![attacker, front, behind]));
+                            }
+                        } else if front_side == side && behind_side == enemy && (
+                            behind_piece == Piece::King
+                                || value(behind_piece) > value(piece)
+                                || behind_piece != Piece::Pawn && !defended(behind_square, enemy)
+                        ) {
+                            motifs.push(motif(MotifKind::DiscoveredAttack, vec![attacker, front, behind]));
+                        }
+                    }
+
+                    // clear square bit
+                    fronts &= fronts - 1;
+                }
+
+                // clear square bit
+                sliders &= sliders - 1;
+            }
+        }
+
+        // Forks of pieces that are worth more than the attacker or are
+        // undefended, pawns aside
+        for piece in Piece::VALUES {
+            let mut pieces = board[(piece, side)];
+            while pieces != 0 {
+                let square = pieces.trailing_zeros() as usize;
+                let mut targets_bb = game.piece_attacks(piece, side, square, occ)
+                    & board.occupation_board(enemy) & !board[(Piece::Pawn, enemy)];
+
+                let mut targets = Vec::new();
+                while targets_bb != 0 {
+                    let target = piece_at(targets_bb.trailing_zeros() as usize);
+                    if value(target.0) > value(piece) || !defended(target.2, enemy) {
+                        targets.push(target);
+                    }
+
+                    // clear square bit
+                    targets_bb &= targets_bb - 1;
+                }
+                if targets.len() >= 2 {
+                    targets.insert(0, (piece, side, square));
+                    motifs.push(motif(MotifKind::Fork, targets));
+                }
+
+                // clear square bit
+                pieces &= pieces - 1;
+            }
+        }
+
+        let mut hanging = board.occupation_board(enemy) & !board[(Piece::King, enemy)] & attacks[side as usize];
+        while hanging != 0 {
+            let square = hanging.trailing_zeros() as usize;
+            if !defended(square, enemy) {
+                motifs.push(motif(MotifKind::Hanging, vec![piece_at(square)]));
+            }
+
+            // clear square bit
+            hanging &= hanging - 1;
+        }
+    }
+
+    motifs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    fn motifs(fen: &str) -> Vec<(MotifKind, Side, Vec<String>)> {
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        find_motifs(&game)
+            .into_iter()
+            .map(|motif| {
+                let squares = motif.squares().into_iter().map(Board::square_to_algebraic).collect();
+                (motif.kind, motif.side, squares)
+            })
+            .collect()
+    }
+
+    fn squares(squares: &[&str]) -> Vec<String> {
+        squares.iter().map(|square| square.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find_motifs() {
+        // The bishop pins the undefended knight to the king
+        let found = motifs("4k3/8/2n5/1B6/8/8/8/4K3 w - - 0 1");
+        assert_eq!(found, [
+            (MotifKind::Pin, Side::White, squares(&["b5", "c6", "e8"])),
+            (MotifKind::Hanging, Side::White, squares(&["c6"])),
+        ]);
+
+        // The knight forks the king and the rook
+        let found = motifs("r3k3/2N5/8/8/8/8/8/4K3 b - - 0 1");
+        assert!(found.contains(&(MotifKind::Fork, Side::White, squares(&["c7", "e8", "a8"]))));
+
+        // The rook skewers the king and the queen
+        let found = motifs("q7/8/8/k7/8/8/8/R3K3 b - - 0 1");
+        assert!(found.contains(&(MotifKind::Skewer, Side::White, squares(&["a1", "a5", "a8"]))));
+
+        // Moving the knight gives a discovered check
+        let found = motifs("4k3/8/8/8/8/8/4N3/4R1K1 w - - 0 1");
+        assert_eq!(found, [(MotifKind::DiscoveredAttack, Side::White, squares(&["e1", "e2", "e8"]))]);
+
+        // The starting position has none
+        assert!(motifs("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_empty());
+    }
+
+    #[test]
+    fn test_display() {
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "4k3/8/2n5/1B6/8/8/8/4K3 w - - 0 1").unwrap();
+        let found: Vec<String> = find_motifs(&game).iter().map(Motif::to_string).collect();
+        assert_eq!(found, [
+            "Pin for White: the white Bishop on b5 pins the black Knight on c6 to the black King on e8",
+            "Hanging piece for White: the black Knight on c6 is attacked and undefended",
+        ]);
+    }
+}
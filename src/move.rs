@@ -1,4 +1,4 @@
-use crate::board::{Piece, Side};
+use crate::board::{Board, Piece, Side, BOARD_WIDTH};
 use crate::move_bitboards::{file, rank};
 use crate::search::{MAX_GAME_PLY, MAX_KILLER_MOVES};
 
@@ -11,14 +11,21 @@ pub enum MoveType {
     CastleShort,
 
     Promotion(Piece),
-    CapturePromotion(Piece, Piece)
+    CapturePromotion(Piece, Piece),
+
+    /// A piece from the hand put on an empty square in crazyhouse, with the
+    /// target square as the from square too
+    Drop,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum DrawReason {
     FiftyMoveRule,
+    SeventyFiveMoveRule,
+    #[allow(dead_code)]
     InsufficientMaterial,
     ThreeFoldRepetition,
+    FiveFoldRepetition,
     Stalemate
 }
 
@@ -26,6 +33,8 @@ pub enum DrawReason {
 pub enum MoveResult {
     Check,
     Checkmate,
+    /// The side that moved gave its third check in three-check
+    ThirdCheck,
     Draw(DrawReason)
 }
 
@@ -48,11 +57,12 @@ pub const NULL_MOVE: Move = Move {
 
 impl Move {
     const PIECE_SYMBOLS: &[&'static str] = &["", "N", "B", "R", "Q", "K"];
-    pub fn to_algebraic(&self) -> String {
-        self.to_algebraic_with_state(&vec![])
-    }
+    pub fn to_algebraic_with_state(self, legal_moves: &[Move]) -> String {
+        if self.move_type == MoveType::Drop {
+            // Pawn drops have no piece letter, e.g. `@e4` and `N@f3`
+            return format!("{}@{}", Self::PIECE_SYMBOLS[self.piece as usize], idx_to_square(self.to_square));
+        }
 
-    pub fn to_algebraic_with_state(&self, legal_moves: &Vec<Move>) -> String {
         let from_algabraic = idx_to_square(self.from_square);
         let to_algabraic = idx_to_square(self.to_square);
         let mut piece_symbol = Self::PIECE_SYMBOLS[self.piece as usize].to_owned();
@@ -61,7 +71,7 @@ impl Move {
         let is_capture_move = self.is_capture();
 
         // Check for multiple legal knight/rook/queen moves to same target_square
-        if legal_moves.len() != 0 {
+        if !legal_moves.is_empty() {
             let ambiguous_pieces = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
 
             for piece in ambiguous_pieces {
@@ -69,12 +79,12 @@ impl Move {
                     let same_pieces_to_target_rank = legal_moves.iter().filter(|m| {
                         m.piece == piece
                             && m.to_square == self.to_square
-                            && (rank(m.to_square as usize) == rank(self.to_square as usize))
+                            && (rank(m.to_square) == rank(self.to_square))
                     }).count();
                     let same_pieces_to_target_file = legal_moves.iter().filter(|m| {
                         m.piece == piece
                             && m.to_square == self.to_square
-                            && (file(m.to_square as usize) == file(self.to_square as usize))
+                            && (file(m.to_square) == file(self.to_square))
                     }).count();
 
                     if same_pieces_to_target_rank == 2 {
@@ -117,32 +127,93 @@ impl Move {
         }
     }
 
-    pub fn is_quiet(&self) -> bool {
+    /// The move in coordinate notation, e.g. `e2e4`, `e1g1`, `e7e8q` or the
+    /// drop `P@e4`, as parsed by `GameState::parse_move`.
+    pub fn to_coordinate(self) -> String {
+        if self.move_type == MoveType::Drop {
+            return format!("{}@{}", self.piece.fen_char(Side::White), idx_to_square(self.to_square));
+        }
+
+        let move_str = format!("{}{}", idx_to_square(self.from_square), idx_to_square(self.to_square));
         match self.move_type {
-            MoveType::Quiet | MoveType::Promotion(_) => true,
-            _ => false
+            MoveType::Promotion(promotion_piece) | MoveType::CapturePromotion(_, promotion_piece) =>
+                format!("{}{}", move_str, promotion_piece.fen_char(Side::Black)),
+            _ => move_str
         }
     }
 
+    pub fn is_quiet(&self) -> bool {
+        matches!(self.move_type, MoveType::Quiet | MoveType::Promotion(_))
+    }
+
     pub fn is_capture(&self) -> bool {
-        match self.move_type {
-            MoveType::Capture(_) | MoveType::CapturePromotion(_, _)
-                | MoveType::EnPassantCapture(_) => true,
-            _ => false
-        }
+        matches!(
+            self.move_type,
+            MoveType::Capture(_) | MoveType::CapturePromotion(_, _) | MoveType::EnPassantCapture(_)
+        )
     }
 
     pub fn is_promotion(&self) -> bool {
-        match self.move_type {
-            MoveType::Promotion(_) | MoveType::CapturePromotion(_, _) => true,
-            _ => false
-        }
+        matches!(self.move_type, MoveType::Promotion(_) | MoveType::CapturePromotion(_, _))
     }
 
+    #[allow(dead_code)]
     pub fn is_castling(&self) -> bool {
         self.move_type == MoveType::CastleShort || self.move_type == MoveType::CastleLong
     }
 
+    /// Square the rook moves from and the square it moves to for castling
+    /// moves, the king's squares are the move's from and to squares.
+    pub fn castling_rook_squares(&self) -> Option<(usize, usize)> {
+        let side = self.side as usize;
+        match self.move_type {
+            MoveType::CastleShort => Some((Board::ROOK_SHORT_SQUARES[side], Board::ROOK_SHORT_CASTLE_SQUARES[side])),
+            MoveType::CastleLong => Some((Board::ROOK_LONG_SQUARES[side], Board::ROOK_LONG_CASTLE_SQUARES[side])),
+            _ => None,
+        }
+    }
+
+    /// Pieces removed from (sign -1) and put on (sign +1) squares by the move,
+    /// as `(piece, side, square, sign)`, for updating incrementally kept state
+    /// when the move is made. Unused entries have a sign of 0.
+    pub fn piece_changes(&self) -> [(Piece, Side, usize, i32); 4] {
+        let mut changes = [(Piece::Pawn, self.side, 0, 0); 4];
+
+        match self.move_type {
+            MoveType::CastleShort | MoveType::CastleLong => {
+                let (rook_square, rook_target) = self.castling_rook_squares().unwrap();
+
+                changes[0] = (Piece::King, self.side, self.from_square, -1);
+                changes[1] = (Piece::King, self.side, self.to_square, 1);
+                changes[2] = (Piece::Rook, self.side, rook_square, -1);
+                changes[3] = (Piece::Rook, self.side, rook_target, 1);
+            },
+            MoveType::Drop => changes[0] = (self.piece, self.side, self.to_square, 1),
+            _ => {
+                let new_square_piece = match self.move_type {
+                    MoveType::Promotion(promotion_piece)
+                    | MoveType::CapturePromotion(_, promotion_piece) => promotion_piece,
+                    _ => self.piece,
+                };
+                changes[0] = (self.piece, self.side, self.from_square, -1);
+                changes[1] = (new_square_piece, self.side, self.to_square, 1);
+
+                match self.move_type {
+                    MoveType::Capture(captured_piece) | MoveType::CapturePromotion(captured_piece, _) =>
+                        changes[2] = (captured_piece, self.side.opposite(), self.to_square, -1),
+                    MoveType::EnPassantCapture(captured_piece) => {
+                        let enemy_pawn_square = (self.to_square as i64
+                            + ((self.side as i64)*2 - 1)*(BOARD_WIDTH as i64)) as usize;
+                        changes[2] = (captured_piece, self.side.opposite(), enemy_pawn_square, -1);
+                    },
+                    _ => {}
+                }
+            },
+        }
+
+        changes
+    }
+
     const MVVLA: [[u32; 6]; 6] = [
         [15, 14, 13, 12, 11, 10], // Victim Pawn
         [25, 24, 23, 22, 21, 20], // Victim Knight
@@ -1,8 +1,6 @@
 use crate::board::{Piece, Side, BOARD_HEIGHT, BOARD_WIDTH, N_SQUARES};
 use std::ops::Index;
 
-use log::info;
-
 pub const FILE_MASKS: [u64; BOARD_WIDTH] = [
     0b100000001000000010000000100000001000000010000000100000001,
     0b1000000010000000100000001000000010000000100000001000000010,
@@ -26,15 +24,18 @@ pub const RANK_MASKS: [u64; BOARD_WIDTH] = [
 ];
 
 #[inline]
-pub fn file(square: usize) -> usize {
+pub const fn file(square: usize) -> usize {
     square % BOARD_WIDTH
 }
 
 #[inline]
-pub fn rank(square: usize) -> usize {
+pub const fn rank(square: usize) -> usize {
     square / BOARD_HEIGHT
 }
 
+/// Pseudo-legal move bitboards and ray masks, generated at compile time.
+pub static PSEUDO_LEGAL_MOVES: MoveBitboards = MoveBitboards::generate();
+
 #[derive(Debug, Copy, Clone)]
 pub enum RayDirection {
     // Rook ray directions
@@ -96,25 +97,21 @@ impl Index<(Piece, Side, usize)> for MoveBitboards {
     }
 }
 
-impl Default for MoveBitboards {
-    fn default() -> Self {
-        Self {
-            pawn_moves: [[0; N_SQUARES]; Side::N_SIDES],
-            pawn_capture_moves: [[0; N_SQUARES]; Side::N_SIDES],
-            knight_moves: [0; N_SQUARES],
-            king_moves: [0; N_SQUARES],
+impl MoveBitboards {
+    const EMPTY: Self = Self {
+        pawn_moves: [[0; N_SQUARES]; Side::N_SIDES],
+        pawn_capture_moves: [[0; N_SQUARES]; Side::N_SIDES],
+        knight_moves: [0; N_SQUARES],
+        king_moves: [0; N_SQUARES],
 
-            rook_masks: [0; N_SQUARES],
-            bishop_masks: [0; N_SQUARES],
-            queen_masks: [0; N_SQUARES],
+        rook_masks: [0; N_SQUARES],
+        bishop_masks: [0; N_SQUARES],
+        queen_masks: [0; N_SQUARES],
 
-            rays: [[0; N_SQUARES]; RayDirection::N_DIRECTIONS],
-            comp_rays: [[0; N_SQUARES]; Piece::N_SLIDING_PIECES],
-        }
-    }
-}
+        rays: [[0; N_SQUARES]; RayDirection::N_DIRECTIONS],
+        comp_rays: [[0; N_SQUARES]; Piece::N_SLIDING_PIECES],
+    };
 
-impl MoveBitboards {
     #[inline]
     pub fn get_piece_blocker_mask(&self, piece: Piece, square: usize) -> u64 {
         match piece {
@@ -126,20 +123,21 @@ impl MoveBitboards {
     }
 
     #[inline]
-    pub fn get_comp_rays(&self, piece: Piece) -> &[u64; N_SQUARES] {
+    pub const fn get_comp_rays(&self, piece: Piece) -> &[u64; N_SQUARES] {
         &self.comp_rays[(piece as usize) - Piece::SLIDER_START_VALUE]
     }
 
     #[inline]
-    fn set_comp_rays(&mut self, piece: Piece, square: usize, bb: u64) {
+    const fn set_comp_rays(&mut self, piece: Piece, square: usize, bb: u64) {
         self.comp_rays[(piece as usize) - Piece::SLIDER_START_VALUE][square] = bb;
     }
 
-    pub fn init_legal_moves() -> Self {
-        info!("Initialising pseudo-legal moves and ray masks");
-
-        let mut legal_moves = MoveBitboards::default();
-        for square in 0..N_SQUARES {
+    /// Generate the pseudo-legal move bitboards and ray masks. Evaluated at
+    /// compile time for `PSEUDO_LEGAL_MOVES`.
+    const fn generate() -> Self {
+        let mut legal_moves = Self::EMPTY;
+        let mut square = 0;
+        while square < N_SQUARES {
             let piece_file = file(square);
             let piece_rank = rank(square);
 
@@ -149,16 +147,20 @@ impl MoveBitboards {
             legal_moves.init_rook_moves(square, piece_file, piece_rank);
             legal_moves.init_queen_moves(square);
             legal_moves.init_king_moves(square, piece_file, piece_rank);
+            square += 1;
         }
 
         legal_moves
     }
 
-    pub fn get_bishop_rays(&self, square: usize, blocker_mask: u64) -> u64 {
+    pub const fn get_bishop_rays(&self, square: usize, blocker_mask: u64) -> u64 {
         let mut moves_bb = 0u64;
 
-        for direction in RayDirection::BISHOP_DIRECTIONS {
+        let mut i = 0;
+        while i < RayDirection::BISHOP_DIRECTIONS.len() {
+            let direction = RayDirection::BISHOP_DIRECTIONS[i];
             let direction_idx = direction as usize;
+            i += 1;
             let ray = self.rays[direction_idx][square];
             moves_bb |= ray;
 
@@ -178,11 +180,14 @@ impl MoveBitboards {
         moves_bb
     }
 
-    pub fn get_rook_rays(&self, square: usize, blocker_mask: u64) -> u64 {
+    pub const fn get_rook_rays(&self, square: usize, blocker_mask: u64) -> u64 {
         let mut moves_bb = 0u64;
 
-        for direction in RayDirection::ROOK_DIRECTIONS {
+        let mut i = 0;
+        while i < RayDirection::ROOK_DIRECTIONS.len() {
+            let direction = RayDirection::ROOK_DIRECTIONS[i];
             let direction_idx = direction as usize;
+            i += 1;
             let ray = self.rays[direction_idx][square];
             moves_bb |= ray;
 
@@ -202,7 +207,7 @@ impl MoveBitboards {
         moves_bb
     }
 
-    fn init_pawn_moves(&mut self, square: usize, file: usize, rank: usize) {
+    const fn init_pawn_moves(&mut self, square: usize, file: usize, rank: usize) {
         // white pawns move forward
         if rank != BOARD_HEIGHT - 1 {
             self.pawn_moves[Side::White as usize][square] |= 1 << (square + BOARD_WIDTH);
@@ -246,7 +251,7 @@ impl MoveBitboards {
         }
     }
 
-    fn init_knight_moves(&mut self, square: usize, file: usize, rank: usize) {
+    const fn init_knight_moves(&mut self, square: usize, file: usize, rank: usize) {
         // Up and left-right
         if rank <= BOARD_WIDTH - 3 {
             if file != BOARD_WIDTH - 1 {
@@ -292,14 +297,14 @@ impl MoveBitboards {
         }
     }
 
-    fn init_bishop_moves(&mut self, square: usize, file: usize, rank: usize) {
+    const fn init_bishop_moves(&mut self, square: usize, file: usize, rank: usize) {
         // Left diagonal
         // North-west
         if rank < BOARD_WIDTH - 1 && file < BOARD_HEIGHT - 1 {
             let mut up_left_diag_idx = square + BOARD_WIDTH + 1;
             let mut i = 1;
 
-            while up_left_diag_idx <= BOARD_WIDTH * BOARD_HEIGHT - 1 && i < BOARD_WIDTH - file {
+            while up_left_diag_idx < BOARD_WIDTH * BOARD_HEIGHT && i < BOARD_WIDTH - file {
                 self.rays[RayDirection::NorthWest as usize][square] |= 1 << up_left_diag_idx;
                 up_left_diag_idx += BOARD_WIDTH + 1;
                 i += 1;
@@ -323,7 +328,7 @@ impl MoveBitboards {
             let mut up_right_diag_idx = square + BOARD_WIDTH - 1;
             let mut i = 1;
 
-            while up_right_diag_idx <= BOARD_WIDTH * BOARD_HEIGHT - 1 && i <= file {
+            while up_right_diag_idx < BOARD_WIDTH * BOARD_HEIGHT && i <= file {
                 self.rays[RayDirection::NorthEast as usize][square] |= 1 << up_right_diag_idx;
                 up_right_diag_idx += BOARD_WIDTH - 1;
                 i += 1;
@@ -367,27 +372,35 @@ impl MoveBitboards {
         self.bishop_masks[square] &= EDGE_MASK;
     }
 
-    fn init_rook_moves(&mut self, square: usize, file: usize, rank: usize) {
+    const fn init_rook_moves(&mut self, square: usize, file: usize, rank: usize) {
         // Rank moves
         // West
-        for i in 1..=(BOARD_WIDTH - 1 - file) {
-            self.rays[RayDirection::West as usize][square] |= 1 << square + i;
+        let mut i = 1;
+        while i <= BOARD_WIDTH - 1 - file {
+            self.rays[RayDirection::West as usize][square] |= 1 << (square + i);
+            i += 1;
         }
 
         // East
-        for i in 1..=file {
-            self.rays[RayDirection::East as usize][square] |= 1 << square - i;
+        let mut i = 1;
+        while i <= file {
+            self.rays[RayDirection::East as usize][square] |= 1 << (square - i);
+            i += 1;
         }
 
         // File moves
         // North
-        for i in 1..=(BOARD_HEIGHT - 1 - rank) {
-            self.rays[RayDirection::North as usize][square] |= 1 << square + i * BOARD_WIDTH;
+        let mut i = 1;
+        while i <= BOARD_HEIGHT - 1 - rank {
+            self.rays[RayDirection::North as usize][square] |= 1 << (square + i * BOARD_WIDTH);
+            i += 1;
         }
 
         // South
-        for i in 1..=rank {
-            self.rays[RayDirection::South as usize][square] |= 1 << square - i * BOARD_WIDTH;
+        let mut i = 1;
+        while i <= rank {
+            self.rays[RayDirection::South as usize][square] |= 1 << (square - i * BOARD_WIDTH);
+            i += 1;
         }
 
         self.rook_masks[square] = self.rays[RayDirection::North as usize][square]
@@ -404,11 +417,11 @@ impl MoveBitboards {
         self.rook_masks[square] &= !(1 << ((BOARD_WIDTH - 1) * BOARD_WIDTH + file));
 
         // Rank edges
-        self.rook_masks[square] &= !(1 << BOARD_WIDTH * rank + BOARD_WIDTH - 1);
-        self.rook_masks[square] &= !(1 << BOARD_WIDTH * rank);
+        self.rook_masks[square] &= !(1 << (BOARD_WIDTH * rank + BOARD_WIDTH - 1));
+        self.rook_masks[square] &= !(1 << (BOARD_WIDTH * rank));
     }
 
-    fn init_queen_moves(&mut self, square: usize) {
+    const fn init_queen_moves(&mut self, square: usize) {
         // Blocker masks
         self.queen_masks[square] = self.rook_masks[square] | self.bishop_masks[square];
 
@@ -417,7 +430,7 @@ impl MoveBitboards {
             self.get_comp_rays(Piece::Bishop)[square] | self.get_comp_rays(Piece::Rook)[square]);
     }
 
-    fn init_king_moves(&mut self, square: usize, file: usize, rank: usize) {
+    const fn init_king_moves(&mut self, square: usize, file: usize, rank: usize) {
         // Rank moves
         // Left
         if file != 7 {
@@ -466,82 +479,70 @@ impl MoveBitboards {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bitboard::Bb;
 
     #[test]
     fn test_moves() {
-        let legal_moves = MoveBitboards::init_legal_moves();
+        let legal_moves = &PSEUDO_LEGAL_MOVES;
+        let squares = |squares: &[&str]| Bb::from_squares(squares).unwrap();
 
         // king moves
-        assert_eq!(legal_moves.king_moves[0], 770);
-        assert_eq!(legal_moves.king_moves[7], 49216);
-        assert_eq!(legal_moves.king_moves[35], 30872694685696);
+        assert_eq!(Bb(legal_moves.king_moves[0]), squares(&["g1", "g2", "h2"]));
+        assert_eq!(Bb(legal_moves.king_moves[7]), squares(&["b1", "a2", "b2"]));
+        assert_eq!(Bb(legal_moves.king_moves[35]), squares(&["d4", "e4", "f4", "d5", "f5", "d6", "e6", "f6"]));
 
         // knight moves
-        assert_eq!(legal_moves.knight_moves[0], 132096);
-        assert_eq!(legal_moves.knight_moves[36], 11333767002587136);
-        assert_eq!(legal_moves.knight_moves[23], 275414786112);
-        assert_eq!(legal_moves.knight_moves[60], 19184278881435648);
+        assert_eq!(Bb(legal_moves.knight_moves[0]), squares(&["f2", "g3"]));
+        assert_eq!(Bb(legal_moves.knight_moves[36]), squares(&["c3", "e3", "b4", "f4", "b6", "f6", "c7", "e7"]));
+        assert_eq!(Bb(legal_moves.knight_moves[23]), squares(&["b1", "c2", "c4", "b5"]));
+        assert_eq!(Bb(legal_moves.knight_moves[60]), squares(&["c6", "e6", "b7", "f7"]));
 
         // pawn moves
-        assert_eq!(legal_moves.pawn_moves[Side::White as usize][8], 16842752);
-        assert_eq!(legal_moves.pawn_moves[Side::White as usize][23], 2147483648);
-        assert_eq!(
-            legal_moves.pawn_moves[Side::White as usize][51],
-            576460752303423488
-        );
-        assert_eq!(
-            legal_moves.pawn_moves[Side::White as usize][40],
-            281474976710656
-        );
-        assert_eq!(legal_moves.pawn_moves[Side::Black as usize][40], 4294967296);
-        assert_eq!(
-            legal_moves.pawn_moves[Side::Black as usize][50],
-            4415226380288
-        );
-        assert_eq!(legal_moves.pawn_moves[Side::Black as usize][10], 4);
-
-        assert_eq!(
-            legal_moves.pawn_capture_moves[Side::White as usize][8],
-            131072
-        );
-        assert_eq!(
-            legal_moves.pawn_capture_moves[Side::White as usize][9],
-            327680
-        );
-        assert_eq!(legal_moves.pawn_capture_moves[Side::Black as usize][8], 2);
-        assert_eq!(legal_moves.pawn_capture_moves[Side::Black as usize][9], 5);
+        assert_eq!(Bb(legal_moves.pawn_moves[Side::White as usize][8]), squares(&["h3", "h4"]));
+        assert_eq!(Bb(legal_moves.pawn_moves[Side::White as usize][23]), squares(&["a4"]));
+        assert_eq!(Bb(legal_moves.pawn_moves[Side::White as usize][51]), squares(&["e8"]));
+        assert_eq!(Bb(legal_moves.pawn_moves[Side::White as usize][40]), squares(&["h7"]));
+        assert_eq!(Bb(legal_moves.pawn_moves[Side::Black as usize][40]), squares(&["h5"]));
+        assert_eq!(Bb(legal_moves.pawn_moves[Side::Black as usize][50]), squares(&["f5", "f6"]));
+        assert_eq!(Bb(legal_moves.pawn_moves[Side::Black as usize][10]), squares(&["f1"]));
+
+        assert_eq!(Bb(legal_moves.pawn_capture_moves[Side::White as usize][8]), squares(&["g3"]));
+        assert_eq!(Bb(legal_moves.pawn_capture_moves[Side::White as usize][9]), squares(&["f3", "h3"]));
+        assert_eq!(Bb(legal_moves.pawn_capture_moves[Side::Black as usize][8]), squares(&["g1"]));
+        assert_eq!(Bb(legal_moves.pawn_capture_moves[Side::Black as usize][9]), squares(&["f1", "h1"]));
 
         // blocker masks
         // rook
-        assert_eq!(legal_moves.rook_masks[28], 4521262379438080);
-        assert_eq!(legal_moves.rook_masks[0], 282578800148862);
-        assert_eq!(legal_moves.rook_masks[63], 9115426935197958144);
+        assert_eq!(Bb(legal_moves.rook_masks[28]), squares(&["d2", "d3", "b4", "c4", "e4", "f4", "g4", "d5", "d6", "d7"]));
+        assert_eq!(Bb(legal_moves.rook_masks[0]), squares(&["b1", "c1", "d1", "e1", "f1", "g1", "h2", "h3", "h4", "h5", "h6", "h7"]));
+        assert_eq!(Bb(legal_moves.rook_masks[63]), squares(&["a2", "a3", "a4", "a5", "a6", "a7", "b8", "c8", "d8", "e8", "f8", "g8"]));
 
         // bishop
-        assert_eq!(legal_moves.bishop_masks[43], 5629586008178688);
-        assert_eq!(legal_moves.bishop_masks[2], 275415828992);
-
-        // queen
-        assert_eq!(legal_moves.queen_masks[36], 23705944086286848);
+        assert_eq!(Bb(legal_moves.bishop_masks[43]), squares(&["b3", "c4", "g4", "d5", "f5", "d7", "f7"]));
+        assert_eq!(Bb(legal_moves.bishop_masks[2]), squares(&["e2", "g2", "d3", "c4", "b5"]));
+
+        // queen on d5, without the edges of the board
+        let queen_mask: Bb = "
+            . . . . . . . .
+            . 1 . 1 . 1 . .
+            . . 1 1 1 . . .
+            . 1 1 . 1 1 1 .
+            . . 1 1 1 . . .
+            . 1 . 1 . 1 . .
+            . . . 1 . . 1 .
+            . . . . . . . .
+        ".parse().unwrap();
+        assert_eq!(Bb(legal_moves.queen_masks[36]), queen_mask);
 
         // Rays
-        assert_eq!(
-            legal_moves.rays[RayDirection::North as usize][10],
-            289360691352305664
-        );
-        assert_eq!(legal_moves.rays[RayDirection::South as usize][10], 4);
-        assert_eq!(legal_moves.rays[RayDirection::East as usize][10], 768);
-        assert_eq!(legal_moves.rays[RayDirection::West as usize][10], 63488);
-
-        assert_eq!(
-            legal_moves.rays[RayDirection::NorthEast as usize][10],
-            16908288
-        );
-        assert_eq!(legal_moves.rays[RayDirection::SouthEast as usize][10], 2);
-        assert_eq!(legal_moves.rays[RayDirection::SouthWest as usize][10], 8);
-        assert_eq!(
-            legal_moves.rays[RayDirection::NorthWest as usize][10],
-            36099303471054848
-        );
+        assert_eq!(Bb(legal_moves.rays[RayDirection::North as usize][10]), squares(&["f3", "f4", "f5", "f6", "f7", "f8"]));
+        assert_eq!(Bb(legal_moves.rays[RayDirection::South as usize][10]), squares(&["f1"]));
+        assert_eq!(Bb(legal_moves.rays[RayDirection::East as usize][10]), squares(&["g2", "h2"]));
+        assert_eq!(Bb(legal_moves.rays[RayDirection::West as usize][10]), squares(&["a2", "b2", "c2", "d2", "e2"]));
+
+        assert_eq!(Bb(legal_moves.rays[RayDirection::NorthEast as usize][10]), squares(&["g3", "h4"]));
+        assert_eq!(Bb(legal_moves.rays[RayDirection::SouthEast as usize][10]), squares(&["g1"]));
+        assert_eq!(Bb(legal_moves.rays[RayDirection::SouthWest as usize][10]), squares(&["e1"]));
+        assert_eq!(Bb(legal_moves.rays[RayDirection::NorthWest as usize][10]), squares(&["e3", "d4", "c5", "b6", "a7"]));
     }
 }
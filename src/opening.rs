@@ -0,0 +1,79 @@
+use rand::seq::SliceRandom;
+
+use crate::game::GameState;
+use crate::r#move::Move;
+use crate::search::SearchAsync;
+
+/// How the computer picks its first move of a game: a random move among the
+/// legal moves scoring close to the best one in a shallow search, so games
+/// don't all start the same way.
+#[derive(Clone, Copy, Debug)]
+pub struct OpeningPolicy {
+    depth: usize,
+    margin: i32,
+}
+
+impl OpeningPolicy {
+    /// * `depth`: Depth to score the legal moves at
+    /// * `margin`: Moves scoring within this margin (in centipawns) of the best move are considered
+    pub fn new(depth: usize, margin: i32) -> Self {
+        Self { depth, margin }
+    }
+
+    /// Pick the move to play in `game`'s position, or None if there are no
+    /// legal moves.
+    ///
+    /// * `searcher`: Searcher to score the moves with
+    /// * `game`: Position to pick a move in
+    /// * `search_moves`: Only pick among these moves, or all legal moves if empty
+    pub fn pick_move(&self, searcher: &mut SearchAsync, game: &mut GameState, search_moves: &[Move]) -> Option<Move> {
+        searcher.set_time_limit(None);
+        let mut scored_moves = searcher.score_legal_moves(game, self.depth);
+        if !search_moves.is_empty() {
+            scored_moves.retain(|(m, _)| search_moves.contains(m));
+        }
+
+        // The search may have been stopped before scoring any move
+        if scored_moves.is_empty() {
+            return game.get_legal_moves().0.first().copied();
+        }
+
+        self.candidates(&scored_moves).choose(&mut rand::thread_rng()).copied()
+    }
+
+    fn candidates(&self, scored_moves: &[(Move, i32)]) -> Vec<Move> {
+        let best_score = scored_moves.iter().map(|(_, score)| *score).max().unwrap_or(0);
+        scored_moves
+            .iter()
+            .filter(|(_, score)| *score >= best_score - self.margin)
+            .map(|(m, _)| *m)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+    use crate::r#move::NULL_MOVE;
+
+    #[test]
+    fn test_opening_policy() {
+        let good = NULL_MOVE;
+        let ok = Move { from_square: 1, ..NULL_MOVE };
+        let bad = Move { from_square: 2, ..NULL_MOVE };
+        let scored_moves = [(good, 30), (ok, 10), (bad, -100)];
+
+        assert_eq!(OpeningPolicy::new(2, 0).candidates(&scored_moves), [good]);
+        assert_eq!(OpeningPolicy::new(2, 20).candidates(&scored_moves), [good, ok]);
+
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        let m = OpeningPolicy::new(2, 30).pick_move(&mut SearchAsync::new(), &mut game, &[]).unwrap();
+        assert!(game.get_legal_moves().0.contains(&m));
+
+        // Only the given moves are picked from, however bad
+        let a3 = game.get_legal_moves().0.into_iter().find(|m| m.to_coordinate() == "a2a3").unwrap();
+        assert_eq!(OpeningPolicy::new(2, 0).pick_move(&mut SearchAsync::new(), &mut game, &[a3]), Some(a3));
+    }
+}
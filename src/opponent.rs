@@ -0,0 +1,150 @@
+//! Weak sparring opponents for beginners, who can't win against the engine
+//! even at the lowest skill level, and baselines to check the engine
+//! against. They play the computer's moves instead of the engine's search.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::seq::SliceRandom;
+
+use crate::board::Piece;
+use crate::error::Error;
+use crate::eval::{eval, EvalParams};
+use crate::game::GameState;
+use crate::r#move::{Move, MoveType};
+use crate::search::SearchAsync;
+
+/// Who makes the computer's moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Opponent {
+    /// The engine's search, at the set skill level
+    #[default]
+    Engine,
+    /// A random legal move
+    Random,
+    /// The capture of the most valuable piece, or a random move without
+    /// captures
+    Greedy,
+    /// The best move of a search one ply deep
+    Shallow,
+}
+
+impl Opponent {
+    /// The computer's move in `game`'s position, with the evaluation from
+    /// white's perspective and the principal variation like
+    /// `SearchAsync::find_best_legal_move`. The opponents that don't search
+    /// evaluate the position statically.
+    ///
+    /// * `searcher`: Searcher of the engine
+    /// * `game`: Position to move in
+    /// * `depth`: Depth the engine searches to
+    /// * `search_moves`: Only consider these moves, or all legal moves if empty
+    pub fn find_move(
+        self,
+        searcher: &mut SearchAsync,
+        game: &mut GameState,
+        depth: usize,
+        search_moves: &[Move],
+    ) -> (i32, Move, Vec<Move>) {
+        let depth = if self == Opponent::Shallow { 1 } else { depth };
+        let mut moves = game.get_legal_moves().0;
+        if !search_moves.is_empty() {
+            moves.retain(|m| search_moves.contains(m));
+        }
+
+        match self.pick_move(&moves) {
+            Some(m) => (eval(game), m, vec![m]),
+            None => searcher.find_best_legal_move(game, depth, search_moves),
+        }
+    }
+
+    /// The move of an opponent that doesn't search among `moves`, or None
+    /// for those that do.
+    fn pick_move(self, moves: &[Move]) -> Option<Move> {
+        let mut rng = rand::thread_rng();
+        match self {
+            Opponent::Engine | Opponent::Shallow => None,
+            Opponent::Random => moves.choose(&mut rng).copied(),
+            Opponent::Greedy => {
+                let best_gain = moves.iter().map(material_gain).max()?;
+                let best_moves: Vec<Move> = moves.iter().filter(|m| material_gain(m) == best_gain).copied().collect();
+                best_moves.choose(&mut rng).copied()
+            },
+        }
+    }
+}
+
+/// Material won by a move, the captured piece and the promotion.
+fn material_gain(m: &Move) -> i32 {
+    let value = |piece| EvalParams::DEFAULT.piece_values[piece as usize].mg();
+    let pawn = value(Piece::Pawn);
+    match m.move_type {
+        MoveType::Capture(captured) | MoveType::EnPassantCapture(captured) => value(captured),
+        MoveType::Promotion(promoted) => value(promoted) - pawn,
+        MoveType::CapturePromotion(captured, promoted) => value(captured) + value(promoted) - pawn,
+        _ => 0,
+    }
+}
+
+impl FromStr for Opponent {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "engine" => Ok(Opponent::Engine),
+            "random" => Ok(Opponent::Random),
+            "greedy" => Ok(Opponent::Greedy),
+            "shallow" => Ok(Opponent::Shallow),
+            _ => Err(Error::InvalidOpponent(string.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Opponent {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Opponent::Engine => "engine",
+            Opponent::Random => "random",
+            Opponent::Greedy => "greedy",
+            Opponent::Shallow => "shallow",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_opponents() {
+        // The queen is hanging
+        let fen = "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        let (legal_moves, _) = game.get_legal_moves();
+        let mut searcher = SearchAsync::new();
+
+        for _ in 0..10 {
+            let (_, m, pv) = Opponent::Greedy.find_move(&mut searcher, &mut game, 6, &[]);
+            assert_eq!(m.to_coordinate(), "d2d5");
+            assert_eq!(pv, [m]);
+
+            let (_, m, _) = Opponent::Random.find_move(&mut searcher, &mut game, 6, &[]);
+            assert!(legal_moves.contains(&m));
+        }
+
+        // Only the given moves are picked from
+        let quiet = *legal_moves.iter().find(|m| !m.is_capture()).unwrap();
+        assert_eq!(Opponent::Greedy.find_move(&mut searcher, &mut game, 6, &[quiet]).1, quiet);
+
+        // Searching one ply is enough to take it too
+        let (_, m, _) = Opponent::Shallow.find_move(&mut searcher, &mut game, 6, &[]);
+        assert_eq!(m.to_coordinate(), "d2d5");
+        assert_eq!(searcher.completed_depth(), 1);
+
+        assert_eq!("greedy".parse::<Opponent>().unwrap(), Opponent::Greedy);
+        assert_eq!(Opponent::Shallow.to_string(), "shallow");
+        assert!(matches!("grandmaster".parse::<Opponent>(), Err(Error::InvalidOpponent(_))));
+    }
+}
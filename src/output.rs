@@ -0,0 +1,437 @@
+//! What the games and analysis print on stdout: text and boards for people,
+//! or with `--output json` an object per line for scripts and GUIs, in the
+//! format of the game log:
+//!
+//! ```text
+//! {"event":"position","fen":"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"}
+//! {"event":"legal_moves","side":"black","moves":["a6","a5","b6","b5","Na6","Nc6"]}
+//! {"event":"move","ply":2,"side":"black","player":"engine","move":"e5","uci":"e7e5","score":"+0.02"}
+//! {"event":"iteration","depth":5,"score":"+0.31","pv":["e2e4","e7e5","g1f3"],"pv_san":"1. e4 e5 2. Nf3"}
+//! {"event":"result","result":"1-0","reason":"Checkmate"}
+//! ```
+//!
+//! The diagnostic log stays on stderr in either format.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use clap::ValueEnum;
+use tracing::info;
+
+use pawndropper::annotation::Annotation;
+use pawndropper::board::{Board, BoardStyle, Piece, Side, Variant};
+use pawndropper::game::GameState;
+use pawndropper::r#move::Move;
+use pawndropper::search::format_score;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// When the boards are drawn in color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    // If the terminal supports colors, see `terminal_supports_colors`
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => terminal_supports_colors(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// How large the boards are printed, see `Board::to_ascii_large`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BoardLayout {
+    // A character per square
+    #[default]
+    Compact,
+    // Squares three characters wide with checkered backgrounds
+    Large,
+    // Large, with a border with the ranks and files
+    Framed,
+}
+
+/// Which side is at the bottom of the printed board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum View {
+    White,
+    Black,
+    // The side the user plays
+    #[default]
+    Auto,
+}
+
+impl View {
+    /// The side at the bottom of the board in a game where the computer
+    /// plays `cpu_side`.
+    pub fn orientation(self, cpu_side: Side) -> Side {
+        match self {
+            View::White => Side::White,
+            View::Black => Side::Black,
+            View::Auto => cpu_side.opposite(),
+        }
+    }
+}
+
+impl From<Side> for View {
+    fn from(side: Side) -> Self {
+        if side == Side::White { View::White } else { View::Black }
+    }
+}
+
+/// Whether stdout is a terminal that shows ANSI colors. `NO_COLOR` turns
+/// them off, and the Windows console only shows them in Windows Terminal,
+/// ConEmu, with ANSICON, or in a terminal emulator that sets `TERM`.
+pub fn terminal_supports_colors() -> bool {
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) || !io::stdout().is_terminal() {
+        return false;
+    }
+
+    if cfg!(windows) {
+        env::var_os("WT_SESSION").is_some()
+            || env::var_os("ANSICON").is_some()
+            || env::var("ConEmuANSI").is_ok_and(|value| value == "ON")
+            || env::var_os("TERM").is_some()
+    } else {
+        env::var("TERM").is_ok_and(|term| term != "dumb")
+    }
+}
+
+/// Prints in `format`, with the boards of text drawn in `board_style` and
+/// `board_layout`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Output {
+    pub format: OutputFormat,
+    pub board_style: BoardStyle,
+    pub board_layout: BoardLayout,
+}
+
+fn side_name(side: Side) -> &'static str {
+    if side == Side::White { "white" } else { "black" }
+}
+
+// Strings as a JSON array, the strings don't need escaping
+fn json_array(strings: impl IntoIterator<Item = String>) -> String {
+    let quoted: Vec<String> = strings.into_iter().map(|s| format!("\"{}\"", s)).collect();
+    format!("[{}]", quoted.join(","))
+}
+
+/// The principal variation `pv` of `game`'s position in numbered algebraic
+/// notation, up to the first move that isn't legal.
+pub fn format_principal_variation(game: &GameState, pv: &[Move], mut legal_moves: Vec<Move>) -> String {
+    let mut game_clone = game.clone();
+    let mut moves_str = "".to_owned();
+
+    if game.board.side_to_move == Side::Black {
+        moves_str.push_str(&format!("{}. ..", game.move_number));
+    }
+
+    for m in pv {
+        if !legal_moves.contains(m) {
+            break;
+        }
+        if game_clone.board.side_to_move == Side::White {
+            moves_str.push_str(&format!("{}. ", game_clone.move_number));
+        }
+        moves_str.push_str(&m.to_algebraic_with_state(&legal_moves));
+        moves_str.push(' ');
+
+        (_, legal_moves) = game_clone.make_move(m);
+    }
+    moves_str
+}
+
+/// The moves of `pv` from `game` in algebraic notation without move numbers,
+/// e.g. `e4 e5 Nf3`, up to the first move that isn't legal.
+fn format_pv_moves(game: &GameState, pv: &[Move]) -> String {
+    let mut game = game.clone();
+    let (mut legal_moves, _) = game.get_legal_moves();
+    let mut moves = Vec::with_capacity(pv.len());
+
+    for m in pv {
+        if !legal_moves.contains(m) {
+            break;
+        }
+        moves.push(m.to_algebraic_with_state(&legal_moves));
+        (_, legal_moves) = game.make_move(m);
+    }
+    moves.join(" ")
+}
+
+// Characters between the brackets of the evaluation bar
+const EVAL_BAR_WIDTH: usize = 20;
+
+/// White's share of an evaluation bar for the evaluation `eval` in
+/// centipawns from white's perspective: white's expected score, about 0.64
+/// a pawn up and 0.85 a minor piece up.
+pub fn white_share(eval: i32) -> f64 {
+    1.0/(1.0 + 10f64.powf(-eval as f64/400.0))
+}
+
+/// The evaluation `eval` from white's perspective as a bar of `width`
+/// characters filled by white's share, e.g. `[#############       ] +1.30`.
+pub fn format_eval_bar(eval: i32, width: usize) -> String {
+    let filled = (white_share(eval)*width as f64).round() as usize;
+    format!("[{}{}] {}", "#".repeat(filled), " ".repeat(width - filled), format_score(eval))
+}
+
+/// The pieces `side` captured in `game` and its lead in material if it's
+/// ahead, e.g. `♙♙♘ +2`, empty if neither.
+pub fn format_captured(game: &GameState, side: Side, style: BoardStyle) -> String {
+    let mut captured: String = game.captured_pieces(side)
+        .into_iter()
+        .map(|piece| match style {
+            BoardStyle::Letters => piece.fen_char(side.opposite()).to_string(),
+            BoardStyle::Unicode | BoardStyle::Monochrome => piece.ascii(side.opposite()).to_owned(),
+        })
+        .collect();
+
+    let lead = game.board.material_points(side) - game.board.material_points(side.opposite());
+    if lead > 0 {
+        if !captured.is_empty() {
+            captured.push(' ');
+        }
+        captured.push_str(&format!("+{}", lead));
+    }
+    captured
+}
+
+/// The pieces `side` has in hand in crazyhouse, the most valuable first, in
+/// the glyphs or letters of `style`.
+pub fn format_hand(board: &Board, side: Side, style: BoardStyle) -> String {
+    Piece::ALL_BUT_KING.iter()
+        .rev()
+        .flat_map(|&piece| (0..board.in_hand(piece, side)).map(move |_| match style {
+            BoardStyle::Letters => piece.fen_char(side).to_string(),
+            BoardStyle::Unicode | BoardStyle::Monochrome => piece.ascii(side).to_owned(),
+        }))
+        .collect()
+}
+
+impl Output {
+    /// The position of `game`, as a board with `orientation` at the bottom or
+    /// as FEN.
+    pub fn position(self, game: &GameState, orientation: Side) {
+        match self.format {
+            OutputFormat::Text => {
+                let board = &game.board;
+                let text = match self.board_layout {
+                    BoardLayout::Compact => board.to_ascii_with_style(orientation, self.board_style),
+                    BoardLayout::Large => board.to_ascii_large(orientation, self.board_style, false),
+                    BoardLayout::Framed => board.to_ascii_large(orientation, self.board_style, true),
+                };
+
+                // Each side's captured material, or in crazyhouse its pieces
+                // in hand, next to its back rank, the framed board starts
+                // with its border
+                let top_rank = if self.board_layout == BoardLayout::Framed { 1 } else { 0 };
+                let mut lines: Vec<String> = text.lines().map(str::to_owned).collect();
+                for (line, side) in [(top_rank, orientation.opposite()), (top_rank + 7, orientation)] {
+                    let captured = if board.variant == Variant::Crazyhouse {
+                        format_hand(board, side, self.board_style)
+                    } else {
+                        format_captured(game, side, self.board_style)
+                    };
+                    if !captured.is_empty() {
+                        lines[line].push_str(&format!("   {}", captured));
+                    }
+                }
+                if board.variant == Variant::ThreeCheck {
+                    lines.push(format!(
+                        "Checks given: white {}, black {}",
+                        board.checks[Side::White as usize],
+                        board.checks[Side::Black as usize]
+                    ));
+                }
+                println!("{}\n", lines.join("\n"));
+            },
+            OutputFormat::Json => println!("{{\"event\":\"position\",\"fen\":\"{}\"}}", game.to_fen()),
+        }
+    }
+
+    /// The legal moves of the side to move in `game`, which are only logged
+    /// in text.
+    pub fn legal_moves(self, game: &GameState, legal_moves: &[Move]) {
+        let moves = legal_moves.iter().map(|m| m.to_algebraic_with_state(legal_moves));
+        match self.format {
+            OutputFormat::Text => {
+                let moves: Vec<String> = moves.collect();
+                info!("[{:?}] Legal moves: [{}]", game.board.side_to_move, moves.join(" "));
+            },
+            OutputFormat::Json => println!(
+                "{{\"event\":\"legal_moves\",\"side\":\"{}\",\"moves\":{}}}",
+                side_name(game.board.side_to_move),
+                json_array(moves)
+            ),
+        }
+    }
+
+    /// The user's move `m` in `game`, which is still in the position before
+    /// the move. The user typed it already, so it's only printed in JSON.
+    pub fn user_move(self, game: &GameState, m: &Move, legal_moves: &[Move]) {
+        if self.format == OutputFormat::Json {
+            println!("{}}}", Self::move_fields(game, m, legal_moves, "user"));
+        }
+    }
+
+    /// The engine's move `m` in `game`, which is still in the position before
+    /// the move, with the evaluation `eval` from white's perspective if it's
+    /// shown.
+    pub fn engine_move(self, game: &GameState, m: &Move, legal_moves: &[Move], eval: Option<i32>) {
+        match self.format {
+            OutputFormat::Text => {
+                let move_number = if game.board.side_to_move == Side::White {
+                    format!("{}. ", game.move_number)
+                } else {
+                    format!("{}. ..", game.move_number)
+                };
+                match eval {
+                    Some(eval) => println!("{}{} ({})", move_number, m.to_algebraic_with_state(legal_moves), format_score(eval)),
+                    None => println!("{}{}", move_number, m.to_algebraic_with_state(legal_moves)),
+                }
+            },
+            OutputFormat::Json => {
+                let fields = Self::move_fields(game, m, legal_moves, "engine");
+                match eval {
+                    Some(eval) => println!("{},\"score\":\"{}\"}}", fields, format_score(eval)),
+                    None => println!("{}}}", fields),
+                }
+            },
+        }
+    }
+
+    /// The annotation of the user's move, the last move of `game`, which lost
+    /// `loss` centipawns, e.g. `12. Nxe5?? (blunder, -3.20)`.
+    pub fn annotation(self, game: &GameState, annotation: Annotation, loss: i32) {
+        let mut before = game.clone();
+        let Some(m) = before.undo_move() else {
+            return;
+        };
+        let (legal_moves, _) = before.get_legal_moves();
+        let algebraic = m.to_algebraic_with_state(&legal_moves);
+
+        match self.format {
+            OutputFormat::Text => {
+                let move_number = if before.board.side_to_move == Side::White {
+                    format!("{}. ", before.move_number)
+                } else {
+                    format!("{}. ..", before.move_number)
+                };
+                println!("{}{}{} ({}, {})", move_number, algebraic, annotation, annotation.name(), format_score(-loss));
+            },
+            OutputFormat::Json => println!(
+                "{{\"event\":\"annotation\",\"ply\":{},\"move\":\"{}\",\"annotation\":\"{}\",\"loss\":{}}}",
+                before.half_move_number,
+                algebraic,
+                annotation,
+                loss
+            ),
+        }
+    }
+
+    /// The evaluation `eval` of the engine's search for its move, from
+    /// white's perspective, as a bar in text. JSON has the score in the
+    /// iterations already.
+    pub fn eval_bar(self, eval: i32) {
+        if self.format == OutputFormat::Text {
+            println!("{}", format_eval_bar(eval, EVAL_BAR_WIDTH));
+        }
+    }
+
+    /// The end of the game, won by `winner` or drawn, for `reason`. `message`
+    /// is what's printed in text.
+    pub fn result(self, winner: Option<Side>, reason: &str, message: &str) {
+        match self.format {
+            OutputFormat::Text => println!("{}", message),
+            OutputFormat::Json => {
+                let result = match winner {
+                    Some(Side::White) => "1-0",
+                    Some(Side::Black) => "0-1",
+                    None => "1/2-1/2",
+                };
+                println!("{{\"event\":\"result\",\"result\":\"{}\",\"reason\":\"{}\"}}", result, reason);
+            },
+        }
+    }
+
+    /// A completed iteration of a search of `game`, with the evaluation
+    /// `eval` from white's perspective and the principal variation `pv`.
+    pub fn iteration(self, game: &GameState, depth: usize, eval: i32, pv: &[Move]) {
+        let (legal_moves, _) = game.get_legal_moves();
+        let pv_san = format_principal_variation(game, pv, legal_moves);
+        match self.format {
+            OutputFormat::Text => println!("Depth {}: {} {}", depth, format_score(eval), pv_san),
+            OutputFormat::Json => println!(
+                "{{\"event\":\"iteration\",\"depth\":{},\"score\":\"{}\",\"pv\":{},\"pv_san\":\"{}\"}}",
+                depth,
+                format_score(eval),
+                json_array(pv.iter().map(|m| m.to_coordinate())),
+                pv_san.trim_end()
+            ),
+        }
+    }
+
+    /// A completed iteration of the engine's search for its move in `game`,
+    /// printed while it thinks, e.g. `depth 7 score +0.42 pv e4 e5 Nf3`. In
+    /// JSON it's an iteration like those of `analyze`.
+    pub fn thinking(self, game: &GameState, depth: usize, eval: i32, pv: &[Move]) {
+        match self.format {
+            OutputFormat::Text => println!("depth {} score {} pv {}", depth, format_score(eval), format_pv_moves(game, pv)),
+            OutputFormat::Json => self.iteration(game, depth, eval, pv),
+        }
+    }
+
+    /// The end of a search of `game` that found `best_move` after `nodes`
+    /// nodes.
+    pub fn search_done(self, game: &GameState, best_move: &Move, nodes: u64) {
+        match self.format {
+            OutputFormat::Text => println!("Searched {} nodes", nodes),
+            OutputFormat::Json => {
+                let (legal_moves, _) = game.get_legal_moves();
+                println!(
+                    "{{\"event\":\"best_move\",\"move\":\"{}\",\"uci\":\"{}\",\"nodes\":{}}}",
+                    best_move.to_algebraic_with_state(&legal_moves),
+                    best_move.to_coordinate(),
+                    nodes
+                );
+            },
+        }
+    }
+
+    // The fields of a move line, without the closing brace
+    fn move_fields(game: &GameState, m: &Move, legal_moves: &[Move], player: &str) -> String {
+        format!(
+            "{{\"event\":\"move\",\"ply\":{},\"side\":\"{}\",\"player\":\"{}\",\"move\":\"{}\",\"uci\":\"{}\"",
+            game.half_move_number,
+            side_name(game.board.side_to_move),
+            player,
+            m.to_algebraic_with_state(legal_moves),
+            m.to_coordinate()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_eval_bar() {
+        assert_eq!(format_eval_bar(0, 20), format!("[{}{}] +0.00", "#".repeat(10), " ".repeat(10)));
+        assert_eq!(format_eval_bar(130, 20), format!("[{}{}] +1.30", "#".repeat(14), " ".repeat(6)));
+        assert_eq!(format_eval_bar(-130, 20), format!("[{}{}] -1.30", "#".repeat(6), " ".repeat(14)));
+        assert!(format_eval_bar(-5000, 20).starts_with(&format!("[{}]", " ".repeat(20))));
+    }
+}
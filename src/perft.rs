@@ -0,0 +1,246 @@
+//! Perft: count the leaf nodes of the legal move tree to a fixed depth, for
+//! validating the move generator against known counts and for benchmarking
+//! its throughput.
+
+use std::thread;
+use std::time::Instant;
+
+use tracing::{debug, debug_span};
+
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::r#move::Move;
+
+/// Number of leaf nodes of the legal move tree of `depth` plies below the
+/// current position.
+///
+/// * `game`: Game state, restored to the current position when done
+/// * `depth`: Number of plies to count
+pub fn perft(game: &mut GameState, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    if depth == 1 {
+        // Only the number of moves is needed, not the moves themselves
+        return game.legal_moves_iter().count() as u64;
+    }
+
+    let (legal_moves, _) = game.get_legal_moves();
+    legal_moves.iter().map(|m| perft_move(game, m, depth)).sum()
+}
+
+/// Number of leaf nodes of the legal move tree of `depth` plies below the
+/// current position, starting with `m`.
+fn perft_move(game: &mut GameState, m: &Move, depth: usize) -> u64 {
+    let pos_hash = game.pos_hash;
+    let castling_right_long = game.board.castling_right_long;
+    let castling_right_short = game.board.castling_right_short;
+    let en_passant_square = game.board.en_passant_square;
+    let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
+
+    game.update_board_with_move(m);
+    let nodes = perft(game, depth - 1);
+    game.update_board_undo_move(
+        m,
+        pos_hash,
+        &castling_right_long,
+        &castling_right_short,
+        en_passant_square,
+        half_move_of_last_irreversible,
+    );
+
+    nodes
+}
+
+/// Perft split by root move, with the root moves divided over all available
+/// cores. Returns the leaf node count below every root move.
+///
+/// * `game`: Game state to count from
+/// * `depth`: Number of plies to count, at least 1
+pub fn perft_divide(game: &GameState, depth: usize) -> Vec<(Move, u64)> {
+    let (legal_moves, _) = game.get_legal_moves();
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = legal_moves.len().div_ceil(n_threads).max(1);
+    let span = debug_span!("perft_divide", depth, threads = n_threads);
+    let _guard = span.enter();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = legal_moves
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let span = span.clone();
+                scope.spawn(move || {
+                    let _guard = span.enter();
+                    let mut game = game.clone();
+                    let divide = chunk.iter()
+                        .map(|m| (*m, perft_move(&mut game, m, depth)))
+                        .collect::<Vec<_>>();
+                    debug!(root_moves = chunk.len(), nodes = divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), "Counted moves");
+                    divide
+                })
+            })
+            .collect();
+
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+impl GameState {
+    /// Number of leaf nodes of the legal move tree of `depth` plies below the
+    /// position, see `perft`. Compare it with known perft results to validate
+    /// positions and the move generator.
+    ///
+    /// ```
+    /// use pawndropper::game::GameState;
+    /// use pawndropper::magic::MagicBitboard;
+    /// use pawndropper::move_bitboards::PSEUDO_LEGAL_MOVES;
+    ///
+    /// let game = GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get());
+    /// assert_eq!(game.perft(3), 8902);
+    /// ```
+    pub fn perft(&self, depth: usize) -> u64 {
+        perft(&mut self.clone(), depth)
+    }
+
+    /// Perft split by root move, counted on all available cores, see
+    /// `perft_divide`. `depth` is at least 1.
+    pub fn perft_divide(&self, depth: usize) -> Vec<(Move, u64)> {
+        perft_divide(self, depth)
+    }
+}
+
+/// Run perft on `fen` after `moves` to each depth up to `depth`, printing the
+/// node count below every root move for the last depth.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `fen`: Position to count from
+/// * `moves`: Moves in coordinate notation to play from `fen` first
+/// * `depth`: Maximum number of plies to count
+pub fn run(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    fen: &str,
+    moves: &[String],
+    depth: usize,
+) -> Result<(), Error> {
+    let mut game = GameState::from_fen(pl_moves, magics, fen)?;
+    game.apply_moves(&moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+    let (legal_moves, _) = game.get_legal_moves();
+
+    for d in 1..=depth {
+        let start = Instant::now();
+        let divide = perft_divide(&game, d);
+        let elapsed = start.elapsed();
+
+        if d == depth {
+            for (m, nodes) in &divide {
+                println!("{}: {}", m.to_algebraic_with_state(&legal_moves), nodes);
+            }
+        }
+
+        let nodes: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
+        println!(
+            "Depth {}: {} nodes in {:.2?} ({:.0} nodes/s)",
+            d,
+            nodes,
+            elapsed,
+            nodes as f64/elapsed.as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+/// Positions with known perft results (FEN, depth and leaf node count) for
+/// benchmarking the move generator, covering castling, promotions, en
+/// passant, pins and checks.
+const BENCH_POSITIONS: [(&str, usize, u64); 6] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 5, 4865609),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4, 4085603),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6, 11030083),
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 4, 422333),
+    ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 4, 2103487),
+    ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 4, 3894594),
+];
+
+/// Benchmark the move generator by running perft on a single thread on
+/// positions with known results, printing the node count and speed for each
+/// position and in total. Leaf moves are counted from the move bitboards
+/// rather than generated, so this mostly measures generating the moves of
+/// the inner nodes.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+pub fn bench(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard) -> Result<(), Error> {
+    let mut total_nodes = 0;
+    let start = Instant::now();
+
+    for (fen, depth, expected_nodes) in BENCH_POSITIONS {
+        let mut game = GameState::from_fen(pl_moves, magics, fen)?;
+
+        let position_start = Instant::now();
+        let nodes = perft(&mut game, depth);
+        let elapsed = position_start.elapsed();
+
+        println!(
+            "{} depth {}: {} nodes in {:.2?} ({:.0} nodes/s)",
+            fen,
+            depth,
+            nodes,
+            elapsed,
+            nodes as f64/elapsed.as_secs_f64()
+        );
+        if nodes != expected_nodes {
+            return Err(Error::PerftMismatch { fen: fen.to_owned(), depth, expected: expected_nodes, nodes });
+        }
+
+        total_nodes += nodes;
+    }
+
+    let elapsed = start.elapsed();
+    println!(
+        "Total: {} nodes in {:.2?} ({:.0} nodes/s)",
+        total_nodes,
+        elapsed,
+        total_nodes as f64/elapsed.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    fn divide_total(fen: &str, depth: usize) -> u64 {
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        game.perft_divide(depth).iter().map(|(_, nodes)| nodes).sum()
+    }
+
+    #[test]
+    fn test_perft() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, start).unwrap();
+        let board = game.board.clone();
+        assert_eq!(perft(&mut game, 3), 8902);
+        assert_eq!(game.board, board);
+        assert_eq!(divide_total(start, 4), 197281);
+        assert_eq!(game.perft(2), 400);
+
+        // Castling, promotions, en passant and pins
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(divide_total(kiwipete, 3), 97862);
+
+        let en_passant_pins = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        assert_eq!(divide_total(en_passant_pins, 4), 43238);
+
+        let promotions = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(divide_total(promotions, 3), 9467);
+    }
+}
@@ -0,0 +1,160 @@
+//! Personalities of the engine: bundled profiles of evaluation weights, draw
+//! contempt and time usage that change its style of play rather than its
+//! strength.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::board::Piece;
+use crate::error::Error;
+use crate::eval::EvalParams;
+use crate::score::S;
+use crate::search::SearchAsync;
+
+/// Style of the engine's play.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Personality {
+    /// The engine's own weights, without contempt
+    #[default]
+    Default,
+    /// Active pieces and open lines over material, avoiding draws
+    Aggressive,
+    /// Safe kings and sound pawns, content with a draw, and thinking longer
+    Solid,
+    /// Pawns given up for development and the initiative
+    Gambit,
+    /// Keeps playing for a win in the endgame, where passed pawns decide
+    EndgameGrinder,
+}
+
+impl Personality {
+    pub const ALL: [Personality; 5] = [
+        Personality::Default,
+        Personality::Aggressive,
+        Personality::Solid,
+        Personality::Gambit,
+        Personality::EndgameGrinder,
+    ];
+
+    /// Evaluation weights of the personality, or None for the engine's own.
+    /// Other weights than the engine's are evaluated from scratch, which
+    /// slows the search down somewhat.
+    pub fn eval_params(self) -> Option<EvalParams> {
+        let mut params = EvalParams::DEFAULT;
+        match self {
+            Personality::Default => return None,
+            Personality::Aggressive => {
+                for piece in [Piece::Knight, Piece::Bishop, Piece::Queen] {
+                    for value in &mut params.piece_placement_values[piece as usize] {
+                        *value = *value*2;
+                    }
+                }
+                params.early_queen_penalty = S(0, 0);
+                params.center_pawn_bonus = params.center_pawn_bonus*2;
+            },
+            Personality::Solid => {
+                params.uncastled_king_penalty = params.uncastled_king_penalty*2;
+                params.castled_king_bonus = params.castled_king_bonus*2;
+                params.doubled_pawns_penalty = params.doubled_pawns_penalty*2;
+                for penalty in &mut params.hanging_piece_penalty {
+                    *penalty = *penalty*2;
+                }
+            },
+            Personality::Gambit => {
+                params.piece_values[Piece::Pawn as usize] -= S(30, 0);
+                params.undeveloped_minor_penalty = params.undeveloped_minor_penalty*2;
+                params.wasted_tempo_penalty = params.wasted_tempo_penalty*2;
+            },
+            Personality::EndgameGrinder => {
+                for bonus in &mut params.connected_pawn_bonus {
+                    *bonus += S(0, bonus.eg());
+                }
+                params.bishop_pair_bonus += S(0, params.bishop_pair_bonus.eg());
+            },
+        }
+        Some(params)
+    }
+
+    /// Centipawns a draw counts as lost by, see `SearchAsync::set_contempt`.
+    pub fn contempt(self) -> i32 {
+        match self {
+            Personality::Default => 0,
+            Personality::Aggressive => 30,
+            Personality::Solid => -15,
+            Personality::Gambit => 20,
+            Personality::EndgameGrinder => 50,
+        }
+    }
+
+    /// Factor of the time spent on a move with a clock.
+    pub fn time_usage(self) -> f64 {
+        match self {
+            Personality::Default | Personality::EndgameGrinder => 1.0,
+            Personality::Aggressive => 0.8,
+            Personality::Solid => 1.3,
+            Personality::Gambit => 0.9,
+        }
+    }
+
+    /// Set the personality's weights and contempt on `searcher`.
+    pub fn apply(self, searcher: &mut SearchAsync) {
+        searcher.set_eval_params(self.eval_params());
+        searcher.set_contempt(self.contempt());
+    }
+}
+
+impl FromStr for Personality {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|personality| personality.to_string() == string)
+            .ok_or_else(|| Error::InvalidPersonality(string.to_owned()))
+    }
+}
+
+impl fmt::Display for Personality {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Personality::Default => "default",
+            Personality::Aggressive => "aggressive",
+            Personality::Solid => "solid",
+            Personality::Gambit => "gambit",
+            Personality::EndgameGrinder => "endgame-grinder",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_personalities() {
+        for personality in Personality::ALL {
+            assert_eq!(personality.to_string().parse::<Personality>().unwrap(), personality);
+            assert_eq!(personality.eval_params().is_none(), personality == Personality::Default);
+        }
+        assert!(matches!("reckless".parse::<Personality>(), Err(Error::InvalidPersonality(_))));
+
+        // A gambit player values pawns less
+        let gambit = Personality::Gambit.eval_params().unwrap();
+        assert!(gambit.piece_values[Piece::Pawn as usize].mg() < EvalParams::DEFAULT.piece_values[Piece::Pawn as usize].mg());
+    }
+
+    #[test]
+    fn test_contempt() {
+        // Every move draws by the fifty move rule. The eval is from white's
+        // perspective, the draw counts as lost for the side to move
+        let mut searcher = SearchAsync::new();
+        searcher.set_contempt(Personality::Aggressive.contempt());
+        for (fen, eval) in [("7k/8/5K2/8/8/8/8/8 w - - 99 80", -30), ("7k/8/5K2/8/8/8/8/8 b - - 99 80", 30)] {
+            let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+            assert_eq!(searcher.find_best_legal_move(&mut game, 3, &[]).0, eval);
+        }
+    }
+}
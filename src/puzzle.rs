@@ -0,0 +1,244 @@
+//! Puzzle trainer: positions are shown one by one and the user has a few
+//! attempts to find the best move, which the engine verifies with a search.
+
+use std::collections::HashMap;
+use std::fs;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::board::{BoardStyle, Side};
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::r#move::Move;
+use crate::search::{format_score, is_mate_score, moves_to_mate, SearchAsync};
+use crate::testsuite::{normalize_san, TestPosition};
+
+/// Built-in puzzles, in Extended Position Description format.
+const PUZZLES: &str = include_str!("../data/puzzles.epd");
+
+// A move scoring within this margin (in centipawns) of the solution solves a
+// puzzle too, unless the solution mates
+const SCORE_MARGIN: i32 = 30;
+
+/// Parse puzzles from EPD lines with the solution as `bm`, or FEN lines
+/// without a known solution.
+fn parse_puzzles(text: &str) -> Vec<TestPosition> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            TestPosition::from_epd(line).unwrap_or_else(|_| TestPosition {
+                id: format!("#{}", i + 1),
+                fen: line.to_owned(),
+                best_moves: vec![],
+            })
+        })
+        .collect()
+}
+
+/// Score of the solution of the puzzle from the side to move's perspective,
+/// and the solution. The solution is the best of the puzzle's best moves, or
+/// the engine's best move if it has none.
+///
+/// * `searcher`: Searcher to verify the solution with
+/// * `game`: Position of the puzzle
+/// * `puzzle`: The puzzle
+/// * `depth`: Search depth
+fn solve(
+    searcher: &mut SearchAsync,
+    game: &mut GameState,
+    puzzle: &TestPosition,
+    depth: usize,
+) -> Result<(i32, Move), Error> {
+    let (legal_moves, _) = game.get_legal_moves();
+    if legal_moves.is_empty() {
+        return Err(Error::InvalidPuzzle { id: puzzle.id.clone(), reason: "no legal moves".to_owned() });
+    }
+
+    let best_moves: Vec<Move> = legal_moves
+        .iter()
+        .filter(|m| puzzle.is_best_move(m, &legal_moves))
+        .copied()
+        .collect();
+    if best_moves.is_empty() && !puzzle.best_moves.is_empty() {
+        return Err(Error::InvalidPuzzle {
+            id: puzzle.id.clone(),
+            reason: format!("best move {} is not legal", puzzle.best_moves.join(" ")),
+        });
+    }
+
+    let (eval, solution, _) = searcher.find_best_legal_move(game, depth, &best_moves);
+    Ok((side_eval(game, eval), solution))
+}
+
+/// Whether `m` solves the puzzle: it's one of the puzzle's best moves, or it
+/// scores as well as the solution in the engine's search.
+///
+/// * `searcher`: Searcher to verify the move with
+/// * `game`: Position of the puzzle
+/// * `puzzle`: The puzzle
+/// * `m`: Move to check
+/// * `solution_score`: Score of the solution, see `solve`
+/// * `depth`: Search depth
+fn is_solution(
+    searcher: &mut SearchAsync,
+    game: &mut GameState,
+    puzzle: &TestPosition,
+    m: &Move,
+    solution_score: i32,
+    depth: usize,
+) -> bool {
+    let (legal_moves, _) = game.get_legal_moves();
+    if puzzle.is_best_move(m, &legal_moves) {
+        return true;
+    }
+
+    let margin = if is_mate_score(solution_score) { 0 } else { SCORE_MARGIN };
+    let (eval, _, _) = searcher.find_best_legal_move(game, depth, &[*m]);
+    side_eval(game, eval) >= solution_score - margin
+}
+
+/// Evaluation from white's perspective to the side to move's perspective.
+fn side_eval(game: &GameState, eval: i32) -> i32 {
+    if game.board.side_to_move == Side::White {
+        eval
+    } else {
+        -eval
+    }
+}
+
+/// Present the puzzles of `file`, or the built-in ones, and check the user's
+/// attempts against the engine's search.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `file`: File with a puzzle per line, as EPD with the solution as `bm` or as FEN
+/// * `depth`: Search depth to verify the attempts at
+/// * `attempts`: Number of attempts per puzzle
+/// * `board_style`: How the boards are drawn
+pub fn run(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    file: Option<&str>,
+    depth: usize,
+    attempts: usize,
+    board_style: BoardStyle,
+) -> Result<(), Error> {
+    let puzzles = match file {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|source| Error::Read { path: path.into(), source })?;
+            parse_puzzles(&text)
+        },
+        None => parse_puzzles(PUZZLES),
+    };
+
+    let mut rl = DefaultEditor::new()?;
+    let mut searcher = SearchAsync::new();
+    let mut n_solved = 0;
+
+    'puzzles: for (i, puzzle) in puzzles.iter().enumerate() {
+        let mut game = GameState::from_fen(pl_moves, magics, &puzzle.fen)
+            .map_err(|e| Error::InvalidPuzzle { id: puzzle.id.clone(), reason: e.to_string() })?;
+        let (solution_score, solution) = solve(&mut searcher, &mut game, puzzle, depth)?;
+
+        let (legal_moves, _) = game.get_legal_moves();
+        let move_map: HashMap<String, &Move> = legal_moves
+            .iter()
+            .map(|m| (m.to_algebraic_with_state(&legal_moves), m))
+            .collect();
+
+        let side = game.board.side_to_move;
+        println!("{}", game.board.to_ascii_with_style(side, board_style));
+        if is_mate_score(solution_score) && solution_score > 0 {
+            println!("Puzzle {}/{} ({}): {:?} to move and mate in {}", i + 1, puzzles.len(), puzzle.id, side, moves_to_mate(solution_score));
+        } else {
+            println!("Puzzle {}/{} ({}): {:?} to move, find the best move", i + 1, puzzles.len(), puzzle.id, side);
+        }
+
+        let mut solved = false;
+        let mut attempt = 1;
+        while attempt <= attempts {
+            let line = match rl.readline(&format!("attempt {}/{}> ", attempt, attempts)) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break 'puzzles,
+                Err(err) => return Err(err.into()),
+            };
+
+            if line.trim() == "skip" {
+                break;
+            }
+
+            let Some(&&m) = move_map.get(&normalize_san(line.trim())) else {
+                println!("Illegal move '{}'", line.trim());
+                continue;
+            };
+
+            if is_solution(&mut searcher, &mut game, puzzle, &m, solution_score, depth) {
+                println!("Correct!");
+                solved = true;
+                break;
+            }
+            println!("Not the best move");
+            attempt += 1;
+        }
+
+        if solved {
+            n_solved += 1;
+        } else {
+            println!(
+                "Solution: {} ({})",
+                solution.to_algebraic_with_state(&legal_moves),
+                format_score(solution_score)
+            );
+        }
+    }
+
+    println!("Solved {}/{} puzzles", n_solved, puzzles.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_puzzles() {
+        let mut searcher = SearchAsync::new();
+
+        for puzzle in parse_puzzles(PUZZLES) {
+            let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, &puzzle.fen).unwrap();
+            let (solution_score, _) = solve(&mut searcher, &mut game, &puzzle, 4).unwrap();
+            assert!(solution_score > 200, "{}: {}", puzzle.id, format_score(solution_score));
+
+            // The engine finds the solution on its own
+            let (eval, best_move, _) = searcher.find_best_legal_move(&mut game, 4, &[]);
+            assert_eq!(side_eval(&game, eval), solution_score, "{}", puzzle.id);
+            assert!(is_solution(&mut searcher, &mut game, &puzzle, &best_move, solution_score, 4), "{}", puzzle.id);
+        }
+    }
+
+    #[test]
+    fn test_is_solution() {
+        // Without a best move, any mate in one solves the puzzle
+        let puzzles = parse_puzzles("7k/8/6K1/8/8/8/8/RR6 w - - 0 1\n");
+        let puzzle = &puzzles[0];
+        assert!(puzzle.best_moves.is_empty());
+
+        let mut searcher = SearchAsync::new();
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, &puzzle.fen).unwrap();
+        let (solution_score, _) = solve(&mut searcher, &mut game, puzzle, 2).unwrap();
+        assert_eq!(moves_to_mate(solution_score), 1);
+
+        let (legal_moves, _) = game.get_legal_moves();
+        let move_of = |san: &str| *legal_moves.iter().find(|m| m.to_algebraic_with_state(&legal_moves) == san).unwrap();
+        for (san, solves) in [("Ra8", true), ("Rb8", true), ("Ra7", false), ("Rh1", false)] {
+            assert_eq!(is_solution(&mut searcher, &mut game, puzzle, &move_of(san), solution_score, 2), solves, "{}", san);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Puzzle generation from played games: positions where the evaluation swung
+//! sharply with the last move, a mistake, and exactly one move wins, which a
+//! deeper search verifies. The puzzles are written as EPD lines that the
+//! puzzle trainer reads, with the solution as `bm`, the solution line as `pv`
+//! and the tags in `c0`:
+//!
+//! ```text
+//! 6k1/5ppp/8/8/8/8/2r2PPP/3R2K1 w - - bm Rd8; pv Rd8; id "game.1"; c0 "mate";
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use tracing::info;
+
+use crate::board::Side;
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::opponent::Opponent;
+use crate::r#move::Move;
+use crate::savegame::SavedGame;
+use crate::search::{is_mate_score, SearchAsync};
+use crate::selfplay::play;
+
+// A position is a puzzle candidate when the side to move's evaluation rose by
+// this much (in centipawns) with the last move, and it's winning
+const SWING: i32 = 200;
+const WINNING: i32 = 200;
+
+// Advantage of the solution tagged as crushing rather than an advantage
+const CRUSHING: i32 = 500;
+
+// Random moves the self-play games start with, so they differ
+const OPENING_PLIES: usize = 4;
+
+/// A puzzle found in a game.
+///
+/// * `id`: Game the puzzle was found in and the ply of its position
+/// * `fen`: Position of the puzzle
+/// * `solution`: Solution line in standard algebraic notation, starting with the only winning move
+/// * `tags`: Theme of the puzzle: mate, crushing or advantage
+pub struct GeneratedPuzzle {
+    pub id: String,
+    pub fen: String,
+    pub solution: Vec<String>,
+    pub tags: Vec<&'static str>,
+}
+
+impl GeneratedPuzzle {
+    /// The puzzle as an EPD line, without the move counters of the FEN.
+    pub fn to_epd(&self) -> String {
+        let position: Vec<&str> = self.fen.split(' ').take(4).collect();
+        format!(
+            "{} bm {}; pv {}; id \"{}\"; c0 \"{}\";",
+            position.join(" "),
+            self.solution[0],
+            self.solution.join(" "),
+            self.id,
+            self.tags.join(" "),
+        )
+    }
+}
+
+/// Evaluation from white's perspective to the side to move's perspective.
+fn side_eval(game: &GameState, eval: i32) -> i32 {
+    if game.board.side_to_move == Side::White {
+        eval
+    } else {
+        -eval
+    }
+}
+
+/// `pv` in standard algebraic notation from `game`, up to the last move of
+/// the side to move, so the line ends with the solver's move.
+fn solution_line(game: &GameState, pv: &[Move]) -> Vec<String> {
+    let mut game = game.clone();
+    let (mut legal_moves, _) = game.get_legal_moves();
+    let mut line = Vec::new();
+    for m in pv {
+        if !legal_moves.contains(m) {
+            break;
+        }
+        line.push(m.to_algebraic_with_state(&legal_moves));
+        let result;
+        (result, legal_moves) = game.make_move(m);
+        if result.is_some() {
+            break;
+        }
+    }
+    if line.len() % 2 == 0 {
+        line.pop();
+    }
+
+    line
+}
+
+/// The puzzle of `game`'s position if it has a single winning move at
+/// `depth`, with the solution line of that move.
+fn verify(searcher: &mut SearchAsync, game: &mut GameState, id: String, depth: usize) -> Option<GeneratedPuzzle> {
+    let scores = searcher.score_legal_moves(game, depth);
+    let mut winning = scores.iter().filter(|&&(_, score)| score >= WINNING);
+    let &(solution, score) = winning.next()?;
+    if winning.next().is_some() || scores.len() < 2 {
+        return None;
+    }
+
+    let (_, _, pv) = searcher.find_best_legal_move(game, depth, &[solution]);
+    let pv = if pv.first() == Some(&solution) { pv } else { vec![solution] };
+    let tag = if is_mate_score(score) {
+        "mate"
+    } else if score >= CRUSHING {
+        "crushing"
+    } else {
+        "advantage"
+    };
+
+    Some(GeneratedPuzzle {
+        id,
+        fen: game.to_fen(),
+        solution: solution_line(game, &pv),
+        tags: vec![tag],
+    })
+}
+
+/// Find the puzzles of a played game: the positions after a move that swung
+/// the evaluation by `SWING` towards the side to move, at `scan_depth`, with a
+/// single winning move at `verify_depth`.
+///
+/// * `searcher`: Searcher to evaluate the positions with
+/// * `game`: The game, with all its moves played
+/// * `game_id`: Name of the game, the puzzle ids are the name and the ply
+/// * `scan_depth`: Search depth to evaluate every position at
+/// * `verify_depth`: Search depth to verify the candidates at
+pub fn find_puzzles(
+    searcher: &mut SearchAsync,
+    game: &GameState,
+    game_id: &str,
+    scan_depth: usize,
+    verify_depth: usize,
+) -> Vec<GeneratedPuzzle> {
+    let mut position = game.clone();
+    while position.undo_move().is_some() {}
+    let moves: Vec<Move> = game.played_moves().collect();
+
+    let mut puzzles = Vec::new();
+    let mut previous_eval = None;
+    for (ply, m) in moves.iter().map(Some).chain([None]).enumerate() {
+        let (legal_moves, _) = position.get_legal_moves();
+        if legal_moves.is_empty() {
+            break;
+        }
+
+        let (eval, _, _) = searcher.find_best_legal_move(&mut position, scan_depth, &[]);
+        let eval = side_eval(&position, eval);
+        // The previous evaluation is from the opponent's perspective
+        let swung = previous_eval.is_some_and(|previous_eval: i32| eval + previous_eval >= SWING);
+        if swung && eval >= WINNING {
+            let id = format!("{}.{}", game_id, ply);
+            puzzles.extend(verify(searcher, &mut position, id, verify_depth));
+        }
+        previous_eval = Some(eval);
+
+        match m {
+            Some(m) => {
+                position.make_move(m);
+            },
+            None => break,
+        }
+    }
+
+    puzzles
+}
+
+/// Generate puzzles from the saved games `files`, or from `games` games of
+/// the engine against itself at `depth` without files, and write them to
+/// `output` as EPD, or print them. Positions found in more than one game
+/// are written once.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `files`: Saved games to look for puzzles in (see `SavedGame`)
+/// * `games`: Number of self-play games to play without files
+/// * `depth`: Search depth of every move of the self-play games
+/// * `scan_depth`: Search depth to evaluate every position of the games at
+/// * `verify_depth`: Search depth to verify the winning move at
+/// * `output`: File to write the puzzles to, printed if None
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    files: &[String],
+    games: usize,
+    depth: usize,
+    scan_depth: usize,
+    verify_depth: usize,
+    output: Option<&str>,
+) -> Result<(), Error> {
+    let mut played = Vec::new();
+    for file in files {
+        let saved = SavedGame::load(file)?;
+        let mut game = GameState::from_fen(pl_moves, magics, &saved.fen)?;
+        game.apply_moves(&saved.moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+        let name = Path::new(file).file_stem().map_or(file.clone(), |stem| stem.to_string_lossy().into_owned());
+        played.push((name, game));
+    }
+    if files.is_empty() {
+        let mut rng = rand::thread_rng();
+        for i in 1..=games {
+            let mut game = GameState::new(pl_moves, magics);
+            for _ in 0..OPENING_PLIES {
+                let (legal_moves, _) = game.get_legal_moves();
+                let Some(m) = legal_moves.choose(&mut rng) else { break };
+                game.make_move(m);
+            }
+            let result = play(&mut game, [Opponent::Engine; Side::N_SIDES], depth, None, None, None, |_, _, _, _| ());
+            info!("Self-play game {}: {} ({})", i, result.to_pgn(), result.reason);
+            played.push((format!("selfplay.{}", i), game));
+        }
+    }
+
+    let mut searcher = SearchAsync::new();
+    let mut seen = HashSet::new();
+    let mut lines = Vec::new();
+    for (name, game) in &played {
+        for puzzle in find_puzzles(&mut searcher, game, name, scan_depth, verify_depth) {
+            let epd = puzzle.to_epd();
+            if seen.insert(epd.split(" bm ").next().unwrap_or_default().to_owned()) {
+                info!("Puzzle {} in {}", puzzle.id, name);
+                lines.push(epd);
+            }
+        }
+    }
+
+    match output {
+        Some(path) => {
+            fs::write(path, lines.iter().map(|line| format!("{}\n", line)).collect::<String>())
+                .map_err(|source| Error::Write { path: path.into(), source })?;
+            println!("{} puzzles from {} games written to {}", lines.len(), played.len(), path);
+        },
+        None => lines.iter().for_each(|line| println!("{}", line)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+    use crate::testsuite::TestPosition;
+
+    #[test]
+    fn test_find_puzzles() {
+        // Black's rook leaves the back rank, after which only Rd8 mates
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "2r3k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1").unwrap();
+        game.apply_moves(&["c8c2"]).unwrap();
+        let mut searcher = SearchAsync::new();
+        let puzzles = find_puzzles(&mut searcher, &game, "game", 3, 4);
+        assert_eq!(puzzles.len(), 1);
+
+        let epd = puzzles[0].to_epd();
+        assert_eq!(epd, "6k1/5ppp/8/8/8/8/2r2PPP/3R2K1 w - - bm Rd8; pv Rd8; id \"game.1\"; c0 \"mate\";");
+        let puzzle = TestPosition::from_epd(&epd).unwrap();
+        assert_eq!(puzzle.best_moves, ["Rd8"]);
+
+        // A quiet game has none
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4", "e7e5", "g1f3", "b8c6"]).unwrap();
+        assert!(find_puzzles(&mut searcher, &game, "game", 3, 4).is_empty());
+    }
+}
@@ -0,0 +1,470 @@
+//! Commands of the game against the engine in the terminal. Any input that
+//! isn't a command is a move in algebraic notation.
+
+use std::env;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use pawndropper::board::{Board, Piece, Side};
+use pawndropper::game::{GameState, IllegalReason};
+use pawndropper::r#move::{Move, MoveType};
+
+use crate::output::{BoardLayout, View};
+
+/// The commands with their arguments and what they do, for `help` and tab
+/// completion.
+pub const COMMANDS: [(&str, &str, &str); 22] = [
+    ("help", "", "Print this help"),
+    ("moves", "", "Print the moves played so far (also `history`)"),
+    ("show", "", "Print the board"),
+    ("flip", "", "Print the board from the other side from now on"),
+    ("view", " <white|black|auto>", "Print the board with the given side at the bottom, auto for yours"),
+    ("board", " <compact|large|framed>", "Print the board in the given size from now on"),
+    ("fen", "", "Print the position in Forsyth-Edwards Notation"),
+    ("eval", "", "Print the static evaluation of the position per term"),
+    ("bitboards", "", "Print the bitboards the move generation works with, for debugging"),
+    ("motifs", "", "List the pins, skewers, forks, discovered attacks and hanging pieces"),
+    ("evals", " [file]", "Print a graph of the engine's evaluations so far, or save them as CSV"),
+    ("analyze", "", "Search the position until enter is pressed"),
+    ("clearhash", "", "Forget what the engine's searches so far learned"),
+    ("undo", "", "Take back your last move and the computer's reply"),
+    ("stop", "", "Make the computer move now while it's thinking"),
+    ("offer draw", "", "Offer the computer a draw"),
+    ("resign", "", "Resign the game"),
+    ("new", " [white|black]", "Start a new game, optionally playing the given side"),
+    ("setboard", " <fen>", "Set up a position, playing the side to move"),
+    ("save", " <file>", "Save the game"),
+    ("load", " <file>", "Continue a saved game"),
+    ("quit", "", "Quit, the game can be resumed on the next start"),
+];
+
+/// A line of input.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    History,
+    Show,
+    Flip,
+    View(View),
+    Board(BoardLayout),
+    Fen,
+    Eval,
+    Bitboards,
+    Motifs,
+    /// Graph of the evaluations, or the CSV file to save them to
+    Evals(Option<String>),
+    Analyze,
+    ClearHash,
+    Undo,
+    Stop,
+    OfferDraw,
+    Resign,
+    /// New game with the side the user plays, or the same sides
+    New(Option<Side>),
+    SetBoard(String),
+    Save(String),
+    Load(String),
+    Quit,
+    /// Anything else, which should be a move
+    Move(String),
+}
+
+/// Parse a line of input, with an error for a command with invalid
+/// arguments.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (name, args) = line.split_once(' ').map_or((line, ""), |(name, args)| (name, args.trim()));
+
+    let command = match (name, args) {
+        ("help", "") => Command::Help,
+        ("moves" | "history", "") => Command::History,
+        ("show", "") => Command::Show,
+        ("flip", "") => Command::Flip,
+        ("fen", "") => Command::Fen,
+        ("eval", "") => Command::Eval,
+        ("bitboards", "") => Command::Bitboards,
+        ("motifs", "") => Command::Motifs,
+        ("evals", "") => Command::Evals(None),
+        ("analyze", "") => Command::Analyze,
+        ("clearhash", "") => Command::ClearHash,
+        ("undo", "") => Command::Undo,
+        ("stop", "") => Command::Stop,
+        ("offer", "draw") => Command::OfferDraw,
+        ("resign", "") => Command::Resign,
+        ("quit" | "exit", "") => Command::Quit,
+        ("new", "") => Command::New(None),
+        ("new", side) => Command::New(Some(side.parse().map_err(|_| {
+            format!("Invalid side '{}', expected white or black", side)
+        })?)),
+        ("setboard" | "save" | "load" | "view" | "board", "") => return Err(format!("Usage: {}", usage(name))),
+        ("view", view) => Command::View(View::from_str(view, true).map_err(|_| {
+            format!("Invalid view '{}', expected white, black or auto", view)
+        })?),
+        ("board", layout) => Command::Board(BoardLayout::from_str(layout, true).map_err(|_| {
+            format!("Invalid board '{}', expected compact, large or framed", layout)
+        })?),
+        ("setboard", fen) => Command::SetBoard(fen.to_owned()),
+        ("save", path) => Command::Save(path.to_owned()),
+        ("load", path) => Command::Load(path.to_owned()),
+        ("evals", path) => Command::Evals(Some(path.to_owned())),
+        _ => Command::Move(line.to_owned()),
+    };
+
+    Ok(command)
+}
+
+fn usage(name: &str) -> String {
+    let (_, args, _) = COMMANDS.iter().find(|(command, _, _)| *command == name).unwrap();
+    format!("{}{}", name, args)
+}
+
+/// The text of `help`.
+pub fn help() -> String {
+    let mut help = "Enter a move in algebraic notation (e.g. e4, Nf3, O-O, exd8=Q) or a command:\n".to_owned();
+    for (name, args, description) in COMMANDS {
+        help.push_str(&format!("  {:<22} {}\n", format!("{}{}", name, args), description));
+    }
+    help
+}
+
+/// The legal moves `input` can mean: the move in algebraic notation or in
+/// coordinate notation, or either of them without the promotion piece, or
+/// in algebraic notation without the origin a piece move needs to tell it
+/// apart, e.g. `e7e8` or `Nd2` with two knights that can go to d2. More than
+/// one is for the user to choose from, see `choice_prompt`.
+///
+/// * `input`: Move entered by the user
+/// * `legal_moves`: Legal moves of the position
+pub fn matching_moves(input: &str, legal_moves: &[Move]) -> Vec<Move> {
+    let input = input.trim_end_matches(['+', '#']);
+    if let Some(&m) = legal_moves.iter().find(|m| m.to_algebraic_with_state(legal_moves) == input) {
+        return vec![m];
+    }
+    if !input.is_ascii() {
+        return Vec::new();
+    }
+
+    // Drops in coordinate notation, e.g. `P@e4`
+    if let Some(&m) = legal_moves.iter().find(|m| m.move_type == MoveType::Drop && m.to_coordinate().eq_ignore_ascii_case(input)) {
+        return vec![m];
+    }
+
+    // Coordinate notation
+    let from_square = input.get(0..2).and_then(Board::square_from_algebraic);
+    let to_square = input.get(2..4).and_then(Board::square_from_algebraic);
+    if let (Some(from_square), Some(to_square)) = (from_square, to_square) {
+        let promotion = match input[4..].chars().collect::<Vec<_>>()[..] {
+            [] => None,
+            [c] => Some(Piece::from_fen_char(c).map(|(piece, _)| piece)),
+            _ => return Vec::new(),
+        };
+        return legal_moves.iter()
+            .filter(|m| m.from_square == from_square && m.to_square == to_square)
+            .filter(|m| promotion.is_none_or(|piece| promotion_piece(m) == piece))
+            .copied()
+            .collect();
+    }
+
+    // Algebraic notation, with the origin being what's left of the file
+    // and rank of the origin square between the piece and the target square
+    let (piece, input) = match input.chars().next() {
+        Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => (Piece::from_fen_char(c).unwrap().0, &input[1..]),
+        _ => (Piece::Pawn, input),
+    };
+    let (input, promotion) = match input.split_once('=') {
+        Some((input, promotion)) => match promotion.chars().collect::<Vec<_>>()[..] {
+            [c] => (input, Some(Piece::from_fen_char(c).map(|(piece, _)| piece))),
+            _ => return Vec::new(),
+        },
+        None => (input, None),
+    };
+    let Some((origin, to_square)) = input.len().checked_sub(2).map(|i| input.split_at(i)) else {
+        return Vec::new();
+    };
+    let Some(to_square) = Board::square_from_algebraic(to_square) else {
+        return Vec::new();
+    };
+    let origin = origin.strip_suffix('x').unwrap_or(origin);
+    if origin.len() > 2 {
+        return Vec::new();
+    }
+
+    legal_moves.iter()
+        .filter(|m| {
+            let coordinate = m.to_coordinate();
+            let (from, to) = coordinate.split_at(2);
+            m.piece == piece
+                && m.to_square == to_square
+                && m.move_type != MoveType::Drop
+                && origin.chars().all(|c| from.contains(c))
+                // A pawn move without an origin is straight ahead
+                && (piece != Piece::Pawn || !origin.is_empty() || from[..1] == to[..1])
+                && promotion.is_none_or(|piece| promotion_piece(m) == piece)
+        })
+        .copied()
+        .collect()
+}
+
+/// The question asking which of the moves `candidates`, see
+/// `matching_moves`, the user means: the promotion piece, the origin
+/// square, or else the move.
+pub fn choice_prompt(candidates: &[Move], legal_moves: &[Move]) -> String {
+    if candidates.iter().all(|m| m.from_square == candidates[0].from_square) {
+        let pieces: Vec<String> = candidates.iter()
+            .filter_map(promotion_piece)
+            .map(|piece| piece.fen_char(Side::Black).to_string())
+            .collect();
+        format!("Promote to ({})? ", pieces.join(", "))
+    } else if candidates.iter().all(|m| promotion_piece(m) == promotion_piece(&candidates[0])) {
+        let origins: Vec<String> = candidates.iter().map(|m| m.to_coordinate()[..2].to_owned()).collect();
+        format!("From which square ({})? ", origins.join(", "))
+    } else {
+        let moves: Vec<String> = candidates.iter().map(|m| m.to_algebraic_with_state(legal_moves)).collect();
+        format!("Which move ({})? ", moves.join(", "))
+    }
+}
+
+/// The move of `candidates` the user chose with `answer` to the question
+/// of `choice_prompt`, which can be the promotion piece, the origin square
+/// or the move in either notation, ignoring case.
+pub fn resolve_choice(answer: &str, candidates: &[Move], legal_moves: &[Move]) -> Option<Move> {
+    let answer = answer.trim();
+    let chosen: Vec<&Move> = candidates.iter()
+        .filter(|m| {
+            let coordinate = m.to_coordinate();
+            answer.eq_ignore_ascii_case(&m.to_algebraic_with_state(legal_moves))
+                || answer.eq_ignore_ascii_case(&coordinate)
+                || answer.eq_ignore_ascii_case(&coordinate[..2])
+                || promotion_piece(m).is_some_and(|piece| answer.eq_ignore_ascii_case(&piece.fen_char(Side::Black).to_string()))
+        })
+        .collect();
+
+    match chosen[..] {
+        [&m] => Some(m),
+        _ => None,
+    }
+}
+
+fn promotion_piece(m: &Move) -> Option<Piece> {
+    match m.move_type {
+        MoveType::Promotion(piece) | MoveType::CapturePromotion(_, piece) => Some(piece),
+        _ => None,
+    }
+}
+
+/// Why the move `input` isn't legal, for a move that `matching_moves` finds
+/// no legal move for but that names a move of a piece on the board, e.g. a
+/// pinned piece moving off the pin or castling through check. None for input
+/// that isn't a move or has no piece to move.
+///
+/// * `input`: Move entered by the user
+/// * `game`: Game the move is entered in
+pub fn illegal_reason(input: &str, game: &GameState) -> Option<IllegalReason> {
+    let input = input.trim_end_matches(['+', '#']);
+    if !input.is_ascii() {
+        return None;
+    }
+    let side = game.board.side_to_move;
+
+    // The origin and target squares the input means, legal or not
+    let squares: Vec<(usize, usize)> = if let Some(short) = match input {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    } {
+        let king_targets = if short { Board::KING_SHORT_CASTLE_SQUARES } else { Board::KING_LONG_CASTLE_SQUARES };
+        vec![(Board::KING_SQUARES[side as usize], king_targets[side as usize])]
+    } else if let (Some(from_square), Some(to_square)) = (
+        input.get(0..2).and_then(Board::square_from_algebraic),
+        input.get(2..4).and_then(Board::square_from_algebraic),
+    ) {
+        vec![(from_square, to_square)]
+    } else {
+        // Algebraic notation, from the pieces of the type whose square has
+        // the origin's file and rank in it
+        let (piece, input) = match input.chars().next() {
+            Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => (Piece::from_fen_char(c).unwrap().0, &input[1..]),
+            _ => (Piece::Pawn, input),
+        };
+        let input = input.split_once('=').map_or(input, |(input, _)| input);
+        let (origin, to_square) = input.split_at(input.len().checked_sub(2)?);
+        let to_square = Board::square_from_algebraic(to_square)?;
+        let origin = origin.strip_suffix('x').unwrap_or(origin);
+
+        let mut from_squares = Vec::new();
+        let mut pieces = game.board[(piece, side)];
+        while pieces != 0 {
+            let square = pieces.trailing_zeros() as usize;
+            let name = Board::square_to_algebraic(square);
+            // A pawn move without an origin is straight ahead
+            if origin.chars().all(|c| name.contains(c)) && (piece != Piece::Pawn || !origin.is_empty() || name[..1] == Board::square_to_algebraic(to_square)[..1]) {
+                from_squares.push(square);
+            }
+
+            // clear square bit
+            pieces &= pieces - 1;
+        }
+        // The pawn nearest to the target first
+        from_squares.sort_by_key(|&square| square.abs_diff(to_square));
+
+        from_squares.into_iter().map(|from_square| (from_square, to_square)).collect()
+    };
+
+    // The piece that can move there but for a rule over one that can't
+    let reasons: Vec<IllegalReason> = squares.into_iter()
+        .filter_map(|(from_square, to_square)| game.illegal_reason(from_square, to_square))
+        .collect();
+    reasons.iter()
+        .find(|reason| !matches!(reason, IllegalReason::CantMoveThere(..)))
+        .or(reasons.first())
+        .copied()
+}
+
+/// The legal moves closest to the invalid move `input`, for a "did you
+/// mean" hint: those a typo or two away, ignoring case.
+///
+/// * `input`: Move entered by the user
+/// * `legal_moves`: Legal moves in algebraic notation
+pub fn suggest_moves<'a>(input: &str, legal_moves: &'a [String]) -> Vec<&'a str> {
+    let input = input.to_lowercase();
+    let distances: Vec<usize> = legal_moves.iter().map(|m| edit_distance(&input, &m.to_lowercase())).collect();
+
+    match distances.iter().min() {
+        Some(&min) if min <= 2 => legal_moves.iter()
+            .zip(&distances)
+            .filter(|&(_, &distance)| distance == min)
+            .map(|(m, _)| m.as_str())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut distances: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut previous = distances[0];
+        distances[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous + usize::from(a_char != b_char);
+            previous = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(previous + 1);
+        }
+    }
+
+    distances[b.len()]
+}
+
+/// File the input history is kept in between runs, in
+/// `pawndropper/history` in `$XDG_DATA_HOME` (`~/.local/share` by default).
+pub fn history_path() -> Option<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))?;
+
+    Some(data_home.join("pawndropper").join("history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pawndropper::magic::MagicBitboard;
+    use pawndropper::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("help"), Ok(Command::Help));
+        assert_eq!(parse_command(" moves "), Ok(Command::History));
+        assert_eq!(parse_command("offer draw"), Ok(Command::OfferDraw));
+        assert_eq!(parse_command("stop"), Ok(Command::Stop));
+        assert_eq!(parse_command("bitboards"), Ok(Command::Bitboards));
+        assert_eq!(parse_command("motifs"), Ok(Command::Motifs));
+        assert_eq!(parse_command("clearhash"), Ok(Command::ClearHash));
+        assert_eq!(parse_command("new"), Ok(Command::New(None)));
+        assert_eq!(parse_command("new white"), Ok(Command::New(Some(Side::White))));
+        assert!(parse_command("new blue").is_err());
+        assert_eq!(parse_command("view black"), Ok(Command::View(View::Black)));
+        assert_eq!(parse_command("view auto"), Ok(Command::View(View::Auto)));
+        assert!(parse_command("view").is_err());
+        assert!(parse_command("view up").is_err());
+        assert_eq!(parse_command("board framed"), Ok(Command::Board(BoardLayout::Framed)));
+        assert!(parse_command("board huge").is_err());
+        assert_eq!(parse_command("save game.txt"), Ok(Command::Save("game.txt".to_owned())));
+        assert!(parse_command("load").is_err());
+        assert_eq!(parse_command("evals"), Ok(Command::Evals(None)));
+        assert_eq!(parse_command("evals game.csv"), Ok(Command::Evals(Some("game.csv".to_owned()))));
+        assert_eq!(
+            parse_command("setboard 8/8/8/8/8/8/8/K6k w - - 0 1"),
+            Ok(Command::SetBoard("8/8/8/8/8/8/8/K6k w - - 0 1".to_owned()))
+        );
+        assert_eq!(parse_command("Nf3"), Ok(Command::Move("Nf3".to_owned())));
+    }
+
+    #[test]
+    fn test_matching_moves() {
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), "8/4P3/8/8/8/5N2/8/1N2K2k w - - 0 1").unwrap();
+        let (legal_moves, _) = game.get_legal_moves();
+        let matching = |input: &str| -> Vec<String> {
+            matching_moves(input, &legal_moves).iter().map(|m| m.to_coordinate()).collect()
+        };
+
+        assert_eq!(matching("Nbd2"), vec!["b1d2"]);
+        assert_eq!(matching("Nh4+"), vec!["f3h4"]);
+        assert_eq!(matching("f3d2"), vec!["f3d2"]);
+        assert_eq!(matching("e7e8q"), vec!["e7e8q"]);
+        assert_eq!(matching("e8=R"), vec!["e7e8r"]);
+        assert_eq!(matching("e7e8").len(), 4);
+        assert_eq!(matching("e8").len(), 4);
+        assert_eq!(matching("Nd2").len(), 2);
+        assert!(matching("Nd3").is_empty());
+        assert!(matching("e7e8x").is_empty());
+        assert!(matching("hello").is_empty());
+    }
+
+    #[test]
+    fn test_choice() {
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), "8/4P3/8/8/8/5N2/8/1N2K2k w - - 0 1").unwrap();
+        let (legal_moves, _) = game.get_legal_moves();
+
+        let promotions = matching_moves("e8", &legal_moves);
+        assert_eq!(choice_prompt(&promotions, &legal_moves), "Promote to (n, b, r, q)? ");
+        assert_eq!(resolve_choice("Q", &promotions, &legal_moves).unwrap().to_coordinate(), "e7e8q");
+        assert_eq!(resolve_choice("e8=N", &promotions, &legal_moves).unwrap().to_coordinate(), "e7e8n");
+        assert!(resolve_choice("k", &promotions, &legal_moves).is_none());
+
+        let knights = matching_moves("Nd2", &legal_moves);
+        assert_eq!(choice_prompt(&knights, &legal_moves), "From which square (b1, f3)? ");
+        assert_eq!(resolve_choice(" f3", &knights, &legal_moves).unwrap().to_coordinate(), "f3d2");
+        assert_eq!(resolve_choice("nbd2", &knights, &legal_moves).unwrap().to_coordinate(), "b1d2");
+        assert!(resolve_choice("", &knights, &legal_moves).is_none());
+    }
+
+    #[test]
+    fn test_suggest_moves() {
+        let legal_moves: Vec<String> = ["e4", "e3", "d4", "Nf3", "Nc3", "O-O"].map(str::to_owned).to_vec();
+        assert_eq!(suggest_moves("e5", &legal_moves), vec!["e4", "e3"]);
+        assert_eq!(suggest_moves("nf3", &legal_moves), vec!["Nf3"]);
+        assert_eq!(suggest_moves("Nf4", &legal_moves), vec!["Nf3"]);
+        assert_eq!(suggest_moves("0-0", &legal_moves), vec!["O-O"]);
+        assert!(suggest_moves("Qxh7", &legal_moves).is_empty());
+    }
+
+    #[test]
+    fn test_illegal_reason() {
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), "4k3/8/8/8/1b6/2N5/4P3/4K2R w K - 0 1").unwrap();
+        let reason = |input: &str| illegal_reason(input, &game).map(|reason| reason.to_string());
+
+        assert_eq!(reason("Nd5"), Some("your knight on c3 is pinned to your king by the bishop on b4".to_owned()));
+        assert_eq!(reason("c3b5"), Some("your knight on c3 is pinned to your king by the bishop on b4".to_owned()));
+        assert_eq!(reason("Ne5"), Some("your knight on c3 can't move to e5".to_owned()));
+        assert_eq!(reason("e5"), Some("your pawn on e2 can't move to e5".to_owned()));
+        assert_eq!(reason("O-O-O"), Some("you can't castle long any more".to_owned()));
+        assert_eq!(reason("d2e3"), Some("there is no piece on d2".to_owned()));
+        assert_eq!(reason("Qd4"), None);
+        assert_eq!(reason("hello"), None);
+    }
+}
@@ -0,0 +1,211 @@
+//! The rules that set the variants apart from standard chess, behind the
+//! `Rules` trait that `GameState`, the evaluation and the search call into. A
+//! variant plugs in by implementing the trait and returning it from
+//! `Variant::rules`, instead of checking for the variant wherever its rules
+//! differ. How the pieces move and what the board holds stay with `Board`.
+
+use crate::board::{Board, Piece, Side, Variant};
+use crate::eval::{EvalParams, EvalTerm};
+use crate::game::GameState;
+use crate::r#move::{Move, MoveResult};
+use crate::score::{Score, S};
+
+// Three-check: bonus by the number of checks given, worth more than material
+// as the third wins outright
+const CHECKS_GIVEN_BONUS: [Score; Variant::CHECKS_TO_WIN as usize] = [S(0, 0), S(150, 150), S(450, 450)];
+
+// Crazyhouse: bonus per piece in hand on top of its material value, as it can
+// be dropped on any empty square
+const HAND_PIECE_BONUS: Score = S(20, 20);
+
+// Pawns can't be dropped on the first and last ranks
+const BACK_RANKS: u64 = 0xff000000000000ff;
+
+/// The rules of a variant where they differ from standard chess. Every
+/// method defaults to the standard rules.
+pub trait Rules: Sync {
+    /// Board of the starting position.
+    fn start_board(&self) -> Board;
+
+    /// Number of fields of a FEN string that describe the board, before the
+    /// move counters.
+    fn n_fen_board_fields(&self) -> usize {
+        4
+    }
+
+    /// Update the variant's state of the position after `m` was made on
+    /// `game`, with the other side to move.
+    fn after_move(&self, _game: &mut GameState, _m: &Move) {}
+
+    /// Take back what `after_move` did, before `m` is taken back on `game`.
+    fn before_undo(&self, _game: &mut GameState, _m: &Move) {}
+
+    /// The result of the game by the variant's own rules, lost by the side
+    /// to move. It's checked before checkmate.
+    fn result(&self, _game: &GameState) -> Option<MoveResult> {
+        None
+    }
+
+    /// The squares of `targets`, the empty squares the side to move may put
+    /// a piece on, that it can drop a `piece` from its hand on.
+    fn drop_targets(&self, _game: &GameState, _piece: Piece, _targets: u64) -> u64 {
+        0
+    }
+
+    /// Whether checks are worth more than in chess, so the search follows
+    /// them further.
+    fn checks_matter(&self) -> bool {
+        false
+    }
+
+    /// Whether the endgame knowledge of chess holds, e.g. that a lone minor
+    /// piece can't mate.
+    fn endgame_knowledge(&self) -> bool {
+        true
+    }
+
+    /// Number of pieces of each type that count as attacking the king of
+    /// `side` besides those on the board.
+    fn extra_king_attackers(&self, _game: &GameState, _side: Side) -> [u32; Piece::N_PIECES] {
+        [0; Piece::N_PIECES]
+    }
+
+    /// The variant's own evaluation term of `side`, from its perspective.
+    fn eval_term(&self, _game: &GameState, _params: &EvalParams, _side: Side) -> Option<(EvalTerm, Score)> {
+        None
+    }
+}
+
+impl Variant {
+    /// The rules of the variant.
+    pub fn rules(self) -> &'static dyn Rules {
+        match self {
+            Variant::Standard => &StandardRules,
+            Variant::ThreeCheck => &ThreeCheckRules,
+            Variant::Crazyhouse => &CrazyhouseRules,
+        }
+    }
+}
+
+fn start_board(variant: Variant) -> Board {
+    let mut board = Board::default();
+    board.variant = variant;
+    board
+}
+
+pub struct StandardRules;
+
+impl Rules for StandardRules {
+    fn start_board(&self) -> Board {
+        start_board(Variant::Standard)
+    }
+}
+
+/// Giving check for the third time wins. The checks are counted in
+/// `Board::checks` and written to FEN as the checks each side has left.
+pub struct ThreeCheckRules;
+
+impl Rules for ThreeCheckRules {
+    fn start_board(&self) -> Board {
+        start_board(Variant::ThreeCheck)
+    }
+
+    fn n_fen_board_fields(&self) -> usize {
+        5
+    }
+
+    fn after_move(&self, game: &mut GameState, m: &Move) {
+        if game.in_check() {
+            game.board.checks[m.side as usize] += 1;
+        }
+    }
+
+    fn before_undo(&self, game: &mut GameState, m: &Move) {
+        // The position the move resulted in is still on the board
+        if game.in_check() {
+            game.board.checks[m.side as usize] -= 1;
+        }
+    }
+
+    fn result(&self, game: &GameState) -> Option<MoveResult> {
+        let checks = game.board.checks[game.board.side_to_move.opposite() as usize];
+        (checks >= Variant::CHECKS_TO_WIN).then_some(MoveResult::ThirdCheck)
+    }
+
+    fn checks_matter(&self) -> bool {
+        true
+    }
+
+    fn eval_term(&self, game: &GameState, _params: &EvalParams, side: Side) -> Option<(EvalTerm, Score)> {
+        let checks = (game.board.checks[side as usize] as usize).min(CHECKS_GIVEN_BONUS.len() - 1);
+        Some((EvalTerm::Checks, CHECKS_GIVEN_BONUS[checks]))
+    }
+}
+
+/// Captured pieces go to the capturer's hand, see `Board::hands`, and can be
+/// dropped on an empty square instead of moving.
+pub struct CrazyhouseRules;
+
+impl Rules for CrazyhouseRules {
+    fn start_board(&self) -> Board {
+        start_board(Variant::Crazyhouse)
+    }
+
+    fn drop_targets(&self, game: &GameState, piece: Piece, targets: u64) -> u64 {
+        if game.board.in_hand(piece, game.board.side_to_move) == 0 {
+            0
+        } else if piece == Piece::Pawn {
+            targets & !BACK_RANKS
+        } else {
+            targets
+        }
+    }
+
+    // A bare king can still have pieces in hand to mate with
+    fn endgame_knowledge(&self) -> bool {
+        false
+    }
+
+    // The pieces in the enemy's hand can be dropped next to the king
+    fn extra_king_attackers(&self, game: &GameState, side: Side) -> [u32; Piece::N_PIECES] {
+        let mut attackers = [0; Piece::N_PIECES];
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            attackers[piece as usize] = game.board.in_hand(piece, side.opposite()) as u32;
+        }
+        attackers
+    }
+
+    fn eval_term(&self, game: &GameState, params: &EvalParams, side: Side) -> Option<(EvalTerm, Score)> {
+        let score = Piece::ALL_BUT_KING.iter()
+            .map(|&piece| (params.piece_values[piece as usize] + HAND_PIECE_BONUS)*game.board.in_hand(piece, side) as i32)
+            .fold(Score::ZERO, |total, score| total + score);
+        Some((EvalTerm::Hand, score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_rules() {
+        for variant in [Variant::Standard, Variant::ThreeCheck, Variant::Crazyhouse] {
+            let game = GameState::with_variant(&PSEUDO_LEGAL_MOVES, &MAGICS, variant);
+            assert_eq!(game.board.variant, variant);
+            assert_eq!(game.variant_result(), None);
+            assert_eq!(game.rules().drop_targets(&game, Piece::Knight, !0), 0);
+        }
+
+        // Only crazyhouse drops, and not pawns on the back ranks
+        let fen = "4k3/8/8/8/8/8/8/4K3[PN] w - - 0 1";
+        let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        assert_eq!(game.rules().drop_targets(&game, Piece::Knight, !0), !0);
+        assert_eq!(game.rules().drop_targets(&game, Piece::Pawn, !0), !BACK_RANKS);
+        assert_eq!(game.rules().drop_targets(&game, Piece::Queen, !0), 0);
+        assert!(!game.rules().endgame_knowledge());
+        assert_eq!(game.rules().extra_king_attackers(&game, Side::Black)[Piece::Knight as usize], 1);
+        assert_eq!(game.rules().eval_term(&game, &EvalParams::DEFAULT, Side::Black).map(|(_, score)| score), Some(Score::ZERO));
+    }
+}
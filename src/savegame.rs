@@ -0,0 +1,294 @@
+//! Saving and loading games played in the REPL. A saved game has the
+//! position the game started from, the moves played since, the engine
+//! settings and the clocks, as PGN-style tag pairs followed by the moves in
+//! coordinate notation:
+//!
+//! ```text
+//! [FEN "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"]
+//! [CpuSide "black"]
+//! [Depth "6"]
+//! [Skill "20"]
+//! [Nodes "-"]
+//! [Ponder "false"]
+//! [TimeControl "5+3"]
+//! [WhiteTime "291200"]
+//! [BlackTime "296800"]
+//!
+//! e2e4 e7e5 g1f3
+//! ```
+//!
+//! The remaining times are in milliseconds, and left out without a time
+//! control.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::board::Side;
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+
+/// Engine settings of a game, which can be changed from the command line.
+///
+/// * `depth`: Search depth
+/// * `skill`: Skill level, see `Skill`
+/// * `nodes`: Maximum number of nodes to search, if limited
+/// * `ponder`: Whether to search on the user's time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub depth: usize,
+    pub skill: u8,
+    pub nodes: Option<u64>,
+    pub ponder: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SavedGame {
+    pub fen: String,
+    pub moves: Vec<String>,
+    pub cpu_side: Side,
+    pub settings: Settings,
+    pub clock: Option<Clock>,
+}
+
+/// File the game in progress is saved to after every move, so it can be
+/// resumed after a crash: `pawndropper/autosave.txt` in `$XDG_DATA_HOME`
+/// (`~/.local/share` by default), which only the user can write to.
+pub fn autosave_path() -> Option<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))?;
+
+    Some(autosave_path_in(&data_home))
+}
+
+/// The autosave file in the data directory `data_home`.
+fn autosave_path_in(data_home: &Path) -> PathBuf {
+    data_home.join("pawndropper").join("autosave.txt")
+}
+
+impl SavedGame {
+    /// The game so far, from the position before the first move that can
+    /// be taken back.
+    ///
+    /// * `game`: Game to save
+    /// * `cpu_side`: Side the computer plays
+    /// * `settings`: Engine settings
+    /// * `clock`: Clocks, if the game is played with a time control
+    pub fn new(game: &GameState, cpu_side: Side, settings: Settings, clock: Option<&Clock>) -> Self {
+        let mut start = game.clone();
+        while start.undo_move().is_some() {}
+
+        Self {
+            fen: start.to_fen(),
+            moves: game.played_moves().map(|m| m.to_coordinate()).collect(),
+            cpu_side,
+            settings,
+            clock: clock.cloned(),
+        }
+    }
+
+    /// Replay the saved game, so its moves can be taken back again.
+    pub fn to_game(&self, pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard) -> Result<GameState, Error> {
+        let mut game = GameState::from_fen(pl_moves, magics, &self.fen)?;
+        game.apply_moves(&self.moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+        if game.board.side_to_move == self.cpu_side {
+            return Err(Error::InvalidSavedGame("the computer is to move".to_owned()));
+        }
+
+        Ok(game)
+    }
+
+    /// Write the saved game to the file `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        fs::write(path, self.to_text()).map_err(|source| Error::Write { path: path.to_owned(), source })
+    }
+
+    /// Read a saved game from the file `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| Error::Read { path: path.to_owned(), source })?;
+        Self::parse(&text)
+    }
+
+    fn to_text(&self) -> String {
+        let cpu_side = if self.cpu_side == Side::White { "white" } else { "black" };
+        let nodes = self.settings.nodes.map_or("-".to_owned(), |nodes| nodes.to_string());
+
+        let mut tags = vec![
+            ("FEN", self.fen.clone()),
+            ("CpuSide", cpu_side.to_owned()),
+            ("Depth", self.settings.depth.to_string()),
+            ("Skill", self.settings.skill.to_string()),
+            ("Nodes", nodes),
+            ("Ponder", self.settings.ponder.to_string()),
+        ];
+        match &self.clock {
+            Some(clock) => tags.extend([
+                ("TimeControl", clock.time_control().to_string()),
+                ("WhiteTime", clock.remaining(Side::White).as_millis().to_string()),
+                ("BlackTime", clock.remaining(Side::Black).as_millis().to_string()),
+            ]),
+            None => tags.push(("TimeControl", "-".to_owned())),
+        }
+
+        let mut text = String::new();
+        for (name, value) in tags {
+            writeln!(text, "[{} \"{}\"]", name, value).unwrap();
+        }
+        writeln!(text).unwrap();
+        writeln!(text, "{}", self.moves.join(" ")).unwrap();
+
+        text
+    }
+
+    fn parse(text: &str) -> Result<Self, Error> {
+        let mut tags = Vec::new();
+        let mut moves = Vec::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(tag) = line.strip_prefix('[') {
+                let (name, value) = tag.strip_suffix(']')
+                    .and_then(|tag| tag.split_once(' '))
+                    .and_then(|(name, value)| Some((name, value.strip_prefix('"')?.strip_suffix('"')?)))
+                    .ok_or_else(|| Error::InvalidSavedGame(format!("invalid tag '{}'", line)))?;
+                tags.push((name, value));
+            } else {
+                moves.extend(line.split_whitespace().map(str::to_owned));
+            }
+        }
+
+        let tag = |name| tag_value(&tags, name);
+
+        let cpu_side = match tag("CpuSide")? {
+            "white" => Side::White,
+            "black" => Side::Black,
+            side => return Err(Error::InvalidSavedGame(format!("invalid CpuSide '{}'", side))),
+        };
+        let nodes = match tag("Nodes")? {
+            "-" => None,
+            nodes => Some(parse_tag_value("Nodes", nodes)?),
+        };
+
+        // Games saved without clocks may have no time control tag
+        let clock = match tag("TimeControl").unwrap_or("-") {
+            "-" => None,
+            time_control => {
+                let time_of = |name| -> Result<Duration, Error> {
+                    Ok(Duration::from_millis(parse_tag_value(name, tag(name)?)?))
+                };
+                Some(Clock::with_remaining(
+                    parse_tag_value("TimeControl", time_control)?,
+                    [time_of("WhiteTime")?, time_of("BlackTime")?],
+                ))
+            },
+        };
+
+        Ok(Self {
+            fen: tag("FEN")?.to_owned(),
+            moves,
+            cpu_side,
+            settings: Settings {
+                depth: parse_tag_value("Depth", tag("Depth")?)?,
+                skill: parse_tag_value("Skill", tag("Skill")?)?,
+                nodes,
+                ponder: parse_tag_value("Ponder", tag("Ponder")?)?,
+            },
+            clock,
+        })
+    }
+}
+
+fn tag_value<'a>(tags: &[(&str, &'a str)], name: &str) -> Result<&'a str, Error> {
+    tags.iter()
+        .find(|&&(tag_name, _)| tag_name == name)
+        .map(|&(_, value)| value)
+        .ok_or_else(|| Error::InvalidSavedGame(format!("missing tag '{}'", name)))
+}
+
+fn parse_tag_value<T: FromStr>(name: &str, value: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| Error::InvalidSavedGame(format!("invalid {} '{}'", name, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    const SETTINGS: Settings = Settings { depth: 6, skill: 20, nodes: None, ponder: false };
+
+    #[test]
+    fn test_save_load() {
+        let fen = "r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 3 20";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+        game.apply_moves(&["e1c1", "e8g8", "b7a8q", "g8g7"]).unwrap();
+
+        let settings = Settings { depth: 4, skill: 10, nodes: Some(5000), ponder: true };
+        let saved = SavedGame::new(&game, Side::Black, settings, None);
+        assert_eq!(saved.fen, fen);
+        assert_eq!(saved.moves, ["e1c1", "e8g8", "b7a8q", "g8g7"]);
+        assert_eq!(SavedGame::parse(&saved.to_text()).unwrap(), saved);
+
+        // The clocks are saved to the millisecond
+        let mut clock = Clock::new("5+3".parse().unwrap());
+        clock.punch(Side::White, Duration::from_millis(12345));
+        let saved = SavedGame::new(&game, Side::Black, settings, Some(&clock));
+        assert!(saved.to_text().contains("[WhiteTime \"290655\"]\n[BlackTime \"300000\"]"));
+        assert_eq!(SavedGame::parse(&saved.to_text()).unwrap(), saved);
+
+        let loaded = SavedGame::parse(&SavedGame::new(&game, Side::Black, settings, None).to_text()).unwrap();
+        let mut loaded_game = loaded.to_game(&PSEUDO_LEGAL_MOVES, &MAGICS).unwrap();
+        assert_eq!(loaded_game.to_fen(), game.to_fen());
+        assert_eq!(loaded_game.board, game.board);
+
+        // The loaded moves can be taken back
+        for _ in 0..4 {
+            loaded_game.undo_move().unwrap();
+        }
+        assert_eq!(loaded_game.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_autosave() {
+        let data_home = env::temp_dir().join(format!("pawndropper-test-autosave-{}", std::process::id()));
+        let path = autosave_path_in(&data_home);
+        assert!(path.starts_with(&data_home));
+
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        game.apply_moves(&["e2e4", "e7e5"]).unwrap();
+        let saved = SavedGame::new(&game, Side::Black, SETTINGS, None);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        saved.save(&path).unwrap();
+        assert_eq!(SavedGame::load(&path).unwrap(), saved);
+
+        fs::remove_dir_all(&data_home).unwrap();
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        let text = SavedGame::new(&game, Side::Black, SETTINGS, None).to_text();
+        assert!(SavedGame::parse(&text).is_ok());
+        assert!(SavedGame::parse(&text.replace("[TimeControl \"-\"]\n", "")).is_ok());
+        assert!(SavedGame::parse(&text.replace("\"-\"]\n\n", "\"5+3\"]\n\n")).is_err());
+
+        assert!(SavedGame::parse(&text.replace("[Depth \"6\"]\n", "")).is_err());
+        assert!(SavedGame::parse(&text.replace("\"6\"", "\"six\"")).is_err());
+        assert!(SavedGame::parse(&text.replace("\"black\"", "\"blue\"")).is_err());
+        assert!(SavedGame::parse(&text.replace("[Skill \"20\"]", "[Skill 20]")).is_err());
+
+        // Illegal moves and the computer to move
+        let saved = SavedGame::parse(&format!("{}e2e5", text)).unwrap();
+        assert!(saved.to_game(&PSEUDO_LEGAL_MOVES, &MAGICS).is_err());
+        let saved = SavedGame::parse(&format!("{}e2e4", text)).unwrap();
+        assert!(saved.to_game(&PSEUDO_LEGAL_MOVES, &MAGICS).is_err());
+    }
+}
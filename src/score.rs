@@ -0,0 +1,99 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+/// Middlegame and endgame value of an evaluation term packed into a single
+/// integer, so both are added up in one go and only blended by game phase at
+/// the end of the evaluation. The endgame value is stored in the upper 16 bits
+/// and the middlegame value in the lower 16 bits, which works out with two's
+/// complement arithmetic as long as both stay within the range of an `i16`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score(i32);
+
+/// Pack a middlegame and endgame value into a `Score`.
+#[allow(non_snake_case)]
+#[inline]
+pub const fn S(mg: i32, eg: i32) -> Score {
+    Score((eg << 16).wrapping_add(mg))
+}
+
+impl Score {
+    pub const ZERO: Self = S(0, 0);
+
+    #[inline]
+    pub const fn mg(self) -> i32 {
+        self.0 as i16 as i32
+    }
+
+    #[inline]
+    pub const fn eg(self) -> i32 {
+        // Round up to undo the borrow of a negative middlegame value
+        (self.0.wrapping_add(0x8000) >> 16) as i16 as i32
+    }
+}
+
+impl Add for Score {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl AddAssign for Score {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Score {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl SubAssign for Score {
+    #[inline]
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Score {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self {
+        Self(self.0.wrapping_neg())
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: i32) -> Self {
+        Self(self.0.wrapping_mul(rhs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packing() {
+        for (mg, eg) in [(0, 0), (35, 50), (-2, -10), (920, -890), (-30, 40), (-3940, 3830)] {
+            let score = S(mg, eg);
+            assert_eq!((score.mg(), score.eg()), (mg, eg));
+            assert_eq!(((-score).mg(), (-score).eg()), (-mg, -eg));
+            assert_eq!(((score*3).mg(), (score*3).eg()), (3*mg, 3*eg));
+        }
+
+        let sum = S(95, 110) + S(-310, 260) - S(5, -20);
+        assert_eq!((sum.mg(), sum.eg()), (95 - 310 - 5, 110 + 260 + 20));
+    }
+}
@@ -1,229 +1,1053 @@
-use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, debug_span};
 
 use crate::board::Side;
-use crate::game::GameState;
-use crate::eval::eval;
+use crate::game::{GameState, PositionInfo};
+use crate::eval::{eval_with_info, eval_with_info_and_params, EvalParams};
 use crate::r#move::{Move, MoveResult, NULL_MOVE};
-
-#[derive(PartialEq)]
-enum TransitionTableFlag {
-    Exact,
-    Beta,
-    Alpha
-}
+use crate::skill::Skill;
+use crate::trace::search_trace;
+use crate::tt::{TranspositionTable, TTFlag};
 
 pub const MAX_KILLER_MOVES: usize = 2;
 pub const MAX_GAME_PLY: usize = 1024;
+/// Depth to search to when the search is only limited by time.
+pub const MAX_SEARCH_DEPTH: usize = 64;
+/// Number of moves of a principal variation kept, longer ones are cut off.
+pub const MAX_PV_LENGTH: usize = MAX_SEARCH_DEPTH;
+
+// Scores are in centipawns. Being checkmated at ply `n` scores `-(MATE_SCORE - n)`
+// so shorter mates are preferred, everything beyond `MATE_BOUND` is a mate score
+pub const INFINITY: i32 = 32000;
+pub const MATE_SCORE: i32 = 31000;
+pub const MATE_BOUND: i32 = MATE_SCORE - MAX_GAME_PLY as i32;
+
+#[inline]
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_BOUND
+}
+
+/// Number of moves to mate of a mate score, negative when getting mated.
+pub fn moves_to_mate(score: i32) -> i32 {
+    score.signum()*(MATE_SCORE - score.abs() + 1)/2
+}
+
+/// Format a score as pawns (e.g. `+0.35`), or as the number of moves to mate
+/// (e.g. `#3` or `#-2`) for mate scores.
+pub fn format_score(score: i32) -> String {
+    if is_mate_score(score) {
+        format!("#{}", moves_to_mate(score))
+    } else {
+        format!("{:+.2}", score as f64/100.0)
+    }
+}
+
+/// Mate scores are relative to the root, but a TT entry can be reached at any
+/// ply, so store them relative to the node instead.
+#[inline]
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+#[inline]
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// State of a searcher shared with its handles: the stop signal, and the
+// progress of the running search, published every `STOP_POLL_INTERVAL` nodes
+// and after every iteration
+#[derive(Default)]
+struct SearchStatus {
+    stop: AtomicBool,
+    searching: AtomicBool,
+    nodes: AtomicU64,
+    completed_depth: AtomicUsize,
+}
+
+/// Handle to a searcher, used to interrupt a running search from another
+/// thread (e.g. by time management or Ctrl-C) and to follow its progress. An
+/// interrupted search returns the best move found so far.
+#[derive(Clone)]
+pub struct SearchHandle {
+    status: Arc<SearchStatus>,
+}
+
+impl SearchHandle {
+    pub fn stop(&self) {
+        self.status.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Clear a stop request. The search doesn't reset the signal itself, so
+    /// this needs to be called before searching again.
+    pub fn clear(&self) {
+        self.status.stop.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether the searcher is searching.
+    pub fn is_searching(&self) -> bool {
+        self.status.searching.load(Ordering::Relaxed)
+    }
+
+    /// Number of nodes visited in the running or last search, lagging behind
+    /// by up to a thousand nodes while searching.
+    pub fn nodes(&self) -> u64 {
+        self.status.nodes.load(Ordering::Relaxed)
+    }
+
+    /// Depth of the last completed iteration of the running or last search.
+    pub fn completed_depth(&self) -> usize {
+        self.status.completed_depth.load(Ordering::Relaxed)
+    }
+}
+
+/// Depth, evaluation from white's perspective and principal variation of a
+/// completed iteration.
+pub type Iteration = (usize, i32, Vec<Move>);
+
+/// Tunable search parameters, gathered as data so the SPSA tuner (see
+/// `src/spsa.rs`) can play games with modified copies. The engine itself
+/// searches with `SearchParams::DEFAULT`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchParams {
+    /// Plies the null move search and its verification are reduced by
+    pub null_move_reduction: usize,
+    /// Plies the internal iterative deepening search is reduced by
+    pub iid_reduction: usize,
+    /// Razoring margins indexed by remaining depth
+    pub razoring_margins: [i32; SearchAsync::RAZORING_MAX_DEPTH + 1],
+}
+
+impl SearchParams {
+    pub const DEFAULT: Self = Self {
+        null_move_reduction: 2,
+        iid_reduction: 2,
+        razoring_margins: [0, 300, 500],
+    };
+
+    /// Names of the weights, in the order of `weights`.
+    pub const NAMES: [&'static str; 4] = ["null_move_reduction", "iid_reduction", "razoring_margin_1", "razoring_margin_2"];
+
+    /// Values of the parameters in a fixed order. The razoring margin of
+    /// depth 0 is left out, as the horizon is never razored.
+    pub fn weights(&self) -> Vec<i32> {
+        vec![
+            self.null_move_reduction as i32,
+            self.iid_reduction as i32,
+            self.razoring_margins[1],
+            self.razoring_margins[2],
+        ]
+    }
+
+    /// Set the parameters from values in the order of `weights`, clamped to
+    /// what the search can handle: the reductions to at least a ply and at
+    /// most the depth they're applied from.
+    pub fn set_weights(&mut self, weights: &[i32]) {
+        self.null_move_reduction = weights[0].clamp(1, SearchAsync::NULL_MOVE_MIN_DEPTH as i32) as usize;
+        self.iid_reduction = weights[1].clamp(1, SearchAsync::IID_MIN_DEPTH as i32) as usize;
+        self.razoring_margins[1] = weights[2].max(0);
+        self.razoring_margins[2] = weights[3].max(0);
+    }
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
 
 pub struct SearchAsync {
-    tt: HashMap<u128, (f64, usize, TransitionTableFlag)>,
+    tt: TranspositionTable,
     killer_list: [[Move; MAX_GAME_PLY]; MAX_KILLER_MOVES],
     best_move: Move,
-    pv_list: Vec<Move>
+
+    // Accumulated fractional extensions and check status of the nodes on the
+    // current search path, indexed by ply
+    extension_stack: [usize; MAX_GAME_PLY],
+    in_check_stack: [bool; MAX_GAME_PLY],
+    max_extension: usize,
+
+    // Zobrist hashes of the positions on the current search path, indexed by
+    // ply
+    hash_stack: [u64; MAX_GAME_PLY],
+
+    // Principal variation of each node on the current search path, indexed by
+    // ply, built from the child's on every alpha raise without allocating
+    pv_table: Vec<[Move; MAX_PV_LENGTH]>,
+    pv_length: [usize; MAX_GAME_PLY],
+
+    // Legal moves of each node on the current search path, indexed by ply.
+    // Taken out while the node is searched and put back after, so the moves
+    // are generated into buffers that already have the capacity
+    move_buffers: Vec<Vec<Move>>,
+
+    // Attacks and pins of the position at each ply of the current search
+    // path, computed when its moves are generated and reused to evaluate it
+    position_infos: Vec<PositionInfo>,
+
+    // Nodes at which no null move is tried: right after a null move, and in
+    // null move verification searches
+    null_move_disabled: [bool; MAX_GAME_PLY],
+
+    // Nodes spent on each root move in the last iteration, used to order the
+    // root moves of the next one
+    root_move_nodes: Vec<(Move, u64)>,
+
+    // Stop signal set from another thread to abort a running search, polled
+    // every `STOP_POLL_INTERVAL` nodes, and the published progress
+    status: Arc<SearchStatus>,
+    aborted: bool,
+    best_eval: i32,
+
+    // Number of nodes visited in the last search, and the optional limit after
+    // which the search is stopped
+    nodes: u64,
+    completed_depth: usize,
+    node_limit: Option<u64>,
+
+    // Optional time the search may take, and when the current one started
+    time_limit: Option<Duration>,
+    search_start: Instant,
+
+    skill: Skill,
+
+    // Parameters of the search, and optional evaluation weights to evaluate
+    // with instead of `EvalParams::DEFAULT`
+    params: SearchParams,
+    eval_params: Option<EvalParams>,
+
+    // Centipawns the side to move at the root counts a draw as lost by, and
+    // that side
+    contempt: i32,
+    root_side: Side,
+
+    // Optional receiver of the result of each completed iteration
+    iteration_sender: Option<Sender<Iteration>>,
+}
+
+impl Default for SearchAsync {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SearchAsync {
-    const TRANSITION_TABLE_CAPACITY: usize = 1_000_000;
+    const TRANSPOSITION_TABLE_SIZE_MB: usize = 64;
+
+    // Must be a power of two
+    const STOP_POLL_INTERVAL: u64 = 1024;
+
+    // Width of the null window used to test moves in the principal variation
+    // search
+    const NULL_WINDOW: i32 = 1;
+
+    // Extensions are counted in fractions of a ply
+    const ONE_PLY: usize = 4;
+    const CHECK_EXTENSION: usize = Self::ONE_PLY;
+    const SINGLE_REPLY_EXTENSION: usize = Self::ONE_PLY / 2;
+
+    const IID_MIN_DEPTH: usize = 4;
+
+    // Number of quiescence plies in which quiet checking moves are searched,
+    // more where checks matter, e.g. three-check where any check can decide
+    // the game
+    const QSEARCH_CHECK_PLIES: usize = 1;
+    const CHECKS_MATTER_QSEARCH_CHECK_PLIES: usize = 3;
+
+    const NULL_MOVE_MIN_DEPTH: usize = 3;
+    // From this depth on a null move cutoff is verified with a reduced search
+    // of the node itself, so zugzwang positions aren't pruned blindly
+    const NULL_MOVE_VERIFICATION_MIN_DEPTH: usize = 5;
+
+    const RAZORING_MAX_DEPTH: usize = 2;
 
     pub fn new() -> Self {
+        Self::with_hash_size(Self::TRANSPOSITION_TABLE_SIZE_MB)
+    }
+
+    /// A searcher with a transposition table of `size_mb` megabytes, rounded
+    /// down to a power of two.
+    pub fn with_hash_size(size_mb: usize) -> Self {
         Self {
-            tt: HashMap::with_capacity(Self::TRANSITION_TABLE_CAPACITY),
+            tt: TranspositionTable::new(size_mb),
             killer_list: [[NULL_MOVE; MAX_GAME_PLY]; MAX_KILLER_MOVES],
             best_move: NULL_MOVE,
-            pv_list: vec![]
+
+            extension_stack: [0; MAX_GAME_PLY],
+            in_check_stack: [false; MAX_GAME_PLY],
+            max_extension: 0,
+
+            hash_stack: [0; MAX_GAME_PLY],
+            pv_table: vec![[NULL_MOVE; MAX_PV_LENGTH]; MAX_GAME_PLY],
+            pv_length: [0; MAX_GAME_PLY],
+            move_buffers: vec![Vec::new(); MAX_GAME_PLY],
+            position_infos: vec![PositionInfo::default(); MAX_GAME_PLY],
+            null_move_disabled: [false; MAX_GAME_PLY],
+            root_move_nodes: vec![],
+
+            status: Arc::new(SearchStatus::default()),
+            aborted: false,
+            best_eval: -INFINITY,
+
+            nodes: 0,
+            completed_depth: 0,
+            node_limit: None,
+
+            time_limit: None,
+            search_start: Instant::now(),
+
+            skill: Skill::default(),
+            params: SearchParams::DEFAULT,
+            eval_params: None,
+            contempt: 0,
+            root_side: Side::White,
+            iteration_sender: None,
+        }
+    }
+
+    pub fn handle(&self) -> SearchHandle {
+        SearchHandle {
+            status: Arc::clone(&self.status),
         }
     }
 
+    /// Per mille of the transposition table in use, see
+    /// `TranspositionTable::hashfull`.
+    pub fn hashfull(&self) -> usize {
+        self.tt.hashfull()
+    }
+
+    /// Forget what earlier searches learned, the transposition table and the
+    /// killer moves, so an unrelated position is searched from scratch.
+    pub fn clear_hash(&mut self) {
+        self.tt.clear();
+        self.killer_list = [[NULL_MOVE; MAX_GAME_PLY]; MAX_KILLER_MOVES];
+    }
+
+    pub fn set_node_limit(&mut self, node_limit: Option<u64>) {
+        self.node_limit = node_limit;
+    }
+
+    /// Stop searching after `time_limit`, and don't start another iteration
+    /// after half of it.
+    pub fn set_time_limit(&mut self, time_limit: Option<Duration>) {
+        self.time_limit = time_limit;
+    }
+
+    pub fn set_skill(&mut self, skill: Skill) {
+        self.skill = skill;
+    }
+
+    pub fn set_params(&mut self, params: SearchParams) {
+        self.params = params;
+    }
+
+    /// Evaluate with the weights of `eval_params` instead of the engine's,
+    /// computing material and piece placement from scratch in every node.
+    pub fn set_eval_params(&mut self, eval_params: Option<EvalParams>) {
+        self.eval_params = eval_params;
+    }
+
+    /// Score draws as `contempt` centipawns worse than even for the side to
+    /// move at the root, so it avoids them with a positive contempt and
+    /// steers into them with a negative one.
+    pub fn set_contempt(&mut self, contempt: i32) {
+        self.contempt = contempt;
+    }
+
+    /// Send the result of each completed iteration to `iteration_sender`, e.g.
+    /// to show the search progress.
+    pub fn set_iteration_sender(&mut self, iteration_sender: Option<Sender<Iteration>>) {
+        self.iteration_sender = iteration_sender;
+    }
+
+    /// Number of nodes (including quiescence nodes) visited in the last search.
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Depth of the last completed iteration of the last search.
+    pub fn completed_depth(&self) -> usize {
+        self.completed_depth
+    }
+
+    /// Count a visited node and poll the stop signal every so often, checking
+    /// the atomic at every node is needlessly expensive.
+    #[inline]
+    fn visit_node(&mut self) {
+        self.nodes += 1;
+        if self.nodes & (Self::STOP_POLL_INTERVAL - 1) == 0 {
+            let out_of_time = self.time_limit.is_some_and(|time_limit| self.search_start.elapsed() >= time_limit);
+            self.status.nodes.store(self.nodes, Ordering::Relaxed);
+            if out_of_time || self.status.stop.load(Ordering::Relaxed) {
+                self.aborted = true;
+            }
+        }
+
+        let node_limit = match (self.node_limit, self.skill.node_limit()) {
+            (Some(node_limit), Some(skill_node_limit)) => Some(node_limit.min(skill_node_limit)),
+            (node_limit, skill_node_limit) => node_limit.or(skill_node_limit),
+        };
+        if let Some(node_limit) = node_limit {
+            if self.nodes >= node_limit {
+                self.aborted = true;
+            }
+        }
+    }
+
+    #[inline]
+    fn stopped(&self) -> bool {
+        self.aborted
+    }
+
+    fn set_completed_depth(&mut self, depth: usize) {
+        self.completed_depth = depth;
+        self.status.completed_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Search the position with iterative deepening up to `depth` plies and
+    /// return the evaluation from white's perspective, the best move and the
+    /// principal variation. If the search is stopped, the result of the last
+    /// completed iteration is returned, or the best move of the unfinished
+    /// iteration if it already found one.
+    ///
+    /// * `game`: Position to search
+    /// * `depth`: Depth to search to
+    /// * `search_moves`: Only consider these root moves, or all legal moves if empty
     pub fn find_best_legal_move(
         &mut self,
         game: &mut GameState,
         depth: usize,
-    ) -> (f64, Move, Vec<Move>) {
-        let (mut legal_moves_opposite, in_check) = game.get_legal_moves();
-        self.order_moves(&mut legal_moves_opposite, 1);
+        search_moves: &[Move],
+    ) -> (i32, Move, Vec<Move>) {
+        self.status.searching.store(true, Ordering::Relaxed);
+        let result = self.search_root(game, depth, search_moves);
+        self.status.nodes.store(self.nodes, Ordering::Relaxed);
+        self.status.searching.store(false, Ordering::Relaxed);
+
+        result
+    }
+
+    fn search_root(&mut self, game: &mut GameState, depth: usize, search_moves: &[Move]) -> (i32, Move, Vec<Move>) {
+        let depth = depth.min(self.skill.max_depth());
+        let (mut root_moves, in_check) = game.get_legal_moves();
+        if !search_moves.is_empty() {
+            root_moves.retain(|m| search_moves.contains(m));
+        }
+        self.order_moves(&mut root_moves, 1);
+
+        // Fall back to the first ordered move in case the search is stopped
+        // before any move is fully searched
+        self.best_move = root_moves.first().copied().unwrap_or(NULL_MOVE);
+        self.root_side = game.board.side_to_move;
+        self.aborted = false;
+        self.nodes = 0;
+        self.set_completed_depth(0);
+        self.search_start = Instant::now();
+        self.tt.new_search();
+        self.root_move_nodes = root_moves.iter().map(|m| (*m, 0)).collect();
+
+        self.extension_stack[0] = 0;
+        self.in_check_stack[0] = false;
+        self.position_infos[1] = game.position_info();
 
-        let alpha = f64::MIN;
-        let beta = f64::MAX;
         let mult = if game.board.side_to_move == Side::White {
-            1.0
+            1
         } else {
-            -1.0
+            -1
         };
 
-        let mut root_pv: Vec<Move> = vec![];
+        // At a limited skill level, every root move gets an exact score to pick
+        // a move from
+        if self.skill.is_limited() {
+            self.max_extension = (depth*Self::ONE_PLY/2).max(Self::ONE_PLY);
 
-        let eval = self.negamax(
-            game,
-            &legal_moves_opposite,
-            depth,
-            1,
-            in_check,
-            alpha,
-            beta,
-            &mut root_pv
-        );
-        (mult*eval, self.best_move, root_pv)
+            let scored_moves = self.score_root_moves(game, &root_moves, in_check, depth);
+            if !self.stopped() {
+                self.set_completed_depth(depth);
+            }
+            return match self.skill.pick_move(&scored_moves) {
+                Some((skill_move, eval)) => {
+                    self.best_move = skill_move;
+                    (mult*eval, skill_move, vec![skill_move])
+                },
+                None => (0, self.best_move, vec![]),
+            };
+        }
+
+        let mut eval = 0;
+        let mut best_move = self.best_move;
+        let mut pv: Vec<Move> = vec![];
+
+        for iteration_depth in 1..=depth {
+            let _span = debug_span!("iteration", depth = iteration_depth).entered();
+
+            // Extensions along a single path may at most add half the nominal depth
+            self.max_extension = (iteration_depth*Self::ONE_PLY/2).max(Self::ONE_PLY);
+            self.best_eval = -INFINITY;
+
+            let iteration_eval = self.negamax(
+                game,
+                &root_moves,
+                iteration_depth,
+                1,
+                in_check,
+                -INFINITY,
+                INFINITY
+            );
+            let root_pv = self.pv(1).to_vec();
+
+            if self.aborted {
+                // The previous best move is searched first, so a different
+                // best move of the unfinished iteration has proven better
+                if self.best_eval != -INFINITY {
+                    eval = self.best_eval;
+                    if self.best_move != best_move {
+                        best_move = self.best_move;
+                        pv = if root_pv.first() == Some(&best_move) {
+                            root_pv
+                        } else {
+                            vec![best_move]
+                        };
+                    }
+                }
+                break;
+            }
+
+            eval = iteration_eval;
+            best_move = self.best_move;
+            pv = root_pv;
+            self.set_completed_depth(iteration_depth);
+            debug!(eval = mult*eval, nodes = self.nodes, best_move = %best_move.to_coordinate(), "Iteration completed");
+
+            if let Some(sender) = &self.iteration_sender {
+                // The principal variation of the first iteration is empty.
+                // Nobody may be listening anymore
+                let iteration_pv = if pv.is_empty() { vec![best_move] } else { pv.clone() };
+                let _ = sender.send((iteration_depth, mult*eval, iteration_pv));
+            }
+
+            self.order_root_moves(&mut root_moves, best_move);
+
+            // The next iteration would most likely not finish in time
+            if self.time_limit.is_some_and(|time_limit| self.search_start.elapsed() >= time_limit/2) {
+                break;
+            }
+        }
+
+        self.best_move = best_move;
+        (mult*eval, best_move, pv)
+    }
+
+    /// Search every legal move to `depth` plies with a full window and return
+    /// the moves with their exact scores from the side to move's perspective.
+    /// Moves not fully searched before the search is stopped are left out.
+    pub fn score_legal_moves(&mut self, game: &mut GameState, depth: usize) -> Vec<(Move, i32)> {
+        let (mut root_moves, in_check) = game.get_legal_moves();
+        self.order_moves(&mut root_moves, 1);
+
+        self.aborted = false;
+        self.nodes = 0;
+        self.search_start = Instant::now();
+        self.tt.new_search();
+
+        self.extension_stack[0] = 0;
+        self.in_check_stack[0] = false;
+        self.max_extension = (depth*Self::ONE_PLY/2).max(Self::ONE_PLY);
+
+        self.status.searching.store(true, Ordering::Relaxed);
+        let scored_moves = self.score_root_moves(game, &root_moves, in_check, depth);
+        self.status.nodes.store(self.nodes, Ordering::Relaxed);
+        self.status.searching.store(false, Ordering::Relaxed);
+
+        scored_moves
     }
 
+    /// Search every root move with a full window to get its exact score, for
+    /// picking a move at a limited skill level. Moves not fully searched before
+    /// the search is stopped are left out.
+    fn score_root_moves(
+        &mut self,
+        game: &mut GameState,
+        root_moves: &[Move],
+        in_check: bool,
+        depth: usize,
+    ) -> Vec<(Move, i32)> {
+        self.hash_stack[1] = game.pos_hash;
+        self.extension_stack[1] = 0;
+        self.in_check_stack[1] = in_check;
+
+        let pos_hash = game.pos_hash;
+        let castling_right_long = game.board.castling_right_long;
+        let castling_right_short = game.board.castling_right_short;
+        let en_passant_square = game.board.en_passant_square;
+        let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
+
+        let mut scored_moves = Vec::with_capacity(root_moves.len());
+        for m in root_moves {
+            game.update_board_with_move(m);
+
+            let mut legal_moves_opposite = mem::take(&mut self.move_buffers[2]);
+            self.position_infos[2] = game.position_info();
+            let in_check = game.generate_legal_moves(&self.position_infos[2], &mut legal_moves_opposite);
+            self.order_moves(&mut legal_moves_opposite, 1);
+
+            let eval = -self.negamax(
+                game,
+                &legal_moves_opposite,
+                depth,
+                2,
+                in_check,
+                -INFINITY,
+                INFINITY
+            );
+
+            game.update_board_undo_move(
+                m,
+                pos_hash,
+                &castling_right_long,
+                &castling_right_short,
+                en_passant_square,
+                half_move_of_last_irreversible,
+            );
+            self.move_buffers[2] = legal_moves_opposite;
+
+            if self.stopped() {
+                break;
+            }
+
+            scored_moves.push((*m, eval));
+        }
+
+        scored_moves
+    }
+
+    /// Order the root moves for the next iteration: the best move of the last
+    /// iteration first, then the others by the number of nodes their subtrees
+    /// took, a move that needed a large tree to refute is likely a good one.
+    fn order_root_moves(&self, root_moves: &mut [Move], best_move: Move) {
+        let nodes_of = |m: &Move| {
+            self.root_move_nodes
+                .iter()
+                .find(|(root_move, _)| root_move == m)
+                .map_or(0, |(_, nodes)| *nodes)
+        };
+
+        root_moves.sort_by_key(|m| std::cmp::Reverse((*m == best_move, nodes_of(m))));
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn negamax(
         &mut self,
         game: &mut GameState,
-        legal_moves: &Vec<Move>,
+        legal_moves: &[Move],
         mut max_depth: usize,
         ply: usize,
         in_check: bool,
-        mut alpha: f64,
-        beta: f64,
-        pv: &mut Vec<Move>
-    ) -> f64 {
-        if in_check || legal_moves.len() == 1 {
-            max_depth += 1;
+        mut alpha: i32,
+        beta: i32
+    ) -> i32 {
+        self.clear_pv(ply);
+        self.visit_node();
+        if self.stopped() {
+            return 0;
+        }
+
+        search_trace!(self.nodes, ply, "node depth {} [{}, {}]", max_depth.saturating_sub(ply), alpha, beta);
+
+        // A position repeated on the search path can be repeated again by
+        // either side, score it as a draw
+        self.hash_stack[ply] = game.pos_hash;
+        if self.is_path_repetition(ply) {
+            search_trace!(self.nodes, ply, "repetition");
+            return self.draw_score(game);
+        }
+
+        // Losing by the variant's rules, e.g. the third check of three-check,
+        // counts as being checkmated
+        if game.variant_result().is_some() {
+            search_trace!(self.nodes, ply, "variant result");
+            return -(MATE_SCORE - ply as i32);
+        }
+
+        // Fifty move rule, unless the last move was checkmate
+        if game.half_move_clock() >= GameState::FIFTY_MOVE_RULE_HALF_MOVES
+            && !(in_check && legal_moves.is_empty()) {
+            search_trace!(self.nodes, ply, "fifty move rule");
+            return self.draw_score(game);
+        }
+
+        let extension = self.extend(ply, in_check, legal_moves.len(), game.rules().checks_matter());
+        if extension > 0 {
+            search_trace!(self.nodes, ply, "extended by {}", extension);
         }
+        max_depth += extension;
 
         if ply >= max_depth {
             return self.qsearch(
                 game,
                 legal_moves,
                 max_depth + 15,
+                Self::qsearch_check_plies(game),
                 ply,
                 in_check,
                 alpha,
-                beta,
-                pv
+                beta
             );
         }
 
-        if legal_moves.len() == 0 {
-            let mult = if game.board.side_to_move == Side::White {
-                1.0
-            } else {
-                -1.0
-            };
+        if legal_moves.is_empty() {
             let move_result = game.get_move_result(legal_moves, in_check);
 
             match move_result {
-                Some(MoveResult::Checkmate) => {
-                    return mult*(2000.0 + ply as f64)
-                }
-                Some(MoveResult::Draw(_)) => return 0.0,
+                Some(MoveResult::Checkmate | MoveResult::ThirdCheck) => return -(MATE_SCORE - ply as i32),
+                Some(MoveResult::Draw(_)) => return self.draw_score(game),
                 _ => (),
             }
         }
 
-        let tt_entry = self.tt.get(&game.pos_hash);
-        if let Some(eval) = tt_entry  {
-            let tt_eval = eval.0;
-            let tt_depth = eval.1;
-            let tt_flag = &eval.2;
+        let depth = max_depth - ply;
 
-            let use_tt_entry = tt_depth > ply &&
-                (*tt_flag == TransitionTableFlag::Exact
-                 || (*tt_flag == TransitionTableFlag::Beta && tt_eval >= beta)
-                 || (*tt_flag == TransitionTableFlag::Alpha && tt_eval <= alpha));
+        // Use the stored result of a search at least as deep as this one. Not at
+        // the root, where we need to know the best move
+        let mut hash_move = None;
+        let tt_entry = self.tt.probe(game.pos_hash);
+        if let Some(tt_entry) = tt_entry {
+            // The stored best move only has its squares and promotion piece
+            hash_move = legal_moves.iter().find(|m| tt_entry.best_move.matches(m)).copied();
+
+            let tt_eval = score_from_tt(tt_entry.eval as i32, ply);
+            let tt_flag = tt_entry.flag();
+            let use_tt_entry = ply > 1 && tt_entry.depth as usize >= depth &&
+                (tt_flag == TTFlag::Exact
+                 || (tt_flag == TTFlag::Beta && tt_eval >= beta)
+                 || (tt_flag == TTFlag::Alpha && tt_eval <= alpha));
 
             if use_tt_entry {
+                search_trace!(self.nodes, ply, "tt cutoff {} ({:?}, depth {})", tt_eval, tt_flag, tt_entry.depth);
                 return tt_eval;
             }
         }
 
+        // Razoring: close to the horizon, if the static eval is so far below
+        // alpha that even a margin can't bring it back up, verify with a
+        // quiescence search and prune the node if it confirms the position is
+        // hopeless
+        if !in_check && ply > 1 && depth <= Self::RAZORING_MAX_DEPTH {
+            let mult = if game.board.side_to_move == Side::White {
+                1
+            } else {
+                -1
+            };
+            let static_eval = mult*self.static_eval(game, ply);
+
+            if static_eval + self.params.razoring_margins[depth] < alpha {
+                let razor_eval = self.qsearch(
+                    game,
+                    legal_moves,
+                    max_depth + 15,
+                    Self::qsearch_check_plies(game),
+                    ply,
+                    in_check,
+                    alpha,
+                    beta
+                );
+
+                if self.stopped() {
+                    return 0;
+                }
+
+                if razor_eval < alpha {
+                    search_trace!(self.nodes, ply, "razored {}", razor_eval);
+                    return razor_eval;
+                }
+            }
+        }
+
+        let is_pv_node = beta - alpha > Self::NULL_WINDOW;
+
+        // Null move pruning: if passing the turn still fails high on a reduced
+        // search, a real move will almost certainly fail high too. Not without
+        // pieces, where being forced to move (zugzwang) is common
+        if !in_check && !is_pv_node && ply > 1 && !self.null_move_disabled[ply]
+            && depth >= Self::NULL_MOVE_MIN_DEPTH
+            && game.board.has_non_pawn_material(game.board.side_to_move) {
+            let mult = if game.board.side_to_move == Side::White {
+                1
+            } else {
+                -1
+            };
+
+            if mult*self.static_eval(game, ply) >= beta {
+                let pos_hash = game.pos_hash;
+                let en_passant_square = game.make_null_move();
+
+                let mut legal_moves_opposite = mem::take(&mut self.move_buffers[ply + 1]);
+                self.position_infos[ply + 1] = game.position_info();
+                game.generate_legal_moves(&self.position_infos[ply + 1], &mut legal_moves_opposite);
+                self.order_moves(&mut legal_moves_opposite, ply + 1);
+
+                self.null_move_disabled[ply + 1] = true;
+                let null_eval = -self.negamax(
+                    game,
+                    &legal_moves_opposite,
+                    max_depth - self.params.null_move_reduction,
+                    ply + 1,
+                    false,
+                    -beta,
+                    -beta + Self::NULL_WINDOW
+                );
+                self.null_move_disabled[ply + 1] = false;
+
+                game.undo_null_move(pos_hash, en_passant_square);
+                self.move_buffers[ply + 1] = legal_moves_opposite;
+
+                if self.stopped() {
+                    return 0;
+                }
+
+                if null_eval >= beta {
+                    // Verify the cutoff at high depths with a reduced search
+                    // of this node without null move, from the unextended
+                    // depth as the search extends the node again
+                    let verified = depth < Self::NULL_MOVE_VERIFICATION_MIN_DEPTH || {
+                        self.null_move_disabled[ply] = true;
+                        let verification_eval = self.negamax(
+                            game,
+                            legal_moves,
+                            (max_depth - extension).saturating_sub(self.params.null_move_reduction),
+                            ply,
+                            in_check,
+                            beta - Self::NULL_WINDOW,
+                            beta
+                        );
+                        self.null_move_disabled[ply] = false;
+
+                        if self.stopped() {
+                            return 0;
+                        }
+
+                        verification_eval >= beta
+                    };
+
+                    if verified {
+                        search_trace!(self.nodes, ply, "null move cutoff {}", null_eval);
+                        return beta;
+                    }
+                }
+            }
+        }
+
+        // Internal iterative deepening: at PV nodes with no move known to be
+        // good from the TT, run a reduced depth search to find one. Not at the
+        // root, which the previous iteration ordered, and where the reduced
+        // search would leave its best move as the result of a stopped search.
+        // The reduced search extends the node again, so it starts from the
+        // unextended depth
+        if hash_move.is_none() && is_pv_node && !in_check && ply > 1
+            && depth >= Self::IID_MIN_DEPTH && legal_moves.len() > 1 {
+            let iid_max_depth = (max_depth - extension).saturating_sub(self.params.iid_reduction);
+            search_trace!(self.nodes, ply, "iid depth {}", iid_max_depth.saturating_sub(ply));
+            self.negamax(
+                game,
+                legal_moves,
+                iid_max_depth,
+                ply,
+                in_check,
+                alpha,
+                beta
+            );
+
+            if self.stopped() {
+                return 0;
+            }
+
+            hash_move = self.pv(ply).first().copied();
+        }
+
+        // Try the hash move first, before captures and killers, then the
+        // others in their order
+        let hash_move_idx = hash_move.and_then(|hash_move| legal_moves.iter().position(|m| *m == hash_move));
+        let ordered_moves = hash_move_idx.map(|idx| &legal_moves[idx])
+            .into_iter()
+            .chain(legal_moves.iter().enumerate().filter(|&(idx, _)| Some(idx) != hash_move_idx).map(|(_, m)| m));
+
+        // The reduced searches of this node above left their variations behind
+        self.clear_pv(ply);
+
         // Needed for undoing moves
         let pos_hash = game.pos_hash;
         let castling_right_long = game.board.castling_right_long;
         let castling_right_short = game.board.castling_right_short;
-        let half_move_of_last_capture = game.half_move_of_last_capture;
+        let en_passant_square = game.board.en_passant_square;
+        let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
 
-        let mut best_eval: f64;
+        let old_alpha = alpha;
+        let mut best_eval = -INFINITY;
+        let mut best_move = NULL_MOVE;
 
-        best_eval = f64::MIN;
+        for (i, m) in ordered_moves.enumerate() {
+            let nodes_before = self.nodes;
 
-        for m in legal_moves {
-            let mut node_pv: Vec<Move> = vec![];
+            search_trace!(self.nodes, ply, "{}", m.to_algebraic_with_state(&[]));
 
             game.update_board_with_move(m);
 
-            let (mut legal_moves_opposite, in_check) = game.get_legal_moves();
+            let mut legal_moves_opposite = mem::take(&mut self.move_buffers[ply + 1]);
+            self.position_infos[ply + 1] = game.position_info();
+            let in_check = game.generate_legal_moves(&self.position_infos[ply + 1], &mut legal_moves_opposite);
             self.order_moves(&mut legal_moves_opposite, ply);
 
-            let eval = -self.negamax(
-                game,
-                &legal_moves_opposite,
-                max_depth,
-                ply + 1,
-                in_check,
-                -beta,
-                -alpha,
-                &mut node_pv
-            );
+            // Principal variation search: the first (best ordered) move is
+            // searched with the full window, the remaining moves with a null
+            // window to prove they're worse. If that fails high, the move
+            // might be better after all and is re-searched with the full window
+            let eval = if i == 0 {
+                -self.negamax(
+                    game,
+                    &legal_moves_opposite,
+                    max_depth,
+                    ply + 1,
+                    in_check,
+                    -beta,
+                    -alpha
+                )
+            } else {
+                let mut eval = -self.negamax(
+                    game,
+                    &legal_moves_opposite,
+                    max_depth,
+                    ply + 1,
+                    in_check,
+                    -alpha - Self::NULL_WINDOW,
+                    -alpha
+                );
+
+                if eval > alpha && eval < beta {
+                    eval = -self.negamax(
+                        game,
+                        &legal_moves_opposite,
+                        max_depth,
+                        ply + 1,
+                        in_check,
+                        -beta,
+                        -alpha
+                    );
+                }
+
+                eval
+            };
 
             game.update_board_undo_move(
                 m,
                 pos_hash,
                 &castling_right_long,
                 &castling_right_short,
-                half_move_of_last_capture,
+                en_passant_square,
+                half_move_of_last_irreversible,
             );
+            self.move_buffers[ply + 1] = legal_moves_opposite;
+
+            if self.stopped() {
+                // Aborted, the result of this move is incomplete and can't be used
+                return 0;
+            }
+
+            if ply == 1 {
+                if let Some(root_move) = self.root_move_nodes.iter_mut().find(|(root_move, _)| root_move == m) {
+                    root_move.1 = self.nodes - nodes_before;
+                }
+            }
 
             if eval > best_eval {
                 best_eval = eval;
+                best_move = *m;
                 if ply == 1 {
                     self.best_move = *m;
+                    self.best_eval = eval;
                 }
             }
 
             if eval >= beta {
+                search_trace!(self.nodes, ply, "beta cutoff {} by {}", eval, m.to_algebraic_with_state(&[]));
                 self.store_killer(m, ply);
+                self.tt.store(game.pos_hash, score_to_tt(beta, ply), depth, TTFlag::Beta, *m);
                 return beta;
             }
             
             if eval > alpha {
                 alpha = eval;
 
-                pv.clear();
-                pv.push(*m);
-                pv.append(&mut node_pv);
+                self.update_pv(ply, *m);
             }
         }
 
-        let flag = if best_eval >= beta {
-            TransitionTableFlag::Beta
-        } else if best_eval > alpha {
-            TransitionTableFlag::Exact
+        let flag = if alpha > old_alpha {
+            TTFlag::Exact
         } else {
-            TransitionTableFlag::Alpha
+            TTFlag::Alpha
         };
-        self.tt.insert(game.pos_hash, (alpha, ply, flag));
+        self.tt.store(game.pos_hash, score_to_tt(alpha, ply), depth, flag, best_move);
 
         alpha
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn qsearch(
         &mut self,
         game: &mut GameState,
-        legal_moves: &Vec<Move>,
+        legal_moves: &[Move],
         max_depth: usize,
+        check_plies: usize,
         ply: usize,
         in_check: bool,
-        mut alpha: f64,
-        beta: f64,
-        pv: &mut Vec<Move>
-    ) -> f64 {
+        mut alpha: i32,
+        beta: i32
+    ) -> i32 {
+        self.clear_pv(ply);
+
         // Continue searching until the position is quiet, i.e. positions where
         // there are no winning tactical moves to be made.
         // This avoids the horizon effect
+        self.visit_node();
+        if self.stopped() {
+            return 0;
+        }
+
         let mult = if game.board.side_to_move == Side::White {
-            1.0
+            1
         } else {
-            -1.0
+            -1
         };
-        let stand_pat = mult*eval(game);
+        let stand_pat = mult*self.static_eval(game, ply);
 
-        let move_result = game.get_move_result(legal_moves, in_check);
+        // Below the first qsearch ply only tactical moves are generated when
+        // not in check, so look for any legal move to tell stalemates apart
+        let has_legal_moves = !legal_moves.is_empty() || (!in_check && game.has_legal_moves());
+        let move_result = game.move_result(has_legal_moves, in_check);
 
         match move_result {
-            Some(MoveResult::Checkmate) => {
-                return mult*(2000.0 + ply as f64)
-            }
-            Some(MoveResult::Draw(_)) => return 0.0,
+            Some(MoveResult::Checkmate | MoveResult::ThirdCheck) => return -(MATE_SCORE - ply as i32),
+            Some(MoveResult::Draw(_)) => return self.draw_score(game),
+            // Scored as a draw, as the side worse off would claim it
+            _ if game.claimable_draw().is_some() => return self.draw_score(game),
             _ => (),
         }
 
@@ -231,6 +1055,8 @@ impl SearchAsync {
             return stand_pat;
         }
 
+        search_trace!(self.nodes, ply, "qsearch stand pat {} [{}, {}]", stand_pat, alpha, beta);
+
         if stand_pat >= beta {
             return beta;
         }
@@ -244,31 +1070,41 @@ impl SearchAsync {
         let pos_hash = game.pos_hash;
         let castling_right_long = game.board.castling_right_long;
         let castling_right_short = game.board.castling_right_short;
-        let half_move_of_last_capture = game.half_move_of_last_capture;
+        let en_passant_square = game.board.en_passant_square;
+        let half_move_of_last_irreversible = game.half_move_of_last_irreversible;
 
         for m in legal_moves {
-            if !in_check && !(m.is_capture() || m.is_promotion()) {
-                // Only check for unstabilizing moves such as captures and promotions
-                // when not in check. When in check, consider all moves
+            if !(in_check || m.is_capture() || m.is_promotion()
+                 || (check_plies > 0 && game.gives_check(m))) {
+                // Only check for unstabilizing moves such as captures, promotions
+                // and (in the first plies) checks when not in check. When in
+                // check, consider all moves
                 continue;
             }
 
-            let mut node_pv: Vec<Move> = vec![];
+            if !in_check && m.is_capture() && game.see(m) < 0 {
+                // Losing capture according to static exchange evaluation, it
+                // can't raise alpha here so don't bother searching it
+                search_trace!(self.nodes, ply, "see pruned {}", m.to_algebraic_with_state(&[]));
+                continue;
+            }
 
             game.update_board_with_move(m);
 
-            let (mut legal_moves_opposite, in_check) = game.get_legal_moves();
+            let mut legal_moves_opposite = mem::take(&mut self.move_buffers[ply + 1]);
+            self.position_infos[ply + 1] = game.position_info();
+            let in_check = game.generate_tactical_moves(check_plies > 1, &self.position_infos[ply + 1], &mut legal_moves_opposite);
             self.order_moves(&mut legal_moves_opposite, ply);
 
             let eval = -self.qsearch(
                 game,
                 &legal_moves_opposite,
                 max_depth,
+                check_plies.saturating_sub(1),
                 ply + 1,
                 in_check,
                 -beta,
-                -alpha,
-                &mut node_pv
+                -alpha
             );
 
             game.update_board_undo_move(
@@ -276,8 +1112,14 @@ impl SearchAsync {
                 pos_hash,
                 &castling_right_long,
                 &castling_right_short,
-                half_move_of_last_capture,
+                en_passant_square,
+                half_move_of_last_irreversible,
             );
+            self.move_buffers[ply + 1] = legal_moves_opposite;
+
+            if self.stopped() {
+                return 0;
+            }
 
             if eval >= beta {
                 return beta;
@@ -286,24 +1128,106 @@ impl SearchAsync {
             if eval > alpha {
                 alpha = eval;
 
-                pv.clear();
-                pv.push(*m);
-                pv.append(&mut node_pv);
+                self.update_pv(ply, *m);
             }
         }
 
-        let flag = if stand_pat >= beta {
-            TransitionTableFlag::Beta
-        } else if stand_pat > old_alpha {
-            TransitionTableFlag::Exact
+        let flag = if alpha > old_alpha {
+            TTFlag::Exact
         } else {
-            TransitionTableFlag::Alpha
+            TTFlag::Alpha
         };
-        self.tt.insert(game.pos_hash, (alpha, ply, flag));
+        self.tt.store(game.pos_hash, score_to_tt(alpha, ply), 0, flag, NULL_MOVE);
 
         alpha
     }
 
+    /// Score of a draw for the side to move, see `set_contempt`.
+    fn draw_score(&self, game: &GameState) -> i32 {
+        if game.board.side_to_move == self.root_side {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    /// Static evaluation of the position at `ply` of the search path, from
+    /// white's perspective.
+    fn static_eval(&self, game: &GameState, ply: usize) -> i32 {
+        match &self.eval_params {
+            Some(params) => eval_with_info_and_params(game, &self.position_infos[ply], params),
+            None => eval_with_info(game, &self.position_infos[ply]),
+        }
+    }
+
+    fn qsearch_check_plies(game: &GameState) -> usize {
+        if game.rules().checks_matter() {
+            Self::CHECKS_MATTER_QSEARCH_CHECK_PLIES
+        } else {
+            Self::QSEARCH_CHECK_PLIES
+        }
+    }
+
+    /// Determine the number of whole plies to extend the search at this node
+    /// with. Extensions are accumulated in fractional plies along the search
+    /// path and capped by a per-path budget, so they can't balloon the search.
+    ///
+    /// * `ply`: Ply of the node
+    /// * `in_check`: Whether the side to move is in check
+    /// * `n_legal_moves`: Number of legal moves in the node
+    /// * `checks_matter`: Whether checks are worth more than in chess in the variant
+    fn extend(&mut self, ply: usize, in_check: bool, n_legal_moves: usize, checks_matter: bool) -> usize {
+        let parent_extension = self.extension_stack[ply - 1];
+        let mut extension = 0;
+
+        if in_check {
+            // Don't extend repeated consecutive checks by the same side, those
+            // are usually just spite checks pushing the problem further away,
+            // except where checks matter, e.g. closing in on the third check
+            // of three-check
+            let repeated_check = ply > 2 && self.in_check_stack[ply - 2] && !checks_matter;
+            if !repeated_check {
+                extension += Self::CHECK_EXTENSION;
+            }
+        }
+
+        if n_legal_moves == 1 {
+            extension += Self::SINGLE_REPLY_EXTENSION;
+        }
+
+        let path_extension = (parent_extension + extension).min(self.max_extension);
+        self.extension_stack[ply] = path_extension;
+        self.in_check_stack[ply] = in_check;
+
+        // The parent's whole plies are already included in the depth passed down
+        path_extension/Self::ONE_PLY - parent_extension/Self::ONE_PLY
+    }
+
+    /// Whether the position at `ply` already occurred earlier on the current
+    /// search path. Only positions with the same side to move can be equal.
+    fn is_path_repetition(&self, ply: usize) -> bool {
+        let hash = self.hash_stack[ply];
+        (1..ply.saturating_sub(1)).rev().step_by(2).any(|prev_ply| self.hash_stack[prev_ply] == hash)
+    }
+
+    /// Principal variation of the node at `ply` of the current search path.
+    fn pv(&self, ply: usize) -> &[Move] {
+        &self.pv_table[ply][..self.pv_length[ply]]
+    }
+
+    fn clear_pv(&mut self, ply: usize) {
+        self.pv_length[ply] = 0;
+    }
+
+    /// Set the principal variation at `ply` to `m` followed by the child's.
+    fn update_pv(&mut self, ply: usize, m: Move) {
+        let child_length = self.pv_length[ply + 1].min(MAX_PV_LENGTH - 1);
+        let (pv_table, child_pv_table) = self.pv_table.split_at_mut(ply + 1);
+        pv_table[ply][0] = m;
+        pv_table[ply][1..=child_length].copy_from_slice(&child_pv_table[0][..child_length]);
+        self.pv_length[ply] = child_length + 1;
+    }
+
     fn store_killer(&mut self, m: &Move, ply: usize) {
         let first_killer = &self.killer_list[0][ply];
 
@@ -318,9 +1242,101 @@ impl SearchAsync {
         self.killer_list[0][ply] = *m;
     }
 
-    fn order_moves(&self, moves: &mut Vec<Move>, ply: usize) {
+    fn order_moves(&self, moves: &mut [Move], ply: usize) {
         moves.sort_unstable_by(|a, b| {
             b.prio(ply, &self.killer_list).cmp(&a.prio(ply, &self.killer_list))
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+    use crate::r#move::MoveType;
+
+    fn play_moves(game: &mut GameState, moves: &[&str]) {
+        for move_str in moves {
+            let (legal_moves, _) = game.get_legal_moves();
+            let m = *legal_moves.iter()
+                .find(|m| m.to_algebraic_with_state(&legal_moves) == *move_str)
+                .unwrap();
+            game.make_move(&m);
+        }
+    }
+
+    #[test]
+    fn test_finds_mate() {
+        let mut searcher = SearchAsync::new();
+
+        // Scholar's mate, white to play Qxf7#
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        play_moves(&mut game, &["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6"]);
+
+        let (eval, best_move, _) = searcher.find_best_legal_move(&mut game, 3, &[]);
+        assert_eq!(best_move.to_algebraic_with_state(&[]), "Qxf7");
+        assert_eq!(format_score(eval), "#1");
+
+        // Fool's mate, black to play Qh4#
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        play_moves(&mut game, &["f3", "e5", "g4"]);
+
+        let (eval, best_move, _) = searcher.find_best_legal_move(&mut game, 3, &[]);
+        assert_eq!(best_move.to_algebraic_with_state(&[]), "Qh4");
+        assert_eq!(format_score(eval), "#-1");
+
+        // Three-check with two checks given, white wins with the check Ra8+
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 1+3 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+
+        let (eval, best_move, _) = searcher.find_best_legal_move(&mut game, 3, &[]);
+        assert_eq!(best_move.to_algebraic_with_state(&[]), "Ra8");
+        assert_eq!(format_score(eval), "#1");
+
+        // Crazyhouse, white mates on the back rank by dropping the rook
+        let fen = "6k1/5ppp/8/8/8/8/8/K7[R] w - - 0 1";
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, fen).unwrap();
+
+        let (eval, best_move, _) = searcher.find_best_legal_move(&mut game, 3, &[]);
+        assert_eq!(best_move.move_type, MoveType::Drop);
+        assert_eq!(format_score(eval), "#1");
+    }
+
+    #[test]
+    fn test_search_moves() {
+        let mut searcher = SearchAsync::new();
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+        play_moves(&mut game, &["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6"]);
+
+        // Qxf7# is left out
+        let (legal_moves, _) = game.get_legal_moves();
+        let search_moves: Vec<Move> = legal_moves.iter()
+            .filter(|m| ["Qxh7", "Qxe5", "Qe2"].contains(&m.to_algebraic_with_state(&legal_moves).as_str()))
+            .copied()
+            .collect();
+        assert_eq!(search_moves.len(), 3);
+
+        let (_, best_move, pv) = searcher.find_best_legal_move(&mut game, 3, &search_moves);
+        assert!(search_moves.contains(&best_move));
+        assert_eq!(pv.first(), Some(&best_move));
+    }
+
+    #[test]
+    fn test_path_repetition() {
+        let mut searcher = SearchAsync::new();
+        let mut game = GameState::new(&PSEUDO_LEGAL_MOVES, &MAGICS);
+
+        // Shuffle the knights back and forth, the starting position is
+        // repeated at ply 5
+        let mut ply = 1;
+        searcher.hash_stack[ply] = game.pos_hash;
+        for m in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+            play_moves(&mut game, &[m]);
+
+            ply += 1;
+            searcher.hash_stack[ply] = game.pos_hash;
+            assert_eq!(searcher.is_path_repetition(ply), ply == 5);
+        }
+    }
+}
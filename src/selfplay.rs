@@ -0,0 +1,249 @@
+//! Games of the engine against itself, for watching how it plays and for
+//! spotting blunders and crashes over whole games, and matches against the
+//! weak opponents with a running Elo estimate. With a `LearningStore` the
+//! games are learned from, so lines that keep losing in self-play are
+//! avoided in the next games.
+
+use std::time::Duration;
+
+use crate::adjudication::Adjudicator;
+use crate::board::Side;
+use crate::elo::MatchScore;
+use crate::engine::SearchLimits;
+use crate::error::Error;
+use crate::evalhistory::EvalHistory;
+use crate::game::GameState;
+use crate::learning::LearningStore;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::opponent::Opponent;
+use crate::r#move::{Move, MoveResult};
+use crate::search::{format_score, SearchAsync, MAX_SEARCH_DEPTH};
+
+/// Result of a game, as in PGN, and the reason it ended.
+#[derive(Debug, PartialEq)]
+pub struct GameResult {
+    pub winner: Option<Side>,
+    pub reason: String,
+}
+
+impl GameResult {
+    pub fn to_pgn(&self) -> &'static str {
+        match self.winner {
+            Some(Side::White) => "1-0",
+            Some(Side::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+/// Play `game` out with the engine making the moves of both sides, each with
+/// a searcher of its own. A side resigns when it's lost for a few moves in a
+/// row, see `Adjudicator`.
+///
+/// * `game`: Position to play from
+/// * `opponents`: Who plays the moves of each side, by side
+/// * `depth`: Search depth of every move, unless it's searched for `move_time`
+/// * `move_time`: Optional time to search every move for
+/// * `node_limit`: Optional node limit per move
+/// * `learning`: Optional store to avoid the lines lost repeatedly by, and to learn the game in
+/// * `on_move`: Called with the game before every move, the move, the legal
+///   moves and the evaluation from white's perspective
+pub fn play(
+    game: &mut GameState,
+    opponents: [Opponent; Side::N_SIDES],
+    depth: usize,
+    move_time: Option<Duration>,
+    node_limit: Option<u64>,
+    learning: Option<&mut LearningStore>,
+    on_move: impl FnMut(&GameState, &Move, &[Move], i32),
+) -> GameResult {
+    let mut searchers = [SearchAsync::new(), SearchAsync::new()];
+    for searcher in &mut searchers {
+        searcher.set_node_limit(node_limit);
+        searcher.set_time_limit(move_time);
+    }
+    let depth = if move_time.is_some() { MAX_SEARCH_DEPTH } else { depth };
+
+    play_with_searchers(game, &mut searchers, opponents, depth, learning, on_move)
+}
+
+/// Like `play`, with the searchers of both sides set up by the caller, e.g.
+/// with their own search parameters, limits and evaluation weights.
+///
+/// * `searchers`: Searcher of each side, by side
+/// * `depth`: Search depth of every move
+pub fn play_with_searchers(
+    game: &mut GameState,
+    searchers: &mut [SearchAsync; Side::N_SIDES],
+    opponents: [Opponent; Side::N_SIDES],
+    depth: usize,
+    mut learning: Option<&mut LearningStore>,
+    mut on_move: impl FnMut(&GameState, &Move, &[Move], i32),
+) -> GameResult {
+    let mut adjudicators = [Adjudicator::new(), Adjudicator::new()];
+    let mut evals = EvalHistory::new();
+    let (mut legal_moves, _) = game.get_legal_moves();
+    let result = loop {
+        let side = game.board.side_to_move;
+        let search_moves = learning.as_deref().map_or(vec![], |learning| learning.search_moves(game, &legal_moves));
+        let (eval, best_move, _) = opponents[side as usize].find_move(&mut searchers[side as usize], game, depth, &search_moves);
+
+        let side_eval = if side == Side::White { eval } else { -eval };
+        adjudicators[side as usize].record_eval(side_eval);
+        if adjudicators[side as usize].should_resign() {
+            break GameResult { winner: Some(side.opposite()), reason: "Resignation".to_owned() };
+        }
+
+        on_move(game, &best_move, &legal_moves, eval);
+
+        let move_result;
+        (move_result, legal_moves) = game.make_move(&best_move);
+        evals.record(game, eval);
+        match move_result {
+            Some(MoveResult::Checkmate) => {
+                break GameResult { winner: Some(side), reason: "Checkmate".to_owned() };
+            },
+            Some(MoveResult::ThirdCheck) => {
+                break GameResult { winner: Some(side), reason: "Third check".to_owned() };
+            },
+            Some(MoveResult::Draw(reason)) => {
+                break GameResult { winner: None, reason: format!("{:?}", reason) };
+            },
+            _ => {},
+        }
+    };
+
+    if let Some(learning) = &mut learning {
+        learning.record_game(game, result.winner, evals.points());
+    }
+
+    result
+}
+
+/// Play a game of the engine against itself from `fen` after `moves`,
+/// printing every move with its evaluation, and print the result.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `fen`: Position to play from
+/// * `moves`: Moves in coordinate notation to play from `fen` first
+/// * `depth`: Search depth of every move, unless it's searched for `move_time`
+/// * `move_time`: Optional time to search every move for
+/// * `node_limit`: Optional node limit per move
+/// * `learning`: Optional store to avoid the lines lost repeatedly by, and to learn the game in
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    fen: &str,
+    moves: &[String],
+    depth: usize,
+    move_time: Option<Duration>,
+    node_limit: Option<u64>,
+    learning: Option<&mut LearningStore>,
+) -> Result<(), Error> {
+    let mut game = GameState::from_fen(pl_moves, magics, fen)?;
+    game.apply_moves(&moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+
+    let result = play(&mut game, [Opponent::Engine; Side::N_SIDES], depth, move_time, node_limit, learning, |game, m, legal_moves, eval| {
+        let move_number = if game.board.side_to_move == Side::White {
+            format!("{}.", game.move_number)
+        } else {
+            format!("{}. ..", game.move_number)
+        };
+        println!("{} {} ({})", move_number, m.to_algebraic_with_state(legal_moves), format_score(eval));
+    });
+    println!("{} ({})", result.to_pgn(), result.reason);
+    println!("{}", game.to_fen());
+
+    Ok(())
+}
+
+/// Play a match of `games` games of the engine against `opponent` from `fen`
+/// after `moves`, with the engine playing white and black in turn, and print
+/// the result of every game with the engine's running score and Elo
+/// estimate. Returns the engine's score.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `fen`: Position to play from
+/// * `moves`: Moves in coordinate notation to play from `fen` first
+/// * `limits`: Search limits of every move, the depth is searched to unless there's a time limit
+/// * `games`: Number of games to play
+/// * `opponent`: Opponent of the engine
+/// * `learning`: Optional store to avoid the lines lost repeatedly by, and to learn the games in
+#[allow(clippy::too_many_arguments)]
+pub fn run_match(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    fen: &str,
+    moves: &[String],
+    limits: &SearchLimits,
+    games: usize,
+    opponent: Opponent,
+    mut learning: Option<&mut LearningStore>,
+) -> Result<MatchScore, Error> {
+    let mut start = GameState::from_fen(pl_moves, magics, fen)?;
+    start.apply_moves(&moves.iter().map(String::as_str).collect::<Vec<_>>())?;
+    let depth = limits.depth.unwrap_or(MAX_SEARCH_DEPTH);
+
+    let mut score = MatchScore::default();
+    for i in 0..games {
+        let engine_side = if i % 2 == 0 { Side::White } else { Side::Black };
+        let mut opponents = [opponent; Side::N_SIDES];
+        opponents[engine_side as usize] = Opponent::Engine;
+
+        let mut game = start.clone();
+        let result = play(&mut game, opponents, depth, limits.time, limits.nodes, learning.as_deref_mut(), |_, _, _, _| ());
+        score.record(engine_side, result.winner);
+        println!(
+            "Game {}: {} ({}), engine as {}: {}",
+            i + 1,
+            result.to_pgn(),
+            result.reason,
+            if engine_side == Side::White { "white" } else { "black" },
+            score
+        );
+    }
+
+    Ok(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_play() {
+        // A queen up, white mates in a few moves
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "7k/8/5K2/8/8/8/8/Q7 w - - 0 1").unwrap();
+        let result = play(&mut game, [Opponent::Engine; 2], 4, None, None, None, |_, _, _, _| ());
+        assert_eq!(result, GameResult { winner: Some(Side::White), reason: "Checkmate".to_owned() });
+        assert_eq!(result.to_pgn(), "1-0");
+
+        // The mate is learned as a win of white
+        let mut learning = LearningStore::new();
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "7k/8/5K2/8/8/8/8/Q7 w - - 0 1").unwrap();
+        play(&mut game, [Opponent::Engine; 2], 4, None, None, Some(&mut learning), |_, _, _, _| ());
+        assert_eq!(learning.len(), game.n_played_moves());
+        assert_eq!(learning.get(&game).map(|position| position.wins), Some(1));
+    }
+
+    #[test]
+    fn test_run_match() {
+        // The engine mates with the queen as white, the second game is up to
+        // the random mover's queen
+        let limits = SearchLimits { depth: Some(4), ..SearchLimits::default() };
+        let fen = "7k/8/5K2/8/8/8/8/Q7 w - - 0 1";
+        let score = run_match(&PSEUDO_LEGAL_MOVES, &MAGICS, fen, &[], &limits, 2, Opponent::Random, None).unwrap();
+        assert_eq!(score.games(), 2);
+        assert!(score.wins >= 1);
+
+        // Bare kings shuffle until the game is drawn
+        let mut game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "7k/8/5K2/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(play(&mut game, [Opponent::Engine; 2], 2, None, None, None, |_, _, _, _| ()).winner, None);
+    }
+}
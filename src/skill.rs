@@ -0,0 +1,120 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::r#move::Move;
+
+pub const MAX_SKILL_LEVEL: u8 = 20;
+
+/// Deliberately weakened play, so the engine is fun to play against for
+/// beginners. Below the maximum level the search depth and number of nodes are
+/// capped, a random move is picked among the root moves scoring close to the
+/// best one, and at the lowest levels the engine occasionally blunders.
+#[derive(Clone, Copy, Debug)]
+pub struct Skill {
+    level: u8,
+}
+
+impl Skill {
+    // Root moves scoring within this margin (in centipawns) of the best move
+    // are considered equally good, per level below the maximum
+    const MARGIN_PER_LEVEL: i32 = 10;
+
+    // Levels below this occasionally play a random move, with the given
+    // chance per level below it
+    const BLUNDER_MAX_LEVEL: u8 = 6;
+    const BLUNDER_CHANCE_PER_LEVEL: f64 = 0.04;
+
+    const MIN_DEPTH: usize = 2;
+    const MIN_NODE_LIMIT: u64 = 1000;
+
+    pub fn new(level: u8) -> Self {
+        Self {
+            level: level.min(MAX_SKILL_LEVEL),
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.level < MAX_SKILL_LEVEL
+    }
+
+    /// Maximum search depth at this level.
+    pub fn max_depth(&self) -> usize {
+        if self.is_limited() {
+            Self::MIN_DEPTH + self.level as usize/4
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// Maximum number of nodes searched at this level.
+    pub fn node_limit(&self) -> Option<u64> {
+        if self.is_limited() {
+            Some(Self::MIN_NODE_LIMIT << (self.level/2))
+        } else {
+            None
+        }
+    }
+
+    fn margin(&self) -> i32 {
+        (MAX_SKILL_LEVEL - self.level) as i32*Self::MARGIN_PER_LEVEL
+    }
+
+    fn blunder_chance(&self) -> f64 {
+        Self::BLUNDER_MAX_LEVEL.saturating_sub(self.level) as f64*Self::BLUNDER_CHANCE_PER_LEVEL
+    }
+
+    /// Pick the move to play from the root moves and their exact scores.
+    ///
+    /// * `scored_moves`: Root moves with their scores from the side to move's perspective
+    pub fn pick_move(&self, scored_moves: &[(Move, i32)]) -> Option<(Move, i32)> {
+        let mut rng = rand::thread_rng();
+
+        if rng.gen_bool(self.blunder_chance()) {
+            return scored_moves.choose(&mut rng).copied();
+        }
+
+        let best_score = scored_moves.iter().map(|(_, score)| *score).max()?;
+        let candidates: Vec<(Move, i32)> = scored_moves
+            .iter()
+            .filter(|(_, score)| *score >= best_score - self.margin())
+            .copied()
+            .collect();
+
+        candidates.choose(&mut rng).copied()
+    }
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Self::new(MAX_SKILL_LEVEL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::r#move::NULL_MOVE;
+
+    #[test]
+    fn test_pick_move() {
+        let good = NULL_MOVE;
+        let ok = Move { from_square: 1, ..NULL_MOVE };
+        let bad = Move { from_square: 2, ..NULL_MOVE };
+        let scored_moves = [(good, 50), (ok, 0), (bad, -300)];
+
+        // Full strength always plays the best move
+        let skill = Skill::default();
+        assert!(!skill.is_limited());
+        for _ in 0..100 {
+            assert_eq!(skill.pick_move(&scored_moves), Some((good, 50)));
+        }
+
+        // Close moves are picked too, but no blunders
+        let skill = Skill::new(10);
+        for _ in 0..100 {
+            assert_ne!(skill.pick_move(&scored_moves).unwrap().0, bad);
+        }
+
+        assert_eq!(skill.pick_move(&[]), None);
+    }
+}
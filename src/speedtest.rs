@@ -0,0 +1,173 @@
+//! Hardware calibration: a short search of a middlegame position measures
+//! the speed of the search on this machine and how much each depth costs,
+//! which gives an estimate of the time a search to a depth takes and the
+//! depth a search can reach in a given time.
+
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::search::{SearchAsync, MAX_SEARCH_DEPTH};
+
+/// Middlegame position the calibration searches.
+const CALIBRATION_FEN: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+/// Time the calibration at the start of a game takes.
+pub const QUICK_BUDGET: Duration = Duration::from_millis(250);
+
+/// Time a move of the engine should take by default when playing to a depth.
+pub const TARGET_MOVE_TIME: Duration = Duration::from_secs(2);
+
+// Bounds of the effective branching factor, the measured one is noisy at
+// the low depths a quick calibration reaches
+const MIN_BRANCHING_FACTOR: f64 = 1.5;
+const MAX_BRANCHING_FACTOR: f64 = 6.0;
+
+// Range of depths picked for a time
+const MIN_DEPTH: usize = 3;
+const MAX_DEPTH: usize = 30;
+
+/// Search speed of this machine.
+///
+/// * `nps`: Nodes searched per second
+/// * `depth_nodes`: Nodes the iteration of each depth from 1 took
+/// * `branching_factor`: Factor the nodes grow by per depth beyond the measured ones
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub nps: u64,
+    pub depth_nodes: Vec<u64>,
+    pub branching_factor: f64,
+}
+
+impl Calibration {
+    /// Measure the search speed with searches of increasing depth, until
+    /// about `budget` is spent.
+    ///
+    /// * `pl_moves`: Pseudo-legal move bitboards
+    /// * `magics`: Magic bitboards for slider moves
+    /// * `budget`: Time to spend measuring
+    pub fn measure(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard, budget: Duration) -> Self {
+        let mut game = GameState::from_fen(pl_moves, magics, CALIBRATION_FEN).expect("calibration position is valid");
+        let mut searcher = SearchAsync::with_hash_size(16);
+
+        // The earlier iterations are in the transposition table, so each
+        // search costs about as much as its last iteration
+        let start = Instant::now();
+        let mut depth_nodes = Vec::new();
+        for depth in 1..MAX_SEARCH_DEPTH {
+            searcher.find_best_legal_move(&mut game, depth, &[]);
+            depth_nodes.push(searcher.nodes().max(1));
+
+            // The next depth would most likely overrun the budget
+            if start.elapsed() >= budget/2 {
+                break;
+            }
+        }
+        let elapsed = start.elapsed().max(Duration::from_micros(1));
+
+        Self::from_depth_nodes(depth_nodes, elapsed)
+    }
+
+    /// Calibration of `depth_nodes` nodes per depth searched in `elapsed`.
+    fn from_depth_nodes(depth_nodes: Vec<u64>, elapsed: Duration) -> Self {
+        let total: u64 = depth_nodes.iter().sum();
+        let branching_factor = match depth_nodes[..] {
+            [.., previous, last] => last as f64/previous as f64,
+            _ => MAX_BRANCHING_FACTOR,
+        };
+
+        Self {
+            nps: (total as f64/elapsed.as_secs_f64()) as u64,
+            depth_nodes,
+            branching_factor: branching_factor.clamp(MIN_BRANCHING_FACTOR, MAX_BRANCHING_FACTOR),
+        }
+    }
+
+    /// Estimated nodes of a search to `depth`, all its iterations together.
+    pub fn estimated_nodes(&self, depth: usize) -> f64 {
+        let measured = depth.min(self.depth_nodes.len());
+        let mut nodes: f64 = self.depth_nodes[..measured].iter().map(|&nodes| nodes as f64).sum();
+        let mut iteration_nodes = self.depth_nodes.last().copied().unwrap_or(1) as f64;
+        for _ in measured..depth {
+            iteration_nodes *= self.branching_factor;
+            nodes += iteration_nodes;
+        }
+
+        nodes
+    }
+
+    /// Estimated time of a search to `depth` on this machine.
+    pub fn estimated_time(&self, depth: usize) -> Duration {
+        Duration::from_secs_f64(self.estimated_nodes(depth)/self.nps.max(1) as f64)
+    }
+
+    /// Deepest depth a search finishes in about `time` on this machine.
+    pub fn depth_for(&self, time: Duration) -> usize {
+        (MIN_DEPTH..=MAX_DEPTH)
+            .take_while(|&depth| depth == MIN_DEPTH || self.estimated_time(depth) <= time)
+            .last()
+            .unwrap_or(MIN_DEPTH)
+    }
+}
+
+/// Measure the search speed for about a second, and print it with the
+/// estimated time per move of each depth and the default depth it picks.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+pub fn run(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard) -> Result<(), Error> {
+    let calibration = Calibration::measure(pl_moves, magics, Duration::from_secs(1));
+    println!(
+        "{} nodes/s, branching factor {:.1}, measured to depth {}",
+        calibration.nps,
+        calibration.branching_factor,
+        calibration.depth_nodes.len()
+    );
+
+    let default_depth = calibration.depth_for(TARGET_MOVE_TIME);
+    for depth in MIN_DEPTH..=default_depth + 2 {
+        let marker = if depth == default_depth { " (default)" } else { "" };
+        println!("Depth {:>2}: about {:.2?} per move{}", depth, calibration.estimated_time(depth), marker);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates() {
+        // 1000 nodes per second, growing threefold per depth
+        let calibration = Calibration::from_depth_nodes(vec![25, 75, 225, 675], Duration::from_secs(1));
+        assert_eq!(calibration.nps, 1000);
+        assert_eq!(calibration.branching_factor, 3.0);
+
+        assert_eq!(calibration.estimated_nodes(2), 100.0);
+        assert_eq!(calibration.estimated_nodes(6), 1000.0 + 2025.0 + 6075.0);
+        assert_eq!(calibration.estimated_time(4), Duration::from_secs(1));
+
+        assert_eq!(calibration.depth_for(Duration::from_secs(1)), 4);
+        assert_eq!(calibration.depth_for(Duration::from_secs(4)), 5);
+        // At least the minimum depth however slow
+        assert_eq!(calibration.depth_for(Duration::ZERO), MIN_DEPTH);
+
+        // The branching factor is bounded
+        let calibration = Calibration::from_depth_nodes(vec![1, 100], Duration::from_secs(1));
+        assert_eq!(calibration.branching_factor, MAX_BRANCHING_FACTOR);
+    }
+
+    #[test]
+    fn test_measure() {
+        use crate::magic::MAGICS;
+        use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+        let calibration = Calibration::measure(&PSEUDO_LEGAL_MOVES, &MAGICS, Duration::from_millis(50));
+        assert!(calibration.nps > 0);
+        assert!(!calibration.depth_nodes.is_empty());
+        assert!(calibration.depth_for(TARGET_MOVE_TIME) >= MIN_DEPTH);
+    }
+}
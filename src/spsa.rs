@@ -0,0 +1,314 @@
+//! SPSA tuning (simultaneous perturbation stochastic approximation) of the
+//! search parameters and piece values by self-play. Every iteration all tuned
+//! parameters are nudged up or down at random at once, a copy of the engine
+//! with them nudged one way plays a few quick games against a copy with them
+//! nudged the other way, and the parameters move towards the winner's.
+//!
+//! Unlike the Texel tuner (see `src/tuner.rs`) it needs no dataset and can
+//! tune the search, but it takes many games for the noise of their results to
+//! even out.
+
+use std::fmt::{self, Write};
+use std::fs;
+use std::str::FromStr;
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use tracing::info;
+
+use crate::board::{Piece, Side};
+use crate::elo::MatchScore;
+use crate::error::Error;
+use crate::eval::EvalParams;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::opponent::Opponent;
+use crate::score::S;
+use crate::search::{SearchAsync, SearchParams};
+use crate::selfplay::play_with_searchers;
+use crate::tuner::write_scores;
+
+// Exponents of the decay of the gains and the perturbations over the
+// iterations, as recommended by Spall
+const GAIN_DECAY: f64 = 0.602;
+const PERTURBATION_DECAY: f64 = 0.101;
+
+// Stability constant of the gains as a fraction of the iterations, so the
+// first iterations don't throw the parameters around
+const STABILITY: f64 = 0.1;
+
+// Perturbations a parameter moves by in the first iteration if one side wins
+// every game
+const LEARNING_RATE: f64 = 0.5;
+
+// Random moves played from the start position of every pair of games, so the
+// games of an iteration differ
+const OPENING_PLIES: usize = 4;
+
+// Smaller transposition tables than the engine's, the games are quick
+const HASH_SIZE_MB: usize = 16;
+
+/// A group of parameters tuned together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamGroup {
+    /// The reductions and margins of `SearchParams`
+    Search,
+    /// The piece values, but the pawn's which the others are measured in
+    Pieces,
+}
+
+impl FromStr for ParamGroup {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "search" => Ok(ParamGroup::Search),
+            "pieces" => Ok(ParamGroup::Pieces),
+            _ => Err(Error::InvalidParamGroup(string.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for ParamGroup {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            ParamGroup::Search => "search",
+            ParamGroup::Pieces => "pieces",
+        })
+    }
+}
+
+// The piece values that are tuned, as middlegame and endgame values
+const TUNED_PIECES: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// A parameter being tuned, with how far it's perturbed in the first
+/// iteration and the range it's kept in.
+#[derive(Clone, Debug, PartialEq)]
+struct Tunable {
+    name: String,
+    value: f64,
+    perturbation: f64,
+    min: f64,
+    max: f64,
+}
+
+/// The parameters of `groups` with their default values.
+fn tunables(groups: &[ParamGroup]) -> Vec<Tunable> {
+    let mut tunables = vec![];
+    if groups.contains(&ParamGroup::Search) {
+        // Perturbations and ranges in the order of `SearchParams::NAMES`
+        let settings = [(1.0, 1.0, 3.0), (1.0, 1.0, 4.0), (50.0, 0.0, 1000.0), (50.0, 0.0, 1000.0)];
+        for ((name, value), (perturbation, min, max)) in SearchParams::NAMES.iter().zip(SearchParams::DEFAULT.weights()).zip(settings) {
+            tunables.push(Tunable { name: name.to_string(), value: value as f64, perturbation, min, max });
+        }
+    }
+    if groups.contains(&ParamGroup::Pieces) {
+        for piece in TUNED_PIECES {
+            let value = EvalParams::DEFAULT.piece_values[piece as usize];
+            for (phase, value) in [("mg", value.mg()), ("eg", value.eg())] {
+                let name = format!("{:?}_{}", piece, phase).to_lowercase();
+                tunables.push(Tunable { name, value: value as f64, perturbation: 20.0, min: 100.0, max: 2000.0 });
+            }
+        }
+    }
+    tunables
+}
+
+/// Search parameters and evaluation weights with the parameters of `groups`
+/// set to `values`, in the order of `tunables`. Without the piece values the
+/// engine's own evaluation is kept, as it's much faster.
+fn params(groups: &[ParamGroup], values: &[i32]) -> (SearchParams, Option<EvalParams>) {
+    let mut values = values.iter().copied();
+    let mut search_params = SearchParams::DEFAULT;
+    if groups.contains(&ParamGroup::Search) {
+        let weights: Vec<i32> = values.by_ref().take(SearchParams::NAMES.len()).collect();
+        search_params.set_weights(&weights);
+    }
+
+    let eval_params = groups.contains(&ParamGroup::Pieces).then(|| {
+        let mut eval_params = EvalParams::DEFAULT;
+        for piece in TUNED_PIECES {
+            let (mg, eg) = (values.next().unwrap(), values.next().unwrap());
+            eval_params.piece_values[piece as usize] = S(mg, eg);
+        }
+        eval_params
+    });
+
+    (search_params, eval_params)
+}
+
+/// Rust source of the tuned parameters, to paste over the current ones in
+/// `src/search.rs` and `src/eval.rs`.
+fn params_source(groups: &[ParamGroup], search_params: &SearchParams, eval_params: Option<&EvalParams>) -> String {
+    let mut source = String::new();
+    if groups.contains(&ParamGroup::Search) {
+        writeln!(source, "pub const DEFAULT: Self = {:?};", search_params).unwrap();
+    }
+    if let Some(eval_params) = eval_params {
+        write_scores(&mut source, "PIECE_VALUES", "Piece::N_PIECES", &eval_params.piece_values);
+    }
+    source
+}
+
+/// Play an iteration of SPSA and move the tuned parameters towards the
+/// perturbation that scored better. Returns the score of the parameters
+/// perturbed up against those perturbed down.
+///
+/// * `start`: Position the games are played from, after a few random moves
+/// * `groups`: Groups of the tuned parameters
+/// * `tunables`: The tuned parameters
+/// * `iteration`: Number of the iteration, from 1
+/// * `iterations`: Number of iterations of the whole run
+/// * `games`: Number of games to play, rounded up to pairs
+/// * `depth`: Search depth of every move
+fn iterate(
+    start: &GameState,
+    groups: &[ParamGroup],
+    tunables: &mut [Tunable],
+    iteration: usize,
+    iterations: usize,
+    games: usize,
+    depth: usize,
+) -> MatchScore {
+    let mut rng = rand::thread_rng();
+    let stability = STABILITY*iterations as f64;
+    let gain = LEARNING_RATE*((stability + 1.0)/(stability + iteration as f64)).powf(GAIN_DECAY);
+    let perturbation_scale = (iteration as f64).powf(-PERTURBATION_DECAY);
+
+    let deltas: Vec<f64> = tunables.iter().map(|_| if rng.gen() { 1.0 } else { -1.0 }).collect();
+    let perturbed = |sign: f64| -> Vec<i32> {
+        tunables.iter().zip(&deltas)
+            .map(|(tunable, delta)| {
+                let value = tunable.value + sign*delta*tunable.perturbation*perturbation_scale;
+                value.clamp(tunable.min, tunable.max).round() as i32
+            })
+            .collect()
+    };
+
+    // The searchers of the parameters perturbed up and down, in that order
+    let mut searchers = [SearchAsync::with_hash_size(HASH_SIZE_MB), SearchAsync::with_hash_size(HASH_SIZE_MB)];
+    for (searcher, sign) in searchers.iter_mut().zip([1.0, -1.0]) {
+        let (search_params, eval_params) = params(groups, &perturbed(sign));
+        searcher.set_params(search_params);
+        searcher.set_eval_params(eval_params);
+    }
+
+    let mut score = MatchScore::default();
+    for _ in 0..games.div_ceil(2) {
+        let mut opening = start.clone();
+        for _ in 0..OPENING_PLIES {
+            let (legal_moves, _) = opening.get_legal_moves();
+            let Some(m) = legal_moves.choose(&mut rng) else { break };
+            if opening.make_move(m).0.is_some() {
+                break;
+            }
+        }
+
+        // Both play the opening with either color
+        for up_side in Side::VALUES {
+            if up_side == Side::Black {
+                searchers.swap(0, 1);
+            }
+            let mut game = opening.clone();
+            let result = play_with_searchers(&mut game, &mut searchers, [Opponent::Engine; Side::N_SIDES], depth, None, |_, _, _, _| ());
+            score.record(up_side, result.winner);
+            if up_side == Side::Black {
+                searchers.swap(0, 1);
+            }
+        }
+    }
+
+    let result = (score.wins as f64 - score.losses as f64)/score.games().max(1) as f64;
+    for (tunable, delta) in tunables.iter_mut().zip(&deltas) {
+        let step = gain*result*delta*tunable.perturbation*perturbation_scale;
+        tunable.value = (tunable.value + step).clamp(tunable.min, tunable.max);
+    }
+
+    score
+}
+
+/// Tune the parameters of `groups` with `iterations` iterations of SPSA, each
+/// playing `games` games from `fen`. The tuned parameters are written to
+/// `output` after every iteration, so an interrupted run isn't lost.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `fen`: Position to play the games from, after a few random moves
+/// * `groups`: Groups of the parameters to tune
+/// * `iterations`: Number of iterations
+/// * `games`: Number of games per iteration, rounded up to pairs
+/// * `depth`: Search depth of every move
+/// * `output`: Path to write the tuned parameters to, as Rust source
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    fen: &str,
+    groups: &[ParamGroup],
+    iterations: usize,
+    games: usize,
+    depth: usize,
+    output: &str,
+) -> Result<(), Error> {
+    let start = GameState::from_fen(pl_moves, magics, fen)?;
+    let mut tunables = tunables(groups);
+
+    for iteration in 1..=iterations {
+        let iteration_start = Instant::now();
+        let score = iterate(&start, groups, &mut tunables, iteration, iterations, games, depth);
+
+        let values: Vec<String> = tunables.iter().map(|tunable| format!("{} {:.1}", tunable.name, tunable.value)).collect();
+        info!("Iteration {}: {} in {:.2?}, {}", iteration, score, iteration_start.elapsed(), values.join(", "));
+
+        let values: Vec<i32> = tunables.iter().map(|tunable| tunable.value.round() as i32).collect();
+        let (search_params, eval_params) = params(groups, &values);
+        fs::write(output, params_source(groups, &search_params, eval_params.as_ref()))
+            .map_err(|source| Error::Write { path: output.into(), source })?;
+    }
+
+    println!("Tuned parameters after {} iterations written to {}", iterations, output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_params() {
+        let groups = [ParamGroup::Search, ParamGroup::Pieces];
+        let tunables = tunables(&groups);
+        assert_eq!(tunables.len(), SearchParams::NAMES.len() + 2*TUNED_PIECES.len());
+        assert_eq!(tunables[4].name, "knight_mg");
+
+        // The defaults map back to the engine's parameters
+        let values: Vec<i32> = tunables.iter().map(|tunable| tunable.value as i32).collect();
+        assert_eq!(params(&groups, &values), (SearchParams::DEFAULT, Some(EvalParams::DEFAULT)));
+        assert_eq!(params(&[ParamGroup::Search], &values[..4]).1, None);
+
+        // Reductions beyond what the search handles are clamped
+        let (search_params, _) = params(&[ParamGroup::Search], &[0, 9, 250, 400]);
+        assert_eq!(search_params, SearchParams { null_move_reduction: 1, iid_reduction: 4, razoring_margins: [0, 250, 400] });
+        assert!(params_source(&[ParamGroup::Search], &search_params, None).contains("null_move_reduction: 1"));
+
+        assert_eq!("pieces".parse::<ParamGroup>().unwrap(), ParamGroup::Pieces);
+        assert_eq!(ParamGroup::Search.to_string(), "search");
+        assert!(matches!("lmr".parse::<ParamGroup>(), Err(Error::InvalidParamGroup(_))));
+    }
+
+    #[test]
+    fn test_iterate() {
+        // A queen up, the games end quickly
+        let start = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, "7k/8/5K2/8/8/8/8/Q7 w - - 0 1").unwrap();
+        let groups = [ParamGroup::Pieces];
+        let mut tunables = tunables(&groups);
+        let score = iterate(&start, &groups, &mut tunables, 1, 10, 1, 2);
+        assert_eq!(score.games(), 2);
+        assert!(tunables.iter().all(|tunable| tunable.value >= tunable.min && tunable.value <= tunable.max));
+    }
+}
@@ -0,0 +1,163 @@
+//! Built-in tactical test suite: positions with a known best move, searched at
+//! a few depths to get a quick impression of the engine's tactical strength.
+
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::r#move::Move;
+use crate::search::SearchAsync;
+
+/// The first positions of the Win At Chess suite, in Extended Position
+/// Description format.
+const WIN_AT_CHESS: &str = include_str!("../data/wac.epd");
+
+/// A test position with its best move(s) in standard algebraic notation.
+pub struct TestPosition {
+    pub id: String,
+    pub fen: String,
+    pub best_moves: Vec<String>,
+}
+
+impl TestPosition {
+    /// Parse an EPD line, e.g. `<fen> bm Qg6; id "WAC.001";`. Only the `bm`
+    /// and `id` operations are used.
+    pub fn from_epd(line: &str) -> Result<Self, Error> {
+        let fields: Vec<&str> = line.splitn(5, ' ').collect();
+        if fields.len() < 5 {
+            return Err(Error::InvalidEpd { line: line.to_owned(), reason: "missing operations".to_owned() });
+        }
+
+        let mut position = Self {
+            id: String::new(),
+            fen: fields[..4].join(" "),
+            best_moves: vec![],
+        };
+
+        for operation in fields[4].split(';') {
+            let operation = operation.trim();
+            match operation.split_once(' ') {
+                Some(("bm", moves)) => position.best_moves = moves
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect(),
+                Some(("id", id)) => position.id = id.trim_matches('"').to_string(),
+                _ => (),
+            }
+        }
+
+        if position.best_moves.is_empty() {
+            return Err(Error::InvalidEpd { line: line.to_owned(), reason: "no best move".to_owned() });
+        }
+
+        Ok(position)
+    }
+
+    pub fn is_best_move(&self, m: &Move, legal_moves: &[Move]) -> bool {
+        let move_str = m.to_algebraic_with_state(legal_moves);
+        self.best_moves.iter().any(|best_move| normalize_san(best_move) == move_str)
+    }
+}
+
+/// Strip check and annotation symbols from a move in standard algebraic
+/// notation, and write castling with zeros like `Move::to_algebraic_with_state`.
+pub fn normalize_san(san: &str) -> String {
+    san.trim_end_matches(['+', '#', '!', '?']).replace('O', "0")
+}
+
+fn positions() -> Vec<TestPosition> {
+    WIN_AT_CHESS
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| TestPosition::from_epd(line).unwrap())
+        .collect()
+}
+
+/// Search all positions of the test suite at each of `depths` and report how
+/// many are solved, and how long it took to find the solutions.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `depths`: Search depths to run the suite at
+/// * `node_limit`: Optional node limit per search
+pub fn run(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard, depths: &[usize], node_limit: Option<u64>) {
+    let positions = positions();
+
+    for &depth in depths {
+        let mut searcher = SearchAsync::new();
+        searcher.set_node_limit(node_limit);
+
+        let mut n_solved = 0;
+        let mut solve_time = Duration::ZERO;
+        let mut total_time = Duration::ZERO;
+
+        for position in &positions {
+            let mut game = GameState::from_fen(pl_moves, magics, &position.fen).unwrap();
+            let (legal_moves, _) = game.get_legal_moves();
+
+            let start = Instant::now();
+            let (_, best_move, _) = searcher.find_best_legal_move(&mut game, depth, &[]);
+            let elapsed = start.elapsed();
+            total_time += elapsed;
+
+            let move_str = best_move.to_algebraic_with_state(&legal_moves);
+            if position.is_best_move(&best_move, &legal_moves) {
+                n_solved += 1;
+                solve_time += elapsed;
+                info!("{} (depth {}): {} solved in {:.2?}", position.id, depth, move_str, elapsed);
+            } else {
+                info!(
+                    "{} (depth {}): {} failed, expected {}",
+                    position.id,
+                    depth,
+                    move_str,
+                    position.best_moves.join(" ")
+                );
+            }
+        }
+
+        let average_solve_time = if n_solved > 0 {
+            solve_time/n_solved
+        } else {
+            Duration::ZERO
+        };
+        println!(
+            "Depth {}: solved {}/{} ({:.0}%) in {:.2?}, {:.2?} average time to solution",
+            depth,
+            n_solved,
+            positions.len(),
+            100.0*n_solved as f64/positions.len() as f64,
+            total_time,
+            average_solve_time
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magic::MAGICS;
+    use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+
+    #[test]
+    fn test_positions() {
+        for position in positions() {
+            let game = GameState::from_fen(&PSEUDO_LEGAL_MOVES, &MAGICS, &position.fen).unwrap();
+            let (legal_moves, _) = game.get_legal_moves();
+
+            // Every best move must be a legal move in the position
+            for best_move in &position.best_moves {
+                assert!(
+                    legal_moves.iter().any(|m| m.to_algebraic_with_state(&legal_moves) == normalize_san(best_move)),
+                    "{}: {} is not legal",
+                    position.id,
+                    best_move
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Search tree tracing for debugging the search. Built only with the
+//! `search-trace` feature, otherwise the `search_trace!` macro expands to
+//! nothing and its arguments aren't even evaluated.
+//!
+//! Traced nodes are logged at trace level with the `pawndropper::trace`
+//! target, indented by ply, e.g.
+//! `RUST_LOG=pawndropper::trace=trace cargo run --features search-trace`
+
+/// Only the first nodes of a search are traced, the full tree is far too big
+/// to read through.
+#[cfg(feature = "search-trace")]
+pub const MAX_TRACED_NODES: u64 = 10_000;
+
+/// Log a line of the search tree.
+///
+/// * `nodes`: Number of nodes visited so far in the search
+/// * `ply`: Ply of the node, used for indentation
+/// * The remaining arguments are passed on to `format_args!`
+#[cfg(feature = "search-trace")]
+macro_rules! search_trace {
+    ($nodes:expr, $ply:expr, $($arg:tt)+) => {
+        if $nodes <= $crate::trace::MAX_TRACED_NODES {
+            tracing::trace!(
+                target: "pawndropper::trace",
+                "{:indent$}{}",
+                "",
+                format_args!($($arg)+),
+                indent = 2*$ply
+            );
+        }
+    };
+}
+
+#[cfg(not(feature = "search-trace"))]
+macro_rules! search_trace {
+    ($($arg:tt)+) => {};
+}
+
+pub(crate) use search_trace;
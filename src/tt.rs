@@ -0,0 +1,303 @@
+use crate::r#move::{Move, MoveType, NULL_MOVE};
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TTFlag {
+    Exact,
+    Beta,
+    Alpha
+}
+
+impl TTFlag {
+    const VALUES: [Self; 3] = [Self::Exact, Self::Beta, Self::Alpha];
+}
+
+/// A move packed into 16 bits for the transposition table: the from and to
+/// squares and the promotion piece (or the dropped piece of a crazyhouse
+/// drop, whose from and to squares are the same), which is enough to find
+/// the move among the legal moves of the position, see `matches`. Zero is no
+/// move.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PackedMove(u16);
+
+impl PackedMove {
+    pub const NONE: Self = Self(0);
+
+    pub fn new(m: &Move) -> Self {
+        if *m == NULL_MOVE {
+            return Self::NONE;
+        }
+
+        // The promotion piece is 1 (knight) to 4 (queen), 0 without promotion
+        let promotion_piece = match m.move_type {
+            MoveType::Promotion(piece) | MoveType::CapturePromotion(_, piece) => piece as u16,
+            MoveType::Drop => m.piece as u16,
+            _ => 0,
+        };
+        Self(m.from_square as u16 | (m.to_square as u16) << 6 | promotion_piece << 12)
+    }
+
+    pub fn is_none(self) -> bool {
+        self == Self::NONE
+    }
+
+    /// Whether `m` is the packed move.
+    pub fn matches(self, m: &Move) -> bool {
+        !self.is_none() && Self::new(m) == self
+    }
+}
+
+/// A single transposition table entry, packed into 16 bytes so a bucket of
+/// `BUCKET_SIZE` entries fills a cache line.
+///
+/// * `key`: The position's zobrist hash, to verify the entry belongs to the
+///   position (the lower bits are used to index the table)
+/// * `best_move`: Best move found in the position, `PackedMove::NONE` if unknown
+/// * `eval`: Evaluation of the position in centipawns, mate scores relative to the position
+/// * `depth`: Remaining depth the position was searched with
+/// * `flag_age`: Whether `eval` is exact, a lower bound (beta) or an upper
+///   bound (alpha) in the low 2 bits, and the search generation the entry was
+///   written in in the other 6 bits
+#[derive(Clone, Copy, Debug)]
+pub struct TTEntry {
+    pub key: u64,
+    pub best_move: PackedMove,
+    pub eval: i16,
+    pub depth: u8,
+    flag_age: u8,
+}
+
+impl TTEntry {
+    const EMPTY: Self = Self {
+        key: 0,
+        best_move: PackedMove::NONE,
+        eval: 0,
+        depth: 0,
+        flag_age: TTFlag::Alpha as u8,
+    };
+
+    const AGE_SHIFT: u8 = 2;
+
+    #[inline]
+    pub fn flag(&self) -> TTFlag {
+        TTFlag::VALUES[(self.flag_age & 0b11) as usize]
+    }
+
+    #[inline]
+    fn age(&self) -> u8 {
+        self.flag_age >> Self::AGE_SHIFT
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.key == 0
+    }
+}
+
+const BUCKET_SIZE: usize = 4;
+
+/// Entries of positions that map to the same index, aligned to a cache line
+/// so a probe touches a single one.
+#[derive(Clone, Copy)]
+#[repr(align(64))]
+struct Bucket([TTEntry; BUCKET_SIZE]);
+
+/// Fixed-size transposition table. Positions are mapped to buckets of a few
+/// entries by the low bits of their zobrist key, and within a bucket the entry
+/// to overwrite is chosen by depth and age so deep results of the current
+/// search survive.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: u8,
+}
+
+impl TranspositionTable {
+    // Ages wrap around within the bits of `TTEntry::flag_age` they're kept in
+    const AGE_MASK: u8 = 0x3f;
+    // Entries `hashfull` samples
+    const HASHFULL_SAMPLE: usize = 1000;
+
+    pub fn new(size_mb: usize) -> Self {
+        let bucket_bytes = std::mem::size_of::<Bucket>();
+        // Round down to a power of two so the bucket index is a simple mask
+        let max_buckets = ((size_mb*1024*1024)/bucket_bytes).max(1);
+        let n_buckets = 1 << max_buckets.ilog2();
+
+        Self {
+            buckets: vec![Bucket([TTEntry::EMPTY; BUCKET_SIZE]); n_buckets],
+            age: 0,
+        }
+    }
+
+    /// Start a new search generation, entries of older generations are
+    /// preferred for replacement.
+    pub fn new_search(&mut self) {
+        self.age = (self.age + 1) & Self::AGE_MASK;
+    }
+
+    /// Empty every entry, forgetting all searches so far.
+    pub fn clear(&mut self) {
+        self.buckets.fill(Bucket([TTEntry::EMPTY; BUCKET_SIZE]));
+        self.age = 0;
+    }
+
+    /// Per mille of the entries in use, estimated from the first thousand
+    /// entries as the entries are spread evenly over the table.
+    pub fn hashfull(&self) -> usize {
+        let sample = self.buckets.iter().flat_map(|bucket| bucket.0.iter()).take(Self::HASHFULL_SAMPLE);
+        let (used, sampled) = sample.fold((0, 0), |(used, sampled), entry| (used + !entry.is_empty() as usize, sampled + 1));
+
+        used*1000/sampled
+    }
+
+    #[inline]
+    fn bucket_index(&self, hash: u64) -> usize {
+        (hash as usize) & (self.buckets.len() - 1)
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TTEntry> {
+        self.buckets[self.bucket_index(hash)].0
+            .iter()
+            .find(|entry| !entry.is_empty() && entry.key == hash)
+            .copied()
+    }
+
+    /// Store the result of a search of the position with zobrist hash `hash`.
+    /// `eval` must fit in 16 bits, which all scores do, and depths beyond 255
+    /// are stored as 255.
+    pub fn store(&mut self, hash: u64, eval: i32, depth: usize, flag: TTFlag, best_move: Move) {
+        let age = self.age;
+        let depth = depth.min(u8::MAX as usize) as u8;
+        let idx = self.bucket_index(hash);
+        let bucket = &mut self.buckets[idx].0;
+
+        // Overwrite the same position, otherwise pick the least valuable
+        // entry: empty entries first, then entries of previous searches, then
+        // the shallowest entry
+        let slot = match bucket.iter().position(|entry| entry.key == hash) {
+            Some(slot) => slot,
+            None => {
+                let mut slot = 0;
+                let mut worst_score = i32::MAX;
+                for (i, entry) in bucket.iter().enumerate() {
+                    let score = if entry.is_empty() {
+                        i32::MIN
+                    } else if entry.age() != age {
+                        entry.depth as i32 - 1024
+                    } else {
+                        entry.depth as i32
+                    };
+
+                    if score < worst_score {
+                        worst_score = score;
+                        slot = i;
+                    }
+                }
+                slot
+            }
+        };
+
+        let entry = &mut bucket[slot];
+
+        // Keep a deeper result for the same position from this search, even
+        // over an exact one, e.g. of the quiescence search at depth 0
+        if entry.key == hash && entry.age() == age && entry.depth > depth {
+            return;
+        }
+
+        // Don't lose a known best move when storing a result without one
+        let best_move = if best_move == NULL_MOVE && entry.key == hash {
+            entry.best_move
+        } else {
+            PackedMove::new(&best_move)
+        };
+
+        debug_assert!(i16::try_from(eval).is_ok());
+        *entry = TTEntry {
+            key: hash,
+            best_move,
+            eval: eval as i16,
+            depth,
+            flag_age: flag as u8 | age << TTEntry::AGE_SHIFT,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Piece, Side};
+
+    #[test]
+    fn test_entry_size() {
+        assert_eq!(std::mem::size_of::<TTEntry>(), 16);
+        assert_eq!(std::mem::size_of::<Bucket>(), 64);
+    }
+
+    #[test]
+    fn test_store_probe() {
+        let mut tt = TranspositionTable::new(1);
+        let promotion = Move {
+            from_square: 49,
+            to_square: 56,
+            move_type: MoveType::CapturePromotion(Piece::Rook, Piece::Knight),
+            piece: Piece::Pawn,
+            side: Side::White,
+        };
+        let hash = 0x1234_5678_9abc_def0;
+
+        assert!(tt.probe(hash).is_none());
+        tt.store(hash, -30500, 300, TTFlag::Beta, promotion);
+
+        let entry = tt.probe(hash).unwrap();
+        assert_eq!(entry.eval, -30500);
+        assert_eq!(entry.depth, u8::MAX);
+        assert_eq!(entry.flag(), TTFlag::Beta);
+        assert!(entry.best_move.matches(&promotion));
+        assert!(!entry.best_move.matches(&Move {
+            move_type: MoveType::CapturePromotion(Piece::Rook, Piece::Queen),
+            ..promotion
+        }));
+
+        // A result without a best move keeps the known one
+        tt.store(hash, 10, 400, TTFlag::Exact, NULL_MOVE);
+        assert!(tt.probe(hash).unwrap().best_move.matches(&promotion));
+
+        // Same bucket, different position
+        assert!(tt.probe(hash ^ 1 << 63).is_none());
+    }
+
+    #[test]
+    fn test_replacement() {
+        let mut tt = TranspositionTable::new(1);
+        let hash = 0x1234_5678_9abc_def0;
+
+        // A shallower exact result doesn't replace a deeper one
+        tt.store(hash, 50, 8, TTFlag::Exact, NULL_MOVE);
+        tt.store(hash, -20, 0, TTFlag::Exact, NULL_MOVE);
+        let entry = tt.probe(hash).unwrap();
+        assert_eq!((entry.eval, entry.depth), (50, 8));
+
+        // It does in the next search
+        tt.new_search();
+        tt.store(hash, -20, 0, TTFlag::Exact, NULL_MOVE);
+        let entry = tt.probe(hash).unwrap();
+        assert_eq!((entry.eval, entry.depth), (-20, 0));
+    }
+
+    #[test]
+    fn test_hashfull_clear() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+
+        // A position in every bucket of the sample fills a quarter of it, the
+        // low bits of the keys index the buckets
+        for bucket in 0..TranspositionTable::HASHFULL_SAMPLE/BUCKET_SIZE {
+            tt.store(bucket as u64 | 1 << 32, 0, 1, TTFlag::Exact, NULL_MOVE);
+        }
+        assert_eq!(tt.hashfull(), 250);
+
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
+        assert!(tt.probe(1 << 32).is_none());
+    }
+}
@@ -0,0 +1,785 @@
+//! Full-screen terminal interface for games against the engine, `--tui` in
+//! builds with the `tui` feature. The board, the move list, the clocks, an
+//! evaluation bar and the engine's search output are redrawn in place instead
+//! of being printed one after another, and the engine searches in the
+//! background so the clocks and the search output update while it thinks.
+//!
+//! Moves and commands are entered on the input line as in the line
+//! interface. Esc makes the engine move now, Ctrl-C quits.
+
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Widget};
+use ratatui::{DefaultTerminal, Frame};
+
+use pawndropper::adjudication::Adjudicator;
+use pawndropper::annotation::AnnotationThresholds;
+use pawndropper::board::{Board, BoardStyle, Side, Variant};
+use pawndropper::clock::{format_duration, Clock};
+use pawndropper::eval::evaluate;
+use pawndropper::evalhistory::EvalHistory;
+use pawndropper::game::GameState;
+use pawndropper::gamelog::{GameLog, SearchReport};
+use pawndropper::magic::MagicBitboard;
+use pawndropper::motifs::find_motifs;
+use pawndropper::move_bitboards::PSEUDO_LEGAL_MOVES;
+use pawndropper::learning::LearningStore;
+use pawndropper::opening::OpeningPolicy;
+use pawndropper::opponent::Opponent;
+use pawndropper::r#move::{Move, MoveResult};
+use pawndropper::savegame::Settings;
+use pawndropper::search::{format_score, Iteration, SearchAsync, SearchHandle, MAX_SEARCH_DEPTH};
+use pawndropper::skill::Skill;
+use pawndropper::speedtest::Calibration;
+
+use crate::cli::PlayArgs;
+use crate::output::{format_captured, format_hand, format_principal_variation, white_share, View};
+use crate::repl::{self, Command};
+use crate::search_limits;
+
+// How often the screen is redrawn while nothing happens, for the running
+// clock and the search output
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Lines of search output kept for the search pane
+const MAX_SEARCH_LINES: usize = 200;
+
+const LIGHT_SQUARE: Color = Color::Rgb(240, 217, 181);
+const DARK_SQUARE: Color = Color::Rgb(181, 136, 99);
+const LIGHT_LAST_MOVE: Color = Color::Rgb(205, 210, 106);
+const DARK_LAST_MOVE: Color = Color::Rgb(170, 162, 58);
+
+/// Draws a board with `orientation` at the bottom, the squares of the last
+/// move highlighted. The squares grow with the area, which should be at
+/// least 26 columns by 9 rows.
+struct BoardWidget<'a> {
+    board: &'a Board,
+    orientation: Side,
+    last_move: Option<Move>,
+    style: BoardStyle,
+}
+
+impl Widget for BoardWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Squares are about twice as wide as high so they look square, with
+        // a column for the ranks and a row for the files
+        let square_height = ((area.height.saturating_sub(1))/8).clamp(1, 3);
+        let square_width = (square_height*2 + 1).min(area.width.saturating_sub(2)/8).max(1);
+
+        for row in 0..8 {
+            for column in 0..8 {
+                // Square 0 is h1
+                let (file, rank) = if self.orientation == Side::White { (column, 7 - row) } else { (7 - column, row) };
+                let square = (rank*8 + 7 - file) as usize;
+
+                let light = (file + rank) % 2 == 1;
+                let last_move = self.last_move.is_some_and(|m| m.from_square == square || m.to_square == square);
+                let background = match (light, last_move) {
+                    (true, false) => LIGHT_SQUARE,
+                    (false, false) => DARK_SQUARE,
+                    (true, true) => LIGHT_LAST_MOVE,
+                    (false, true) => DARK_LAST_MOVE,
+                };
+
+                let x = area.x + 2 + column*square_width;
+                let y = area.y + row*square_height;
+                let square_area = Rect::new(x, y, square_width, square_height).intersection(area);
+                buf.set_style(square_area, Style::new().bg(background));
+
+                // The same glyphs for both sides, told apart by their color
+                if let Some((piece, side)) = self.board.piece_on(square) {
+                    let symbol = match self.style {
+                        BoardStyle::Letters => piece.fen_char(side).to_string(),
+                        BoardStyle::Unicode | BoardStyle::Monochrome => piece.ascii(Side::White).to_owned(),
+                    };
+                    let color = if side == Side::White { Color::White } else { Color::Black };
+                    buf.set_string(
+                        x + square_width/2,
+                        y + square_height/2,
+                        symbol,
+                        Style::new().fg(color).bg(background).add_modifier(Modifier::BOLD),
+                    );
+                }
+            }
+
+            let rank = if self.orientation == Side::White { 8 - row } else { row + 1 };
+            buf.set_string(area.x, area.y + row*square_height + square_height/2, rank.to_string(), Style::new());
+        }
+
+        for column in 0..8 {
+            let file = if self.orientation == Side::White { column } else { 7 - column };
+            let file = char::from(b'a' + file as u8).to_string();
+            buf.set_string(area.x + 2 + column*square_width + square_width/2, area.y + 8*square_height, file, Style::new());
+        }
+    }
+}
+
+/// Vertical bar split between white and black by the expected score of the
+/// evaluation, with `orientation`'s share at the bottom like the board.
+struct EvalBar {
+    // Evaluation in centipawns from white's perspective
+    eval: Option<i32>,
+    orientation: Side,
+}
+
+impl Widget for EvalBar {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let white_share = self.eval.map_or(0.5, white_share);
+        let bottom_share = if self.orientation == Side::White { white_share } else { 1.0 - white_share };
+        let bottom_rows = (bottom_share*area.height as f64).round() as u16;
+
+        let (top, bottom) = if self.orientation == Side::White { (Color::Black, Color::White) } else { (Color::White, Color::Black) };
+        for row in 0..area.height {
+            let color = if row >= area.height - bottom_rows { bottom } else { top };
+            buf.set_style(Rect::new(area.x, area.y + row, area.width, 1), Style::new().bg(color));
+        }
+    }
+}
+
+// Searcher handed back by the search thread along with its search result
+type SearchResult = (SearchAsync, (i32, Move, Vec<Move>));
+
+/// A search for the engine's move running on its own thread.
+struct Thinking {
+    handle: JoinHandle<SearchResult>,
+    iterations: Receiver<Iteration>,
+    start: Instant,
+}
+
+struct App {
+    game: GameState,
+    legal_moves: Vec<Move>,
+    // The played moves in algebraic notation, and the move number and side
+    // to move of the position the game started from
+    moves: Vec<String>,
+    start: (usize, Side),
+
+    cpu_side: Side,
+    // Side at the bottom of the board
+    view: View,
+    board_style: BoardStyle,
+
+    args: PlayArgs,
+    settings: Settings,
+    opening: OpeningPolicy,
+    clock: Option<Clock>,
+    move_time: Option<Duration>,
+    turn_start: Instant,
+    adjudicator: Adjudicator,
+    game_log: GameLog,
+    // What was learned from earlier games, with `--learn`
+    learning: Option<LearningStore>,
+
+    // Handed to the search thread while the engine thinks
+    searcher: Option<SearchAsync>,
+    search_handle: SearchHandle,
+    thinking: Option<Thinking>,
+
+    // Evaluation of the engine's last search, from white's perspective, which
+    // the user's next move is annotated against
+    eval: Option<i32>,
+    evals: EvalHistory,
+    search_lines: Vec<Line<'static>>,
+
+    input: String,
+    status: String,
+    // Moves the last input can mean, the next input chooses one
+    choices: Vec<Move>,
+    // Description of the result once the game is over
+    result: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    fn new(args: PlayArgs, board_style: BoardStyle, depth: usize, game_log: GameLog, learning: Option<LearningStore>) -> Self {
+        let settings = Settings {
+            depth,
+            skill: args.skill,
+            nodes: args.nodes,
+            ponder: false,
+        };
+
+        let mut searcher = SearchAsync::with_hash_size(args.hash);
+        searcher.set_node_limit(settings.nodes);
+        searcher.set_skill(Skill::new(settings.skill));
+        args.personality.apply(&mut searcher);
+        let search_handle = searcher.handle();
+
+        let game = GameState::with_variant(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), args.variant);
+        let mut app = Self {
+            legal_moves: Vec::new(),
+            moves: Vec::new(),
+            start: (game.move_number, game.board.side_to_move),
+            game,
+            cpu_side: args.cpu_side,
+            view: args.view,
+            board_style,
+            opening: OpeningPolicy::new(args.opening_depth, args.opening_margin),
+            clock: args.tc.map(Clock::new),
+            move_time: args.movetime.map(Duration::from_millis),
+            args,
+            settings,
+            turn_start: Instant::now(),
+            adjudicator: Adjudicator::new(),
+            game_log,
+            learning,
+            searcher: Some(searcher),
+            search_handle,
+            thinking: None,
+            eval: None,
+            evals: EvalHistory::new(),
+            search_lines: Vec::new(),
+            input: String::new(),
+            status: "Enter a move or `help`".to_owned(),
+            choices: Vec::new(),
+            result: None,
+            quit: false,
+        };
+        app.new_game(None);
+        app
+    }
+
+    /// Start a game from the starting position, with the computer's first
+    /// move picked by the opening policy if it plays white, avoiding the lines
+    /// lost repeatedly.
+    fn new_game(&mut self, user_side: Option<Side>) {
+        self.cpu_side = user_side.map_or(self.cpu_side, Side::opposite);
+        self.set_game(GameState::with_variant(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), self.game.board.variant));
+
+        if self.cpu_side == Side::White {
+            let search_moves = self.search_moves();
+            let searcher = self.searcher.as_mut().unwrap();
+            let m = match self.args.opponent {
+                Opponent::Engine => self.opening.pick_move(searcher, &mut self.game, &search_moves).unwrap(),
+                opponent => opponent.find_move(searcher, &mut self.game, MAX_SEARCH_DEPTH, &search_moves).1,
+            };
+            self.moves.push(m.to_algebraic_with_state(&self.legal_moves));
+            (_, self.legal_moves) = self.game.make_move(&m);
+        }
+        self.game_log.start(&self.game, self.cpu_side, &self.settings, self.clock.as_ref().map(Clock::time_control));
+    }
+
+    fn set_game(&mut self, game: GameState) {
+        self.game = game;
+        (self.legal_moves, _) = self.game.get_legal_moves();
+        self.moves.clear();
+        self.start = (self.game.move_number, self.game.board.side_to_move);
+        self.clock = self.args.tc.map(Clock::new);
+        self.turn_start = Instant::now();
+        self.adjudicator.reset();
+        self.eval = None;
+        self.evals.clear();
+        self.search_lines.clear();
+        self.result = None;
+    }
+
+    fn game_over(&mut self, winner: Option<Side>, reason: &str, description: &str) {
+        self.game_log.result(winner, reason);
+        self.result = Some(description.to_owned());
+        self.status = format!("{}, `new` starts a new game", description);
+
+        if let (Some(learning), Some(path)) = (&mut self.learning, &self.args.learn) {
+            learning.record_game(&self.game, winner, self.evals.points());
+            if let Err(err) = learning.save(path) {
+                self.status = format!("Error: {}", err);
+            }
+        }
+    }
+
+    /// Root moves for the engine to search in the current position, leaving
+    /// out those into lines it lost repeatedly. Empty for all moves.
+    fn search_moves(&self) -> Vec<Move> {
+        self.learning.as_ref().map_or(vec![], |learning| learning.search_moves(&self.game, &self.legal_moves))
+    }
+
+    /// Check for the end of the game after a move with result `move_res` by
+    /// `side`.
+    fn check_result(&mut self, move_res: Option<MoveResult>, side: Side) {
+        match move_res {
+            Some(MoveResult::Checkmate) if side == self.cpu_side => {
+                self.game_over(Some(side), "Checkmate", "Checkmate --- computer wins");
+            },
+            Some(MoveResult::Checkmate) => self.game_over(Some(side), "Checkmate", "Checkmate --- computer loses"),
+            Some(MoveResult::ThirdCheck) if side == self.cpu_side => {
+                self.game_over(Some(side), "Third check", "Third check --- computer wins");
+            },
+            Some(MoveResult::ThirdCheck) => self.game_over(Some(side), "Third check", "Third check --- computer loses"),
+            Some(MoveResult::Draw(reason)) => {
+                self.game_over(None, &format!("{:?}", reason), &format!("Draw: {:?}", reason));
+            },
+            // The user claims a draw on their turn
+            _ if side == self.cpu_side => {
+                if let Some(reason) = self.game.claimable_draw() {
+                    self.status = format!("A draw can be claimed: {:?}, `offer draw` claims it", reason);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            },
+            KeyCode::Esc if self.thinking.is_some() => self.search_handle.stop(),
+            KeyCode::Esc => self.input.clear(),
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.input);
+                if !line.trim().is_empty() {
+                    self.submit(&line);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn submit(&mut self, line: &str) {
+        // The answer to which move the last input meant
+        if !self.choices.is_empty() {
+            let choices = std::mem::take(&mut self.choices);
+            match repl::resolve_choice(line, &choices, &self.legal_moves) {
+                Some(m) => self.play_user_move(m),
+                None => self.status = "No move made".to_owned(),
+            }
+            return;
+        }
+
+        let command = match repl::parse_command(line) {
+            Ok(command) => command,
+            Err(err) => {
+                self.status = err;
+                return;
+            },
+        };
+
+        match command {
+            Command::Flip => self.view = View::from(self.view.orientation(self.cpu_side).opposite()),
+            Command::View(view) => self.view = view,
+            Command::Quit => self.quit = true,
+            Command::Fen => self.status = self.game.to_fen(),
+            Command::Help => {
+                self.status = "Moves in algebraic notation, flip, view <white|black|auto>, fen, eval, bitboards, motifs, evals [file], clearhash, undo, stop, offer draw, resign, new [white|black], setboard <fen>, quit".to_owned();
+            },
+            // Already on screen
+            Command::Show | Command::History => {},
+            Command::Analyze | Command::Save(_) | Command::Load(_) | Command::Board(_) => {
+                self.status = "Not available in the full-screen interface".to_owned();
+            },
+            Command::Bitboards => {
+                self.search_lines.extend(self.game.bitboards().to_string().lines().map(|line| Line::raw(line.to_owned())));
+            },
+            Command::Motifs => {
+                let motifs = find_motifs(&self.game);
+                if motifs.is_empty() {
+                    self.status = "No tactical motifs".to_owned();
+                }
+                self.search_lines.extend(motifs.iter().map(|motif| Line::raw(motif.to_string())));
+            },
+            Command::Evals(None) => {
+                self.search_lines.extend(self.evals.graph().lines().map(|line| Line::raw(line.to_owned())));
+            },
+            Command::Evals(Some(path)) => match self.evals.save_csv(&path) {
+                Ok(()) => self.status = format!("Evaluations saved to {}", path),
+                Err(err) => self.status = format!("Error: {}", err),
+            },
+            Command::Stop => match self.thinking {
+                Some(_) => self.search_handle.stop(),
+                None => self.status = "The engine isn't thinking".to_owned(),
+            },
+            _ if self.thinking.is_some() => self.status = "The engine is thinking, Esc makes it move now".to_owned(),
+            Command::Eval => {
+                self.search_lines.extend(evaluate(&self.game).to_string().lines().map(|line| Line::raw(line.to_owned())));
+            },
+            Command::ClearHash => {
+                self.searcher.as_mut().unwrap().clear_hash();
+                self.status = "Hash cleared".to_owned();
+            },
+            Command::New(user_side) => {
+                self.new_game(user_side);
+                self.status = "New game".to_owned();
+            },
+            // The user plays the side to move of the new position
+            Command::SetBoard(fen) => match GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), &fen) {
+                Ok(game) => {
+                    self.set_game(game);
+                    self.cpu_side = self.game.board.side_to_move.opposite();
+                    self.game_log.start(&self.game, self.cpu_side, &self.settings, self.clock.as_ref().map(Clock::time_control));
+                },
+                Err(err) => self.status = format!("Error: {}", err),
+            },
+            _ if self.result.is_some() => self.status = "The game is over, `new` starts a new game".to_owned(),
+            Command::Resign => self.game_over(Some(self.cpu_side), "Resignation", "You resign --- computer wins"),
+            Command::OfferDraw => {
+                if let Some(reason) = self.game.claimable_draw() {
+                    self.game_over(None, &format!("{:?}", reason), &format!("Draw claimed: {:?}", reason));
+                } else if self.adjudicator.accepts_draw(&self.game) {
+                    self.game_over(None, "Agreement", "Draw offer accepted");
+                } else {
+                    self.status = "Draw offer declined".to_owned();
+                }
+            },
+            // Take back the user's last move and the computer's reply
+            Command::Undo => {
+                if self.game.n_played_moves() < 2 || self.moves.len() < 2 {
+                    self.status = "No moves to take back".to_owned();
+                    return;
+                }
+                self.game.undo_move();
+                self.game.undo_move();
+                self.moves.truncate(self.moves.len() - 2);
+                self.game_log.undo(2);
+                self.adjudicator.reset();
+                self.eval = None;
+                self.evals.truncate(self.game.half_move_number);
+                self.turn_start = Instant::now();
+                (self.legal_moves, _) = self.game.get_legal_moves();
+            },
+            Command::Move(line) => self.user_move(&line),
+        }
+    }
+
+    fn user_move(&mut self, line: &str) {
+        match repl::matching_moves(line, &self.legal_moves).as_slice() {
+            [] => {
+                let algebraic: Vec<String> = self.legal_moves.iter().map(|m| m.to_algebraic_with_state(&self.legal_moves)).collect();
+                self.status = match (repl::illegal_reason(line, &self.game), repl::suggest_moves(line, &algebraic).as_slice()) {
+                    (Some(reason), []) => format!("Illegal move '{}': {}", line, reason),
+                    (Some(reason), suggestions) => format!("Illegal move '{}': {}, did you mean {}?", line, reason, suggestions.join(" or ")),
+                    (None, []) => format!("Invalid or illegal move '{}', enter `help` for the commands", line),
+                    (None, suggestions) => format!("Invalid or illegal move '{}', did you mean {}?", line, suggestions.join(" or ")),
+                };
+            },
+            &[m] => self.play_user_move(m),
+            candidates => {
+                self.status = repl::choice_prompt(candidates, &self.legal_moves);
+                self.choices = candidates.to_vec();
+            },
+        }
+    }
+
+    fn play_user_move(&mut self, m: Move) {
+        self.status.clear();
+
+        // The user's time runs from the end of the computer's move
+        let user_time = self.turn_start.elapsed();
+        if let Some(clock) = &mut self.clock {
+            if !clock.punch(self.cpu_side.opposite(), user_time) {
+                self.game_over(Some(self.cpu_side), "Time forfeit", "Time out --- computer wins");
+                return;
+            }
+        }
+        self.turn_start = Instant::now();
+
+        self.game_log.user_move(&self.game, &m, self.cpu_side, user_time);
+        self.moves.push(m.to_algebraic_with_state(&self.legal_moves));
+        let move_res;
+        (move_res, self.legal_moves) = self.game.make_move(&m);
+
+        self.check_result(move_res, self.cpu_side.opposite());
+        if self.result.is_none() {
+            self.start_search();
+        }
+    }
+
+    /// Search for the engine's move on a thread of its own, the result is
+    /// picked up by `update`.
+    fn start_search(&mut self) {
+        let mut searcher = self.searcher.take().unwrap();
+        let (time_limit, depth) = search_limits(self.clock.as_ref(), self.move_time, self.cpu_side, self.settings.depth, self.args.personality, Duration::from_millis(self.args.move_overhead));
+        searcher.set_time_limit(time_limit);
+        let (sender, iterations) = mpsc::channel();
+        searcher.set_iteration_sender(Some(sender));
+
+        self.search_handle.clear();
+        self.search_lines.push(Line::raw(""));
+        let mut game = self.game.clone();
+        let search_moves = self.search_moves();
+        let opponent = self.args.opponent;
+        let handle = thread::spawn(move || {
+            let result = opponent.find_move(&mut searcher, &mut game, depth, &search_moves);
+            searcher.set_iteration_sender(None);
+            (searcher, result)
+        });
+        self.thinking = Some(Thinking { handle, iterations, start: self.turn_start });
+    }
+
+    /// Show the completed iterations of the engine's search, and make its
+    /// move once the search is done.
+    fn update(&mut self) {
+        let Some(thinking) = &self.thinking else {
+            return;
+        };
+
+        for (depth, eval, pv) in thinking.iterations.try_iter() {
+            self.eval = Some(eval);
+            let pv = format_principal_variation(&self.game, &pv, self.legal_moves.clone());
+            self.search_lines.push(Line::from(vec![
+                Span::raw(format!("{:>2} ", depth)),
+                Span::raw(format!("{:>7} ", format_score(eval))).bold(),
+                Span::raw(pv),
+            ]));
+        }
+
+        if !thinking.handle.is_finished() {
+            return;
+        }
+        let thinking = self.thinking.take().unwrap();
+        let duration = thinking.start.elapsed();
+        let (searcher, (best_eval, best_move, pv)) = thinking.handle.join().unwrap();
+        let nodes = searcher.nodes();
+        let depth = searcher.completed_depth();
+        let hashfull = searcher.hashfull();
+        self.searcher = Some(searcher);
+
+        let nps = (nodes as f64/duration.as_secs_f64()) as u64;
+        self.search_lines.push(Line::raw(format!("{} nodes in {:.2}s ({} nps, hash {}‰ full)", nodes, duration.as_secs_f64(), nps, hashfull)).dim());
+        let excess = self.search_lines.len().saturating_sub(MAX_SEARCH_LINES);
+        self.search_lines.drain(..excess);
+
+        if let Some(clock) = &mut self.clock {
+            if !clock.punch(self.cpu_side, duration) {
+                self.game_over(Some(self.cpu_side.opposite()), "Time forfeit", "Time out --- you win");
+                return;
+            }
+        }
+
+        // Judge the user's move by how the evaluation changed
+        if let Some(eval_before) = self.eval {
+            let loss = AnnotationThresholds::loss(self.cpu_side.opposite(), eval_before, best_eval);
+            if let Some(annotation) = self.args.annotate.annotate(loss) {
+                self.game_log.annotation(&self.game, annotation, loss);
+                if let Some(user_move) = self.moves.last_mut() {
+                    self.status = format!("{}{} ({}, {})", user_move, annotation, annotation.name(), format_score(-loss));
+                    user_move.push_str(annotation.symbol());
+                }
+            }
+        }
+
+        self.evals.record(&self.game, best_eval);
+
+        // The evaluation is from white's perspective
+        self.eval = Some(best_eval);
+        self.adjudicator.record_eval(if self.cpu_side == Side::White { best_eval } else { -best_eval });
+        if self.adjudicator.should_resign() {
+            self.game_over(Some(self.cpu_side.opposite()), "Resignation", "Computer resigns --- you win");
+            return;
+        }
+
+        self.game_log.engine_move(&self.game, &best_move, self.cpu_side, &SearchReport {
+            depth,
+            eval: best_eval,
+            pv: format_principal_variation(&self.game, &pv, self.legal_moves.clone()),
+            nodes,
+            time: duration,
+            hashfull,
+        });
+        self.moves.push(best_move.to_algebraic_with_state(&self.legal_moves));
+        let move_res;
+        (move_res, self.legal_moves) = self.game.make_move(&best_move);
+        self.turn_start = Instant::now();
+        self.evals.record(&self.game, best_eval);
+
+        self.check_result(move_res, self.cpu_side);
+    }
+
+    /// Remaining time of `side`, counting down while it's to move.
+    fn remaining(&self, clock: &Clock, side: Side) -> Duration {
+        if self.result.is_none() && self.game.board.side_to_move == side {
+            clock.remaining(side).saturating_sub(self.turn_start.elapsed())
+        } else {
+            clock.remaining(side)
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, input_area, status_area] = Layout::vertical([
+            Constraint::Min(11),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        let [board_area, bar_area, side_area] = Layout::horizontal([
+            Constraint::Length(44),
+            Constraint::Length(3),
+            Constraint::Min(30),
+        ]).areas(main);
+        let [clock_area, moves_area, search_area] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Percentage(40),
+            Constraint::Fill(1),
+        ]).areas(side_area);
+
+        let title = match self.cpu_side {
+            Side::White => " Computer (white) vs. you (black) ",
+            Side::Black => " You (white) vs. computer (black) ",
+        };
+        // With the captured material (in crazyhouse the pieces in hand) of
+        // the side at the top in the top right and of the side at the bottom
+        // below the board
+        let orientation = self.view.orientation(self.cpu_side);
+        let captured = |side| if self.game.board.variant == Variant::Crazyhouse {
+            format_hand(&self.game.board, side, self.board_style)
+        } else {
+            format_captured(&self.game, side, self.board_style)
+        };
+        let block = Block::bordered()
+            .title(title)
+            .title(Line::raw(captured(orientation.opposite())).right_aligned())
+            .title_bottom(Line::raw(captured(orientation)).right_aligned());
+        frame.render_widget(BoardWidget {
+            board: &self.game.board,
+            orientation,
+            last_move: self.game.played_moves().last(),
+            style: self.board_style,
+        }, block.inner(board_area));
+        frame.render_widget(block, board_area);
+
+        // Spans the ranks of the board
+        let score = self.eval.map_or_else(|| "-".to_owned(), format_score);
+        let block = Block::bordered().title_bottom(Line::raw(score).centered());
+        frame.render_widget(EvalBar { eval: self.eval, orientation: self.view.orientation(self.cpu_side) }, block.inner(bar_area));
+        frame.render_widget(block, bar_area);
+
+        let clock_lines: Vec<Line> = match &self.clock {
+            Some(clock) => Side::VALUES.iter().map(|&side| {
+                let name = if side == Side::White { "White" } else { "Black" };
+                let line = Line::raw(format!("{} {:>10}", name, format_duration(self.remaining(clock, side))));
+                if side == self.game.board.side_to_move { line.bold() } else { line.dim() }
+            }).collect(),
+            None => vec![Line::raw(match self.move_time {
+                Some(move_time) => format!("{} ms per move", move_time.as_millis()),
+                None => format!("Depth {}", self.settings.depth),
+            })],
+        };
+        frame.render_widget(Paragraph::new(clock_lines).block(Block::bordered().title(" Clock ")), clock_area);
+
+        // Moves in numbered pairs, scrolled to the last move
+        let (mut move_number, start_side) = self.start;
+        let mut move_lines = Vec::new();
+        let mut moves = self.moves.iter();
+        if start_side == Side::Black {
+            if let Some(m) = moves.next() {
+                move_lines.push(Line::raw(format!("{:>3}. {:<8} {}", move_number, "..", m)));
+                move_number += 1;
+            }
+        }
+        let moves: Vec<&String> = moves.collect();
+        for pair in moves.chunks(2) {
+            move_lines.push(Line::raw(format!("{:>3}. {:<8} {}", move_number, pair[0], pair.get(1).map_or("", |m| m.as_str()))));
+            move_number += 1;
+        }
+        let scroll = (move_lines.len() as u16).saturating_sub(moves_area.height.saturating_sub(2));
+        frame.render_widget(Paragraph::new(move_lines).scroll((scroll, 0)).block(Block::bordered().title(" Moves ")), moves_area);
+
+        let title = match &self.thinking {
+            Some(thinking) => format!(
+                " Search (thinking {:.1}s, {} nodes) ",
+                thinking.start.elapsed().as_secs_f64(),
+                self.search_handle.nodes()
+            ),
+            None => " Search ".to_owned(),
+        };
+        let scroll = (self.search_lines.len() as u16).saturating_sub(search_area.height.saturating_sub(2));
+        frame.render_widget(
+            Paragraph::new(self.search_lines.clone()).scroll((scroll, 0)).block(Block::bordered().title(title)),
+            search_area,
+        );
+
+        let prompt = if self.game.board.side_to_move == Side::White {
+            format!("move {}> ", self.game.move_number)
+        } else {
+            format!("move ..{}> ", self.game.move_number)
+        };
+        let input = Paragraph::new(format!("{}{}", prompt, self.input)).block(Block::bordered());
+        frame.render_widget(input, input_area);
+        frame.set_cursor_position((input_area.x + 1 + (prompt.len() + self.input.chars().count()) as u16, input_area.y + 1));
+
+        let status = match &self.result {
+            Some(_) => Line::raw(self.status.as_str()).bold(),
+            None => Line::raw(self.status.as_str()),
+        };
+        frame.render_widget(status, status_area);
+    }
+}
+
+fn run_app(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if event::poll(REFRESH_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key.code, key.modifiers);
+                }
+            }
+        }
+        app.update();
+    }
+
+    // Don't leave the engine searching on exit
+    if let Some(thinking) = app.thinking.take() {
+        app.search_handle.stop();
+        let _ = thinking.handle.join();
+    }
+    Ok(())
+}
+
+/// Play games against the user in the full-screen interface until they quit,
+/// with the engine searching to `depth` unless on time. The calibration of
+/// this machine gives the time a move takes, if measured.
+pub fn run(args: PlayArgs, board_style: BoardStyle, depth: usize, calibration: Option<Calibration>) -> io::Result<()> {
+    let game_log = GameLog::new(args.log.as_deref()).map_err(io::Error::other)?;
+    let learning = args.learn.as_deref().map(LearningStore::load).transpose().map_err(io::Error::other)?;
+    let mut app = App::new(args, board_style, depth, game_log, learning);
+    if let Some(calibration) = calibration {
+        app.status = format!("Enter a move or `help`, the engine takes about {:.1?} per move", calibration.estimated_time(depth));
+    }
+
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_board(orientation: Side) -> Buffer {
+        let game = GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get());
+        let area = Rect::new(0, 0, 26, 9);
+        let mut buf = Buffer::empty(area);
+        BoardWidget { board: &game.board, orientation, last_move: None, style: BoardStyle::Letters }.render(area, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_board_widget() {
+        // Squares of 3 by 1 cells, the pieces in the middle
+        let buf = render_board(Side::White);
+        assert_eq!(buf[(0, 0)].symbol(), "8");
+        assert_eq!(buf[(3, 0)].symbol(), "r");
+        assert_eq!(buf[(3, 7)].symbol(), "R");
+        assert_eq!(buf[(15, 7)].symbol(), "K");
+        assert_eq!(buf[(3, 8)].symbol(), "a");
+        assert_eq!(buf[(2, 7)].bg, DARK_SQUARE);
+        assert_eq!(buf[(5, 7)].bg, LIGHT_SQUARE);
+
+        let buf = render_board(Side::Black);
+        assert_eq!(buf[(0, 0)].symbol(), "1");
+        assert_eq!(buf[(3, 0)].symbol(), "R");
+        assert_eq!(buf[(12, 0)].symbol(), "K");
+        assert_eq!(buf[(3, 8)].symbol(), "h");
+        assert_eq!(buf[(2, 0)].bg, LIGHT_SQUARE);
+    }
+}
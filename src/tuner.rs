@@ -0,0 +1,321 @@
+//! Texel tuning of the evaluation weights: the weights are optimized to
+//! minimize the error between the game results of a set of labeled positions
+//! and the win probabilities predicted from their static evaluation.
+//!
+//! The dataset is a text file with a FEN and the result of the game it was
+//! taken from on every line, as `1-0`, `0-1` or `1/2-1/2` (optionally quoted,
+//! as in EPD files) or as a white score like `[1.0]`, `[0.5]` or `[0.0]`. The
+//! positions should be quiet, they're evaluated without any search.
+
+use std::fmt::Write;
+use std::fs;
+use std::thread;
+use std::time::Instant;
+
+use tracing::info;
+
+use crate::board::{Board, Piece, BOARD_WIDTH, N_SQUARES};
+use crate::error::Error;
+use crate::eval::{eval_with_params, EvalParams};
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::MoveBitboards;
+use crate::score::Score;
+
+/// A position and the result of its game from white's perspective: 1 for a
+/// white win, 0.5 for a draw and 0 for a black win.
+struct TuningPosition {
+    board: Board,
+    result: f64,
+}
+
+/// Parse the game result of a dataset line.
+fn parse_result(line: &str) -> Option<f64> {
+    line.split(|c: char| c.is_whitespace() || c == ';')
+        .rev()
+        .find_map(|token| match token.trim_matches(['"', '[', ']']) {
+            "1-0" | "1.0" => Some(1.0),
+            "1/2-1/2" | "0.5" => Some(0.5),
+            "0-1" | "0.0" => Some(0.0),
+            _ => None,
+        })
+}
+
+fn load_dataset(path: &str) -> Result<Vec<TuningPosition>, Error> {
+    let contents = fs::read_to_string(path).map_err(|source| Error::Read { path: path.into(), source })?;
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let result = parse_result(line).ok_or_else(|| Error::InvalidDataset(format!("no result in line '{}'", line)))?;
+            let fen: Vec<&str> = line.split_whitespace().take(4).collect();
+            let board = Board::from_fen(&fen.join(" "))?;
+
+            Ok(TuningPosition { board, result })
+        })
+        .collect()
+}
+
+/// Add the color-flipped copy of every position, with the result from the
+/// other side's perspective, and the file-mirrored copies of positions
+/// without castling rights, which mirroring would lose.
+fn augment_dataset(positions: Vec<TuningPosition>) -> Vec<TuningPosition> {
+    let mut augmented = Vec::with_capacity(positions.len()*4);
+
+    for position in positions {
+        let can_castle = position.board.castling_right_long.contains(&true)
+            || position.board.castling_right_short.contains(&true);
+        if !can_castle {
+            let mirrored = position.board.mirrored();
+            augmented.push(TuningPosition { board: mirrored.flipped(), result: 1.0 - position.result });
+            augmented.push(TuningPosition { board: mirrored, result: position.result });
+        }
+
+        augmented.push(TuningPosition { board: position.board.flipped(), result: 1.0 - position.result });
+        augmented.push(position);
+    }
+
+    augmented
+}
+
+/// Expected score of white for a white evaluation of `score` centipawns.
+///
+/// * `k`: Scaling constant fitted to the dataset, see `fit_scaling_constant`
+fn win_probability(score: i32, k: f64) -> f64 {
+    1.0/(1.0 + 10f64.powf(-k*score as f64/400.0))
+}
+
+/// Mean squared error between the game results and the win probabilities
+/// predicted by evaluating with `params`. The positions are split over all
+/// available cores.
+fn error(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    positions: &[TuningPosition],
+    params: &EvalParams,
+    k: f64,
+) -> f64 {
+    let n_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = positions.len().div_ceil(n_threads).max(1);
+
+    let total_error: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || {
+                let mut game = GameState::new(pl_moves, magics);
+                chunk.iter()
+                    .map(|position| {
+                        game.set_board(position.board.clone());
+                        let score = eval_with_params(&game, params);
+                        (position.result - win_probability(score, k)).powi(2)
+                    })
+                    .sum::<f64>()
+            }))
+            .collect();
+
+        handles.into_iter().map(|handle| handle.join().unwrap()).sum()
+    });
+
+    total_error/positions.len() as f64
+}
+
+/// Find the scaling constant that maps evaluations to win probabilities with
+/// the least error for the current weights, by narrowing down a scan.
+fn fit_scaling_constant(pl_moves: &'static MoveBitboards, magics: &'static MagicBitboard, positions: &[TuningPosition]) -> f64 {
+    let params = EvalParams::DEFAULT;
+    let mut best_k = 1.0;
+    let mut best_error = error(pl_moves, magics, positions, &params, best_k);
+
+    for step in [0.1, 0.01, 0.001] {
+        let center = best_k;
+        for i in -10..=10 {
+            let k = center + i as f64*step;
+            if k <= 0.0 {
+                continue;
+            }
+
+            let k_error = error(pl_moves, magics, positions, &params, k);
+            if k_error < best_error {
+                best_error = k_error;
+                best_k = k;
+            }
+        }
+    }
+
+    best_k
+}
+
+fn format_score(score: Score) -> String {
+    format!("S({}, {})", score.mg(), score.eg())
+}
+
+pub(crate) fn write_scores(source: &mut String, name: &str, len: &str, scores: &[Score]) {
+    let scores: Vec<String> = scores.iter().map(|&score| format_score(score)).collect();
+    writeln!(source, "const {}: [Score; {}] = [{}];", name, len, scores.join(", ")).unwrap();
+}
+
+/// Piece placement values are kept as separate middlegame and endgame tables
+/// in `src/eval.rs` for readability, write them the same way.
+fn write_placement_values(
+    source: &mut String,
+    name: &str,
+    values: &[[Score; N_SQUARES]; Piece::N_PIECES],
+    phase_value: fn(Score) -> i32,
+) {
+    writeln!(source, "const {}: [[i32; N_SQUARES]; Piece::N_PIECES] = [", name).unwrap();
+    for piece in Piece::VALUES {
+        writeln!(source, "    // {:?}", piece).unwrap();
+        writeln!(source, "    [").unwrap();
+        for row in values[piece as usize].chunks(BOARD_WIDTH) {
+            let row: Vec<String> = row.iter().map(|&score| format!("{:4}", phase_value(score))).collect();
+            writeln!(source, "       {},", row.join(",")).unwrap();
+        }
+        writeln!(source, "    ],").unwrap();
+    }
+    writeln!(source, "];").unwrap();
+}
+
+/// Rust source of the tuned weights, with the constant names used in
+/// `src/eval.rs` so they can be pasted over the current ones.
+fn params_source(params: &EvalParams) -> String {
+    let mut source = String::new();
+
+    write_scores(&mut source, "PIECE_VALUES", "Piece::N_PIECES", &params.piece_values);
+    write_placement_values(&mut source, "PIECE_PLACEMENT_VALUES_MG", &params.piece_placement_values, Score::mg);
+    write_placement_values(&mut source, "PIECE_PLACEMENT_VALUES_EG", &params.piece_placement_values, Score::eg);
+
+    for (name, score) in [
+        ("BISHOP_PAIR_BONUS", params.bishop_pair_bonus),
+        ("BAD_BISHOP_PENALTY", params.bad_bishop_penalty),
+        ("DOUBLED_PAWNS_PENALTY", params.doubled_pawns_penalty),
+        ("UNCASTLED_KING_PENALTY", params.uncastled_king_penalty),
+        ("CASTLED_KING_BONUS", params.castled_king_bonus),
+        ("UNDEVELOPED_MINOR_PENALTY", params.undeveloped_minor_penalty),
+        ("EARLY_QUEEN_PENALTY", params.early_queen_penalty),
+        ("WASTED_TEMPO_PENALTY", params.wasted_tempo_penalty),
+        ("CENTER_PAWN_BONUS", params.center_pawn_bonus),
+    ] {
+        writeln!(source, "const {}: Score = {};", name, format_score(score)).unwrap();
+    }
+
+    write_scores(&mut source, "CONNECTED_PAWN_BONUS", "BOARD_HEIGHT", &params.connected_pawn_bonus);
+    write_scores(&mut source, "PINNED_PIECE_PENALTY", "Piece::N_PIECES", &params.pinned_piece_penalty);
+    write_scores(&mut source, "HANGING_PIECE_PENALTY", "Piece::N_PIECES", &params.hanging_piece_penalty);
+
+    source
+}
+
+/// Tune the evaluation weights on the positions of `dataset` with a local
+/// search: every weight in turn is nudged up or down, and kept moving in that
+/// direction while the error decreases. The tuned weights are written to
+/// `output` after every pass, so an interrupted run isn't lost.
+///
+/// * `pl_moves`: Pseudo-legal move bitboards
+/// * `magics`: Magic bitboards for slider moves
+/// * `dataset`: Path of the labeled positions
+/// * `output`: Path to write the tuned weights to, as Rust source
+/// * `max_passes`: Maximum number of passes over all weights
+pub fn run(
+    pl_moves: &'static MoveBitboards,
+    magics: &'static MagicBitboard,
+    dataset: &str,
+    output: &str,
+    max_passes: usize,
+    augment: bool,
+) -> Result<(), Error> {
+    let mut positions = load_dataset(dataset)?;
+    if positions.is_empty() {
+        return Err(Error::InvalidDataset(format!("no positions in '{}'", dataset)));
+    }
+    info!("Loaded {} positions", positions.len());
+
+    if augment {
+        positions = augment_dataset(positions);
+        info!("Augmented to {} positions", positions.len());
+    }
+
+    let k = fit_scaling_constant(pl_moves, magics, &positions);
+    let mut params = EvalParams::DEFAULT;
+    let mut best_error = error(pl_moves, magics, &positions, &params, k);
+    info!("Scaling constant {:.3}, initial error {:.6}", k, best_error);
+
+    let mut weights = params.weights();
+
+    for pass in 1..=max_passes {
+        let start = Instant::now();
+        let mut improved = false;
+
+        for i in 0..weights.len() {
+            for delta in [1, -1] {
+                let mut weight_improved = false;
+                loop {
+                    weights[i] += delta;
+                    params.set_weights(&weights);
+
+                    let candidate_error = error(pl_moves, magics, &positions, &params, k);
+                    if candidate_error >= best_error {
+                        weights[i] -= delta;
+                        params.set_weights(&weights);
+                        break;
+                    }
+
+                    best_error = candidate_error;
+                    weight_improved = true;
+                }
+
+                if weight_improved {
+                    improved = true;
+                    break;
+                }
+            }
+        }
+
+        info!("Pass {}: error {:.6} in {:.2?}", pass, best_error, start.elapsed());
+        fs::write(output, params_source(&params)).map_err(|source| Error::Write { path: output.into(), source })?;
+
+        if !improved {
+            break;
+        }
+    }
+
+    println!("Tuned weights with error {:.6} written to {}", best_error, output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_result() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -";
+        assert_eq!(parse_result(&format!("{} [1.0]", fen)), Some(1.0));
+        assert_eq!(parse_result(&format!("{} 0 1 [0.5]", fen)), Some(0.5));
+        assert_eq!(parse_result(&format!("{} c9 \"0-1\";", fen)), Some(0.0));
+        assert_eq!(parse_result(&format!("{} 1/2-1/2", fen)), Some(0.5));
+        assert_eq!(parse_result(fen), None);
+        assert_eq!(parse_result(&format!("{} 0 1", fen)), None);
+    }
+
+    #[test]
+    fn test_augment_dataset() {
+        let positions = vec![
+            TuningPosition { board: Board::default(), result: 1.0 },
+            TuningPosition { board: Board::from_fen("8/5k2/8/8/8/2K5/1P6/8 w - -").unwrap(), result: 0.5 },
+        ];
+        let augmented = augment_dataset(positions);
+
+        // Only the position without castling rights is mirrored
+        let fens: Vec<(String, f64)> = augmented.iter().map(|p| (p.board.to_fen(), p.result)).collect();
+        assert_eq!(fens, [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq -".to_string(), 0.0),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -".to_string(), 1.0),
+            ("8/6p1/5k2/8/8/8/2K5/8 b - -".to_string(), 0.5),
+            ("8/2k5/8/8/8/5K2/6P1/8 w - -".to_string(), 0.5),
+            ("8/1p6/2k5/8/8/8/5K2/8 b - -".to_string(), 0.5),
+            ("8/5k2/8/8/8/2K5/1P6/8 w - -".to_string(), 0.5),
+        ]);
+    }
+}
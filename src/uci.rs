@@ -0,0 +1,328 @@
+//! Universal Chess Interface protocol, for playing in chess GUIs and
+//! tournament managers. Commands are read from the GUI line by line, and a
+//! search started with `go` runs in the background until it's done or the GUI
+//! sends `stop`, reporting every completed iteration with `info`.
+
+use std::io::{self, BufRead, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::board::Side;
+use crate::clock::{Clock, TimeControl};
+use crate::error::Error;
+use crate::game::GameState;
+use crate::magic::MagicBitboard;
+use crate::move_bitboards::PSEUDO_LEGAL_MOVES;
+use crate::search::{is_mate_score, moves_to_mate, SearchAsync, SearchHandle, MAX_SEARCH_DEPTH};
+
+const DEFAULT_HASH_SIZE_MB: usize = 64;
+const MAX_HASH_SIZE_MB: usize = 4096;
+const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+/// Limits of a `go` command, see `parse_go`.
+#[derive(Debug, Default, PartialEq)]
+struct GoLimits {
+    depth: Option<usize>,
+    nodes: Option<u64>,
+    time: Option<Duration>,
+}
+
+/// The engine's state between commands: the position of the last `position`
+/// command, and the searcher, which is lent to the search thread while a
+/// search is running.
+struct Uci<W: Write + Send + 'static> {
+    output: Arc<Mutex<W>>,
+    game: GameState,
+    hash_size_mb: usize,
+    // Time kept in reserve per move for the delays between the GUI and the
+    // clock, e.g. a server over the network
+    move_overhead: Duration,
+    searcher: Option<SearchAsync>,
+    search_handle: SearchHandle,
+    search: Option<JoinHandle<SearchAsync>>,
+}
+
+/// Answer the UCI commands read from `input` on `output` until `quit` or the
+/// end of the input.
+pub fn run<W: Write + Send + 'static>(input: impl BufRead, output: W) -> io::Result<()> {
+    let searcher = SearchAsync::with_hash_size(DEFAULT_HASH_SIZE_MB);
+    let mut uci = Uci {
+        output: Arc::new(Mutex::new(output)),
+        game: GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get()),
+        hash_size_mb: DEFAULT_HASH_SIZE_MB,
+        move_overhead: Clock::DEFAULT_MOVE_OVERHEAD,
+        search_handle: searcher.handle(),
+        searcher: Some(searcher),
+        search: None,
+    };
+
+    for line in input.lines() {
+        let line = line?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        // Anything but these waits for the running search to finish
+        if !matches!(tokens.first(), Some(&("isready" | "stop" | "quit")) | None) {
+            uci.wait_for_search();
+        }
+
+        match tokens.as_slice() {
+            ["uci"] => uci.send(&format!(
+                "id name pawndropper {}\nid author soudy\n\
+                 option name Hash type spin default {} min 1 max {}\n\
+                 option name Move Overhead type spin default {} min 0 max {}\n\
+                 option name Clear Hash type button\nuciok",
+                env!("CARGO_PKG_VERSION"),
+                DEFAULT_HASH_SIZE_MB,
+                MAX_HASH_SIZE_MB,
+                Clock::DEFAULT_MOVE_OVERHEAD.as_millis(),
+                MAX_MOVE_OVERHEAD_MS
+            )),
+            ["isready"] => uci.send("readyok"),
+            ["setoption", "name", "Hash", "value", size] => match size.parse() {
+                Ok(size_mb) if (1..=MAX_HASH_SIZE_MB).contains(&size_mb) => {
+                    uci.hash_size_mb = size_mb;
+                    uci.new_searcher();
+                },
+                _ => uci.send(&format!("info string invalid hash size '{}'", size)),
+            },
+            ["setoption", "name", "Move", "Overhead", "value", millis] => match millis.parse() {
+                Ok(millis) if millis <= MAX_MOVE_OVERHEAD_MS => uci.move_overhead = Duration::from_millis(millis),
+                _ => uci.send(&format!("info string invalid move overhead '{}'", millis)),
+            },
+            ["setoption", "name", "Clear", "Hash"] => uci.searcher.as_mut().unwrap().clear_hash(),
+            ["setoption", ..] => uci.send(&format!("info string unsupported option '{}'", line)),
+            ["ucinewgame"] => {
+                uci.game = GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get());
+                uci.new_searcher();
+            },
+            ["position", args @ ..] => match parse_position(args) {
+                Ok(game) => uci.game = game,
+                Err(err) => uci.send(&format!("info string {}", err)),
+            },
+            ["go", args @ ..] => {
+                let limits = parse_go(args, uci.game.board.side_to_move, uci.move_overhead);
+                uci.start_search(limits);
+            },
+            ["stop"] => {
+                uci.search_handle.stop();
+                uci.wait_for_search();
+            },
+            ["quit"] => break,
+            [] => (),
+            _ => uci.send(&format!("info string unknown command '{}'", line)),
+        }
+    }
+
+    uci.search_handle.stop();
+    uci.wait_for_search();
+    Ok(())
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    fn send(&self, message: &str) {
+        send(&self.output, message);
+    }
+
+    /// Replace the searcher by one with an empty transposition table.
+    fn new_searcher(&mut self) {
+        let searcher = SearchAsync::with_hash_size(self.hash_size_mb);
+        self.search_handle = searcher.handle();
+        self.searcher = Some(searcher);
+    }
+
+    /// Search the current position on a thread of its own, which sends the
+    /// best move when it's done.
+    fn start_search(&mut self, limits: GoLimits) {
+        let mut searcher = self.searcher.take().unwrap();
+        searcher.set_node_limit(limits.nodes);
+        searcher.set_time_limit(limits.time);
+        self.search_handle.clear();
+
+        let (sender, receiver) = mpsc::channel();
+        searcher.set_iteration_sender(Some(sender));
+
+        let output = Arc::clone(&self.output);
+        let mut game = self.game.clone();
+        let side_to_move = game.board.side_to_move;
+        let depth = limits.depth.unwrap_or(MAX_SEARCH_DEPTH);
+
+        self.search = Some(thread::spawn(move || {
+            let start = Instant::now();
+            let best_move = thread::scope(|scope| {
+                // The channel closes when the search is done
+                let output = &output;
+                scope.spawn(move || {
+                    for (depth, eval, pv) in receiver {
+                        let pv: Vec<String> = pv.iter().map(|m| m.to_coordinate()).collect();
+                        send(output, &format!(
+                            "info depth {} score {} time {} pv {}",
+                            depth,
+                            format_score(eval, side_to_move),
+                            start.elapsed().as_millis(),
+                            pv.join(" ")
+                        ));
+                    }
+                });
+
+                let (legal_moves, _) = game.get_legal_moves();
+                let (_, best_move, _) = searcher.find_best_legal_move(&mut game, depth, &[]);
+                searcher.set_iteration_sender(None);
+                (!legal_moves.is_empty()).then_some(best_move)
+            });
+
+            let elapsed = start.elapsed();
+            send(&output, &format!(
+                "info nodes {} time {} nps {} hashfull {}",
+                searcher.nodes(),
+                elapsed.as_millis(),
+                (searcher.nodes() as f64/elapsed.as_secs_f64()) as u64,
+                searcher.hashfull()
+            ));
+            send(&output, &format!("bestmove {}", best_move.map_or("0000".to_owned(), |m| m.to_coordinate())));
+
+            searcher
+        }));
+    }
+
+    /// Wait for the running search, if any, and take back its searcher.
+    fn wait_for_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.searcher = Some(search.join().unwrap());
+        }
+    }
+}
+
+fn send<W: Write>(output: &Mutex<W>, message: &str) {
+    let mut output = output.lock().unwrap();
+    // The GUI may be gone already, there's nobody to tell about it
+    let _ = writeln!(output, "{}", message).and_then(|_| output.flush());
+}
+
+/// A score from white's perspective as a UCI score from the side to move's
+/// perspective, e.g. `cp 35` or `mate -2`.
+fn format_score(eval: i32, side_to_move: Side) -> String {
+    let eval = if side_to_move == Side::White { eval } else { -eval };
+    if is_mate_score(eval) {
+        format!("mate {}", moves_to_mate(eval))
+    } else {
+        format!("cp {}", eval)
+    }
+}
+
+/// Parse the arguments of `position`: `startpos` or `fen <fen>`, optionally
+/// followed by `moves` and the moves played since in coordinate notation.
+fn parse_position(args: &[&str]) -> Result<GameState, Error> {
+    let moves_start = args.iter().position(|&arg| arg == "moves").unwrap_or(args.len());
+    let moves = args.get(moves_start + 1..).unwrap_or(&[]);
+
+    let mut game = match &args[..moves_start] {
+        ["startpos"] => GameState::new(&PSEUDO_LEGAL_MOVES, MagicBitboard::get()),
+        ["fen", fen @ ..] => GameState::from_fen(&PSEUDO_LEGAL_MOVES, MagicBitboard::get(), &fen.join(" "))?,
+        _ => return Err(Error::InvalidFen(format!("expected startpos or fen in '{}'", args.join(" ")))),
+    };
+    game.apply_moves(moves)?;
+
+    Ok(game)
+}
+
+/// Parse the arguments of `go`. With the clock times of the side to move the
+/// time limit is the time the engine spends on a move in a game with these
+/// clocks with `move_overhead` in reserve, and `movetime` sets it directly.
+/// `infinite` and unknown arguments are ignored, so `go infinite` searches
+/// until `stop`.
+fn parse_go(args: &[&str], side_to_move: Side, move_overhead: Duration) -> GoLimits {
+    let mut limits = GoLimits::default();
+    let mut remaining = [None; Side::N_SIDES];
+    let mut increment = [Duration::ZERO; Side::N_SIDES];
+
+    let millis = |value: &str| value.parse().ok().map(Duration::from_millis);
+    for pair in args.windows(2) {
+        match (pair[0], pair[1]) {
+            ("depth", depth) => limits.depth = depth.parse().ok(),
+            ("nodes", nodes) => limits.nodes = nodes.parse().ok(),
+            ("movetime", time) => limits.time = millis(time),
+            ("wtime", time) => remaining[Side::White as usize] = millis(time),
+            ("btime", time) => remaining[Side::Black as usize] = millis(time),
+            ("winc", time) => increment[Side::White as usize] = millis(time).unwrap_or_default(),
+            ("binc", time) => increment[Side::Black as usize] = millis(time).unwrap_or_default(),
+            _ => (),
+        }
+    }
+
+    if let (None, Some(time)) = (limits.time, remaining[side_to_move as usize]) {
+        let time_control = TimeControl { base: time, increment: increment[side_to_move as usize] };
+        let clock = Clock::with_remaining(time_control, [time; Side::N_SIDES]);
+        limits.time = Some(clock.move_time(side_to_move, move_overhead));
+    }
+
+    limits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_uci() {
+        let input = "uci\nisready\nposition fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1 moves g1g2 g8h8\ngo depth 3\n\
+                     position startpos moves e2e4 e9e5\nsetoption name Hash value 1\n\
+                     setoption name Move Overhead value 100\nsetoption name Move Overhead value -1\nsetoption name Clear Hash\n\
+                     position startpos moves e2e4\ngo depth 1\nquit\n";
+        let output = SharedBuffer::default();
+        run(input.as_bytes(), output.clone()).unwrap();
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines.contains(&"uciok"));
+        assert!(lines.contains(&"readyok"));
+        assert!(lines.iter().any(|line| line.starts_with("info depth 3 score mate 1 ")));
+        assert!(lines.contains(&"info string invalid move 'e9e5'"));
+        assert!(lines.contains(&"option name Move Overhead type spin default 50 min 0 max 5000"));
+        assert!(lines.contains(&"info string invalid move overhead '-1'"));
+        assert!(lines.contains(&"option name Clear Hash type button"));
+        assert!(!lines.iter().any(|line| line.starts_with("info string unsupported option")));
+        assert!(lines.iter().any(|line| line.starts_with("info nodes ") && line.contains(" hashfull ")));
+
+        let best_moves: Vec<&&str> = lines.iter().filter(|line| line.starts_with("bestmove ")).collect();
+        assert_eq!(best_moves.len(), 2);
+        assert_eq!(*best_moves[0], "bestmove a1a8");
+        assert_eq!(best_moves[1].len(), "bestmove e7e5".len());
+    }
+
+    #[test]
+    fn test_parse_go() {
+        assert_eq!(parse_go(&["depth", "5", "nodes", "1000"], Side::White, Clock::DEFAULT_MOVE_OVERHEAD), GoLimits {
+            depth: Some(5),
+            nodes: Some(1000),
+            time: None,
+        });
+        assert_eq!(parse_go(&["movetime", "250", "wtime", "60000"], Side::White, Clock::DEFAULT_MOVE_OVERHEAD).time, Some(Duration::from_millis(250)));
+        assert_eq!(parse_go(&["infinite"], Side::Black, Clock::DEFAULT_MOVE_OVERHEAD), GoLimits::default());
+
+        // Only the clock of the side to move counts
+        let time = parse_go(&["wtime", "1000", "btime", "60000", "binc", "1000"], Side::White, Clock::DEFAULT_MOVE_OVERHEAD).time.unwrap();
+        assert!(time > Duration::ZERO && time < Duration::from_millis(100));
+        let time = parse_go(&["wtime", "1000", "btime", "60000", "binc", "1000"], Side::Black, Clock::DEFAULT_MOVE_OVERHEAD).time.unwrap();
+        assert!(time > Duration::from_millis(1000) && time < Duration::from_millis(5000));
+
+        // The overhead comes off the time for the move, but not off a fixed
+        // movetime
+        let time = parse_go(&["btime", "60000", "binc", "1000"], Side::Black, Duration::from_millis(1000)).time.unwrap();
+        assert_eq!(time, parse_go(&["btime", "60000", "binc", "1000"], Side::Black, Duration::ZERO).time.unwrap() - Duration::from_millis(1000));
+        assert_eq!(parse_go(&["movetime", "250"], Side::White, Duration::from_secs(1)).time, Some(Duration::from_millis(250)));
+    }
+}
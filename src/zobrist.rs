@@ -1,53 +1,107 @@
-use crate::board::{N_SQUARES, Board, Piece, Side, BOARD_WIDTH};
+use crate::board::{N_SQUARES, Board, Piece, Side, Variant, BOARD_WIDTH};
 use crate::move_bitboards::file;
+use crate::r#move::Move;
 
-use rand::Rng;
-
-#[derive(Debug, Clone)]
+/// Random keys of the pieces on each square and of the rest of the position
+/// state, XORed together into the hash of a position. The keys are generated
+/// at compile time from a fixed seed, so hashes are the same across games and
+/// runs, and every game shares `ZOBRIST` instead of carrying its own copy.
+#[derive(Debug)]
 pub struct ZobristHasher {
-    rands: [[[u128; N_SQUARES]; Piece::N_PIECES]; Side::N_SIDES],
-    black_to_move_rand: u128,
-    castling_right_long_rands: [u128; 4],
-    castling_right_short_rands: [u128; 4],
-    ep_file_rands: [u128; BOARD_WIDTH],
+    rands: [[[u64; N_SQUARES]; Piece::N_PIECES]; Side::N_SIDES],
+    black_to_move_rand: u64,
+    castling_right_long_rands: [u64; 4],
+    castling_right_short_rands: [u64; 4],
+    ep_file_rands: [u64; BOARD_WIDTH],
+    checks_rands: [[u64; Variant::CHECKS_TO_WIN as usize + 1]; Side::N_SIDES],
+    hand_rands: [[[u64; Variant::MAX_IN_HAND as usize + 1]; Piece::N_PIECES]; Side::N_SIDES],
 }
 
+/// The keys all games hash their positions with.
+pub static ZOBRIST: ZobristHasher = ZobristHasher::generate(ZobristHasher::SEED);
+
 impl ZobristHasher {
-    pub fn new() -> Self {
+    const SEED: u64 = 0x70b5_2f5c_8a3d_e9e1;
+
+    /// Generate the keys from `seed` with SplitMix64.
+    pub const fn generate(seed: u64) -> Self {
+        let mut state = seed;
         let mut hash_instance = Self {
-            rands: [[[0u128; N_SQUARES]; Piece::N_PIECES]; Side::N_SIDES],
+            rands: [[[0u64; N_SQUARES]; Piece::N_PIECES]; Side::N_SIDES],
             black_to_move_rand: 0,
-            castling_right_long_rands: [0u128; 4],
-            castling_right_short_rands: [0u128; 4],
-            ep_file_rands: [0u128; BOARD_WIDTH]
+            castling_right_long_rands: [0u64; 4],
+            castling_right_short_rands: [0u64; 4],
+            ep_file_rands: [0u64; BOARD_WIDTH],
+            checks_rands: [[0u64; Variant::CHECKS_TO_WIN as usize + 1]; Side::N_SIDES],
+            hand_rands: [[[0u64; Variant::MAX_IN_HAND as usize + 1]; Piece::N_PIECES]; Side::N_SIDES],
         };
 
-        let mut rng = rand::thread_rng();
-
-        for side in Side::VALUES {
-            for piece in Piece::VALUES {
-                for i in 0..N_SQUARES {
-                    hash_instance.rands[side as usize][piece as usize][i] = rng.gen::<u128>();
+        let mut side = 0;
+        while side < Side::N_SIDES {
+            let mut piece = 0;
+            while piece < Piece::N_PIECES {
+                let mut square = 0;
+                while square < N_SQUARES {
+                    hash_instance.rands[side][piece][square] = Self::next_rand(&mut state);
+                    square += 1;
                 }
+                piece += 1;
             }
+            side += 1;
+        }
+
+        hash_instance.black_to_move_rand = Self::next_rand(&mut state);
+
+        let mut i = 0;
+        while i < 4 {
+            hash_instance.castling_right_long_rands[i] = Self::next_rand(&mut state);
+            hash_instance.castling_right_short_rands[i] = Self::next_rand(&mut state);
+            i += 1;
         }
 
-        hash_instance.black_to_move_rand = rng.gen::<u128>();
+        let mut i = 0;
+        while i < BOARD_WIDTH {
+            hash_instance.ep_file_rands[i] = Self::next_rand(&mut state);
+            i += 1;
+        }
 
-        for i in 0..4 {
-            hash_instance.castling_right_long_rands[i] = rng.gen::<u128>();
-            hash_instance.castling_right_short_rands[i] = rng.gen::<u128>();
+        let mut side = 0;
+        while side < Side::N_SIDES {
+            let mut checks = 0;
+            while checks <= Variant::CHECKS_TO_WIN as usize {
+                hash_instance.checks_rands[side][checks] = Self::next_rand(&mut state);
+                checks += 1;
+            }
+            side += 1;
         }
 
-        for i in 0..BOARD_WIDTH {
-            hash_instance.ep_file_rands[i] = rng.gen::<u128>();
+        let mut side = 0;
+        while side < Side::N_SIDES {
+            let mut piece = 0;
+            while piece < Piece::N_PIECES {
+                let mut n = 0;
+                while n <= Variant::MAX_IN_HAND as usize {
+                    hash_instance.hand_rands[side][piece][n] = Self::next_rand(&mut state);
+                    n += 1;
+                }
+                piece += 1;
+            }
+            side += 1;
         }
 
         hash_instance
     }
 
-    pub fn hash(&self, board: &Board) -> u128 {
-        let mut hash = 0u128;
+    const fn next_rand(state: &mut u64) -> u64 {
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn hash(&self, board: &Board) -> u64 {
+        let mut hash = 0u64;
 
         // Hash side to play
         if board.side_to_move == Side::Black {
@@ -68,16 +122,82 @@ impl ZobristHasher {
             }
         }
 
-        // Hash castling rights
+        hash ^ self.state_hash(board)
+    }
+
+    /// Hash of the pieces moved by `m` and the side to move, the part of the
+    /// position hash that making `m` toggles apart from the castling rights
+    /// and en passant square (see `state_hash`). Updating the hash with it is
+    /// much cheaper than hashing the new position from scratch.
+    pub fn move_hash(&self, m: &Move) -> u64 {
+        m.piece_changes()
+            .iter()
+            .filter(|&&(_, _, _, sign)| sign != 0)
+            .fold(self.black_to_move_rand, |hash, &(piece, side, square, _)| {
+                hash ^ self.rands[side as usize][piece as usize][square]
+            })
+    }
+
+    /// Hash of the castling rights and en passant square of `board`, the
+    /// checks given in three-check and the pieces in hand in crazyhouse.
+    pub fn state_hash(&self, board: &Board) -> u64 {
+        self.castling_hash(board) ^ self.en_passant_hash(board) ^ self.checks_hash(board) ^ self.hands_hash(board)
+    }
+
+    /// Hash of passing the turn without moving.
+    pub fn null_move_hash(&self) -> u64 {
+        self.black_to_move_rand
+    }
+
+    fn castling_hash(&self, board: &Board) -> u64 {
         let long_castle_rights = board.castling_right_long[0] as usize + board.castling_right_long[1] as usize;
         let short_castle_rights = board.castling_right_short[0] as usize + board.castling_right_short[1] as usize;
-        hash ^= self.castling_right_long_rands[long_castle_rights];
-        hash ^= self.castling_right_short_rands[short_castle_rights];
+        self.castling_right_long_rands[long_castle_rights] ^ self.castling_right_short_rands[short_castle_rights]
+    }
+
+    fn checks_hash(&self, board: &Board) -> u64 {
+        match board.variant {
+            Variant::Standard | Variant::Crazyhouse => 0,
+            // Checks after the game is over don't change the position
+            Variant::ThreeCheck => board.checks.iter().zip(&self.checks_rands)
+                .fold(0, |hash, (&checks, rands)| hash ^ rands[checks.min(Variant::CHECKS_TO_WIN) as usize]),
+        }
+    }
 
-        if board.en_passant_square != 0 {
-            hash ^= self.ep_file_rands[file(board.en_passant_square)];
+    fn hands_hash(&self, board: &Board) -> u64 {
+        if board.variant != Variant::Crazyhouse {
+            return 0;
         }
 
+        let mut hash = 0u64;
+        for side in Side::VALUES {
+            for piece in Piece::ALL_BUT_KING {
+                let n = board.in_hand(piece, side).min(Variant::MAX_IN_HAND);
+                hash ^= self.hand_rands[side as usize][piece as usize][n as usize];
+            }
+        }
         hash
     }
+
+    fn en_passant_hash(&self, board: &Board) -> u64 {
+        match board.en_passant_square {
+            Some(en_passant_square) => self.ep_file_rands[file(en_passant_square)],
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_fixed() {
+        // Learning files store these hashes, so a change to the keys or the
+        // order they're generated in makes every learned position unreachable
+        let start = Board::default();
+        let e4 = Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert_eq!(ZOBRIST.hash(&start), 0x67ec_d5a7_08c3_d18f);
+        assert_eq!(ZOBRIST.hash(&e4), 0xc494_dc4e_5a67_9e36);
+    }
 }